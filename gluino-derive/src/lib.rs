@@ -0,0 +1,602 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Group, Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    meta::ParseNestedMeta, parse_macro_input, parse_quote, spanned::Spanned, Attribute, Data,
+    DeriveInput, Error, Expr, Field, Fields, GenericArgument, GenericParam, Lit, LitInt, LitStr,
+    PathArguments, PathSegment, Token, Type,
+};
+
+/// Derive `gluino::serde::GluinoSpecType`, matching the layout used by `gluino::serde::to_writer`.
+///
+/// Structs become records, tuple structs tuples, newtype structs the spec of their field and enums
+/// enums with a payload per variant. Every derived type is named so recursive types refer back to
+/// themselves, by default after its module path, ident and type arguments such as
+/// `shapes::Pair<u8>`, and `#[gluino(name = "...")]` on the type overrides the name.
+///
+/// Field and variant names follow serde's `rename`, `rename_all` and `rename_all_fields`
+/// attributes, so they match what `gluino::serde::to_writer` writes. Renames that differ between
+/// serializing and deserializing are rejected.
+///
+/// Fields accept, looking through any `Option`, `Box`, `Rc` or `Arc`:
+/// - `#[gluino(size = 8)]`, `#[gluino(size = "2..9")]`, `#[gluino(size = "..7")]`, `#[gluino(size = "3..")]`
+///   for `String`, `Vec`, array, `HashMap` and `BTreeMap` fields
+/// - `#[gluino(encoding = "utf8" | "utf16" | "ascii")]` for `String` fields
+/// - `#[gluino(int = n)]` and `#[gluino(uint = n)]` for integers of 2^n bytes
+/// - `#[gluino(decimal(precision = p, scale = s))]`
+#[proc_macro_derive(GluinoSpecType, attributes(gluino))]
+pub fn derive_gluino_spec_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let name = match parse_container_attrs(&input.attrs)? {
+        Some(name) => quote!(::std::string::String::from(#name)),
+        None => default_name(&input),
+    };
+    let serde_attrs = parse_serde_attrs(&input.attrs)?;
+    let body = match &input.data {
+        Data::Struct(data) => {
+            fields_spec(&data.fields, &ident.to_string(), serde_attrs.rename_all)?
+        }
+        Data::Enum(data) => {
+            let variants = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_attrs = parse_serde_attrs(&variant.attrs)?;
+                    let spec = fields_spec(
+                        &variant.fields,
+                        &format!("{}::{}", ident, variant.ident),
+                        variant_attrs.rename_all.or(serde_attrs.rename_all_fields),
+                    )?;
+                    let variant_name = match (variant_attrs.rename, serde_attrs.rename_all) {
+                        (Some(rename), _) => rename,
+                        (None, Some(rule)) => rule.apply_to_variant(&variant.ident.to_string()),
+                        (None, None) => variant.ident.to_string(),
+                    };
+                    Ok(quote!((::std::string::String::from(#variant_name), #spec)))
+                })
+                .collect::<syn::Result<Vec<TokenStream2>>>()?;
+            quote!(::gluino::spec_parsing::ParsedSpec::Enum(::std::vec![#(#variants),*]))
+        }
+        Data::Union(data) => {
+            return Err(Error::new_spanned(
+                data.union_token,
+                "GluinoSpecType can not be derived for unions",
+            ))
+        }
+    };
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::gluino::serde::GluinoSpecType));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::gluino::serde::GluinoSpecType for #ident #ty_generics #where_clause {
+            fn get_parsed_spec(
+                names_defined: &mut ::std::collections::HashSet<::std::string::String>,
+            ) -> ::gluino::spec_parsing::ParsedSpec {
+                ::gluino::derive_support::named(
+                    <Self as ::gluino::serde::GluinoSpecType>::spec_type_name(),
+                    names_defined,
+                    |names_defined| #body,
+                )
+            }
+
+            fn spec_type_name() -> ::std::string::String {
+                #name
+            }
+        }
+    })
+}
+
+// the ident within the module defining it, so types of the same ident in different modules differ,
+// followed by the type and const arguments when there are any
+fn default_name(input: &DeriveInput) -> TokenStream2 {
+    let ident = input.ident.to_string();
+    let arguments: Vec<TokenStream2> = input
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(param) => {
+                let param = &param.ident;
+                Some(quote!(<#param as ::gluino::serde::GluinoSpecType>::spec_type_name()))
+            }
+            GenericParam::Const(param) => {
+                let param = &param.ident;
+                Some(quote!(::std::string::ToString::to_string(&#param)))
+            }
+            GenericParam::Lifetime(_) => None,
+        })
+        .collect();
+    if arguments.is_empty() {
+        quote!(::std::format!("{}::{}", ::std::module_path!(), #ident))
+    } else {
+        quote!(::std::format!(
+            "{}::{}<{}>",
+            ::std::module_path!(),
+            #ident,
+            [#(#arguments),*].join(", ")
+        ))
+    }
+}
+
+// same shapes and field names serde uses for structs and enum variants
+fn fields_spec(
+    fields: &Fields,
+    owner: &str,
+    rename_all: Option<RenameRule>,
+) -> syn::Result<TokenStream2> {
+    Ok(match fields {
+        Fields::Named(named) => {
+            let fields = named
+                .named
+                .iter()
+                .map(|field| {
+                    let ident = field.ident.as_ref().unwrap().to_string();
+                    let ident = ident.trim_start_matches("r#");
+                    let spec = field_spec(field, &format!("field {} of {}", ident, owner))?;
+                    let field_name = match (parse_serde_attrs(&field.attrs)?.rename, rename_all) {
+                        (Some(rename), _) => rename,
+                        (None, Some(rule)) => rule.apply_to_field(ident),
+                        (None, None) => ident.to_string(),
+                    };
+                    Ok(quote!((::std::string::String::from(#field_name), #spec)))
+                })
+                .collect::<syn::Result<Vec<TokenStream2>>>()?;
+            quote!(::gluino::spec_parsing::ParsedSpec::Record(::std::vec![#(#fields),*]))
+        }
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+            field_spec(&unnamed.unnamed[0], &format!("field 0 of {}", owner))?
+        }
+        Fields::Unnamed(unnamed) => {
+            let fields = unnamed
+                .unnamed
+                .iter()
+                .enumerate()
+                .map(|(index, field)| field_spec(field, &format!("field {} of {}", index, owner)))
+                .collect::<syn::Result<Vec<TokenStream2>>>()?;
+            quote!(::gluino::spec_parsing::ParsedSpec::Tuple(::std::vec![#(#fields),*]))
+        }
+        Fields::Unit => quote!(::gluino::spec_parsing::ParsedSpec::Void),
+    })
+}
+
+fn field_spec(field: &Field, label: &str) -> syn::Result<TokenStream2> {
+    let ty = &field.ty;
+    let attrs = parse_field_attrs(&field.attrs)?;
+    let (attributed_ty, optionals) = attributed_type(ty);
+    if let Some((_, span)) = &attrs.size
+        && (attrs.replacement.is_some() || !is_sized_type(attributed_ty))
+    {
+        return Err(Error::new(
+            *span,
+            format!(
+                "gluino size attribute on {} applies to String, Vec, array, HashMap and BTreeMap fields",
+                label
+            ),
+        ));
+    }
+    if let Some((_, span)) = &attrs.encoding
+        && (attrs.replacement.is_some() || !is_string_type(attributed_ty))
+    {
+        return Err(Error::new(
+            *span,
+            format!("gluino encoding attribute on {} applies to String fields", label),
+        ));
+    }
+    if let Some(mut replacement) = attrs.replacement {
+        // the field's own spec is never built, so names in it are not defined by the field
+        for _ in 0..optionals {
+            replacement = quote!(::gluino::spec_parsing::ParsedSpec::Optional(#replacement.into()));
+        }
+        return Ok(replacement);
+    }
+    let mut spec = quote!(<#ty as ::gluino::serde::GluinoSpecType>::get_parsed_spec(names_defined));
+    if let Some((size, _)) = attrs.size {
+        spec = quote!(::gluino::derive_support::with_size(#spec, #size));
+    }
+    if let Some((encoding, _)) = attrs.encoding {
+        spec = quote!(::gluino::derive_support::with_string_encoding(#spec, #encoding));
+    }
+    Ok(spec)
+}
+
+// field attributes apply to the type within any Option and pointer, returns that type and how many
+// Options it is within
+fn attributed_type(mut ty: &Type) -> (&Type, usize) {
+    let mut optionals = 0;
+    while let Some(segment) = last_segment(ty)
+        && let Some(inner) = single_type_argument(segment)
+    {
+        match segment.ident.to_string().as_str() {
+            "Option" => optionals += 1,
+            "Box" | "Rc" | "Arc" => {}
+            _ => break,
+        }
+        ty = inner;
+    }
+    (ty, optionals)
+}
+
+fn last_segment(ty: &Type) -> Option<&PathSegment> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last(),
+        Type::Group(group) => last_segment(&group.elem),
+        Type::Paren(paren) => last_segment(&paren.elem),
+        _ => None,
+    }
+}
+
+fn single_type_argument(segment: &PathSegment) -> Option<&Type> {
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) if arguments.args.len() == 1 => {
+            match &arguments.args[0] {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// the types with a string, list or map spec
+fn is_sized_type(ty: &Type) -> bool {
+    matches!(ty, Type::Array(_))
+        || last_segment(ty).is_some_and(|segment| {
+            matches!(
+                segment.ident.to_string().as_str(),
+                "String" | "Vec" | "HashMap" | "BTreeMap"
+            )
+        })
+}
+
+fn is_string_type(ty: &Type) -> bool {
+    last_segment(ty).is_some_and(|segment| segment.ident == "String")
+}
+
+fn parse_container_attrs(attrs: &[Attribute]) -> syn::Result<Option<LitStr>> {
+    let mut name = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("gluino")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown gluino type attribute"))
+            }
+        })?;
+    }
+    Ok(name)
+}
+
+// the serde attributes that name fields and variants, others are left to serde
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    rename_all: Option<RenameRule>,
+    rename_all_fields: Option<RenameRule>,
+}
+
+fn parse_serde_attrs(attrs: &[Attribute]) -> syn::Result<SerdeAttrs> {
+    let mut serde_attrs = SerdeAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            let renamed = ["rename", "rename_all", "rename_all_fields"]
+                .into_iter()
+                .find(|renamed| meta.path.is_ident(renamed));
+            if let Some(renamed) = renamed {
+                if !meta.input.peek(Token![=]) {
+                    return Err(meta.error(format!(
+                        "gluino specs have one name for serializing and deserializing, use \
+                         `{} = \"...\"`",
+                        renamed
+                    )));
+                }
+                let lit: LitStr = meta.value()?.parse()?;
+                match renamed {
+                    "rename" => serde_attrs.rename = Some(lit.value()),
+                    "rename_all" => serde_attrs.rename_all = Some(RenameRule::parse(&lit)?),
+                    _ => serde_attrs.rename_all_fields = Some(RenameRule::parse(&lit)?),
+                }
+            } else if meta.input.peek(Token![=]) {
+                meta.value()?.parse::<Expr>()?;
+            } else if !meta.input.is_empty() && !meta.input.peek(Token![,]) {
+                meta.input.parse::<Group>()?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(serde_attrs)
+}
+
+// serde's rename_all rules, variant idents are in PascalCase and field idents in snake_case
+#[derive(Clone, Copy)]
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(lit: &LitStr) -> syn::Result<RenameRule> {
+        Ok(match lit.value().as_str() {
+            "lowercase" => RenameRule::Lower,
+            "UPPERCASE" => RenameRule::Upper,
+            "PascalCase" => RenameRule::Pascal,
+            "camelCase" => RenameRule::Camel,
+            "snake_case" => RenameRule::Snake,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnake,
+            "kebab-case" => RenameRule::Kebab,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebab,
+            _ => return Err(Error::new_spanned(lit, "unknown serde rename rule")),
+        })
+    }
+
+    fn apply_to_variant(self, variant: &str) -> String {
+        match self {
+            RenameRule::Pascal => variant.to_string(),
+            RenameRule::Lower => variant.to_ascii_lowercase(),
+            RenameRule::Upper => variant.to_ascii_uppercase(),
+            RenameRule::Camel => lowercase_first(variant),
+            RenameRule::Snake | RenameRule::ScreamingSnake | RenameRule::Kebab | RenameRule::ScreamingKebab => {
+                let mut snake = String::new();
+                for (i, c) in variant.char_indices() {
+                    if i > 0 && c.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(c.to_ascii_lowercase());
+                }
+                self.apply_to_field(&snake)
+            }
+        }
+    }
+
+    fn apply_to_field(self, field: &str) -> String {
+        match self {
+            RenameRule::Lower | RenameRule::Snake => field.to_string(),
+            RenameRule::Upper | RenameRule::ScreamingSnake => field.to_ascii_uppercase(),
+            RenameRule::Pascal => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for c in field.chars() {
+                    if c == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(c.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(c);
+                    }
+                }
+                pascal
+            }
+            RenameRule::Camel => lowercase_first(&RenameRule::Pascal.apply_to_field(field)),
+            RenameRule::Kebab => field.replace('_', "-"),
+            RenameRule::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
+
+fn lowercase_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+// sizes and encodings keep the span of their attribute for errors
+#[derive(Default)]
+struct FieldAttrs {
+    size: Option<(TokenStream2, Span)>,
+    encoding: Option<(TokenStream2, Span)>,
+    replacement: Option<TokenStream2>,
+}
+
+fn parse_field_attrs(attrs: &[Attribute]) -> syn::Result<FieldAttrs> {
+    let mut field_attrs = FieldAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("gluino")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("size") {
+                field_attrs.size = Some((parse_size(meta.value()?.parse()?)?, attr.span()));
+            } else if meta.path.is_ident("encoding") {
+                field_attrs.encoding = Some((parse_encoding(meta.value()?.parse()?)?, attr.span()));
+            } else if meta.path.is_ident("int") {
+                let n: LitInt = meta.value()?.parse()?;
+                let n = n.base10_parse::<u8>()?;
+                set_replacement(&meta, &mut field_attrs, quote!(::gluino::spec_parsing::ParsedSpec::Int(#n)))?;
+            } else if meta.path.is_ident("uint") {
+                let n: LitInt = meta.value()?.parse()?;
+                let n = n.base10_parse::<u8>()?;
+                set_replacement(&meta, &mut field_attrs, quote!(::gluino::spec_parsing::ParsedSpec::Uint(#n)))?;
+            } else if meta.path.is_ident("decimal") {
+                let (mut precision, mut scale) = (None, None);
+                meta.parse_nested_meta(|inner| {
+                    let value: LitInt = inner.value()?.parse()?;
+                    let value = value.base10_parse::<u64>()?;
+                    if inner.path.is_ident("precision") {
+                        precision = Some(value);
+                    } else if inner.path.is_ident("scale") {
+                        scale = Some(value);
+                    } else {
+                        return Err(inner.error("expected precision or scale"));
+                    }
+                    Ok(())
+                })?;
+                let (Some(precision), Some(scale)) = (precision, scale) else {
+                    return Err(meta.error("decimal needs both precision and scale"));
+                };
+                set_replacement(
+                    &meta,
+                    &mut field_attrs,
+                    quote!(::gluino::spec_parsing::ParsedSpec::Decimal { precision: #precision, scale: #scale }),
+                )?;
+            } else {
+                return Err(meta.error("unknown gluino field attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(field_attrs)
+}
+
+fn set_replacement(meta: &ParseNestedMeta, field_attrs: &mut FieldAttrs, spec: TokenStream2) -> syn::Result<()> {
+    if field_attrs.replacement.replace(spec).is_some() {
+        Err(meta.error("only one of int, uint or decimal can be used"))
+    } else {
+        Ok(())
+    }
+}
+
+// a fixed size or a rust style range, ranges are exclusive of the end like `Size::Range`
+fn parse_size(lit: Lit) -> syn::Result<TokenStream2> {
+    match &lit {
+        Lit::Int(n) => {
+            let n = n.base10_parse::<u64>()?;
+            Ok(quote!(::gluino::spec_parsing::Size::Fixed(#n)))
+        }
+        Lit::Str(s) => {
+            let value = s.value();
+            let Some((start, end)) = value.split_once("..") else {
+                return Err(Error::new_spanned(&lit, "expected a size range such as \"2..9\""));
+            };
+            let bound = |bound: &str| {
+                bound
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| Error::new_spanned(&lit, format!("invalid size bound {:?}", bound)))
+            };
+            Ok(match (start.trim().is_empty(), end.trim().is_empty()) {
+                (true, true) => quote!(::gluino::spec_parsing::Size::Variable),
+                (false, true) => {
+                    let start = bound(start)?;
+                    quote!(::gluino::spec_parsing::Size::GreaterThan(#start))
+                }
+                (true, false) => {
+                    let end = bound(end)?;
+                    quote!(::gluino::spec_parsing::Size::LessThan(#end))
+                }
+                (false, false) => {
+                    let (start, end) = (bound(start)?, bound(end)?);
+                    quote!(::gluino::spec_parsing::Size::Range(
+                        ::gluino::spec_parsing::SizeRange { start: #start, end: #end }
+                    ))
+                }
+            })
+        }
+        _ => Err(Error::new_spanned(&lit, "expected a fixed size or a size range")),
+    }
+}
+
+fn parse_encoding(lit: LitStr) -> syn::Result<TokenStream2> {
+    match lit.value().as_str() {
+        "utf8" => Ok(quote!(::gluino::spec_parsing::StringEncodingFmt::Utf8)),
+        "utf16" => Ok(quote!(::gluino::spec_parsing::StringEncodingFmt::Utf16)),
+        "ascii" => Ok(quote!(::gluino::spec_parsing::StringEncodingFmt::Ascii)),
+        _ => Err(Error::new_spanned(lit, "expected utf8, utf16 or ascii")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_error(input: DeriveInput) -> String {
+        match expand(input) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_misapplied_attributes() {
+        assert_eq!(
+            "gluino size attribute on field count of Misapplied applies to String, Vec, array, \
+             HashMap and BTreeMap fields",
+            expand_error(parse_quote! {
+                struct Misapplied {
+                    #[gluino(size = 3)]
+                    count: Option<u8>,
+                }
+            })
+        );
+        assert_eq!(
+            "gluino size attribute on field 0 of Misapplied::A applies to String, Vec, array, \
+             HashMap and BTreeMap fields",
+            expand_error(parse_quote! {
+                enum Misapplied {
+                    A(#[gluino(uint = 0, size = 3)] Vec<u64>),
+                }
+            })
+        );
+        assert_eq!(
+            "gluino encoding attribute on field names of Misapplied applies to String fields",
+            expand_error(parse_quote! {
+                struct Misapplied {
+                    #[gluino(encoding = "ascii")]
+                    names: Vec<String>,
+                }
+            })
+        );
+        assert!(
+            expand(parse_quote! {
+                struct Applied {
+                    #[gluino(size = "1..9", encoding = "ascii")]
+                    name: Option<Box<String>>,
+                    #[gluino(size = 2)]
+                    pairs: std::collections::BTreeMap<u8, u8>,
+                }
+            })
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_serde_rename_attributes() {
+        assert_eq!(
+            "gluino specs have one name for serializing and deserializing, use `rename = \"...\"`",
+            expand_error(parse_quote! {
+                struct Renamed {
+                    #[serde(default, rename(serialize = "a", deserialize = "b"))]
+                    field: u8,
+                }
+            })
+        );
+        assert_eq!(
+            "unknown serde rename rule",
+            expand_error(parse_quote! {
+                #[serde(rename_all = "Title Case")]
+                enum Renamed {
+                    Variant,
+                }
+            })
+        );
+        // serde attributes that do not name fields or variants are skipped
+        assert!(
+            expand(parse_quote! {
+                #[serde(bound(serialize = "T: Serialize"), rename_all = "kebab-case")]
+                struct Renamed<T> {
+                    #[serde(skip_serializing_if = "Option::is_none", default)]
+                    field: Option<T>,
+                }
+            })
+            .is_ok()
+        );
+        assert_eq!("on-hold", RenameRule::Kebab.apply_to_variant("OnHold"));
+        assert_eq!("SINCE_DAY", RenameRule::ScreamingSnake.apply_to_field("since_day"));
+        assert_eq!("sinceDay", RenameRule::Camel.apply_to_field("since_day"));
+        assert_eq!("onHold", RenameRule::Camel.apply_to_variant("OnHold"));
+    }
+}
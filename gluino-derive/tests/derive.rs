@@ -0,0 +1,417 @@
+use std::{collections::HashSet, io::Cursor};
+
+use gluino::{
+    serde::{from_reader, to_writer, GluinoSpecType},
+    spec::Spec,
+    spec_parsing::{
+        InterchangeBinaryFloatingPointFormat, ParsedSpec, Size, SizeRange, StringEncodingFmt,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+fn parsed_spec<T: GluinoSpecType>() -> ParsedSpec {
+    T::get_parsed_spec(&mut HashSet::new())
+}
+
+fn named(name: &str, spec: ParsedSpec) -> ParsedSpec {
+    ParsedSpec::Name {
+        name: name.into(),
+        spec: spec.into(),
+    }
+}
+
+fn utf8() -> ParsedSpec {
+    ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8)
+}
+
+#[derive(GluinoSpecType, Serialize, Deserialize, Debug, PartialEq)]
+#[gluino(name = "point")]
+struct Point(i32, i32);
+
+#[derive(GluinoSpecType, Serialize, Deserialize, Debug, PartialEq)]
+#[gluino(name = "id")]
+struct Id(u64);
+
+#[derive(GluinoSpecType, Serialize, Deserialize, Debug, PartialEq)]
+#[gluino(name = "shape")]
+enum Shape {
+    Empty,
+    Circle(f64),
+    Line(Point, Point),
+    Rectangle { corner: Point, width: u16 },
+}
+
+#[derive(GluinoSpecType, Serialize, Deserialize, Debug, PartialEq)]
+#[gluino(name = "drawing")]
+struct Drawing {
+    id: Id,
+    #[gluino(size = "1..65", encoding = "ascii")]
+    title: String,
+    #[gluino(size = 2)]
+    shapes: Vec<Shape>,
+    #[gluino(uint = 0)]
+    layer: u32,
+    #[gluino(size = "..9")]
+    author: Option<String>,
+}
+
+#[test]
+fn test_struct_tuple_and_enum_specs() {
+    let point = named("point", ParsedSpec::Tuple(vec![ParsedSpec::Int(2), ParsedSpec::Int(2)]));
+    assert_eq!(point, parsed_spec::<Point>());
+    assert_eq!(named("id", ParsedSpec::Uint(3)), parsed_spec::<Id>());
+    let shape = named(
+        "shape",
+        ParsedSpec::Enum(vec![
+            ("Empty".into(), ParsedSpec::Void),
+            (
+                "Circle".into(),
+                ParsedSpec::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Double),
+            ),
+            (
+                "Line".into(),
+                ParsedSpec::Tuple(vec![
+                    point.clone(),
+                    ParsedSpec::Ref {
+                        name: "point".into(),
+                    },
+                ]),
+            ),
+            (
+                "Rectangle".into(),
+                ParsedSpec::Record(vec![
+                    (
+                        "corner".into(),
+                        ParsedSpec::Ref {
+                            name: "point".into(),
+                        },
+                    ),
+                    ("width".into(), ParsedSpec::Uint(1)),
+                ]),
+            ),
+        ]),
+    );
+    assert_eq!(shape, parsed_spec::<Shape>());
+    assert_eq!(
+        named(
+            "drawing",
+            ParsedSpec::Record(vec![
+                ("id".into(), named("id", ParsedSpec::Uint(3))),
+                (
+                    "title".into(),
+                    ParsedSpec::String(
+                        Size::Range(SizeRange { start: 1, end: 65 }),
+                        StringEncodingFmt::Ascii
+                    )
+                ),
+                (
+                    "shapes".into(),
+                    ParsedSpec::List {
+                        size: Size::Fixed(2),
+                        value_spec: shape.into(),
+                    }
+                ),
+                ("layer".into(), ParsedSpec::Uint(0)),
+                (
+                    "author".into(),
+                    ParsedSpec::Optional(
+                        ParsedSpec::String(Size::LessThan(9), StringEncodingFmt::Utf8).into()
+                    )
+                ),
+            ])
+        ),
+        parsed_spec::<Drawing>()
+    );
+    Drawing::get_spec();
+}
+
+#[test]
+fn test_derived_round_trip() {
+    let drawing = Drawing {
+        id: Id(42),
+        title: "shapes".into(),
+        shapes: vec![
+            Shape::Line(Point(0, 0), Point(-3, 4)),
+            Shape::Rectangle {
+                corner: Point(1, 1),
+                width: 10,
+            },
+        ],
+        layer: 3,
+        author: Some("gluino".into()),
+    };
+    let spec = Drawing::get_spec();
+    let mut bytes = Vec::new();
+    to_writer(&spec, &drawing, &mut bytes).unwrap();
+    assert_eq!(
+        drawing,
+        from_reader::<Drawing, _>(&spec, &mut Cursor::new(bytes)).unwrap()
+    );
+}
+
+#[derive(GluinoSpecType, Serialize, Deserialize, Debug, PartialEq)]
+enum Expr {
+    Literal(i64),
+    Negate(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Let(Box<Binding>),
+}
+
+#[derive(GluinoSpecType, Serialize, Deserialize, Debug, PartialEq)]
+struct Binding {
+    name: String,
+    value: Expr,
+    body: Option<Box<Expr>>,
+}
+
+#[test]
+fn test_recursive_types() {
+    let expr = "derive::Expr";
+    let binding = "derive::Binding";
+    let expr_ref = || ParsedSpec::Ref {
+        name: expr.into(),
+    };
+    assert_eq!(
+        named(
+            expr,
+            ParsedSpec::Enum(vec![
+                ("Literal".into(), ParsedSpec::Int(3)),
+                ("Negate".into(), expr_ref()),
+                ("Add".into(), ParsedSpec::Tuple(vec![expr_ref(), expr_ref()])),
+                (
+                    "Let".into(),
+                    named(
+                        binding,
+                        ParsedSpec::Record(vec![
+                            ("name".into(), utf8()),
+                            ("value".into(), expr_ref()),
+                            ("body".into(), ParsedSpec::Optional(expr_ref().into())),
+                        ])
+                    )
+                ),
+            ])
+        ),
+        parsed_spec::<Expr>()
+    );
+    let spec = Expr::get_spec();
+    let value = Expr::Let(Box::new(Binding {
+        name: "x".into(),
+        value: Expr::Negate(Box::new(Expr::Literal(3))),
+        body: Some(Box::new(Expr::Add(
+            Box::new(Expr::Literal(1)),
+            Box::new(Expr::Literal(2)),
+        ))),
+    }));
+    let mut bytes = Vec::new();
+    to_writer(&spec, &value, &mut bytes).unwrap();
+    assert_eq!(value, from_reader::<Expr, _>(&spec, &mut Cursor::new(bytes)).unwrap());
+}
+
+#[derive(GluinoSpecType)]
+#[allow(dead_code)]
+struct Pair<T> {
+    left: T,
+    right: T,
+}
+
+#[derive(GluinoSpecType)]
+#[allow(dead_code)]
+struct Price {
+    #[gluino(decimal(precision = 10, scale = 2))]
+    amount: Vec<u8>,
+    #[gluino(int = 5)]
+    cents: Option<i64>,
+}
+
+#[test]
+fn test_generics_and_replacements() {
+    assert_ne!(Pair::<u8>::get_spec(), Pair::<String>::get_spec());
+    assert_eq!(
+        Spec::compile(named(
+            "derive::Pair<String>",
+            ParsedSpec::Record(vec![("left".into(), utf8()), ("right".into(), utf8())])
+        ))
+        .unwrap(),
+        Pair::<String>::get_spec()
+    );
+    assert_eq!(
+        named(
+            "derive::Price",
+            ParsedSpec::Record(vec![
+                (
+                    "amount".into(),
+                    ParsedSpec::Decimal {
+                        precision: 10,
+                        scale: 2
+                    }
+                ),
+                ("cents".into(), ParsedSpec::Optional(ParsedSpec::Int(5).into())),
+            ])
+        ),
+        parsed_spec::<Price>()
+    );
+}
+
+#[derive(GluinoSpecType)]
+#[allow(dead_code)]
+struct Grid<T, const N: usize> {
+    cells: [T; N],
+}
+
+#[test]
+fn test_default_names() {
+    assert_eq!("derive::Expr", Expr::spec_type_name());
+    assert_eq!("derive::Pair<u8>", Pair::<u8>::spec_type_name());
+    assert_eq!("derive::Pair<Vec<point>>", Pair::<Vec<Point>>::spec_type_name());
+    assert_eq!(
+        "derive::Pair<Option<(u8, String)>>",
+        Pair::<Option<(u8, String)>>::spec_type_name()
+    );
+    assert_eq!(
+        "derive::Grid<derive::Pair<bool>, 3>",
+        Grid::<Pair<bool>, 3>::spec_type_name()
+    );
+    assert_eq!(
+        named(
+            "derive::Pair<derive::Pair<bool>>",
+            ParsedSpec::Record(vec![
+                (
+                    "left".into(),
+                    named(
+                        "derive::Pair<bool>",
+                        ParsedSpec::Record(vec![
+                            ("left".into(), ParsedSpec::Bool),
+                            ("right".into(), ParsedSpec::Bool)
+                        ])
+                    )
+                ),
+                (
+                    "right".into(),
+                    ParsedSpec::Ref {
+                        name: "derive::Pair<bool>".into()
+                    }
+                ),
+            ])
+        ),
+        parsed_spec::<Pair<Pair<bool>>>()
+    );
+}
+
+#[derive(GluinoSpecType)]
+#[allow(dead_code)]
+struct Replaced {
+    #[gluino(uint = 0)]
+    first: Option<Id>,
+    second: Id,
+}
+
+#[test]
+fn test_replaced_spec_not_built() {
+    assert_eq!(
+        named(
+            "derive::Replaced",
+            ParsedSpec::Record(vec![
+                ("first".into(), ParsedSpec::Optional(ParsedSpec::Uint(0).into())),
+                ("second".into(), named("id", ParsedSpec::Uint(3))),
+            ])
+        ),
+        parsed_spec::<Replaced>()
+    );
+}
+
+mod first {
+    #[derive(gluino::serde::GluinoSpecType)]
+    #[allow(dead_code)]
+    pub struct Item(pub u8);
+}
+
+mod second {
+    #[derive(gluino::serde::GluinoSpecType)]
+    #[allow(dead_code)]
+    pub struct Item(pub bool);
+}
+
+#[derive(GluinoSpecType)]
+#[allow(dead_code)]
+struct Items {
+    first: first::Item,
+    second: second::Item,
+}
+
+#[test]
+fn test_same_ident_in_different_modules() {
+    assert_eq!(
+        named(
+            "derive::Items",
+            ParsedSpec::Record(vec![
+                ("first".into(), named("derive::first::Item", ParsedSpec::Uint(0))),
+                ("second".into(), named("derive::second::Item", ParsedSpec::Bool)),
+            ])
+        ),
+        parsed_spec::<Items>()
+    );
+}
+
+#[derive(GluinoSpecType, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct Renamed {
+    user_name: String,
+    #[serde(rename = "ID", default)]
+    user_id: u8,
+    status: Status,
+}
+
+#[derive(GluinoSpecType, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", rename_all_fields = "SCREAMING-KEBAB-CASE")]
+enum Status {
+    OnHold { since_day: u8 },
+    #[serde(rename = "gone", rename_all = "PascalCase")]
+    LeftForGood { last_day: u8 },
+    Active,
+}
+
+#[test]
+fn test_serde_renames() {
+    assert_eq!(
+        named(
+            "derive::Renamed",
+            ParsedSpec::Record(vec![
+                ("userName".into(), utf8()),
+                ("ID".into(), ParsedSpec::Uint(0)),
+                (
+                    "status".into(),
+                    named(
+                        "derive::Status",
+                        ParsedSpec::Enum(vec![
+                            (
+                                "on_hold".into(),
+                                ParsedSpec::Record(vec![("SINCE-DAY".into(), ParsedSpec::Uint(0))])
+                            ),
+                            (
+                                "gone".into(),
+                                ParsedSpec::Record(vec![("LastDay".into(), ParsedSpec::Uint(0))])
+                            ),
+                            ("active".into(), ParsedSpec::Void),
+                        ])
+                    )
+                ),
+            ])
+        ),
+        parsed_spec::<Renamed>()
+    );
+    let spec = Renamed::get_spec();
+    for status in [
+        Status::OnHold { since_day: 3 },
+        Status::LeftForGood { last_day: 9 },
+        Status::Active,
+    ] {
+        let value = Renamed {
+            user_name: "gluino".into(),
+            user_id: 7,
+            status,
+        };
+        let mut bytes = Vec::new();
+        to_writer(&spec, &value, &mut bytes).unwrap();
+        assert_eq!(value, from_reader::<Renamed, _>(&spec, &mut Cursor::new(bytes)).unwrap());
+    }
+}
@@ -0,0 +1,177 @@
+//! Checks that a new version of a spec can replace an old one.
+//!
+//! Backward compatible: data written with the old spec can be read with the new one.
+//! Forward compatible: data written with the new spec can be read with the old one.
+//! Full compatible: both.
+
+use std::fmt::{self, Display};
+
+use strum::EnumIter;
+
+use crate::{
+    serde::{SpecResolutionError, build_resolver},
+    spec::Spec,
+    spec_path::SpecPath,
+};
+
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy, EnumIter)]
+pub enum CompatibilityMode {
+    Backward,
+    Forward,
+    Full,
+}
+
+/// Which way data can not be read, `Backward` is old data with the new spec
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub enum CompatibilityDirection {
+    Backward,
+    Forward,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Incompatibility {
+    pub direction: CompatibilityDirection,
+    pub error: SpecResolutionError,
+}
+
+impl Incompatibility {
+    pub fn path(&self) -> &SpecPath {
+        self.error.path()
+    }
+}
+
+impl Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.direction {
+            CompatibilityDirection::Backward => write!(
+                f,
+                "old data can not be read with the new spec: {}",
+                self.error
+            ),
+            CompatibilityDirection::Forward => write!(
+                f,
+                "new data can not be read with the old spec: {}",
+                self.error
+            ),
+        }
+    }
+}
+
+/// Every incompatibility between `old` and `new` for `mode`, empty when compatible.
+///
+/// Changes that only keep some data from being read, like a narrowed `Size` or a removed enum variant,
+/// are incompatibilities too. Subtrees with equal fingerprints are not compared.
+pub fn check_compatibility(
+    old: &Spec,
+    new: &Spec,
+    mode: CompatibilityMode,
+) -> Vec<Incompatibility> {
+    let mut incompatibilities = Vec::new();
+    if matches!(mode, CompatibilityMode::Backward | CompatibilityMode::Full) {
+        incompatibilities.extend(build_resolver(old, new).1.into_iter().map(|error| {
+            Incompatibility {
+                direction: CompatibilityDirection::Backward,
+                error,
+            }
+        }));
+    }
+    if matches!(mode, CompatibilityMode::Forward | CompatibilityMode::Full) {
+        incompatibilities.extend(build_resolver(new, old).1.into_iter().map(|error| {
+            Incompatibility {
+                direction: CompatibilityDirection::Forward,
+                error,
+            }
+        }));
+    }
+    incompatibilities
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use crate::{serde::SpecResolutionErrorKind, test_utils::compile};
+
+    use super::*;
+
+    fn check(
+        old: &str,
+        new: &str,
+        mode: CompatibilityMode,
+    ) -> Vec<(CompatibilityDirection, SpecResolutionErrorKind, String)> {
+        check_compatibility(&compile(old), &compile(new), mode)
+            .iter()
+            .map(|i| (i.direction, (&i.error).into(), i.path().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_specs_are_compatible() {
+        let spec = "name Node = record { value: int(2), children: list<ref Node> }";
+        for mode in CompatibilityMode::iter() {
+            assert!(check(spec, spec, mode).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_modes() {
+        use CompatibilityDirection::*;
+        use CompatibilityMode as Mode;
+        use SpecResolutionErrorKind as Kind;
+        let old = "record {
+            orders: list<record { price: decimal(9, 2), size: uint(1), side: enum { buy: void, sell: void } }>,
+            note: optional<string>,
+            id: string<..32>,
+        }";
+        // adding an optional field and widening an int only breaks old readers
+        let widened = "record {
+            orders: list<record { price: decimal(9, 2), size: uint(2), side: enum { buy: void, sell: void } }>,
+            note: optional<string>,
+            id: string<..32>,
+            tag: optional<string>,
+        }";
+        assert!(check(old, widened, Mode::Backward).is_empty());
+        assert_eq!(
+            vec![(Forward, Kind::Narrowing, ".orders[].size".to_string())],
+            check(old, widened, Mode::Forward)
+        );
+        assert_eq!(
+            check(old, widened, Mode::Forward),
+            check(old, widened, Mode::Full)
+        );
+
+        // removing a required field or adding an enum variant breaks old readers, narrowing a size new ones
+        let narrowed = "record {
+            orders: list<record { size: uint(1), side: enum { buy: void, sell: void, hold: void } }>,
+            note: optional<string>,
+            id: string<..16>,
+        }";
+        assert_eq!(
+            vec![
+                (Backward, Kind::SizeNotCovered, ".id".to_string()),
+                (Forward, Kind::MissingReaderField, ".orders[]".to_string()),
+                (
+                    Forward,
+                    Kind::MissingReaderEnumVariant,
+                    ".orders[].side".to_string()
+                ),
+            ],
+            check(old, narrowed, Mode::Full)
+        );
+
+        let changed = "record {
+            orders: list<record { price: decimal(9, 2), size: int(1), side: enum { buy: void, sell: void } }>,
+            note: optional<string<ascii>>,
+            id: string<..32>,
+        }";
+        assert_eq!(
+            vec![
+                (Backward, Kind::Narrowing, ".orders[].size".to_string()),
+                (Backward, Kind::StringEncodingMismatch, ".note".to_string()),
+                (Forward, Kind::Narrowing, ".orders[].size".to_string()),
+                (Forward, Kind::StringEncodingMismatch, ".note".to_string()),
+            ],
+            check(old, changed, Mode::Full)
+        );
+    }
+}
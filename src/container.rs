@@ -0,0 +1,551 @@
+//! Self describing files of values, the writer spec travels with the data.
+//!
+//! ```text
+//! magic        b"GLU\x01"
+//! fingerprint  32 bytes, the fingerprint of the spec
+//! spec         ParsedSpec::to_bytes
+//! sync marker  16 bytes, unique to the file
+//! blocks       until the end of the file
+//!     count        varint, number of values
+//!     length       varint, number of bytes of values
+//!     values       each serialized with the unit serializer of the spec
+//!     sync marker  the file's sync marker again
+//! ```
+//!
+//! Blocks are written once they hold `block_size` values or on `flush_block`/`finish`.
+
+use std::{
+    fmt::{self, Display},
+    io::{self, Cursor, Read, Write},
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    fingerprint::SpecFingerprint,
+    limits::{DecodeBudget, DecodeLimits},
+    serde::{
+        GluinoDeserializationError, GluinoSerializationError, GluinoValue, GluinoValueDe,
+        GluinoValueSer, decode_u64, get_unit_deserialization_function_with_limits,
+        get_unit_serialization_function,
+    },
+    spec::{Spec, SpecCompileError},
+    spec_parsing::{ParsedSpec, SpecParsingError},
+    util::variable_length_encode_u64,
+};
+
+pub const CONTAINER_MAGIC: [u8; 4] = *b"GLU\x01";
+pub const SYNC_MARKER_LEN: usize = 16;
+pub const DEFAULT_BLOCK_SIZE: usize = 1024;
+pub const DEFAULT_MAX_BLOCK_LENGTH: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, EnumDiscriminants)]
+#[strum_discriminants(name(ContainerErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum ContainerError {
+    Io(io::Error),
+    NotAContainer([u8; 4]),
+    InvalidSpec(SpecParsingError),
+    UncompilableSpec(SpecCompileError),
+    FingerprintMismatch {
+        expected: SpecFingerprint,
+        actual: SpecFingerprint,
+    },
+    SyncMarkerMismatch {
+        block: u64,
+    },
+    BlockLengthMismatch {
+        block: u64,
+        expected_length: u64,
+        actual_length: u64,
+    },
+    BlockTooLong {
+        block: u64,
+        length: u64,
+        max_length: u64,
+    },
+    Serialization(GluinoSerializationError),
+    Deserialization {
+        block: u64,
+        error: GluinoDeserializationError,
+    },
+}
+
+impl Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::NotAContainer(magic) => write!(f, "not a container, starts with {:?}", magic),
+            Self::InvalidSpec(e) => write!(f, "unable to read the writer spec: {}", e),
+            Self::UncompilableSpec(e) => write!(f, "unable to compile the writer spec: {}", e),
+            Self::FingerprintMismatch { expected, actual } => write!(
+                f,
+                "writer spec has fingerprint {}, the header says {}",
+                hex::encode(actual.as_bytes()),
+                hex::encode(expected.as_bytes())
+            ),
+            Self::SyncMarkerMismatch { block } => {
+                write!(f, "block {}: sync marker does not match", block)
+            }
+            Self::BlockLengthMismatch {
+                block,
+                expected_length,
+                actual_length,
+            } => write!(
+                f,
+                "block {}: values take {} bytes, the block has {}",
+                block, actual_length, expected_length
+            ),
+            Self::BlockTooLong {
+                block,
+                length,
+                max_length,
+            } => write!(
+                f,
+                "block {}: length {} is more than the max of {}",
+                block, length, max_length
+            ),
+            Self::Serialization(e) => write!(f, "{}", e),
+            Self::Deserialization { block, error } => write!(f, "block {}: {}", block, error),
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+impl From<io::Error> for ContainerError {
+    fn from(e: io::Error) -> Self {
+        ContainerError::Io(e)
+    }
+}
+
+impl From<GluinoSerializationError> for ContainerError {
+    fn from(e: GluinoSerializationError) -> Self {
+        ContainerError::Serialization(e)
+    }
+}
+
+pub struct ContainerWriter<W: Write> {
+    writer: W,
+    ser: Box<dyn GluinoValueSer<Vec<u8>>>,
+    sync_marker: [u8; SYNC_MARKER_LEN],
+    block_size: usize,
+    block: Vec<u8>,
+    block_count: u64,
+}
+
+impl<W: Write> ContainerWriter<W> {
+    /// Writes the header for `spec` to `writer`
+    pub fn new(spec: &Spec, mut writer: W) -> Result<ContainerWriter<W>, ContainerError> {
+        let sync_marker = new_sync_marker(spec.fingerprint());
+        writer.write_all(&CONTAINER_MAGIC)?;
+        writer.write_all(spec.fingerprint().as_bytes())?;
+        spec.to_parsed_spec().write_as_bytes(&mut writer)?;
+        writer.write_all(&sync_marker)?;
+        Ok(ContainerWriter {
+            writer,
+            ser: get_unit_serialization_function::<Vec<u8>>(spec),
+            sync_marker,
+            block_size: DEFAULT_BLOCK_SIZE,
+            block: Vec::new(),
+            block_count: 0,
+        })
+    }
+
+    /// Number of values per block, at least 1
+    pub fn with_block_size(mut self, block_size: usize) -> ContainerWriter<W> {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    pub fn write(&mut self, value: GluinoValue) -> Result<(), ContainerError> {
+        let len = self.block.len();
+        if let Err(e) = self.ser.serialize(value, &mut self.block) {
+            // drop whatever part of the value was written
+            self.block.truncate(len);
+            return Err(e.into());
+        }
+        self.block_count += 1;
+        if self.block_count as usize >= self.block_size {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the values so far as a block
+    pub fn flush_block(&mut self) -> Result<(), ContainerError> {
+        if self.block_count == 0 {
+            return Ok(());
+        }
+        variable_length_encode_u64(self.block_count, &mut self.writer)?;
+        variable_length_encode_u64(self.block.len() as u64, &mut self.writer)?;
+        self.writer.write_all(&self.block)?;
+        self.writer.write_all(&self.sync_marker)?;
+        self.block.clear();
+        self.block_count = 0;
+        Ok(())
+    }
+
+    /// Writes the last block and returns the writer, values not written by `flush_block` or
+    /// `finish` are lost when the container writer is dropped.
+    pub fn finish(mut self) -> Result<W, ContainerError> {
+        self.flush_block()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Iterates over the values of a container, stopping after the first error
+pub struct ContainerReader<R: Read> {
+    reader: R,
+    spec: Spec,
+    de: Box<dyn GluinoValueDe<Cursor<Vec<u8>>>>,
+    sync_marker: [u8; SYNC_MARKER_LEN],
+    max_block_length: u64,
+    limits: DecodeLimits,
+    block: Cursor<Vec<u8>>,
+    block_remaining: u64,
+    blocks_read: u64,
+    failed: bool,
+}
+
+impl<R: Read> ContainerReader<R> {
+    /// Reads the header and compiles the writer spec
+    pub fn new(reader: R) -> Result<ContainerReader<R>, ContainerError> {
+        ContainerReader::new_with_limits(reader, &DecodeLimits::default())
+    }
+
+    /// Reads the header and compiles the writer spec, failing on a spec, block or value that goes
+    /// over `limits`. The values of a block count as a collection.
+    pub fn new_with_limits(
+        mut reader: R,
+        limits: &DecodeLimits,
+    ) -> Result<ContainerReader<R>, ContainerError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != CONTAINER_MAGIC {
+            return Err(ContainerError::NotAContainer(magic));
+        }
+        let mut fingerprint = [0u8; 32];
+        reader.read_exact(&mut fingerprint)?;
+        let fingerprint = SpecFingerprint::from_bytes(fingerprint);
+        let parsed_spec = ParsedSpec::read_from_bytes_with_limits(&mut reader, limits)
+            .map_err(ContainerError::InvalidSpec)?;
+        let spec =
+            Spec::compile_with_limits(parsed_spec, limits).map_err(ContainerError::UncompilableSpec)?;
+        if spec.fingerprint() != &fingerprint {
+            return Err(ContainerError::FingerprintMismatch {
+                expected: fingerprint,
+                actual: spec.fingerprint().clone(),
+            });
+        }
+        let mut sync_marker = [0u8; SYNC_MARKER_LEN];
+        reader.read_exact(&mut sync_marker)?;
+        Ok(ContainerReader {
+            reader,
+            de: get_unit_deserialization_function_with_limits::<Cursor<Vec<u8>>>(&spec, limits),
+            spec,
+            sync_marker,
+            max_block_length: DEFAULT_MAX_BLOCK_LENGTH,
+            limits: *limits,
+            block: Cursor::new(Vec::new()),
+            block_remaining: 0,
+            blocks_read: 0,
+            failed: false,
+        })
+    }
+
+    /// Blocks claiming more bytes fail with `BlockTooLong` before anything is read for them
+    pub fn with_max_block_length(mut self, max_block_length: u64) -> ContainerReader<R> {
+        self.max_block_length = max_block_length;
+        self
+    }
+
+    /// The spec the values were written with
+    pub fn spec(&self) -> &Spec {
+        &self.spec
+    }
+
+    // false at the end of the file
+    fn read_block(&mut self) -> Result<bool, ContainerError> {
+        let mut first = [0u8; 1];
+        loop {
+            match self.reader.read(&mut first) {
+                Ok(0) => return Ok(false),
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let block = self.blocks_read;
+        let deserialization = |error| ContainerError::Deserialization { block, error };
+        let mut header = first.chain(&mut self.reader);
+        let count = decode_u64(&mut header).map_err(deserialization)?;
+        // a small block could otherwise claim any number of values, read until decoding fails
+        DecodeBudget::new(self.limits)
+            .collection_length(count)
+            .map_err(|e| deserialization(e.into()))?;
+        let length = decode_u64(&mut header).map_err(deserialization)?;
+        if length > self.max_block_length {
+            return Err(ContainerError::BlockTooLong {
+                block,
+                length,
+                max_length: self.max_block_length,
+            });
+        }
+        let mut bytes = Vec::new();
+        (&mut self.reader).take(length).read_to_end(&mut bytes)?;
+        if (bytes.len() as u64) < length {
+            return Err(ContainerError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let mut sync_marker = [0u8; SYNC_MARKER_LEN];
+        self.reader.read_exact(&mut sync_marker)?;
+        if sync_marker != self.sync_marker {
+            return Err(ContainerError::SyncMarkerMismatch { block });
+        }
+        self.block = Cursor::new(bytes);
+        self.block_remaining = count;
+        self.blocks_read += 1;
+        Ok(true)
+    }
+
+    fn read_value(&mut self) -> Result<Option<GluinoValue>, ContainerError> {
+        while self.block_remaining == 0 {
+            if !self.read_block()? {
+                return Ok(None);
+            }
+            self.check_block_consumed()?;
+        }
+        let block = self.blocks_read - 1;
+        let value = self
+            .de
+            .deserialize(&mut self.block)
+            .map_err(|error| ContainerError::Deserialization { block, error })?;
+        self.block_remaining -= 1;
+        self.check_block_consumed()?;
+        Ok(Some(value))
+    }
+
+    // once every value of the block is read, all of its bytes should be too
+    fn check_block_consumed(&self) -> Result<(), ContainerError> {
+        let expected_length = self.block.get_ref().len() as u64;
+        if self.block_remaining == 0 && self.block.position() != expected_length {
+            return Err(ContainerError::BlockLengthMismatch {
+                block: self.blocks_read - 1,
+                expected_length,
+                actual_length: self.block.position(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for ContainerReader<R> {
+    type Item = Result<GluinoValue, ContainerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.read_value() {
+            Ok(value) => value.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+fn new_sync_marker(fingerprint: &SpecFingerprint) -> [u8; SYNC_MARKER_LEN] {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = Sha256::new();
+    hasher.update(fingerprint.as_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    hasher.update(COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    if let Ok(time) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.update(time.as_nanos().to_le_bytes());
+    }
+    hasher.finalize()[..SYNC_MARKER_LEN].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use crate::limits::DecodeLimitError;
+    use crate::test_utils::{InterruptingReader, compile};
+
+    use super::*;
+
+    fn order(id: u32) -> GluinoValue {
+        GluinoValue::Record(vec![
+            GluinoValue::Uint32(id),
+            GluinoValue::String(format!("order {}", id)),
+            GluinoValue::Optional(id.is_multiple_of(2).then(|| Box::new(GluinoValue::Bool(true)))),
+        ])
+    }
+
+    fn write_orders(count: u32, block_size: usize) -> (Spec, Vec<u8>) {
+        let spec = compile("record { id: uint(2), name: string, flag: optional<bool> }");
+        let mut writer = ContainerWriter::new(&spec, Vec::new())
+            .unwrap()
+            .with_block_size(block_size);
+        for id in 0..count {
+            writer.write(order(id)).unwrap();
+        }
+        (spec, writer.finish().unwrap())
+    }
+
+    fn read_all(bytes: Vec<u8>) -> Result<Vec<GluinoValue>, ContainerError> {
+        ContainerReader::new(Cursor::new(bytes))?.collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for (count, block_size) in [(0, 3), (1, 3), (3, 3), (10, 3), (10, 100)] {
+            let (spec, bytes) = write_orders(count, block_size);
+            let reader = ContainerReader::new(Cursor::new(bytes.clone())).unwrap();
+            assert_eq!(spec.fingerprint(), reader.spec().fingerprint());
+            assert_eq!(
+                (0..count).map(order).collect::<Vec<_>>(),
+                read_all(bytes).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_interrupted_reads() {
+        let (_, bytes) = write_orders(5, 2);
+        let reader = ContainerReader::new(InterruptingReader::new(Cursor::new(bytes))).unwrap();
+        assert_eq!(
+            (0..5).map(order).collect::<Vec<_>>(),
+            reader.collect::<Result<Vec<_>, _>>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_recursive_spec() {
+        let spec = compile("name List = record { value: int(0), next: optional<ref List> }");
+        let list = GluinoValue::Record(vec![
+            GluinoValue::Int8(1),
+            GluinoValue::Optional(Some(Box::new(GluinoValue::Record(vec![
+                GluinoValue::Int8(2),
+                GluinoValue::Optional(None),
+            ])))),
+        ]);
+        let mut writer = ContainerWriter::new(&spec, Vec::new()).unwrap();
+        writer.write(list.clone()).unwrap();
+        writer.flush_block().unwrap();
+        writer.write(list.clone()).unwrap();
+        assert_eq!(
+            vec![list.clone(), list],
+            read_all(writer.finish().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_container_errors() {
+        let (spec, bytes) = write_orders(4, 2);
+        let header_len = 4 + 32 + spec.to_parsed_spec().to_bytes().len() + SYNC_MARKER_LEN;
+        for kind in ContainerErrorKind::iter() {
+            let mut bytes = bytes.clone();
+            let e = match kind {
+                ContainerErrorKind::Io => read_all(bytes[..bytes.len() - 1].to_vec()).unwrap_err(),
+                ContainerErrorKind::NotAContainer => read_all(b"GLU\x02".to_vec()).unwrap_err(),
+                ContainerErrorKind::InvalidSpec => {
+                    bytes[36] = 0xFF;
+                    read_all(bytes).unwrap_err()
+                }
+                // a const set with a duplicate value
+                ContainerErrorKind::UncompilableSpec => {
+                    let mut bytes = CONTAINER_MAGIC.to_vec();
+                    bytes.extend_from_slice(&[0; 32]);
+                    bytes.extend(
+                        ParsedSpec::from_text("const_set<uint(0)> { 0x01, 0x01 }")
+                            .unwrap()
+                            .to_bytes(),
+                    );
+                    read_all(bytes).unwrap_err()
+                }
+                ContainerErrorKind::FingerprintMismatch => {
+                    bytes[4] ^= 0xFF;
+                    read_all(bytes).unwrap_err()
+                }
+                ContainerErrorKind::SyncMarkerMismatch => {
+                    *bytes.last_mut().unwrap() ^= 0xFF;
+                    read_all(bytes).unwrap_err()
+                }
+                ContainerErrorKind::BlockLengthMismatch => {
+                    // the first block says it has one value
+                    bytes[header_len] = 1;
+                    read_all(bytes).unwrap_err()
+                }
+                ContainerErrorKind::BlockTooLong => ContainerReader::new(Cursor::new(bytes))
+                    .unwrap()
+                    .with_max_block_length(8)
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap_err(),
+                ContainerErrorKind::Serialization => {
+                    let (spec, _) = write_orders(0, 1);
+                    ContainerWriter::new(&spec, Vec::new())
+                        .unwrap()
+                        .write(GluinoValue::Bool(true))
+                        .unwrap_err()
+                }
+                ContainerErrorKind::Deserialization => {
+                    // the first block says it has three values
+                    bytes[header_len] = 3;
+                    read_all(bytes).unwrap_err()
+                }
+            };
+            assert_eq!(kind, ContainerErrorKind::from(&e), "{}", e);
+            // inner errors are displayed as themselves
+            match &e {
+                ContainerError::InvalidSpec(inner) => {
+                    assert!(e.to_string().ends_with(&inner.to_string()), "{}", e)
+                }
+                ContainerError::UncompilableSpec(inner) => {
+                    assert!(e.to_string().ends_with(&inner.to_string()), "{}", e)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_block_count_limit() {
+        let (spec, bytes) = write_orders(4, 4);
+        let header_len = 4 + 32 + spec.to_parsed_spec().to_bytes().len() + SYNC_MARKER_LEN;
+        let is_count_limit = |e: ContainerError| {
+            matches!(
+                e,
+                ContainerError::Deserialization {
+                    block: 0,
+                    error: GluinoDeserializationError::LimitExceeded(
+                        DecodeLimitError::MaxCollectionLengthExceeded { .. }
+                    ),
+                }
+            )
+        };
+
+        // an empty block claiming 2^40 values
+        let mut corrupt = bytes[..header_len].to_vec();
+        variable_length_encode_u64(1 << 40, &mut corrupt).unwrap();
+        variable_length_encode_u64(0, &mut corrupt).unwrap();
+        corrupt.extend_from_slice(&bytes[header_len - SYNC_MARKER_LEN..header_len]);
+        assert!(is_count_limit(read_all(corrupt).unwrap_err()));
+
+        let limits = DecodeLimits {
+            max_collection_length: 3,
+            ..DecodeLimits::default()
+        };
+        let reader = ContainerReader::new_with_limits(Cursor::new(bytes), &limits).unwrap();
+        assert!(is_count_limit(
+            reader.collect::<Result<Vec<_>, _>>().unwrap_err()
+        ));
+    }
+}
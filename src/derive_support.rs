@@ -0,0 +1,56 @@
+//! Used by code generated with `#[derive(GluinoSpecType)]`, not part of the public api
+
+use std::collections::HashSet;
+
+use crate::spec_parsing::{ParsedSpec, Size, StringEncodingFmt};
+
+/// Wrap the spec of a derived type in its name, or refer to the name if it is already defined
+pub fn named(
+    name: String,
+    names_defined: &mut HashSet<String>,
+    spec: impl FnOnce(&mut HashSet<String>) -> ParsedSpec,
+) -> ParsedSpec {
+    if names_defined.insert(name.clone()) {
+        ParsedSpec::Name {
+            name,
+            spec: spec(names_defined).into(),
+        }
+    } else {
+        ParsedSpec::Ref { name }
+    }
+}
+
+// field attributes apply to the type within any Option
+fn map_through_optional(spec: ParsedSpec, f: impl FnOnce(ParsedSpec) -> ParsedSpec) -> ParsedSpec {
+    match spec {
+        ParsedSpec::Optional(inner) => ParsedSpec::Optional(map_through_optional(*inner, f).into()),
+        spec => f(spec),
+    }
+}
+
+/// The derive macro only applies sizes to fields of types with a string, list or map spec
+pub fn with_size(spec: ParsedSpec, size: Size) -> ParsedSpec {
+    map_through_optional(spec, |spec| match spec {
+        ParsedSpec::String(_, fmt) => ParsedSpec::String(size, fmt),
+        ParsedSpec::Bytes(_) => ParsedSpec::Bytes(size),
+        ParsedSpec::List { value_spec, .. } => ParsedSpec::List { size, value_spec },
+        ParsedSpec::Map {
+            key_spec,
+            value_spec,
+            ..
+        } => ParsedSpec::Map {
+            size,
+            key_spec,
+            value_spec,
+        },
+        spec => spec,
+    })
+}
+
+/// The derive macro only applies encodings to fields of types with a string spec
+pub fn with_string_encoding(spec: ParsedSpec, fmt: StringEncodingFmt) -> ParsedSpec {
+    map_through_optional(spec, |spec| match spec {
+        ParsedSpec::String(size, _) => ParsedSpec::String(size, fmt),
+        spec => spec,
+    })
+}
@@ -3,6 +3,7 @@ use sha2::Sha256;
 use std::collections::HashMap;
 use std::fmt::Debug;
 
+use crate::spec::NamedSpec;
 use crate::spec::Spec;
 use crate::spec::SpecType;
 
@@ -11,13 +12,9 @@ pub struct SpecFingerprint {
     bytes: [u8; 32],
 }
 
-pub(crate) const PLACE_HOLDER: SpecFingerprint = SpecFingerprint {
-    bytes: [0; 32],
-};
-
 impl SpecFingerprint {
     pub fn new(
-        named_schema: &HashMap<String, Spec>,
+        named_schema: &HashMap<String, NamedSpec>,
         structure: &SpecType,
     ) -> SpecFingerprint {
         let mut hasher = Sha256::new();
@@ -27,6 +24,14 @@ impl SpecFingerprint {
             bytes: result.into(),
         }
     }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> SpecFingerprint {
+        SpecFingerprint { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
 }
 
 impl Debug for SpecFingerprint {
@@ -34,7 +39,7 @@ impl Debug for SpecFingerprint {
         let mut s = String::with_capacity(32 + 32 / 4);
         s.push_str(&hex::encode(&self.bytes[0..8]));
         for chunk in self.bytes[8..].chunks(8) {
-            s.push_str("-");
+            s.push('-');
             s.push_str(&hex::encode(chunk));
         }
         f.write_str(&s)
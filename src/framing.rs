@@ -0,0 +1,699 @@
+//! Checksummed frames of values for pipes and append only logs.
+//!
+//! ```text
+//! frame        tag         0x01 with a fingerprint, 0x02 with a short id
+//!              length      varint, number of bytes of payload
+//!              spec id     32 byte fingerprint or varint short id
+//!              payload
+//!              crc         CRC-32C of everything before it in the frame, little endian u32
+//! sync marker  SYNC_MARKER, 16 bytes starting with 0xFF
+//! ```
+//!
+//! Writers emit a sync marker before the first frame and after every `sync_interval` frames. When a
+//! frame is corrupt or torn the reader reports it and, on the next read, skips ahead to the next
+//! sync marker instead of giving up on the rest of the stream.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display},
+    io::{self, Cursor, Read, Write},
+};
+
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    fingerprint::SpecFingerprint,
+    serde::{
+        GluinoDeserializationError, GluinoSerializationError, GluinoValue, GluinoValueDe,
+        GluinoValueSer,
+    },
+    util::{
+        self, VariableLengthResult, crc32c, variable_length_decode_u64, variable_length_encode_u64,
+    },
+};
+
+pub const SYNC_MARKER: [u8; 16] = [
+    0xFF, 0x47, 0x4C, 0x55, 0x49, 0x4E, 0x4F, 0x53, 0x59, 0x4E, 0x43, 0x00, 0xA5, 0x5A, 0xC3, 0x3C,
+];
+pub const DEFAULT_SYNC_INTERVAL: u64 = 64;
+pub const DEFAULT_MAX_FRAME_LENGTH: u64 = 64 * 1024 * 1024;
+
+const FINGERPRINT_TAG: u8 = 0x01;
+const SHORT_ID_TAG: u8 = 0x02;
+const SYNC_TAG: u8 = SYNC_MARKER[0];
+
+/// Which spec the payload of a frame was written with
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub enum FrameSpecId {
+    Fingerprint(SpecFingerprint),
+    // ids agreed on out of band, e.g. versions in a SpecRegistry subject
+    ShortId(u64),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Frame {
+    pub spec_id: FrameSpecId,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Deserializes the payload, which has to be exactly one value
+    pub fn decode(
+        self,
+        de: &dyn GluinoValueDe<Cursor<Vec<u8>>>,
+    ) -> Result<GluinoValue, FramingError> {
+        let length = self.payload.len() as u64;
+        let mut payload = Cursor::new(self.payload);
+        let value = de.deserialize(&mut payload)?;
+        if payload.position() != length {
+            return Err(FramingError::TrailingPayloadBytes(
+                length - payload.position(),
+            ));
+        }
+        Ok(value)
+    }
+}
+
+#[derive(Debug, EnumDiscriminants)]
+#[strum_discriminants(name(FramingErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum FramingError {
+    Io(io::Error),
+    // corruption, the reader resynchronizes on the next read
+    UnknownFrameTag {
+        offset: u64,
+        tag: u8,
+    },
+    InvalidSyncMarker {
+        offset: u64,
+    },
+    LengthOverflow {
+        offset: u64,
+    },
+    FrameTooLong {
+        offset: u64,
+        length: u64,
+        max_length: u64,
+    },
+    TruncatedFrame {
+        offset: u64,
+    },
+    ChecksumMismatch {
+        offset: u64,
+        expected: u32,
+        actual: u32,
+    },
+    // payloads
+    Serialization(GluinoSerializationError),
+    Deserialization(GluinoDeserializationError),
+    TrailingPayloadBytes(u64),
+}
+
+impl FramingError {
+    /// Whether the stream itself is damaged, reading again resynchronizes at the next sync marker
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            Self::UnknownFrameTag { .. }
+                | Self::InvalidSyncMarker { .. }
+                | Self::LengthOverflow { .. }
+                | Self::FrameTooLong { .. }
+                | Self::TruncatedFrame { .. }
+                | Self::ChecksumMismatch { .. }
+        )
+    }
+}
+
+impl Display for FramingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::UnknownFrameTag { offset, tag } => {
+                write!(f, "byte {}: unknown frame tag {:#04x}", offset, tag)
+            }
+            Self::InvalidSyncMarker { offset } => write!(f, "byte {}: invalid sync marker", offset),
+            Self::LengthOverflow { offset } => {
+                write!(f, "byte {}: frame length does not fit in 64 bits", offset)
+            }
+            Self::FrameTooLong {
+                offset,
+                length,
+                max_length,
+            } => write!(
+                f,
+                "byte {}: frame length {} is more than the max of {}",
+                offset, length, max_length
+            ),
+            Self::TruncatedFrame { offset } => write!(f, "byte {}: frame ends early", offset),
+            Self::ChecksumMismatch {
+                offset,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "byte {}: frame checksum {:#010x} does not match {:#010x}",
+                offset, actual, expected
+            ),
+            Self::Serialization(e) => write!(f, "{}", e),
+            Self::Deserialization(e) => write!(f, "{}", e),
+            Self::TrailingPayloadBytes(n) => {
+                write!(f, "{} bytes left in the payload after the value", n)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FramingError {}
+
+impl From<io::Error> for FramingError {
+    fn from(e: io::Error) -> Self {
+        FramingError::Io(e)
+    }
+}
+
+impl From<GluinoSerializationError> for FramingError {
+    fn from(e: GluinoSerializationError) -> Self {
+        FramingError::Serialization(e)
+    }
+}
+
+impl From<GluinoDeserializationError> for FramingError {
+    fn from(e: GluinoDeserializationError) -> Self {
+        FramingError::Deserialization(e)
+    }
+}
+
+pub struct FrameWriter<W: Write> {
+    writer: W,
+    sync_interval: u64,
+    frames_since_sync: Option<u64>,
+    frame: Vec<u8>,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(writer: W) -> FrameWriter<W> {
+        FrameWriter {
+            writer,
+            sync_interval: DEFAULT_SYNC_INTERVAL,
+            frames_since_sync: None,
+            frame: Vec::new(),
+        }
+    }
+
+    /// Frames between sync markers, at least 1
+    pub fn with_sync_interval(mut self, sync_interval: u64) -> FrameWriter<W> {
+        self.sync_interval = sync_interval.max(1);
+        self
+    }
+
+    /// Writes `payload` as one frame with a single write
+    pub fn write_frame(
+        &mut self,
+        spec_id: &FrameSpecId,
+        payload: &[u8],
+    ) -> Result<(), FramingError> {
+        match self.frames_since_sync {
+            Some(frames) if frames < self.sync_interval => {}
+            _ => self.write_sync_marker()?,
+        }
+        self.frame.clear();
+        match spec_id {
+            FrameSpecId::Fingerprint(fingerprint) => {
+                self.frame.push(FINGERPRINT_TAG);
+                variable_length_encode_u64(payload.len() as u64, &mut self.frame)?;
+                self.frame.extend_from_slice(fingerprint.as_bytes());
+            }
+            FrameSpecId::ShortId(id) => {
+                self.frame.push(SHORT_ID_TAG);
+                variable_length_encode_u64(payload.len() as u64, &mut self.frame)?;
+                variable_length_encode_u64(*id, &mut self.frame)?;
+            }
+        }
+        self.frame.extend_from_slice(payload);
+        let crc = crc32c(&self.frame);
+        self.frame.extend_from_slice(&crc.to_le_bytes());
+        self.writer.write_all(&self.frame)?;
+        self.frames_since_sync = self.frames_since_sync.map(|frames| frames + 1);
+        Ok(())
+    }
+
+    /// Serializes `value` with `ser` and writes it as one frame
+    pub fn write_value(
+        &mut self,
+        spec_id: &FrameSpecId,
+        ser: &dyn GluinoValueSer<Vec<u8>>,
+        value: GluinoValue,
+    ) -> Result<(), FramingError> {
+        let mut payload = Vec::new();
+        ser.serialize(value, &mut payload)?;
+        self.write_frame(spec_id, &payload)
+    }
+
+    pub fn write_sync_marker(&mut self) -> Result<(), FramingError> {
+        self.writer.write_all(&SYNC_MARKER)?;
+        self.frames_since_sync = Some(0);
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), FramingError> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads frames, as an iterator it skips past corrupt frames after reporting them and stops at the
+/// end of the stream or after an io error. Reads are small, buffer unbuffered readers.
+pub struct FrameReader<R: Read> {
+    reader: R,
+    max_frame_length: u64,
+    // bytes given back after a corrupt frame, read before the reader
+    pending: VecDeque<u8>,
+    // bytes of the frame being read, given back if it turns out corrupt
+    frame: Vec<u8>,
+    offset: u64,
+    needs_resync: bool,
+    done: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(reader: R) -> FrameReader<R> {
+        FrameReader {
+            reader,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            pending: VecDeque::new(),
+            frame: Vec::new(),
+            offset: 0,
+            needs_resync: false,
+            done: false,
+        }
+    }
+
+    /// Frames claiming a longer payload are treated as corrupt
+    pub fn with_max_frame_length(mut self, max_frame_length: u64) -> FrameReader<R> {
+        self.max_frame_length = max_frame_length;
+        self
+    }
+
+    /// Byte offset in the stream of the next byte to read
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The next frame, `None` at the end of the stream. After a corruption error this
+    /// first skips to the next sync marker.
+    pub fn read_frame(&mut self) -> Result<Option<Frame>, FramingError> {
+        if self.needs_resync {
+            self.needs_resync = false;
+            if !self.resync()? {
+                return Ok(None);
+            }
+        }
+        loop {
+            self.frame.clear();
+            let start = self.offset;
+            let result = self.read_frame_at(start);
+            match result {
+                Ok(Some(FrameOrSync::Frame(frame))) => return Ok(Some(frame)),
+                Ok(Some(FrameOrSync::Sync)) => continue,
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    if e.is_corruption() {
+                        // everything after the first byte of the frame may be the start of the next one
+                        for b in self.frame.drain(1..).rev() {
+                            self.pending.push_front(b);
+                        }
+                        self.offset = start + 1;
+                        self.needs_resync = true;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Reads the next frame and deserializes its payload with `de`
+    pub fn read_value(
+        &mut self,
+        de: &dyn GluinoValueDe<Cursor<Vec<u8>>>,
+    ) -> Result<Option<(FrameSpecId, GluinoValue)>, FramingError> {
+        match self.read_frame()? {
+            Some(frame) => {
+                let spec_id = frame.spec_id.clone();
+                Ok(Some((spec_id, frame.decode(de)?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Skips to just after the next sync marker, false if the stream ended first
+    pub fn resync(&mut self) -> Result<bool, FramingError> {
+        let mut window = VecDeque::with_capacity(SYNC_MARKER.len());
+        while let Some(b) = self.next_byte()? {
+            if window.len() == SYNC_MARKER.len() {
+                window.pop_front();
+            }
+            window.push_back(b);
+            if window.iter().eq(SYNC_MARKER.iter()) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>, FramingError> {
+        let b = match self.pending.pop_front() {
+            Some(b) => b,
+            None => {
+                let mut b = [0u8; 1];
+                loop {
+                    match self.reader.read(&mut b) {
+                        Ok(0) => return Ok(None),
+                        Ok(_) => break,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                b[0]
+            }
+        };
+        self.offset += 1;
+        Ok(Some(b))
+    }
+
+    fn frame_byte(&mut self, start: u64) -> Result<u8, FramingError> {
+        let b = self
+            .next_byte()?
+            .ok_or(FramingError::TruncatedFrame { offset: start })?;
+        self.frame.push(b);
+        Ok(b)
+    }
+
+    fn frame_bytes(&mut self, n: u64, start: u64) -> Result<(), FramingError> {
+        let from_pending = (n as usize).min(self.pending.len());
+        self.frame.extend(self.pending.drain(..from_pending));
+        let from_reader = n - from_pending as u64;
+        let read = (&mut self.reader)
+            .take(from_reader)
+            .read_to_end(&mut self.frame)? as u64;
+        self.offset += from_pending as u64 + read;
+        if read < from_reader {
+            return Err(FramingError::TruncatedFrame { offset: start });
+        }
+        Ok(())
+    }
+
+    fn frame_varint(&mut self, start: u64) -> Result<u64, FramingError> {
+        let mut bytes = Vec::new();
+        loop {
+            let b = self.frame_byte(start)?;
+            bytes.push(b);
+            if b & 0x80 == 0 {
+                break;
+            }
+        }
+        match variable_length_decode_u64(&mut bytes.as_slice()) {
+            Ok(VariableLengthResult::Respresentable(n)) => Ok(n),
+            Ok(VariableLengthResult::Unrepresentable(_)) => {
+                Err(FramingError::LengthOverflow { offset: start })
+            }
+            Err(util::VariableLengthDecodingError::IncompleteVariableLengthEncoding) => {
+                Err(FramingError::TruncatedFrame { offset: start })
+            }
+            Err(util::VariableLengthDecodingError::IoError(e)) => Err(e.into()),
+        }
+    }
+
+    fn read_frame_at(&mut self, start: u64) -> Result<Option<FrameOrSync>, FramingError> {
+        let tag = match self.next_byte()? {
+            Some(tag) => tag,
+            None => return Ok(None),
+        };
+        self.frame.push(tag);
+        match tag {
+            SYNC_TAG => {
+                for expected in &SYNC_MARKER[1..] {
+                    if self.frame_byte(start)? != *expected {
+                        return Err(FramingError::InvalidSyncMarker { offset: start });
+                    }
+                }
+                Ok(Some(FrameOrSync::Sync))
+            }
+            FINGERPRINT_TAG | SHORT_ID_TAG => {
+                let length = self.frame_varint(start)?;
+                if length > self.max_frame_length {
+                    return Err(FramingError::FrameTooLong {
+                        offset: start,
+                        length,
+                        max_length: self.max_frame_length,
+                    });
+                }
+                let spec_id = if tag == FINGERPRINT_TAG {
+                    self.frame_bytes(32, start)?;
+                    let fingerprint = self.frame[self.frame.len() - 32..].try_into().unwrap();
+                    FrameSpecId::Fingerprint(SpecFingerprint::from_bytes(fingerprint))
+                } else {
+                    FrameSpecId::ShortId(self.frame_varint(start)?)
+                };
+                let payload_start = self.frame.len();
+                self.frame_bytes(length, start)?;
+                let payload = self.frame[payload_start..].to_vec();
+                let actual = crc32c(&self.frame);
+                let mut crc = [0u8; 4];
+                for b in crc.iter_mut() {
+                    *b = self.frame_byte(start)?;
+                }
+                let expected = u32::from_le_bytes(crc);
+                if expected != actual {
+                    return Err(FramingError::ChecksumMismatch {
+                        offset: start,
+                        expected,
+                        actual,
+                    });
+                }
+                Ok(Some(FrameOrSync::Frame(Frame { spec_id, payload })))
+            }
+            tag => Err(FramingError::UnknownFrameTag { offset: start, tag }),
+        }
+    }
+}
+
+enum FrameOrSync {
+    Frame(Frame),
+    Sync,
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Result<Frame, FramingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = !e.is_corruption();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        serde::{get_unit_deserialization_function, get_unit_serialization_function},
+        spec::Spec,
+        spec_parsing::ParsedSpec,
+    };
+
+    use super::*;
+
+    fn frames(count: u64) -> Vec<Frame> {
+        (0..count)
+            .map(|i| Frame {
+                spec_id: if i % 3 == 0 {
+                    FrameSpecId::Fingerprint(SpecFingerprint::from_bytes([i as u8; 32]))
+                } else {
+                    FrameSpecId::ShortId(i * 1000)
+                },
+                payload: vec![i as u8; i as usize * 7],
+            })
+            .collect()
+    }
+
+    fn write(frames: &[Frame], sync_interval: u64) -> Vec<u8> {
+        let mut writer = FrameWriter::new(Vec::new()).with_sync_interval(sync_interval);
+        for frame in frames {
+            writer.write_frame(&frame.spec_id, &frame.payload).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn read(bytes: Vec<u8>) -> Vec<Result<Frame, FramingErrorKind>> {
+        FrameReader::new(Cursor::new(bytes))
+            .map(|r| r.map_err(|e| FramingErrorKind::from(&e)))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for sync_interval in [1, 2, 64] {
+            let frames = frames(10);
+            assert_eq!(
+                frames.iter().cloned().map(Ok).collect::<Vec<_>>(),
+                read(write(&frames, sync_interval))
+            );
+        }
+        assert!(read(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_values() {
+        let spec =
+            Spec::compile(ParsedSpec::from_text("record { id: uint(3), name: string }").unwrap())
+                .unwrap();
+        let ser = get_unit_serialization_function::<Vec<u8>>(&spec);
+        let de = get_unit_deserialization_function::<Cursor<Vec<u8>>>(&spec);
+        let value = |id| {
+            GluinoValue::Record(vec![
+                GluinoValue::Uint64(id),
+                GluinoValue::String(id.to_string()),
+            ])
+        };
+        let spec_id = FrameSpecId::Fingerprint(spec.fingerprint().clone());
+        let mut writer = FrameWriter::new(Vec::new());
+        writer
+            .write_value(&spec_id, ser.as_ref(), value(1))
+            .unwrap();
+        writer
+            .write_value(&spec_id, ser.as_ref(), value(2))
+            .unwrap();
+        writer.write_frame(&spec_id, &[0x01]).unwrap();
+        let mut reader = FrameReader::new(Cursor::new(writer.into_inner()));
+        assert_eq!(
+            Some((spec_id.clone(), value(1))),
+            reader.read_value(de.as_ref()).unwrap()
+        );
+        assert_eq!(
+            Some((spec_id, value(2))),
+            reader.read_value(de.as_ref()).unwrap()
+        );
+        let e = reader.read_value(de.as_ref()).unwrap_err();
+        assert_eq!(FramingErrorKind::Deserialization, (&e).into(), "{}", e);
+        assert!(!e.is_corruption());
+        assert!(reader.read_value(de.as_ref()).unwrap().is_none());
+
+        let mut payload = Vec::new();
+        ser.serialize(value(3), &mut payload).unwrap();
+        payload.push(0x00);
+        let e = Frame {
+            spec_id: FrameSpecId::ShortId(0),
+            payload,
+        }
+        .decode(de.as_ref())
+        .unwrap_err();
+        assert_eq!(FramingErrorKind::TrailingPayloadBytes, (&e).into(), "{}", e);
+    }
+
+    #[test]
+    fn test_resynchronization() {
+        let frames = frames(9);
+        let bytes = write(&frames, 3);
+        let frame_len =
+            |frame: &Frame| write(std::slice::from_ref(frame), 1).len() - SYNC_MARKER.len();
+        // sync, 0, 1, 2, sync, 3, 4, 5, sync, 6, 7, 8
+        let start_of = |i: usize| {
+            SYNC_MARKER.len() * (1 + i / 3) + frames[..i].iter().map(frame_len).sum::<usize>()
+        };
+        let expected = |errors: Vec<(usize, FramingErrorKind)>, skipped: &[usize]| {
+            let mut expected: Vec<Result<Frame, FramingErrorKind>> = Vec::new();
+            for (i, frame) in frames.iter().enumerate() {
+                if let Some((_, kind)) = errors.iter().find(|(at, _)| *at == i) {
+                    expected.push(Err(*kind));
+                }
+                if !skipped.contains(&i) {
+                    expected.push(Ok(frame.clone()));
+                }
+            }
+            expected
+        };
+
+        // a flipped payload byte loses the rest of the frames up to the next sync marker
+        let mut corrupt = bytes.clone();
+        corrupt[start_of(4) + 5] ^= 0xFF;
+        assert_eq!(
+            expected(vec![(4, FramingErrorKind::ChecksumMismatch)], &[4, 5]),
+            read(corrupt)
+        );
+
+        let mut corrupt = bytes.clone();
+        corrupt[start_of(1)] = 0x7F;
+        assert_eq!(
+            expected(vec![(1, FramingErrorKind::UnknownFrameTag)], &[1, 2]),
+            read(corrupt)
+        );
+
+        let mut corrupt = bytes.clone();
+        corrupt[start_of(3) - 1] ^= 0xFF;
+        assert_eq!(
+            expected(vec![(3, FramingErrorKind::InvalidSyncMarker)], &[3, 4, 5]),
+            read(corrupt)
+        );
+
+        let mut corrupt = bytes.clone();
+        corrupt.splice(start_of(7) + 1..start_of(7) + 2, [0xFF; 10]);
+        assert_eq!(
+            expected(vec![(7, FramingErrorKind::LengthOverflow)], &[7, 8]),
+            read(corrupt)
+        );
+
+        let mut corrupt = bytes.clone();
+        corrupt.splice(start_of(7) + 1..start_of(7) + 2, [0xFF, 0xFF, 0xFF, 0x7F]);
+        assert_eq!(
+            expected(vec![(7, FramingErrorKind::FrameTooLong)], &[7, 8]),
+            read(corrupt)
+        );
+
+        // a torn write followed by a new writer appending to the log
+        let mut torn = bytes[..start_of(8) - 3].to_vec();
+        torn.extend(write(&frames[8..], 3));
+        assert_eq!(
+            expected(vec![(8, FramingErrorKind::ChecksumMismatch)], &[7]),
+            read(torn)
+        );
+
+        let torn = bytes[..bytes.len() - 1].to_vec();
+        assert_eq!(
+            expected(vec![(8, FramingErrorKind::TruncatedFrame)], &[8]),
+            read(torn)
+        );
+    }
+
+    #[test]
+    fn test_offsets() {
+        let frames = frames(2);
+        let mut bytes = write(&frames, 64);
+        let second = bytes.len() - write(&frames[1..], 64).len() + SYNC_MARKER.len();
+        bytes[second + 5] ^= 0xFF;
+        let mut reader = FrameReader::new(Cursor::new(bytes));
+        assert_eq!(frames[0], reader.read_frame().unwrap().unwrap());
+        assert_eq!(second as u64, reader.offset());
+        match reader.read_frame() {
+            Err(FramingError::ChecksumMismatch { offset, .. }) => assert_eq!(second as u64, offset),
+            r => panic!("{:?}", r),
+        }
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+}
@@ -1,11 +1,24 @@
 pub mod spec;
-mod fingerprint;
+pub mod compatibility;
+pub mod fingerprint;
 pub mod serde;
 pub mod spec_parsing;
+pub mod spec_path;
+pub mod spec_builder;
+pub mod spec_visitor;
+pub mod limits;
+pub mod spec_diff;
+pub mod registry;
+pub mod container;
+pub mod framing;
+pub mod spec_json;
+pub mod spec_text;
+#[doc(hidden)]
+pub mod derive_support;
 #[cfg(test)]
 mod test_utils;
 mod util;
-mod compiled_spec_visitor_pattern;
+mod spec_compiler;
 
 pub fn change_data() {
     println!("Today")
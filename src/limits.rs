@@ -0,0 +1,258 @@
+//! Limits on reading specs and values from untrusted bytes.
+//!
+//! Sizes and counts in the bytes are checked against the limits before anything is allocated
+//! for them, and nesting is bounded so recursive specs and values can not run out the stack.
+
+use std::fmt::{self, Display};
+
+use strum::{EnumDiscriminants, EnumIter};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct DecodeLimits {
+    /// Deepest nesting of specs in a spec or of values in a value
+    pub max_depth: usize,
+    /// Bytes allocated while reading one spec or value
+    pub max_total_allocation: u64,
+    /// Items of a list or map and fields, variants or const values of a spec
+    pub max_collection_length: u64,
+    /// Bytes of a string or bytes value and of a name in a spec
+    pub max_string_length: u64,
+    /// Specs in a spec, counting every nested spec
+    pub max_spec_nodes: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_depth: 128,
+            max_total_allocation: 1 << 30,
+            max_collection_length: 1 << 24,
+            max_string_length: 1 << 28,
+            max_spec_nodes: 1 << 16,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// No limits, for trusted input only
+    pub fn unlimited() -> DecodeLimits {
+        DecodeLimits {
+            max_depth: usize::MAX,
+            max_total_allocation: u64::MAX,
+            max_collection_length: u64::MAX,
+            max_string_length: u64::MAX,
+            max_spec_nodes: usize::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, EnumDiscriminants)]
+#[strum_discriminants(name(DecodeLimitErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum DecodeLimitError {
+    MaxDepthExceeded(usize),
+    MaxTotalAllocationExceeded(u64),
+    MaxCollectionLengthExceeded {
+        length: u64,
+        max_collection_length: u64,
+    },
+    MaxStringLengthExceeded {
+        length: u64,
+        max_string_length: u64,
+    },
+    MaxSpecNodesExceeded(usize),
+}
+
+impl Display for DecodeLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MaxDepthExceeded(max_depth) => {
+                write!(f, "nested deeper than the limit of {}", max_depth)
+            }
+            Self::MaxTotalAllocationExceeded(max_total_allocation) => write!(
+                f,
+                "needs more than the limit of {} allocated bytes",
+                max_total_allocation
+            ),
+            Self::MaxCollectionLengthExceeded {
+                length,
+                max_collection_length,
+            } => write!(
+                f,
+                "collection of {} is longer than the limit of {}",
+                length, max_collection_length
+            ),
+            Self::MaxStringLengthExceeded {
+                length,
+                max_string_length,
+            } => write!(
+                f,
+                "string of {} bytes is longer than the limit of {}",
+                length, max_string_length
+            ),
+            Self::MaxSpecNodesExceeded(max_spec_nodes) => {
+                write!(
+                    f,
+                    "spec has more than the limit of {} specs",
+                    max_spec_nodes
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeLimitError {}
+
+/// What one spec or value read has used up of its limits
+#[derive(Debug)]
+pub(crate) struct DecodeBudget {
+    limits: DecodeLimits,
+    depth: usize,
+    allocated: u64,
+    spec_nodes: usize,
+}
+
+impl DecodeBudget {
+    pub(crate) fn new(limits: DecodeLimits) -> DecodeBudget {
+        DecodeBudget {
+            limits,
+            depth: 0,
+            allocated: 0,
+            spec_nodes: 0,
+        }
+    }
+
+    /// Goes one level deeper, `exit` goes back up
+    #[inline]
+    pub(crate) fn enter(&mut self) -> Result<(), DecodeLimitError> {
+        if self.depth >= self.limits.max_depth {
+            return Err(DecodeLimitError::MaxDepthExceeded(self.limits.max_depth));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) fn exit(&mut self) {
+        self.depth -= 1;
+    }
+
+    #[inline]
+    pub(crate) fn allocate(&mut self, bytes: u64) -> Result<(), DecodeLimitError> {
+        self.allocated = self.allocated.saturating_add(bytes);
+        if self.allocated > self.limits.max_total_allocation {
+            Err(DecodeLimitError::MaxTotalAllocationExceeded(
+                self.limits.max_total_allocation,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks the length of a collection that is not allocated
+    #[inline]
+    pub(crate) fn collection_length(&self, length: u64) -> Result<(), DecodeLimitError> {
+        if length > self.limits.max_collection_length {
+            Err(DecodeLimitError::MaxCollectionLengthExceeded {
+                length,
+                max_collection_length: self.limits.max_collection_length,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Allocates `length` items of `item_size` bytes
+    #[inline]
+    pub(crate) fn collection(
+        &mut self,
+        length: u64,
+        item_size: usize,
+    ) -> Result<(), DecodeLimitError> {
+        self.collection_length(length)?;
+        self.allocate(length.saturating_mul(item_size as u64))
+    }
+
+    /// Checks the length of a string that is not allocated
+    #[inline]
+    pub(crate) fn string_length(&self, length: u64) -> Result<(), DecodeLimitError> {
+        if length > self.limits.max_string_length {
+            Err(DecodeLimitError::MaxStringLengthExceeded {
+                length,
+                max_string_length: self.limits.max_string_length,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Allocates a string of `length` bytes
+    #[inline]
+    pub(crate) fn string(&mut self, length: u64) -> Result<(), DecodeLimitError> {
+        self.string_length(length)?;
+        self.allocate(length)
+    }
+
+    #[inline]
+    pub(crate) fn spec_node(&mut self) -> Result<(), DecodeLimitError> {
+        self.spec_nodes += 1;
+        if self.spec_nodes > self.limits.max_spec_nodes {
+            Err(DecodeLimitError::MaxSpecNodesExceeded(
+                self.limits.max_spec_nodes,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let mut budget = DecodeBudget::new(DecodeLimits {
+            max_depth: 2,
+            max_total_allocation: 100,
+            max_collection_length: 10,
+            max_string_length: 20,
+            max_spec_nodes: 3,
+        });
+        budget.enter().unwrap();
+        budget.enter().unwrap();
+        assert_eq!(Err(DecodeLimitError::MaxDepthExceeded(2)), budget.enter());
+        budget.exit();
+        budget.enter().unwrap();
+
+        budget.collection(10, 8).unwrap();
+        assert_eq!(
+            DecodeLimitErrorKind::MaxCollectionLengthExceeded,
+            (&budget.collection(11, 0).unwrap_err()).into()
+        );
+        assert_eq!(
+            DecodeLimitErrorKind::MaxStringLengthExceeded,
+            (&budget.string(21).unwrap_err()).into()
+        );
+        budget.string(20).unwrap();
+        // checking lengths allocates nothing
+        budget.collection_length(10).unwrap();
+        budget.string_length(20).unwrap();
+        assert_eq!(
+            DecodeLimitErrorKind::MaxStringLengthExceeded,
+            (&budget.string_length(21).unwrap_err()).into()
+        );
+        assert_eq!(
+            Err(DecodeLimitError::MaxTotalAllocationExceeded(100)),
+            budget.string(1)
+        );
+
+        for _ in 0..3 {
+            budget.spec_node().unwrap();
+        }
+        assert_eq!(
+            Err(DecodeLimitError::MaxSpecNodesExceeded(3)),
+            budget.spec_node()
+        );
+    }
+}
@@ -0,0 +1,498 @@
+//! Compiled specs stored by fingerprint, with a version history per subject.
+//!
+//! A persisted registry is a directory of
+//! - `specs/<hex fingerprint>.gluino`, the spec as written by `ParsedSpec::write_as_bytes`
+//! - `subjects/<hex sha256 of the subject name>`, the subject name as a variable length encoded
+//!   length and its utf8 bytes, followed by the fingerprint of every version, oldest first
+//!
+//! Files are written beside their final path and renamed into place, so a directory can be shared
+//! by readers and an interrupted write never leaves part of a file behind.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    fingerprint::SpecFingerprint,
+    spec::{Spec, SpecCompileError},
+    spec_parsing::{ParsedSpec, SpecParsingError},
+    util::{VariableLengthResult, variable_length_decode_u64, variable_length_encode_u64},
+};
+
+const SPECS_DIR: &str = "specs";
+const SUBJECTS_DIR: &str = "subjects";
+const SPEC_EXTENSION: &str = "gluino";
+
+#[derive(Default)]
+pub struct SpecRegistry {
+    specs: HashMap<SpecFingerprint, Spec>,
+    subjects: HashMap<String, Vec<SpecFingerprint>>,
+    directory: Option<PathBuf>,
+}
+
+#[derive(Debug, EnumDiscriminants)]
+#[strum_discriminants(name(SpecRegistryErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum SpecRegistryError {
+    Io(PathBuf, io::Error),
+    InvalidSpecFile(PathBuf, SpecParsingError),
+    UncompilableSpecFile(PathBuf, SpecCompileError),
+    FingerprintMismatch(PathBuf),
+    InvalidFileName(PathBuf),
+    InvalidSubjectFile(PathBuf),
+    UnknownFingerprint(SpecFingerprint),
+}
+
+impl Display for SpecRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Self::InvalidSpecFile(path, e) => {
+                write!(f, "{}: unable to read spec: {}", path.display(), e)
+            }
+            Self::UncompilableSpecFile(path, e) => {
+                write!(f, "{}: unable to compile spec: {}", path.display(), e)
+            }
+            Self::FingerprintMismatch(path) => {
+                write!(f, "{}: spec does not match its fingerprint", path.display())
+            }
+            Self::InvalidFileName(path) => {
+                write!(f, "{}: not a registry file name", path.display())
+            }
+            Self::InvalidSubjectFile(path) => {
+                write!(
+                    f,
+                    "{}: subject history is not a subject name and a list of fingerprints",
+                    path.display()
+                )
+            }
+            Self::UnknownFingerprint(fingerprint) => {
+                write!(
+                    f,
+                    "no spec with fingerprint {}",
+                    hex::encode(fingerprint.as_bytes())
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpecRegistryError {}
+
+impl SpecRegistry {
+    /// An in memory registry
+    pub fn new() -> SpecRegistry {
+        SpecRegistry::default()
+    }
+
+    /// Loads the registry persisted in `directory`, creating it if needed.
+    /// Everything registered afterwards is persisted there too.
+    pub fn open<P: AsRef<Path>>(directory: P) -> Result<SpecRegistry, SpecRegistryError> {
+        let directory = directory.as_ref().to_path_buf();
+        let specs_dir = directory.join(SPECS_DIR);
+        let subjects_dir = directory.join(SUBJECTS_DIR);
+        for dir in [&specs_dir, &subjects_dir] {
+            fs::create_dir_all(dir).map_err(|e| SpecRegistryError::Io(dir.clone(), e))?;
+        }
+        let mut registry = SpecRegistry {
+            directory: Some(directory),
+            ..SpecRegistry::default()
+        };
+        for path in read_dir(&specs_dir)? {
+            let fingerprint = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(fingerprint_from_hex)
+                .ok_or_else(|| SpecRegistryError::InvalidFileName(path.clone()))?;
+            let file = File::open(&path).map_err(|e| SpecRegistryError::Io(path.clone(), e))?;
+            let parsed_spec = ParsedSpec::read_from_bytes(&mut BufReader::new(file))
+                .map_err(|e| SpecRegistryError::InvalidSpecFile(path.clone(), e))?;
+            let spec = Spec::compile(parsed_spec)
+                .map_err(|e| SpecRegistryError::UncompilableSpecFile(path.clone(), e))?;
+            if spec.fingerprint() != &fingerprint {
+                return Err(SpecRegistryError::FingerprintMismatch(path));
+            }
+            registry.specs.insert(fingerprint, spec);
+        }
+        for path in read_dir(&subjects_dir)? {
+            let file_name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .filter(|name| fingerprint_from_hex(name).is_some())
+                .ok_or_else(|| SpecRegistryError::InvalidFileName(path.clone()))?
+                .to_string();
+            let mut bytes = Vec::new();
+            File::open(&path)
+                .and_then(|mut file| file.read_to_end(&mut bytes))
+                .map_err(|e| SpecRegistryError::Io(path.clone(), e))?;
+            let (subject, fingerprints) = read_subject_file(&bytes)
+                .ok_or_else(|| SpecRegistryError::InvalidSubjectFile(path.clone()))?;
+            if subject_file_name(&subject) != file_name {
+                return Err(SpecRegistryError::InvalidFileName(path));
+            }
+            let versions = fingerprints
+                .chunks(32)
+                .map(|chunk| {
+                    let fingerprint = SpecFingerprint::from_bytes(chunk.try_into().unwrap());
+                    if registry.specs.contains_key(&fingerprint) {
+                        Ok(fingerprint)
+                    } else {
+                        Err(SpecRegistryError::UnknownFingerprint(fingerprint))
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+            registry.subjects.insert(subject, versions);
+        }
+        Ok(registry)
+    }
+
+    /// Stores `spec` under its fingerprint, returning the fingerprint
+    pub fn register(&mut self, spec: Spec) -> Result<SpecFingerprint, SpecRegistryError> {
+        let fingerprint = spec.fingerprint().clone();
+        if self.specs.contains_key(&fingerprint) {
+            return Ok(fingerprint);
+        }
+        if let Some(directory) = &self.directory {
+            let path = directory.join(SPECS_DIR).join(format!(
+                "{}.{}",
+                hex::encode(fingerprint.as_bytes()),
+                SPEC_EXTENSION
+            ));
+            write_then_rename(path, |writer| {
+                spec.to_parsed_spec().write_as_bytes(writer).map(|_| ())
+            })?;
+        }
+        self.specs.insert(fingerprint.clone(), spec);
+        Ok(fingerprint)
+    }
+
+    /// Registers `spec` as the latest version of `subject`, returning its version.
+    /// Versions start at 1, registering the latest version again does not add a version.
+    pub fn register_subject(
+        &mut self,
+        subject: &str,
+        spec: Spec,
+    ) -> Result<usize, SpecRegistryError> {
+        let fingerprint = self.register(spec)?;
+        let versions = self
+            .subjects
+            .get(subject)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if versions.last() == Some(&fingerprint) {
+            return Ok(versions.len());
+        }
+        if let Some(directory) = &self.directory {
+            // the whole history is rewritten, appending could leave part of a fingerprint behind
+            let path = directory.join(SUBJECTS_DIR).join(subject_file_name(subject));
+            write_then_rename(path, |writer| {
+                variable_length_encode_u64(subject.len() as u64, writer)?;
+                writer.write_all(subject.as_bytes())?;
+                for version in versions.iter().chain([&fingerprint]) {
+                    writer.write_all(version.as_bytes())?;
+                }
+                Ok(())
+            })?;
+        }
+        let versions = self.subjects.entry(subject.to_string()).or_default();
+        versions.push(fingerprint);
+        Ok(versions.len())
+    }
+
+    pub fn get(&self, fingerprint: &SpecFingerprint) -> Option<&Spec> {
+        self.specs.get(fingerprint)
+    }
+
+    pub fn contains(&self, fingerprint: &SpecFingerprint) -> bool {
+        self.specs.contains_key(fingerprint)
+    }
+
+    pub fn latest(&self, subject: &str) -> Option<&Spec> {
+        self.subjects
+            .get(subject)
+            .and_then(|versions| versions.last())
+            .and_then(|fingerprint| self.specs.get(fingerprint))
+    }
+
+    pub fn get_version(&self, subject: &str, version: usize) -> Option<&Spec> {
+        self.subjects
+            .get(subject)
+            .and_then(|versions| versions.get(version.checked_sub(1)?))
+            .and_then(|fingerprint| self.specs.get(fingerprint))
+    }
+
+    /// Fingerprints of every version of `subject`, oldest first
+    pub fn versions(&self, subject: &str) -> Option<&[SpecFingerprint]> {
+        self.subjects.get(subject).map(Vec::as_slice)
+    }
+
+    pub fn subjects(&self) -> impl Iterator<Item = &str> {
+        self.subjects.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.specs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+}
+
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>, SpecRegistryError> {
+    fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .filter(|path| {
+                    path.as_ref()
+                        .map(|path| path.extension().is_none_or(|extension| extension != "tmp"))
+                        .unwrap_or(true)
+                })
+                .collect()
+        })
+        .map_err(|e| SpecRegistryError::Io(dir.to_path_buf(), e))
+}
+
+// write beside `path` then rename so readers never see part of a file. The file is synced before
+// the rename, so a crash can not leave it renamed but empty, and the directory after it
+fn write_then_rename(
+    path: PathBuf,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> Result<(), SpecRegistryError> {
+    let temp_path = path.with_extension("tmp");
+    File::create(&temp_path)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, &path))
+        .and_then(|_| sync_dir(&path))
+        .map_err(|e| SpecRegistryError::Io(path, e))
+}
+
+// makes the renaming of `path` durable, only unix can open a directory to sync it
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+// subject names can be longer than a file name can be, so files are named by their hash
+fn subject_file_name(subject: &str) -> String {
+    hex::encode(Sha256::digest(subject.as_bytes()))
+}
+
+// the subject name and the fingerprints that follow it
+fn read_subject_file(mut bytes: &[u8]) -> Option<(String, &[u8])> {
+    let VariableLengthResult::Respresentable(len) = variable_length_decode_u64(&mut bytes).ok()?
+    else {
+        return None;
+    };
+    let len = usize::try_from(len).ok().filter(|len| *len <= bytes.len())?;
+    let (subject, fingerprints) = bytes.split_at(len);
+    let subject = String::from_utf8(subject.to_vec()).ok()?;
+    (fingerprints.len() % 32 == 0).then_some((subject, fingerprints))
+}
+
+fn fingerprint_from_hex(hex: &str) -> Option<SpecFingerprint> {
+    hex::decode(hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(SpecFingerprint::from_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::compile;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("gluino-registry-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_subject_versions() {
+        let mut registry = SpecRegistry::new();
+        let v1 = compile("record { id: uint(3) }");
+        let v2 = compile("record { id: uint(3), name: optional<string> }");
+        assert_eq!(1, registry.register_subject("users", v1.clone()).unwrap());
+        assert_eq!(1, registry.register_subject("users", v1.clone()).unwrap());
+        assert_eq!(2, registry.register_subject("users", v2.clone()).unwrap());
+        assert_eq!(3, registry.register_subject("users", v1.clone()).unwrap());
+        assert_eq!(
+            1,
+            registry
+                .register_subject("ids", compile("uint(3)"))
+                .unwrap()
+        );
+        assert_eq!(3, registry.len());
+        assert_eq!(
+            Some(v1.fingerprint()),
+            registry.latest("users").map(Spec::fingerprint)
+        );
+        assert_eq!(
+            Some(v2.fingerprint()),
+            registry.get_version("users", 2).map(Spec::fingerprint)
+        );
+        assert!(registry.get_version("users", 0).is_none());
+        assert!(registry.get_version("users", 4).is_none());
+        assert!(registry.latest("orders").is_none());
+        assert_eq!(
+            Some(
+                &[
+                    v1.fingerprint().clone(),
+                    v2.fingerprint().clone(),
+                    v1.fingerprint().clone()
+                ][..]
+            ),
+            registry.versions("users")
+        );
+        assert_eq!(
+            Some(v2.fingerprint()),
+            registry.get(v2.fingerprint()).map(Spec::fingerprint)
+        );
+    }
+
+    #[test]
+    fn test_persistence() {
+        let dir = temp_dir("persistence");
+        let recursive = compile("name Tree = record { value: int(2), children: list<ref Tree> }");
+        let other = compile("map<string, bytes<16>>");
+        // longer than a file name can be
+        let long_subject = "subject ".repeat(40);
+        {
+            let mut registry = SpecRegistry::open(&dir).unwrap();
+            registry.register(other.clone()).unwrap();
+            registry.register_subject("trees", compile("bool")).unwrap();
+            registry
+                .register_subject("trees", recursive.clone())
+                .unwrap();
+            registry
+                .register_subject("odd / subject", other.clone())
+                .unwrap();
+            registry
+                .register_subject(&long_subject, recursive.clone())
+                .unwrap();
+            registry.register_subject(&long_subject, other.clone()).unwrap();
+        }
+        // left behind by an interrupted write
+        fs::write(
+            dir.join(SUBJECTS_DIR)
+                .join(subject_file_name("trees"))
+                .with_extension("tmp"),
+            [0x05],
+        )
+        .unwrap();
+        let registry = SpecRegistry::open(&dir).unwrap();
+        assert_eq!(3, registry.len());
+        assert_eq!(
+            Some(recursive.fingerprint()),
+            registry.latest("trees").map(Spec::fingerprint)
+        );
+        assert_eq!(2, registry.versions("trees").unwrap().len());
+        assert_eq!(
+            Some(other.fingerprint()),
+            registry.latest("odd / subject").map(Spec::fingerprint)
+        );
+        let mut subjects: Vec<&str> = registry.subjects().collect();
+        subjects.sort();
+        assert_eq!(vec!["odd / subject", &long_subject, "trees"], subjects);
+        assert_eq!(
+            Some(&[recursive.fingerprint().clone(), other.fingerprint().clone()][..]),
+            registry.versions(&long_subject)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_errors() {
+        let dir = temp_dir("errors");
+        let mut registry = SpecRegistry::open(&dir).unwrap();
+        let fingerprint = registry.register(compile("bool")).unwrap();
+        let spec_path = dir.join(SPECS_DIR).join(format!(
+            "{}.{}",
+            hex::encode(fingerprint.as_bytes()),
+            SPEC_EXTENSION
+        ));
+
+        fs::write(&spec_path, compile("uint(0)").to_parsed_spec().to_bytes()).unwrap();
+        let e = SpecRegistry::open(&dir).err().unwrap();
+        assert_eq!(
+            SpecRegistryErrorKind::FingerprintMismatch,
+            (&e).into(),
+            "{}",
+            e
+        );
+
+        fs::write(&spec_path, [0xFF]).unwrap();
+        let e = SpecRegistry::open(&dir).err().unwrap();
+        assert_eq!(SpecRegistryErrorKind::InvalidSpecFile, (&e).into(), "{}", e);
+        if let SpecRegistryError::InvalidSpecFile(_, inner) = &e {
+            assert!(e.to_string().ends_with(&inner.to_string()), "{}", e);
+        }
+
+        fs::remove_file(&spec_path).unwrap();
+        let subject_path = dir.join(SUBJECTS_DIR).join(subject_file_name("s"));
+        let mut subject_file = vec![0x01, b's'];
+        subject_file.extend(fingerprint.as_bytes());
+        fs::write(&subject_path, &subject_file).unwrap();
+        let e = SpecRegistry::open(&dir).err().unwrap();
+        assert_eq!(
+            SpecRegistryErrorKind::UnknownFingerprint,
+            (&e).into(),
+            "{}",
+            e
+        );
+
+        // part of a fingerprint
+        fs::write(&subject_path, [0x01, b's', 0x00]).unwrap();
+        let e = SpecRegistry::open(&dir).err().unwrap();
+        assert_eq!(
+            SpecRegistryErrorKind::InvalidSubjectFile,
+            (&e).into(),
+            "{}",
+            e
+        );
+
+        // a name longer than the file
+        fs::write(&subject_path, [0x02, b's']).unwrap();
+        let e = SpecRegistry::open(&dir).err().unwrap();
+        assert_eq!(
+            SpecRegistryErrorKind::InvalidSubjectFile,
+            (&e).into(),
+            "{}",
+            e
+        );
+
+        // a file named for another subject
+        fs::write(&subject_path, [0x01, b't']).unwrap();
+        let e = SpecRegistry::open(&dir).err().unwrap();
+        assert_eq!(SpecRegistryErrorKind::InvalidFileName, (&e).into(), "{}", e);
+
+        fs::remove_file(&subject_path).unwrap();
+        fs::write(dir.join(SUBJECTS_DIR).join("not hex"), [0x01, b's']).unwrap();
+        let e = SpecRegistry::open(&dir).err().unwrap();
+        assert_eq!(SpecRegistryErrorKind::InvalidFileName, (&e).into(), "{}", e);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1326 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use ::serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor},
+    forward_to_deserialize_any,
+    ser::{self, Serialize, Serializer},
+};
+
+use crate::{
+    limits::DecodeLimits,
+    spec::{NameScope, Spec, SpecType},
+    spec_parsing::{InterchangeBinaryFloatingPointFormat, SpecKind, StringEncodingFmt},
+};
+
+use super::{
+    get_unit_deserialization_function, get_unit_serialization_function,
+    GluinoDeserializationError, GluinoSerializationError, GluinoValue,
+    GluinoValueKind, F32, F64,
+};
+
+/// Serialize any `serde::Serialize` value according to the spec.
+/// Structs map to records by field name, enums to enums by variant name, `Option` to optionals
+/// and sequences/maps to lists, tuples and maps.
+pub fn to_writer<T, W>(spec: &Spec, value: &T, mut writer: W) -> Result<usize, GluinoSerializationError>
+where
+    T: Serialize + ?Sized,
+    W: Write,
+{
+    let value = value.serialize(ValueSerializer::new(spec, NameScope::default()))?;
+    // unit serializers only take writer types without borrows, so the value goes through bytes
+    // on its way to a writer that may be borrowed
+    let mut bytes = Vec::new();
+    let written = get_unit_serialization_function::<Vec<u8>>(spec).serialize(value, &mut bytes)?;
+    writer.write_all(&bytes)?;
+    Ok(written)
+}
+
+/// Deserialize any `serde::Deserialize` value written according to the spec, the inverse of [to_writer]
+pub fn from_reader<T, R>(spec: &Spec, mut reader: R) -> Result<T, GluinoDeserializationError>
+where
+    T: DeserializeOwned,
+    R: Read,
+{
+    let value = get_unit_deserialization_function::<R>(spec).deserialize(&mut reader)?;
+    T::deserialize(ValueDeserializer::new(spec, NameScope::default(), value))
+}
+
+struct ValueSerializer<'s> {
+    spec: &'s Spec,
+    names: NameScope<'s>,
+}
+
+impl<'s> ValueSerializer<'s> {
+    fn new(spec: &'s Spec, names: NameScope<'s>) -> Self {
+        // names are transparent to rust types
+        let (spec, names) = names.resolve(spec);
+        ValueSerializer { spec, names }
+    }
+
+    fn mismatch(&self, rust_type: &'static str) -> GluinoSerializationError {
+        GluinoSerializationError::RustTypeSpecMismatch {
+            rust_type,
+            spec_kind: self.spec.kind(),
+        }
+    }
+
+    // a single value, which may also be one of the values of a const set
+    fn leaf<F>(self, rust_type: &'static str, to_value: F) -> Result<GluinoValue, GluinoSerializationError>
+    where
+        F: Fn(&'s SpecType) -> Option<Result<GluinoValue, GluinoSerializationError>>,
+    {
+        match self.spec.spec_type() {
+            SpecType::ConstSet(const_spec, const_values) => {
+                let value = ValueSerializer::new(const_spec, self.names).leaf(rust_type, to_value)?;
+                const_values
+                    .iter()
+                    .position(|const_value| const_value == &value)
+                    .map(|idx| GluinoValue::ConstSet(idx as u64))
+                    .ok_or(GluinoSerializationError::UnknownConstSetValue(value))
+            }
+            spec_type => to_value(spec_type).unwrap_or_else(|| Err(self.mismatch(rust_type))),
+        }
+    }
+
+    fn variant(
+        &self,
+        rust_type: &'static str,
+        variant: &str,
+    ) -> Result<(u64, ValueSerializer<'s>), GluinoSerializationError> {
+        match self.spec.spec_type() {
+            SpecType::Enum {
+                variants,
+                variant_to_spec,
+            } => enum_variant(variants, variant_to_spec, variant, self.names),
+            _ => Err(self.mismatch(rust_type)),
+        }
+    }
+}
+
+fn enum_variant<'s>(
+    variants: &[String],
+    variant_to_spec: &'s HashMap<String, Spec>,
+    variant: &str,
+    names: NameScope<'s>,
+) -> Result<(u64, ValueSerializer<'s>), GluinoSerializationError> {
+    let variant_id = variants
+        .iter()
+        .position(|name| name == variant)
+        .ok_or_else(|| GluinoSerializationError::UnknownEnumVariant(variant.to_string()))?;
+    Ok((
+        variant_id as u64,
+        ValueSerializer::new(variant_to_spec.get(variant).unwrap(), names),
+    ))
+}
+
+fn signed_integer(spec_type: &SpecType, v: i128) -> Option<Result<GluinoValue, GluinoSerializationError>> {
+    match spec_type {
+        SpecType::Int(n) => Some(int_value(*n, v)),
+        SpecType::Uint(n) => Some(
+            u128::try_from(v)
+                .map_err(|_| GluinoSerializationError::IntegerOutOfRange {
+                    spec_kind: SpecKind::Uint,
+                    n: *n,
+                })
+                .and_then(|v| uint_value(*n, v)),
+        ),
+        _ => None,
+    }
+}
+
+fn unsigned_integer(spec_type: &SpecType, v: u128) -> Option<Result<GluinoValue, GluinoSerializationError>> {
+    match spec_type {
+        SpecType::Int(n) => Some(
+            i128::try_from(v)
+                .map_err(|_| GluinoSerializationError::IntegerOutOfRange {
+                    spec_kind: SpecKind::Int,
+                    n: *n,
+                })
+                .and_then(|v| int_value(*n, v)),
+        ),
+        SpecType::Uint(n) => Some(uint_value(*n, v)),
+        _ => None,
+    }
+}
+
+fn int_value(n: u8, v: i128) -> Result<GluinoValue, GluinoSerializationError> {
+    let out_of_range = GluinoSerializationError::IntegerOutOfRange {
+        spec_kind: SpecKind::Int,
+        n,
+    };
+    Ok(match n {
+        0 => GluinoValue::Int8(v.try_into().map_err(|_| out_of_range)?),
+        1 => GluinoValue::Int16(v.try_into().map_err(|_| out_of_range)?),
+        2 => GluinoValue::Int32(v.try_into().map_err(|_| out_of_range)?),
+        3 => GluinoValue::Int64(v.try_into().map_err(|_| out_of_range)?),
+        4 => GluinoValue::Int128(v),
+        _ => GluinoValue::BigInt(
+            n,
+            big_integer_bytes(n, v.to_le_bytes(), if v < 0 { 0xFF } else { 0x00 })?,
+        ),
+    })
+}
+
+fn uint_value(n: u8, v: u128) -> Result<GluinoValue, GluinoSerializationError> {
+    let out_of_range = GluinoSerializationError::IntegerOutOfRange {
+        spec_kind: SpecKind::Uint,
+        n,
+    };
+    Ok(match n {
+        0 => GluinoValue::Uint8(v.try_into().map_err(|_| out_of_range)?),
+        1 => GluinoValue::Uint16(v.try_into().map_err(|_| out_of_range)?),
+        2 => GluinoValue::Uint32(v.try_into().map_err(|_| out_of_range)?),
+        3 => GluinoValue::Uint64(v.try_into().map_err(|_| out_of_range)?),
+        4 => GluinoValue::Uint128(v),
+        _ => GluinoValue::BigUint(
+            n,
+            big_integer_bytes(n, v.to_le_bytes(), 0x00)?,
+        ),
+    })
+}
+
+// little endian 2^n bytes, extended with the fill byte. Sizes too big to be read back under the
+// default decode limits are unsupported rather than allocated
+fn big_integer_bytes(n: u8, le_bytes: [u8; 16], fill: u8) -> Result<Vec<u8>, GluinoSerializationError> {
+    let len = 1u64
+        .checked_shl(n as u32)
+        .filter(|len| *len <= DecodeLimits::default().max_total_allocation)
+        .ok_or(GluinoSerializationError::UnsupportedIntegerSize(n))?;
+    let mut bytes = le_bytes.to_vec();
+    bytes.resize(len as usize, fill);
+    Ok(bytes)
+}
+
+impl<'s> ser::Serializer for ValueSerializer<'s> {
+    type Ok = GluinoValue;
+    type Error = GluinoSerializationError;
+    type SerializeSeq = SeqSerializer<'s>;
+    type SerializeTuple = SeqSerializer<'s>;
+    type SerializeTupleStruct = SeqSerializer<'s>;
+    type SerializeTupleVariant = SeqSerializer<'s>;
+    type SerializeMap = MapSerializer<'s>;
+    type SerializeStruct = RecordSerializer<'s>;
+    type SerializeStructVariant = RecordSerializer<'s>;
+
+    fn serialize_bool(self, v: bool) -> Result<GluinoValue, Self::Error> {
+        self.leaf("bool", |spec_type| {
+            matches!(spec_type, SpecType::Bool).then_some(Ok(GluinoValue::Bool(v)))
+        })
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<GluinoValue, Self::Error> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<GluinoValue, Self::Error> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<GluinoValue, Self::Error> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<GluinoValue, Self::Error> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<GluinoValue, Self::Error> {
+        self.leaf("integer", |spec_type| signed_integer(spec_type, v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<GluinoValue, Self::Error> {
+        self.serialize_u128(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<GluinoValue, Self::Error> {
+        self.serialize_u128(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<GluinoValue, Self::Error> {
+        self.serialize_u128(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<GluinoValue, Self::Error> {
+        self.serialize_u128(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<GluinoValue, Self::Error> {
+        self.leaf("unsigned integer", |spec_type| unsigned_integer(spec_type, v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<GluinoValue, Self::Error> {
+        self.leaf("f32", |spec_type| match spec_type {
+            SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Single) => {
+                Some(Ok(GluinoValue::Float(F32(v))))
+            }
+            SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Double) => {
+                Some(Ok(GluinoValue::Double(F64(v.into()))))
+            }
+            _ => None,
+        })
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<GluinoValue, Self::Error> {
+        self.leaf("f64", |spec_type| match spec_type {
+            SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Double) => {
+                Some(Ok(GluinoValue::Double(F64(v))))
+            }
+            _ => None,
+        })
+    }
+
+    fn serialize_char(self, v: char) -> Result<GluinoValue, Self::Error> {
+        self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<GluinoValue, Self::Error> {
+        self.leaf("str", |spec_type| match spec_type {
+            SpecType::String(_, StringEncodingFmt::Utf8) => Some(Ok(GluinoValue::String(v.to_string()))),
+            SpecType::String(_, _) => Some(Ok(GluinoValue::NonUtf8String(v.as_bytes().to_vec()))),
+            _ => None,
+        })
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<GluinoValue, Self::Error> {
+        self.leaf("bytes", |spec_type| {
+            matches!(spec_type, SpecType::Bytes(_)).then(|| Ok(GluinoValue::Bytes(v.to_vec())))
+        })
+    }
+
+    fn serialize_none(self) -> Result<GluinoValue, Self::Error> {
+        self.leaf("None", |spec_type| {
+            matches!(spec_type, SpecType::Optional(_)).then_some(Ok(GluinoValue::Optional(None)))
+        })
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<GluinoValue, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let names = self.names;
+        self.leaf("Some", |spec_type| match spec_type {
+            SpecType::Optional(inner) => Some(
+                value
+                    .serialize(ValueSerializer::new(inner, names))
+                    .map(|inner_value| GluinoValue::Optional(Some(Box::new(inner_value)))),
+            ),
+            _ => None,
+        })
+    }
+
+    fn serialize_unit(self) -> Result<GluinoValue, Self::Error> {
+        self.leaf("()", |spec_type| {
+            matches!(spec_type, SpecType::Void).then_some(Ok(GluinoValue::Void))
+        })
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<GluinoValue, Self::Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<GluinoValue, Self::Error> {
+        let names = self.names;
+        self.leaf("unit variant", |spec_type| match spec_type {
+            SpecType::Enum {
+                variants,
+                variant_to_spec,
+            } => Some(
+                enum_variant(variants, variant_to_spec, variant, names).and_then(|(variant_id, payload)| {
+                    Ok(GluinoValue::Enum(variant_id, Box::new(payload.serialize_unit()?)))
+                }),
+            ),
+            _ => None,
+        })
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<GluinoValue, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<GluinoValue, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let names = self.names;
+        self.leaf("newtype variant", |spec_type| match spec_type {
+            SpecType::Enum {
+                variants,
+                variant_to_spec,
+            } => Some(
+                enum_variant(variants, variant_to_spec, variant, names).and_then(|(variant_id, payload)| {
+                    Ok(GluinoValue::Enum(variant_id, Box::new(value.serialize(payload)?)))
+                }),
+            ),
+            _ => None,
+        })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer<'s>, Self::Error> {
+        SeqSerializer::new(self, "sequence", len, None)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'s>, Self::Error> {
+        SeqSerializer::new(self, "tuple", Some(len), None)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer<'s>, Self::Error> {
+        SeqSerializer::new(self, "tuple struct", Some(len), None)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer<'s>, Self::Error> {
+        let (variant_id, payload) = self.variant("tuple variant", variant)?;
+        SeqSerializer::new(payload, "tuple variant", Some(len), Some(variant_id))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer<'s>, Self::Error> {
+        match self.spec.spec_type() {
+            SpecType::Map {
+                key_spec,
+                value_spec,
+                ..
+            } => Ok(MapSerializer {
+                key_spec,
+                value_spec,
+                names: self.names,
+                entries: Vec::with_capacity(len.unwrap_or(0)),
+                key: None,
+            }),
+            _ => Err(self.mismatch("map")),
+        }
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<RecordSerializer<'s>, Self::Error> {
+        RecordSerializer::new(self, "struct", None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<RecordSerializer<'s>, Self::Error> {
+        let (variant_id, payload) = self.variant("struct variant", variant)?;
+        RecordSerializer::new(payload, "struct variant", Some(variant_id))
+    }
+}
+
+fn wrap_variant(variant_id: Option<u64>, value: GluinoValue) -> GluinoValue {
+    match variant_id {
+        Some(variant_id) => GluinoValue::Enum(variant_id, Box::new(value)),
+        None => value,
+    }
+}
+
+enum ElementSpecs<'s> {
+    List(&'s Spec),
+    Tuple(&'s [Spec]),
+}
+
+struct SeqSerializer<'s> {
+    element_specs: ElementSpecs<'s>,
+    names: NameScope<'s>,
+    values: Vec<GluinoValue>,
+    variant_id: Option<u64>,
+}
+
+impl<'s> SeqSerializer<'s> {
+    fn new(
+        serializer: ValueSerializer<'s>,
+        rust_type: &'static str,
+        len: Option<usize>,
+        variant_id: Option<u64>,
+    ) -> Result<SeqSerializer<'s>, GluinoSerializationError> {
+        let element_specs = match serializer.spec.spec_type() {
+            SpecType::List { value_spec, .. } => ElementSpecs::List(value_spec),
+            SpecType::Tuple(field_specs) => ElementSpecs::Tuple(field_specs),
+            _ => return Err(serializer.mismatch(rust_type)),
+        };
+        Ok(SeqSerializer {
+            element_specs,
+            names: serializer.names,
+            values: Vec::with_capacity(len.unwrap_or(0)),
+            variant_id,
+        })
+    }
+
+    fn push<T>(&mut self, value: &T) -> Result<(), GluinoSerializationError>
+    where
+        T: ?Sized + Serialize,
+    {
+        let spec = match self.element_specs {
+            ElementSpecs::List(value_spec) => value_spec,
+            ElementSpecs::Tuple(field_specs) => field_specs.get(self.values.len()).ok_or(
+                GluinoSerializationError::IncorrectNumberOfFields {
+                    correct_number_of_fields: field_specs.len(),
+                    actual_number_of_fields: self.values.len() + 1,
+                },
+            )?,
+        };
+        self.values.push(value.serialize(ValueSerializer::new(spec, self.names))?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<GluinoValue, GluinoSerializationError> {
+        let value = match self.element_specs {
+            ElementSpecs::List(_) => GluinoValue::List(self.values),
+            ElementSpecs::Tuple(field_specs) if field_specs.len() != self.values.len() => {
+                return Err(GluinoSerializationError::IncorrectNumberOfFields {
+                    correct_number_of_fields: field_specs.len(),
+                    actual_number_of_fields: self.values.len(),
+                })
+            }
+            ElementSpecs::Tuple(_) => GluinoValue::Tuple(self.values),
+        };
+        Ok(wrap_variant(self.variant_id, value))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer<'_> {
+    type Ok = GluinoValue;
+    type Error = GluinoSerializationError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<GluinoValue, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer<'_> {
+    type Ok = GluinoValue;
+    type Error = GluinoSerializationError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<GluinoValue, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer<'_> {
+    type Ok = GluinoValue;
+    type Error = GluinoSerializationError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<GluinoValue, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer<'_> {
+    type Ok = GluinoValue;
+    type Error = GluinoSerializationError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<GluinoValue, Self::Error> {
+        self.finish()
+    }
+}
+
+struct MapSerializer<'s> {
+    key_spec: &'s Spec,
+    value_spec: &'s Spec,
+    names: NameScope<'s>,
+    entries: Vec<(GluinoValue, GluinoValue)>,
+    key: Option<GluinoValue>,
+}
+
+impl ser::SerializeMap for MapSerializer<'_> {
+    type Ok = GluinoValue;
+    type Error = GluinoSerializationError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.key = Some(key.serialize(ValueSerializer::new(self.key_spec, self.names))?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| <GluinoSerializationError as ser::Error>::custom("map value serialized before its key"))?;
+        self.entries
+            .push((key, value.serialize(ValueSerializer::new(self.value_spec, self.names))?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<GluinoValue, Self::Error> {
+        Ok(GluinoValue::Map(self.entries))
+    }
+}
+
+struct RecordSerializer<'s> {
+    fields: &'s [String],
+    field_to_spec: &'s HashMap<String, Spec>,
+    field_to_index: &'s HashMap<String, usize>,
+    names: NameScope<'s>,
+    values: Vec<Option<GluinoValue>>,
+    variant_id: Option<u64>,
+}
+
+impl<'s> RecordSerializer<'s> {
+    fn new(
+        serializer: ValueSerializer<'s>,
+        rust_type: &'static str,
+        variant_id: Option<u64>,
+    ) -> Result<RecordSerializer<'s>, GluinoSerializationError> {
+        match serializer.spec.spec_type() {
+            SpecType::Record {
+                fields,
+                field_to_spec,
+                field_to_index,
+            } => Ok(RecordSerializer {
+                fields,
+                field_to_spec,
+                field_to_index,
+                names: serializer.names,
+                values: vec![None; fields.len()],
+                variant_id,
+            }),
+            _ => Err(serializer.mismatch(rust_type)),
+        }
+    }
+
+    fn set<T>(&mut self, key: &'static str, value: &T) -> Result<(), GluinoSerializationError>
+    where
+        T: ?Sized + Serialize,
+    {
+        let index = *self
+            .field_to_index
+            .get(key)
+            .ok_or_else(|| GluinoSerializationError::UnknownRecordField(key.to_string()))?;
+        self.values[index] = Some(value.serialize(ValueSerializer::new(
+            self.field_to_spec.get(key).unwrap(),
+            self.names,
+        ))?);
+        Ok(())
+    }
+
+    // fields left out of the rust value are only allowed when they are optional
+    fn finish(self) -> Result<GluinoValue, GluinoSerializationError> {
+        let values = self
+            .fields
+            .iter()
+            .zip(self.values)
+            .map(|(field, value)| match value {
+                Some(value) => Ok(value),
+                None => ValueSerializer::new(self.field_to_spec.get(field).unwrap(), self.names)
+                    .serialize_none()
+                    .map_err(|_| GluinoSerializationError::MissingRecordField(field.clone())),
+            })
+            .collect::<Result<Vec<GluinoValue>, GluinoSerializationError>>()?;
+        Ok(wrap_variant(self.variant_id, GluinoValue::Record(values)))
+    }
+}
+
+impl ser::SerializeStruct for RecordSerializer<'_> {
+    type Ok = GluinoValue;
+    type Error = GluinoSerializationError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.set(key, value)
+    }
+
+    fn end(self) -> Result<GluinoValue, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for RecordSerializer<'_> {
+    type Ok = GluinoValue;
+    type Error = GluinoSerializationError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.set(key, value)
+    }
+
+    fn end(self) -> Result<GluinoValue, Self::Error> {
+        self.finish()
+    }
+}
+
+struct ValueDeserializer<'s> {
+    spec: &'s Spec,
+    names: NameScope<'s>,
+    value: GluinoValue,
+}
+
+impl<'s> ValueDeserializer<'s> {
+    fn new(spec: &'s Spec, names: NameScope<'s>, value: GluinoValue) -> Self {
+        // names are transparent to rust types
+        let (spec, names) = names.resolve(spec);
+        ValueDeserializer { spec, names, value }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = GluinoDeserializationError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let names = self.names;
+        match (self.spec.spec_type(), self.value) {
+            (_, GluinoValue::Void) => visitor.visit_unit(),
+            (_, GluinoValue::Bool(v)) => visitor.visit_bool(v),
+            (_, GluinoValue::Int8(v)) => visitor.visit_i8(v),
+            (_, GluinoValue::Int16(v)) => visitor.visit_i16(v),
+            (_, GluinoValue::Int32(v)) => visitor.visit_i32(v),
+            (_, GluinoValue::Int64(v)) => visitor.visit_i64(v),
+            (_, GluinoValue::Int128(v)) => visitor.visit_i128(v),
+            (_, GluinoValue::Uint8(v)) => visitor.visit_u8(v),
+            (_, GluinoValue::Uint16(v)) => visitor.visit_u16(v),
+            (_, GluinoValue::Uint32(v)) => visitor.visit_u32(v),
+            (_, GluinoValue::Uint64(v)) => visitor.visit_u64(v),
+            (_, GluinoValue::Uint128(v)) => visitor.visit_u128(v),
+            (_, GluinoValue::Float(F32(v))) => visitor.visit_f32(v),
+            (_, GluinoValue::Double(F64(v))) => visitor.visit_f64(v),
+            (_, GluinoValue::String(v)) => visitor.visit_string(v),
+            // most rust integer visitors only accept up to 64 bits
+            (_, GluinoValue::BigInt(_, v)) => match narrow_big_integer(&v, true)
+                .map(i128::from_le_bytes)
+            {
+                Some(n) => match i64::try_from(n) {
+                    Ok(n) => visitor.visit_i64(n),
+                    Err(_) => visitor.visit_i128(n),
+                },
+                None => visitor.visit_byte_buf(v),
+            },
+            (_, GluinoValue::BigUint(_, v)) => match narrow_big_integer(&v, false)
+                .map(u128::from_le_bytes)
+            {
+                Some(n) => match u64::try_from(n) {
+                    Ok(n) => visitor.visit_u64(n),
+                    Err(_) => visitor.visit_u128(n),
+                },
+                None => visitor.visit_byte_buf(v),
+            },
+            // no rust native representation, left as raw little endian bytes
+            (_, GluinoValue::Bytes(v))
+            | (_, GluinoValue::NonUtf8String(v))
+            | (_, GluinoValue::BinaryFloatingPoint(_, v))
+            | (_, GluinoValue::DecimalFloatingPoint(_, v))
+            | (_, GluinoValue::Decimal(v)) => visitor.visit_byte_buf(v),
+            (SpecType::Optional(inner), GluinoValue::Optional(v)) => match v {
+                Some(v) => visitor.visit_some(ValueDeserializer::new(inner, names, *v)),
+                None => visitor.visit_none(),
+            },
+            (SpecType::List { value_spec, .. }, GluinoValue::List(values)) => {
+                visitor.visit_seq(SeqDeserializer::new(
+                    values
+                        .into_iter()
+                        .map(|value| ValueDeserializer::new(value_spec, names, value))
+                        .collect(),
+                ))
+            }
+            (SpecType::Tuple(field_specs), GluinoValue::Tuple(values)) => {
+                visitor.visit_seq(SeqDeserializer::new(
+                    field_specs
+                        .iter()
+                        .zip(values)
+                        .map(|(spec, value)| ValueDeserializer::new(spec, names, value))
+                        .collect(),
+                ))
+            }
+            (
+                SpecType::Map {
+                    key_spec,
+                    value_spec,
+                    ..
+                },
+                GluinoValue::Map(entries),
+            ) => visitor.visit_map(MapDeserializer::new(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| {
+                        (
+                            ValueDeserializer::new(key_spec, names, key),
+                            ValueDeserializer::new(value_spec, names, value),
+                        )
+                    })
+                    .collect(),
+            )),
+            (
+                SpecType::Record {
+                    fields,
+                    field_to_spec,
+                    ..
+                },
+                GluinoValue::Record(values),
+            ) => visitor.visit_map(MapDeserializer::new(
+                fields
+                    .iter()
+                    .zip(values)
+                    .map(|(field, value)| {
+                        (
+                            field.as_str().into_deserializer(),
+                            ValueDeserializer::new(field_to_spec.get(field).unwrap(), names, value),
+                        )
+                    })
+                    .collect(),
+            )),
+            (
+                SpecType::Enum {
+                    variants,
+                    variant_to_spec,
+                },
+                GluinoValue::Enum(variant_id, payload),
+            ) => {
+                let variant = variants
+                    .get(variant_id as usize)
+                    .ok_or(GluinoDeserializationError::InvalidVariantId {
+                        variant_id: variant_id as usize,
+                        max_variant_id: variants.len().saturating_sub(1),
+                    })?;
+                visitor.visit_enum(EnumDeserializer {
+                    variant: variant.as_str().into_deserializer(),
+                    payload: ValueDeserializer::new(variant_to_spec.get(variant).unwrap(), names, *payload),
+                })
+            }
+            (SpecType::Union(variants), GluinoValue::Union(variant_id, payload)) => {
+                let variant_spec = variants.get(variant_id as usize).ok_or(
+                    GluinoDeserializationError::InvalidVariantId {
+                        variant_id: variant_id as usize,
+                        max_variant_id: variants.len().saturating_sub(1),
+                    },
+                )?;
+                visitor.visit_enum(EnumDeserializer {
+                    variant: variant_id.into_deserializer(),
+                    payload: ValueDeserializer::new(variant_spec, names, *payload),
+                })
+            }
+            (SpecType::ConstSet(const_spec, const_values), GluinoValue::ConstSet(idx)) => {
+                match const_values.get(idx as usize) {
+                    Some(value) => ValueDeserializer::new(const_spec, names, value.clone()).deserialize_any(visitor),
+                    None => Err(de::Error::custom(format!("unknown const set index {}", idx))),
+                }
+            }
+            (_, value) => Err(de::Error::custom(format!(
+                "{:?} value does not match the spec",
+                GluinoValueKind::from(value)
+            ))),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.spec.spec_type() {
+            SpecType::Optional(_) => self.deserialize_any(visitor),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct enum identifier
+    }
+}
+
+// the low 16 bytes of a big integer when the rest are only its extension
+fn narrow_big_integer(le_bytes: &[u8], signed: bool) -> Option<[u8; 16]> {
+    let (low, high) = le_bytes.split_at_checked(16)?;
+    let fill = if signed && low[15] & 0x80 > 0 { 0xFF } else { 0x00 };
+    high.iter().all(|b| *b == fill).then(|| low.try_into().unwrap())
+}
+
+impl<'de> de::VariantAccess<'de> for ValueDeserializer<'_> {
+    type Error = GluinoDeserializationError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+struct SeqDeserializer<'s> {
+    elements: std::vec::IntoIter<ValueDeserializer<'s>>,
+}
+
+impl<'s> SeqDeserializer<'s> {
+    fn new(elements: Vec<ValueDeserializer<'s>>) -> Self {
+        SeqDeserializer {
+            elements: elements.into_iter(),
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqDeserializer<'_> {
+    type Error = GluinoDeserializationError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.elements
+            .next()
+            .map(|element| seed.deserialize(element))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+// keys are either values of a map or the field names of a record
+struct MapDeserializer<'s, K> {
+    entries: std::vec::IntoIter<(K, ValueDeserializer<'s>)>,
+    value: Option<ValueDeserializer<'s>>,
+}
+
+impl<'s, K> MapDeserializer<'s, K> {
+    fn new(entries: Vec<(K, ValueDeserializer<'s>)>) -> Self {
+        MapDeserializer {
+            entries: entries.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de, K> de::MapAccess<'de> for MapDeserializer<'_, K>
+where
+    K: de::Deserializer<'de, Error = GluinoDeserializationError>,
+{
+    type Error = GluinoDeserializationError;
+
+    fn next_key_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S>(&mut self, seed: S) -> Result<S::Value, Self::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::custom("map value deserialized before its key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+// variants are identified by name for enums and by index for unions
+struct EnumDeserializer<'s, K> {
+    variant: K,
+    payload: ValueDeserializer<'s>,
+}
+
+impl<'de, 's, K> de::EnumAccess<'de> for EnumDeserializer<'s, K>
+where
+    K: de::Deserializer<'de, Error = GluinoDeserializationError>,
+{
+    type Error = GluinoDeserializationError;
+    type Variant = ValueDeserializer<'s>;
+
+    fn variant_seed<S>(self, seed: S) -> Result<(S::Value, ValueDeserializer<'s>), Self::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        Ok((seed.deserialize(self.variant)?, self.payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, io::Cursor};
+
+    use ::serde::{Deserialize, Serialize};
+
+    use crate::spec_parsing::{ParsedSpec, Size};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Point(i32, i32),
+        Rectangle { width: u16, height: u16 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Drawing {
+        name: String,
+        shapes: Vec<Shape>,
+        tags: BTreeMap<String, u64>,
+        author: Option<String>,
+        layer: (u8, bool),
+    }
+
+    fn string() -> ParsedSpec {
+        ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8)
+    }
+
+    fn shape_spec() -> ParsedSpec {
+        ParsedSpec::Enum(vec![
+            ("Empty".into(), ParsedSpec::Void),
+            (
+                "Circle".into(),
+                ParsedSpec::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Double),
+            ),
+            ("Point".into(), ParsedSpec::Tuple(vec![ParsedSpec::Int(2), ParsedSpec::Int(2)])),
+            (
+                "Rectangle".into(),
+                ParsedSpec::Record(vec![
+                    ("width".into(), ParsedSpec::Uint(1)),
+                    ("height".into(), ParsedSpec::Uint(1)),
+                ]),
+            ),
+        ])
+    }
+
+    fn drawing_spec() -> Spec {
+        // record fields deliberately in a different order than the struct
+        Spec::compile(ParsedSpec::Record(vec![
+            (
+                "layer".into(),
+                ParsedSpec::Tuple(vec![ParsedSpec::Uint(0), ParsedSpec::Bool]),
+            ),
+            ("author".into(), ParsedSpec::Optional(string().into())),
+            ("name".into(), string()),
+            (
+                "shapes".into(),
+                ParsedSpec::List {
+                    size: Size::Variable,
+                    value_spec: shape_spec().into(),
+                },
+            ),
+            (
+                "tags".into(),
+                ParsedSpec::Map {
+                    size: Size::Variable,
+                    key_spec: string().into(),
+                    value_spec: ParsedSpec::Uint(3).into(),
+                },
+            ),
+        ]))
+        .expect("Unable to compile")
+    }
+
+    fn drawing() -> Drawing {
+        Drawing {
+            name: "gluino".into(),
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(2.5),
+                Shape::Point(-1, 7),
+                Shape::Rectangle {
+                    width: 3,
+                    height: 4,
+                },
+            ],
+            tags: BTreeMap::from([("a".into(), 1), ("b".into(), u64::MAX)]),
+            author: None,
+            layer: (2, true),
+        }
+    }
+
+    fn round_trip<T>(spec: &Spec, value: &T) -> T
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let mut bytes = Vec::new();
+        let written = to_writer(spec, value, &mut bytes).expect("Unable to serialize");
+        assert_eq!(written, bytes.len());
+        let mut reader = Cursor::new(bytes);
+        let result = from_reader::<T, _>(spec, &mut reader).expect("Unable to deserialize");
+        assert_eq!(written as u64, reader.position());
+        result
+    }
+
+    #[test]
+    fn test_struct_round_trip() {
+        let spec = drawing_spec();
+        let drawing = drawing();
+        assert_eq!(drawing, round_trip(&spec, &drawing));
+        let drawing = Drawing {
+            author: Some("me".into()),
+            ..drawing
+        };
+        assert_eq!(drawing, round_trip(&spec, &drawing));
+    }
+
+    #[test]
+    fn test_matches_unit_serde() {
+        let spec = drawing_spec();
+        let mut bytes = Vec::new();
+        to_writer(&spec, &drawing(), &mut bytes).unwrap();
+        let value = get_unit_deserialization_function::<Cursor<Vec<u8>>>(&spec)
+            .deserialize(&mut Cursor::new(bytes))
+            .unwrap();
+        if let GluinoValue::Record(fields) = value {
+            assert_eq!(fields[0], GluinoValue::Tuple(vec![GluinoValue::Uint8(2), GluinoValue::Bool(true)]));
+            assert_eq!(fields[1], GluinoValue::Optional(None));
+            assert_eq!(fields[2], GluinoValue::String("gluino".into()));
+        } else {
+            panic!("Expected a record, got {:?}", value)
+        }
+    }
+
+    #[test]
+    fn test_recursive_round_trip() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Tree {
+            value: i64,
+            children: Vec<Tree>,
+        }
+        let spec = Spec::compile(ParsedSpec::Name {
+            name: "tree".into(),
+            spec: ParsedSpec::Record(vec![
+                ("value".into(), ParsedSpec::Int(3)),
+                (
+                    "children".into(),
+                    ParsedSpec::List {
+                        size: Size::Variable,
+                        value_spec: ParsedSpec::Ref {
+                            name: "tree".into(),
+                        }
+                        .into(),
+                    },
+                ),
+            ])
+            .into(),
+        })
+        .expect("Unable to compile");
+        let leaf = |value| Tree {
+            value,
+            children: vec![],
+        };
+        let tree = Tree {
+            value: 1,
+            children: vec![
+                leaf(2),
+                Tree {
+                    value: 3,
+                    children: vec![leaf(4)],
+                },
+            ],
+        };
+        assert_eq!(tree, round_trip(&spec, &tree));
+    }
+
+    #[test]
+    fn test_const_set_round_trip() {
+        let spec = Spec::compile(ParsedSpec::ConstSet(
+            string().into(),
+            ["red", "green", "blue"]
+                .iter()
+                .map(|color| {
+                    let mut bytes = vec![color.len() as u8];
+                    bytes.extend_from_slice(color.as_bytes());
+                    bytes
+                })
+                .collect(),
+        ))
+        .expect("Unable to compile");
+        assert_eq!("green", round_trip(&spec, &"green".to_string()));
+        let mut bytes = Vec::new();
+        to_writer(&spec, "blue", &mut bytes).unwrap();
+        assert_eq!(vec![4, b'b', b'l', b'u', b'e'], bytes);
+        assert!(matches!(
+            to_writer(&spec, "purple", &mut Vec::new()),
+            Err(GluinoSerializationError::UnknownConstSetValue(_))
+        ));
+    }
+
+    #[test]
+    fn test_integer_widths() {
+        let spec = Spec::compile(ParsedSpec::Uint(5)).unwrap();
+        assert_eq!(u128::MAX, round_trip(&spec, &u128::MAX));
+        let spec = Spec::compile(ParsedSpec::Int(6)).unwrap();
+        assert_eq!(i128::MIN, round_trip(&spec, &i128::MIN));
+        assert_eq!(-5i8, round_trip(&spec, &-5i8));
+        let spec = Spec::compile(ParsedSpec::Int(0)).unwrap();
+        assert_eq!(-3i64, round_trip(&spec, &-3i64));
+        assert!(matches!(
+            to_writer(&spec, &300u16, &mut Vec::new()),
+            Err(GluinoSerializationError::IntegerOutOfRange {
+                spec_kind: SpecKind::Int,
+                n: 0
+            })
+        ));
+        let spec = Spec::compile(ParsedSpec::Uint(2)).unwrap();
+        assert!(matches!(
+            to_writer(&spec, &-1i32, &mut Vec::new()),
+            Err(GluinoSerializationError::IntegerOutOfRange {
+                spec_kind: SpecKind::Uint,
+                n: 2
+            })
+        ));
+        // 2^40 bytes wide
+        let spec = Spec::compile(ParsedSpec::Uint(40)).unwrap();
+        assert!(matches!(
+            to_writer(&spec, &1u8, &mut Vec::new()),
+            Err(GluinoSerializationError::UnsupportedIntegerSize(40))
+        ));
+        let spec = Spec::compile(ParsedSpec::Int(200)).unwrap();
+        assert!(matches!(
+            to_writer(&spec, &-1i8, &mut Vec::new()),
+            Err(GluinoSerializationError::UnsupportedIntegerSize(200))
+        ));
+    }
+
+    #[test]
+    fn test_serialization_errors() {
+        #[derive(Serialize)]
+        struct Extra {
+            name: String,
+            extra: bool,
+        }
+        #[derive(Serialize)]
+        struct Missing {
+            author: Option<String>,
+        }
+        #[derive(Serialize)]
+        enum Unknown {
+            Triangle,
+        }
+        let spec = drawing_spec();
+        assert!(matches!(
+            to_writer(&spec, &Extra { name: "".into(), extra: true }, &mut Vec::new()),
+            Err(GluinoSerializationError::UnknownRecordField(field)) if field == "extra"
+        ));
+        assert!(matches!(
+            to_writer(&spec, &Missing { author: None }, &mut Vec::new()),
+            Err(GluinoSerializationError::MissingRecordField(field)) if field == "layer"
+        ));
+        let spec = Spec::compile(shape_spec()).unwrap();
+        assert!(matches!(
+            to_writer(&spec, &Unknown::Triangle, &mut Vec::new()),
+            Err(GluinoSerializationError::UnknownEnumVariant(variant)) if variant == "Triangle"
+        ));
+        assert!(matches!(
+            to_writer(&spec, &true, &mut Vec::new()),
+            Err(GluinoSerializationError::RustTypeSpecMismatch {
+                rust_type: "bool",
+                spec_kind: SpecKind::Enum
+            })
+        ));
+    }
+
+    #[test]
+    fn test_deserialization_type_mismatch() {
+        let spec = Spec::compile(ParsedSpec::Bool).unwrap();
+        let mut bytes = Vec::new();
+        to_writer(&spec, &true, &mut bytes).unwrap();
+        assert!(matches!(
+            from_reader::<String, _>(&spec, Cursor::new(bytes)),
+            Err(GluinoDeserializationError::Custom(_))
+        ));
+    }
+
+    #[test]
+    fn test_borrowed_writer_and_reader() {
+        let spec = Spec::compile(ParsedSpec::Uint(1)).unwrap();
+        let mut buffer = [0u8; 4];
+        let written = to_writer(&spec, &300u16, &mut buffer[..]).unwrap();
+        assert_eq!(300u16, from_reader::<u16, _>(&spec, &buffer[..written]).unwrap());
+    }
+}
@@ -1,6 +1,64 @@
-use std::{io::Read, marker::PhantomData};
+use std::{io::Read, marker::PhantomData, mem::size_of};
 
-use super::{encode::Encodable, GluinoDeserializationError, GluinoValue, GluinoValueDe};
+use crate::{
+    spec_parsing::{InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, Size},
+    spec_path::PathSegment,
+    util::{self, variable_length_decode_u64},
+};
+
+use super::{
+    encode::Encodable, ser_impls::path_segment, DecodeReader, GluinoDeserializationError,
+    GluinoValue, GluinoValueDe, GluinoValueKind, ReaderDe,
+};
+
+#[inline]
+pub(crate) fn decode_u64<R: Read>(reader: &mut R) -> Result<u64, GluinoDeserializationError> {
+    match variable_length_decode_u64(reader)? {
+        util::VariableLengthResult::Respresentable(n) => Ok(n),
+        util::VariableLengthResult::Unrepresentable(v) => Err(
+            GluinoDeserializationError::IntegerOverflowVariableLengthDecodingError(v),
+        ),
+    }
+}
+
+/// Read the number of items/bytes of a sized value, the inverse of the size prefix written by
+/// the serializers when `SerSizeValidator::need_write_size`
+#[inline]
+pub(crate) fn decode_size<R: Read>(
+    spec_size: &Size,
+    size_value_kind: GluinoValueKind,
+    reader: &mut R,
+) -> Result<u64, GluinoDeserializationError> {
+    let size = match spec_size {
+        Size::Fixed(n) => return Ok(*n),
+        _ => decode_u64(reader)?,
+    };
+    if spec_size.can_be(size) {
+        Ok(size)
+    } else {
+        Err(GluinoDeserializationError::IncorrectDataSize {
+            expected_size: spec_size.clone(),
+            actual_size: size,
+            size_value_kind,
+        })
+    }
+}
+
+/// Read exactly `n` bytes without trusting `n` for the up front allocation
+#[inline]
+fn read_n_bytes<R: Read>(
+    n: u64,
+    reader: &mut DecodeReader<R>,
+) -> Result<Vec<u8>, GluinoDeserializationError> {
+    reader.budget.allocate(n)?;
+    let mut bytes = Vec::new();
+    let n_actual = reader.take(n).read_to_end(&mut bytes)?;
+    if (n_actual as u64) < n {
+        Err(GluinoDeserializationError::UnexpectedEndOfBytes)
+    } else {
+        Ok(bytes)
+    }
+}
 
 pub(crate) struct VoidGluinoValueDe;
 
@@ -13,6 +71,23 @@ where
     }
 }
 
+pub(crate) struct BoolDe;
+
+impl<R> GluinoValueDe<R> for BoolDe
+where
+    R: Read,
+{
+    fn deserialize(&self, reader: &mut R) -> Result<GluinoValue, GluinoDeserializationError> {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        match byte[0] {
+            0 => Ok(GluinoValue::Bool(false)),
+            1 => Ok(GluinoValue::Bool(true)),
+            b => Err(GluinoDeserializationError::InvalidBoolByte(b)),
+        }
+    }
+}
+
 pub(crate) struct NativeSingleDe<E: Encodable> {
     _d: PhantomData<E>,
 }
@@ -28,3 +103,344 @@ impl<R: Read, E: Encodable> GluinoValueDe<R> for NativeSingleDe<E> {
         Ok(E::decode(reader)?)
     }
 }
+
+pub(crate) struct BigIntValueDe {
+    pub(crate) n: u8,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for BigIntValueDe
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let bytes = read_n_bytes(big_integer_bytes(self.n)?, reader)?;
+        Ok(GluinoValue::BigInt(self.n, bytes))
+    }
+}
+
+pub(crate) struct BigUintValueDe {
+    pub(crate) n: u8,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for BigUintValueDe
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let bytes = read_n_bytes(big_integer_bytes(self.n)?, reader)?;
+        Ok(GluinoValue::BigUint(self.n, bytes))
+    }
+}
+
+/// Read the size of a string or bytes value, checking it against the string length limit
+#[inline]
+fn decode_string_size<R: Read>(
+    spec_size: &Size,
+    size_value_kind: GluinoValueKind,
+    reader: &mut DecodeReader<R>,
+) -> Result<u64, GluinoDeserializationError> {
+    let size = decode_size(spec_size, size_value_kind, reader)?;
+    reader.budget.string(size)?;
+    Ok(size)
+}
+
+/// Deserialize a part of a value, one level deeper
+#[inline]
+pub(crate) fn deserialize_nested<R: Read>(
+    de: &ReaderDe<'_, R>,
+    reader: &mut DecodeReader<&mut R>,
+) -> Result<GluinoValue, GluinoDeserializationError> {
+    reader.budget.enter()?;
+    let value = de.deserialize(reader)?;
+    reader.budget.exit();
+    Ok(value)
+}
+
+#[inline]
+fn big_integer_bytes(n: u8) -> Result<u64, GluinoDeserializationError> {
+    1u64.checked_shl(n as u32)
+        .ok_or(GluinoDeserializationError::UnsupportedIntegerSize(n))
+}
+
+pub(crate) struct BinaryFloatingPointValueDe {
+    pub(crate) fmt: InterchangeBinaryFloatingPointFormat,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for BinaryFloatingPointValueDe
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let bytes = read_n_bytes(
+            (self.fmt.significand_bits() + self.fmt.exponent_bits()) >> 3,
+            reader,
+        )?;
+        Ok(GluinoValue::BinaryFloatingPoint(self.fmt.clone(), bytes))
+    }
+}
+
+pub(crate) struct DecimalFloatingPointValueDe {
+    pub(crate) fmt: InterchangeDecimalFloatingPointFormat,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for DecimalFloatingPointValueDe
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let bytes = read_n_bytes(self.fmt.minimum_byes_needed() as u64, reader)?;
+        Ok(GluinoValue::DecimalFloatingPoint(self.fmt.clone(), bytes))
+    }
+}
+
+pub(crate) struct DecimalDe;
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for DecimalDe
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let size = decode_u64(reader)?;
+        Ok(GluinoValue::Decimal(read_n_bytes(size, reader)?))
+    }
+}
+
+pub(crate) struct ByteValueDe {
+    pub(crate) spec_size: Size,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for ByteValueDe
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let size = decode_string_size(&self.spec_size, GluinoValueKind::Bytes, reader)?;
+        Ok(GluinoValue::Bytes(read_n_bytes(size, reader)?))
+    }
+}
+
+pub(crate) struct Utf8De {
+    pub(crate) spec_size: Size,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for Utf8De
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let size = decode_string_size(&self.spec_size, GluinoValueKind::String, reader)?;
+        Ok(GluinoValue::String(String::from_utf8(read_n_bytes(
+            size, reader,
+        )?)?))
+    }
+}
+
+pub(crate) struct NonUtf8De {
+    pub(crate) spec_size: Size,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for NonUtf8De
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let size = decode_string_size(&self.spec_size, GluinoValueKind::NonUtf8String, reader)?;
+        Ok(GluinoValue::NonUtf8String(read_n_bytes(size, reader)?))
+    }
+}
+
+pub(crate) struct MapDe<'d, R> {
+    pub(crate) spec_size: Size,
+    pub(crate) key_de: ReaderDe<'d, R>,
+    pub(crate) value_de: ReaderDe<'d, R>,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for MapDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let size = decode_size(&self.spec_size, GluinoValueKind::Map, reader)?;
+        reader
+            .budget
+            .collection(size, size_of::<(GluinoValue, GluinoValue)>())?;
+        let mut entries = Vec::new();
+        for index in 0..size as usize {
+            let offset = reader.offset();
+            let key = deserialize_nested(&self.key_de, reader)
+                .map_err(|e| e.within(PathSegment::EntryKey(index), offset))?;
+            let offset = reader.offset();
+            let value = deserialize_nested(&self.value_de, reader)
+                .map_err(|e| e.within(PathSegment::Element(index), offset))?;
+            entries.push((key, value));
+        }
+        Ok(GluinoValue::Map(entries))
+    }
+}
+
+pub(crate) struct ListDe<'d, R> {
+    pub(crate) spec_size: Size,
+    pub(crate) value_de: ReaderDe<'d, R>,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for ListDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let size = decode_size(&self.spec_size, GluinoValueKind::List, reader)?;
+        reader.budget.collection(size, size_of::<GluinoValue>())?;
+        let mut values = Vec::new();
+        for index in 0..size as usize {
+            let offset = reader.offset();
+            values.push(
+                deserialize_nested(&self.value_de, reader)
+                    .map_err(|e| e.within(PathSegment::Element(index), offset))?,
+            );
+        }
+        Ok(GluinoValue::List(values))
+    }
+}
+
+pub(crate) struct OptionalValueDe<'d, R> {
+    pub(crate) inner_de: ReaderDe<'d, R>,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for OptionalValueDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let mut flag = [0u8];
+        reader.read_exact(&mut flag)?;
+        let offset = reader.offset();
+        Ok(GluinoValue::Optional(match flag[0] {
+            0 => None,
+            1 => {
+                reader.budget.allocate(size_of::<GluinoValue>() as u64)?;
+                Some(Box::new(
+                    deserialize_nested(&self.inner_de, reader)
+                        .map_err(|e| e.positioned(offset))?,
+                ))
+            }
+            b => return Err(GluinoDeserializationError::InvalidOptionalFlag(b)),
+        }))
+    }
+}
+
+pub(crate) struct ProductValueDe<'d, R> {
+    pub(crate) field_des: Vec<ReaderDe<'d, R>>,
+    pub(crate) field_names: Option<Vec<String>>,
+    // GluinoValue::Record or GluinoValue::Tuple
+    pub(crate) product_value: fn(Vec<GluinoValue>) -> GluinoValue,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for ProductValueDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        reader
+            .budget
+            .allocate((self.field_des.len() * size_of::<GluinoValue>()) as u64)?;
+        let mut fields = Vec::with_capacity(self.field_des.len());
+        for (index, de) in self.field_des.iter().enumerate() {
+            let offset = reader.offset();
+            fields.push(
+                deserialize_nested(de, reader)
+                    .map_err(|e| e.within(path_segment(&self.field_names, index), offset))?,
+            );
+        }
+        Ok((self.product_value)(fields))
+    }
+}
+
+pub(crate) struct SumValueDe<'d, R> {
+    pub(crate) variant_des: Vec<ReaderDe<'d, R>>,
+    pub(crate) variant_names: Option<Vec<String>>,
+    // GluinoValue::Enum or GluinoValue::Union
+    pub(crate) sum_value: fn(u64, Box<GluinoValue>) -> GluinoValue,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for SumValueDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let variant_id = decode_u64(reader)?;
+        if let Some(variant_de) = self.variant_des.get(variant_id as usize) {
+            reader.budget.allocate(size_of::<GluinoValue>() as u64)?;
+            let offset = reader.offset();
+            let value = deserialize_nested(variant_de, reader).map_err(|e| {
+                e.within(path_segment(&self.variant_names, variant_id as usize), offset)
+            })?;
+            Ok((self.sum_value)(variant_id, Box::new(value)))
+        } else {
+            Err(GluinoDeserializationError::InvalidVariantId {
+                variant_id: variant_id as usize,
+                max_variant_id: self.variant_des.len().saturating_sub(1),
+            })
+        }
+    }
+}
+
+pub(crate) struct ConstSetDe<'d, R> {
+    pub(crate) const_values: Vec<GluinoValue>,
+    pub(crate) const_de: ReaderDe<'d, R>,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for ConstSetDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let value = self.const_de.deserialize(reader)?;
+        self.const_values
+            .iter()
+            .position(|const_value| const_value == &value)
+            .map(|idx| GluinoValue::ConstSet(idx as u64))
+            .ok_or(GluinoDeserializationError::UnknownConstSetValue(value))
+    }
+}
@@ -0,0 +1,1278 @@
+//! Spec guided transcoding between json documents and `GluinoValue`s.
+//!
+//! | spec | json |
+//! |------|------|
+//! | void | `null` |
+//! | bool | `true`/`false` |
+//! | uint/int up to 64 bits | number, strings of digits are also accepted |
+//! | wider uint/int | string of digits, numbers are also accepted |
+//! | single/double | number, or `"NaN"`, `"Infinity"`, `"-Infinity"` |
+//! | decimal | string such as `"-12.50"`, numbers are also accepted |
+//! | other floating points | hex string of the raw bytes |
+//! | string | string |
+//! | bytes | base64 or hex string, see `JsonOptions` |
+//! | optional | `null` or the value |
+//! | list/tuple | array |
+//! | map | object when the keys are strings, otherwise an array of `[key, value]` pairs |
+//! | record | object by field name, missing optional fields are `None` |
+//! | enum | `{"variant": value}`, or `"variant"` for void variants |
+//! | union | the value of the first variant it can be read as |
+//! | const set | the value of the constant |
+//!
+//! Not everything survives the trip through json exactly, these are the in-exacts:
+//! - `Some` of a void or of an optional that is `None` is written as `null` and read back as `None`
+//! - union values are read as the first variant that accepts them
+//! - floating points that are not single or double have no json number form
+//!
+//! Decimals are held as the little endian two's complement bytes of the unscaled value,
+//! `-12.50` with a scale of 2 is -1250.
+//! Errors carry the json pointer, e.g. `/orders/0/price`, of the offending value.
+
+use std::fmt::{self, Display};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde_json::{Map, Number, Value};
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    spec::{DecimalFmt, Spec, SpecType},
+    spec_parsing::{InterchangeBinaryFloatingPointFormat, SpecKind, StringEncodingFmt},
+};
+
+use super::{F32, F64, GluinoValue, GluinoValueKind};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum JsonBytesEncoding {
+    #[default]
+    Base64,
+    Hex,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct JsonOptions {
+    pub bytes_encoding: JsonBytesEncoding,
+}
+
+pub fn json_to_value(spec: &Spec, json: &Value) -> Result<GluinoValue, JsonTranscodingError> {
+    json_to_value_with_options(spec, json, &JsonOptions::default())
+}
+
+pub fn json_to_value_with_options(
+    spec: &Spec,
+    json: &Value,
+    options: &JsonOptions,
+) -> Result<GluinoValue, JsonTranscodingError> {
+    Transcoder::new(options).read_value(spec, json)
+}
+
+pub fn value_to_json(spec: &Spec, value: &GluinoValue) -> Result<Value, JsonTranscodingError> {
+    value_to_json_with_options(spec, value, &JsonOptions::default())
+}
+
+pub fn value_to_json_with_options(
+    spec: &Spec,
+    value: &GluinoValue,
+    options: &JsonOptions,
+) -> Result<Value, JsonTranscodingError> {
+    Transcoder::new(options).write_json(spec, value)
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, EnumDiscriminants)]
+#[strum_discriminants(name(JsonTranscodingErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum JsonTranscodingError {
+    UnexpectedJson {
+        path: String,
+        spec_kind: SpecKind,
+        found: Value,
+    },
+    InvalidNumber(String, String),
+    IntegerOutOfRange {
+        path: String,
+        spec_kind: SpecKind,
+        n: u8,
+    },
+    DecimalOutOfRange {
+        path: String,
+        precision: u64,
+        scale: u64,
+    },
+    InvalidBytes(String, String),
+    InvalidString(String),
+    IncorrectNumberOfElements {
+        path: String,
+        expected: usize,
+        actual: usize,
+    },
+    UnknownRecordField(String, String),
+    MissingRecordField(String, String),
+    UnknownEnumVariant(String, String),
+    NoMatchingUnionVariant(String),
+    UnknownConstSetValue(String, Value),
+    InvalidVariantId(String, u64),
+    UnknownConstSetIndex(String, u64),
+    ValueSpecMismatch {
+        path: String,
+        spec_kind: SpecKind,
+        value_kind: GluinoValueKind,
+    },
+}
+
+impl Display for JsonTranscodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedJson {
+                path,
+                spec_kind,
+                found,
+            } => write!(f, "{}: {} can not be read as {:?}", path, found, spec_kind),
+            Self::InvalidNumber(path, number) => write!(f, "{}: invalid number {:?}", path, number),
+            Self::IntegerOutOfRange { path, spec_kind, n } => {
+                write!(
+                    f,
+                    "{}: integer out of range of {:?}({})",
+                    path, spec_kind, n
+                )
+            }
+            Self::DecimalOutOfRange {
+                path,
+                precision,
+                scale,
+            } => write!(
+                f,
+                "{}: decimal does not fit precision {} and scale {}",
+                path, precision, scale
+            ),
+            Self::InvalidBytes(path, bytes) => write!(f, "{}: invalid bytes {:?}", path, bytes),
+            Self::InvalidString(path) => {
+                write!(f, "{}: string is not valid for its encoding", path)
+            }
+            Self::IncorrectNumberOfElements {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: expected {} elements, found {}",
+                path, expected, actual
+            ),
+            Self::UnknownRecordField(path, field) => {
+                write!(f, "{}: unknown record field {:?}", path, field)
+            }
+            Self::MissingRecordField(path, field) => {
+                write!(f, "{}: missing record field {:?}", path, field)
+            }
+            Self::UnknownEnumVariant(path, variant) => {
+                write!(f, "{}: unknown enum variant {:?}", path, variant)
+            }
+            Self::NoMatchingUnionVariant(path) => {
+                write!(f, "{}: no union variant accepts the value", path)
+            }
+            Self::UnknownConstSetValue(path, value) => {
+                write!(f, "{}: {} is not in the const set", path, value)
+            }
+            Self::InvalidVariantId(path, id) => write!(f, "{}: invalid variant id {}", path, id),
+            Self::UnknownConstSetIndex(path, index) => {
+                write!(f, "{}: unknown const set index {}", path, index)
+            }
+            Self::ValueSpecMismatch {
+                path,
+                spec_kind,
+                value_kind,
+            } => write!(
+                f,
+                "{}: {:?} value for {:?} spec",
+                path, value_kind, spec_kind
+            ),
+        }
+    }
+}
+
+impl std::error::Error for JsonTranscodingError {}
+
+struct Transcoder<'o> {
+    options: &'o JsonOptions,
+    path: String,
+}
+
+impl<'o> Transcoder<'o> {
+    fn new(options: &'o JsonOptions) -> Self {
+        Transcoder {
+            options,
+            path: String::new(),
+        }
+    }
+
+    // extends the json pointer while descending and truncates it again on the way back out
+    fn at<T>(
+        &mut self,
+        segment: impl Display,
+        f: impl FnOnce(&mut Self) -> Result<T, JsonTranscodingError>,
+    ) -> Result<T, JsonTranscodingError> {
+        let len = self.path.len();
+        self.path.push('/');
+        self.path
+            .push_str(&segment.to_string().replace('~', "~0").replace('/', "~1"));
+        let result = f(self);
+        self.path.truncate(len);
+        result
+    }
+
+    fn unexpected(&self, spec: &Spec, json: &Value) -> JsonTranscodingError {
+        JsonTranscodingError::UnexpectedJson {
+            path: self.path.clone(),
+            spec_kind: spec.kind(),
+            found: json.clone(),
+        }
+    }
+
+    fn mismatch(&self, spec: &Spec, value: &GluinoValue) -> JsonTranscodingError {
+        JsonTranscodingError::ValueSpecMismatch {
+            path: self.path.clone(),
+            spec_kind: spec.kind(),
+            value_kind: value.into(),
+        }
+    }
+
+    fn read_value(
+        &mut self,
+        spec: &Spec,
+        json: &Value,
+    ) -> Result<GluinoValue, JsonTranscodingError> {
+        let spec = &spec.resolve();
+        Ok(match (spec.spec_type(), json) {
+            (SpecType::Void, Value::Null) => GluinoValue::Void,
+            (SpecType::Bool, Value::Bool(b)) => GluinoValue::Bool(*b),
+            (SpecType::Uint(n), Value::Number(_) | Value::String(_)) => {
+                let (negative, magnitude) = self.integer(spec, json)?;
+                self.uint_value(*n, negative, magnitude)?
+            }
+            (SpecType::Int(n), Value::Number(_) | Value::String(_)) => {
+                let (negative, magnitude) = self.integer(spec, json)?;
+                self.int_value(*n, negative, magnitude)?
+            }
+            (
+                SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Single),
+                Value::Number(_) | Value::String(_),
+            ) => GluinoValue::Float(F32(self.float(spec, json)? as f32)),
+            (
+                SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Double),
+                Value::Number(_) | Value::String(_),
+            ) => GluinoValue::Double(F64(self.float(spec, json)?)),
+            (SpecType::BinaryFloatingPoint(fmt), Value::String(s)) => {
+                GluinoValue::BinaryFloatingPoint(fmt.clone(), self.hex(s)?)
+            }
+            (SpecType::DecimalFloatingPoint(fmt), Value::String(s)) => {
+                GluinoValue::DecimalFloatingPoint(fmt.clone(), self.hex(s)?)
+            }
+            (SpecType::Decimal(fmt), Value::Number(_) | Value::String(_)) => {
+                GluinoValue::Decimal(self.decimal(fmt, json)?)
+            }
+            (SpecType::String(_, fmt), Value::String(s)) => match fmt {
+                StringEncodingFmt::Utf8 => GluinoValue::String(s.clone()),
+                StringEncodingFmt::Utf16 => GluinoValue::NonUtf8String(
+                    s.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+                ),
+                StringEncodingFmt::Ascii if s.is_ascii() => {
+                    GluinoValue::NonUtf8String(s.as_bytes().to_vec())
+                }
+                StringEncodingFmt::Ascii => {
+                    return Err(JsonTranscodingError::InvalidString(self.path.clone()));
+                }
+            },
+            (SpecType::Bytes(_), Value::String(s)) => GluinoValue::Bytes(self.bytes(s)?),
+            (SpecType::Optional(_), Value::Null) => GluinoValue::Optional(None),
+            (SpecType::Optional(inner), json) => {
+                GluinoValue::Optional(Some(Box::new(self.read_value(inner, json)?)))
+            }
+            (SpecType::List { value_spec, .. }, Value::Array(elements)) => GluinoValue::List(
+                elements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, element)| self.at(i, |t| t.read_value(value_spec, element)))
+                    .collect::<Result<_, _>>()?,
+            ),
+            (
+                SpecType::Map {
+                    key_spec,
+                    value_spec,
+                    ..
+                },
+                Value::Object(entries),
+            ) => GluinoValue::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| {
+                        self.at(key, |t| {
+                            Ok((
+                                t.read_value(key_spec, &Value::String(key.clone()))?,
+                                t.read_value(value_spec, value)?,
+                            ))
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            (
+                SpecType::Map {
+                    key_spec,
+                    value_spec,
+                    ..
+                },
+                Value::Array(pairs),
+            ) => GluinoValue::Map(
+                pairs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, pair)| {
+                        self.at(i, |t| match pair.as_array().map(Vec::as_slice) {
+                            Some([key, value]) => Ok((
+                                t.at(0, |t| t.read_value(key_spec, key))?,
+                                t.at(1, |t| t.read_value(value_spec, value))?,
+                            )),
+                            _ => Err(t.unexpected(spec, pair)),
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            (
+                SpecType::Record {
+                    fields,
+                    field_to_spec,
+                    field_to_index,
+                },
+                Value::Object(entries),
+            ) => {
+                let mut values = vec![None; fields.len()];
+                for (field, json) in entries {
+                    let index = *field_to_index.get(field).ok_or_else(|| {
+                        JsonTranscodingError::UnknownRecordField(self.path.clone(), field.clone())
+                    })?;
+                    values[index] = Some(self.at(field, |t| {
+                        t.read_value(field_to_spec.get(field).unwrap(), json)
+                    })?);
+                }
+                GluinoValue::Record(
+                    fields
+                        .iter()
+                        .zip(values)
+                        .map(|(field, value)| match value {
+                            Some(value) => Ok(value),
+                            None => match field_to_spec.get(field).unwrap().resolve().spec_type() {
+                                SpecType::Optional(_) => Ok(GluinoValue::Optional(None)),
+                                _ => Err(JsonTranscodingError::MissingRecordField(
+                                    self.path.clone(),
+                                    field.clone(),
+                                )),
+                            },
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (SpecType::Tuple(field_specs), Value::Array(elements)) => {
+                if field_specs.len() != elements.len() {
+                    return Err(JsonTranscodingError::IncorrectNumberOfElements {
+                        path: self.path.clone(),
+                        expected: field_specs.len(),
+                        actual: elements.len(),
+                    });
+                }
+                GluinoValue::Tuple(
+                    field_specs
+                        .iter()
+                        .zip(elements)
+                        .enumerate()
+                        .map(|(i, (field_spec, element))| {
+                            self.at(i, |t| t.read_value(field_spec, element))
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (
+                SpecType::Enum {
+                    variants,
+                    variant_to_spec,
+                },
+                Value::String(_) | Value::Object(_),
+            ) => {
+                let (variant, json) = match json {
+                    Value::String(variant) => (variant, &Value::Null),
+                    Value::Object(entries) if entries.len() == 1 => entries.iter().next().unwrap(),
+                    _ => return Err(self.unexpected(spec, json)),
+                };
+                let variant_id = variants.iter().position(|v| v == variant).ok_or_else(|| {
+                    JsonTranscodingError::UnknownEnumVariant(self.path.clone(), variant.clone())
+                })?;
+                GluinoValue::Enum(
+                    variant_id as u64,
+                    Box::new(self.at(variant, |t| {
+                        t.read_value(variant_to_spec.get(variant).unwrap(), json)
+                    })?),
+                )
+            }
+            (SpecType::Union(variant_specs), json) => variant_specs
+                .iter()
+                .enumerate()
+                .find_map(|(variant_id, variant_spec)| {
+                    self.read_value(variant_spec, json)
+                        .ok()
+                        .map(|value| GluinoValue::Union(variant_id as u64, Box::new(value)))
+                })
+                .ok_or_else(|| JsonTranscodingError::NoMatchingUnionVariant(self.path.clone()))?,
+            (SpecType::ConstSet(const_spec, const_values), json) => {
+                let value = self.read_value(const_spec, json)?;
+                let index = const_values
+                    .iter()
+                    .position(|const_value| const_value == &value)
+                    .ok_or_else(|| {
+                        JsonTranscodingError::UnknownConstSetValue(self.path.clone(), json.clone())
+                    })?;
+                GluinoValue::ConstSet(index as u64)
+            }
+            (_, json) => return Err(self.unexpected(spec, json)),
+        })
+    }
+
+    fn write_json(
+        &mut self,
+        spec: &Spec,
+        value: &GluinoValue,
+    ) -> Result<Value, JsonTranscodingError> {
+        let spec = &spec.resolve();
+        Ok(match (spec.spec_type(), value) {
+            (SpecType::Void, GluinoValue::Void) => Value::Null,
+            (SpecType::Bool, GluinoValue::Bool(b)) => Value::Bool(*b),
+            (SpecType::Int(0), GluinoValue::Int8(n)) => Value::from(*n),
+            (SpecType::Int(1), GluinoValue::Int16(n)) => Value::from(*n),
+            (SpecType::Int(2), GluinoValue::Int32(n)) => Value::from(*n),
+            (SpecType::Int(3), GluinoValue::Int64(n)) => Value::from(*n),
+            (SpecType::Int(4), GluinoValue::Int128(n)) => Value::String(n.to_string()),
+            (SpecType::Int(n), GluinoValue::BigInt(m, bytes)) if n == m => {
+                let (negative, magnitude) = from_twos_complement(bytes, true);
+                Value::String(signed_digits(negative, magnitude))
+            }
+            (SpecType::Uint(0), GluinoValue::Uint8(n)) => Value::from(*n),
+            (SpecType::Uint(1), GluinoValue::Uint16(n)) => Value::from(*n),
+            (SpecType::Uint(2), GluinoValue::Uint32(n)) => Value::from(*n),
+            (SpecType::Uint(3), GluinoValue::Uint64(n)) => Value::from(*n),
+            (SpecType::Uint(4), GluinoValue::Uint128(n)) => Value::String(n.to_string()),
+            (SpecType::Uint(n), GluinoValue::BigUint(m, bytes)) if n == m => {
+                Value::String(magnitude_to_digits(bytes.clone()))
+            }
+            // shortest representation of the f32 rather than its exact f64 value
+            (
+                SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Single),
+                GluinoValue::Float(F32(v)),
+            ) => float_json(v.to_string().parse().unwrap()),
+            (
+                SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Double),
+                GluinoValue::Double(F64(v)),
+            ) => float_json(*v),
+            (SpecType::BinaryFloatingPoint(_), GluinoValue::BinaryFloatingPoint(_, bytes))
+            | (SpecType::DecimalFloatingPoint(_), GluinoValue::DecimalFloatingPoint(_, bytes)) => {
+                Value::String(hex::encode(bytes))
+            }
+            (SpecType::Decimal(fmt), GluinoValue::Decimal(bytes)) => {
+                let (negative, magnitude) = from_twos_complement(bytes, true);
+                let digits = format!(
+                    "{:0>width$}",
+                    magnitude_to_digits(magnitude),
+                    width = fmt.scale as usize + 1
+                );
+                let (whole, fraction) = digits.split_at(digits.len() - fmt.scale as usize);
+                let sign = if negative { "-" } else { "" };
+                Value::String(match fraction {
+                    "" => format!("{}{}", sign, whole),
+                    fraction => format!("{}{}.{}", sign, whole, fraction),
+                })
+            }
+            (SpecType::String(..), GluinoValue::String(s)) => Value::String(s.clone()),
+            (SpecType::String(_, fmt), GluinoValue::NonUtf8String(bytes)) => {
+                let s = match fmt {
+                    StringEncodingFmt::Utf16 if bytes.len() % 2 == 0 => char::decode_utf16(
+                        bytes
+                            .chunks_exact(2)
+                            .map(|unit| u16::from_le_bytes([unit[0], unit[1]])),
+                    )
+                    .collect::<Result<String, _>>()
+                    .ok(),
+                    StringEncodingFmt::Ascii if bytes.is_ascii() => {
+                        String::from_utf8(bytes.clone()).ok()
+                    }
+                    _ => None,
+                };
+                Value::String(
+                    s.ok_or_else(|| JsonTranscodingError::InvalidString(self.path.clone()))?,
+                )
+            }
+            (SpecType::Bytes(_), GluinoValue::Bytes(bytes)) => {
+                Value::String(match self.options.bytes_encoding {
+                    JsonBytesEncoding::Base64 => BASE64.encode(bytes),
+                    JsonBytesEncoding::Hex => hex::encode(bytes),
+                })
+            }
+            (SpecType::Optional(_), GluinoValue::Optional(None)) => Value::Null,
+            (SpecType::Optional(inner), GluinoValue::Optional(Some(value))) => {
+                self.write_json(inner, value)?
+            }
+            (SpecType::List { value_spec, .. }, GluinoValue::List(elements)) => Value::Array(
+                elements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, element)| self.at(i, |t| t.write_json(value_spec, element)))
+                    .collect::<Result<_, _>>()?,
+            ),
+            (
+                SpecType::Map {
+                    key_spec,
+                    value_spec,
+                    ..
+                },
+                GluinoValue::Map(entries),
+            ) => {
+                let entries = entries
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (key, value))| {
+                        self.at(i, |t| {
+                            Ok((
+                                t.at(0, |t| t.write_json(key_spec, key))?,
+                                t.at(1, |t| t.write_json(value_spec, value))?,
+                            ))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                match key_spec.resolve().spec_type() {
+                    SpecType::String(..) => Value::Object(
+                        entries
+                            .into_iter()
+                            .map(|(key, value)| match key {
+                                Value::String(key) => (key, value),
+                                _ => unreachable!("String specs are written as json strings"),
+                            })
+                            .collect::<Map<_, _>>(),
+                    ),
+                    _ => Value::Array(
+                        entries
+                            .into_iter()
+                            .map(|(key, value)| Value::Array(vec![key, value]))
+                            .collect(),
+                    ),
+                }
+            }
+            (
+                SpecType::Record {
+                    fields,
+                    field_to_spec,
+                    ..
+                },
+                GluinoValue::Record(values),
+            ) => {
+                if fields.len() != values.len() {
+                    return Err(JsonTranscodingError::IncorrectNumberOfElements {
+                        path: self.path.clone(),
+                        expected: fields.len(),
+                        actual: values.len(),
+                    });
+                }
+                Value::Object(
+                    fields
+                        .iter()
+                        .zip(values)
+                        .map(|(field, value)| {
+                            self.at(field, |t| {
+                                Ok((
+                                    field.clone(),
+                                    t.write_json(field_to_spec.get(field).unwrap(), value)?,
+                                ))
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (SpecType::Tuple(field_specs), GluinoValue::Tuple(values)) => {
+                if field_specs.len() != values.len() {
+                    return Err(JsonTranscodingError::IncorrectNumberOfElements {
+                        path: self.path.clone(),
+                        expected: field_specs.len(),
+                        actual: values.len(),
+                    });
+                }
+                Value::Array(
+                    field_specs
+                        .iter()
+                        .zip(values)
+                        .enumerate()
+                        .map(|(i, (field_spec, value))| {
+                            self.at(i, |t| t.write_json(field_spec, value))
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (
+                SpecType::Enum {
+                    variants,
+                    variant_to_spec,
+                },
+                GluinoValue::Enum(variant_id, value),
+            ) => {
+                let variant = variants.get(*variant_id as usize).ok_or_else(|| {
+                    JsonTranscodingError::InvalidVariantId(self.path.clone(), *variant_id)
+                })?;
+                let variant_spec = variant_to_spec.get(variant).unwrap();
+                match variant_spec.resolve().spec_type() {
+                    SpecType::Void => Value::String(variant.clone()),
+                    _ => {
+                        let json = self.at(variant, |t| t.write_json(variant_spec, value))?;
+                        Value::Object(Map::from_iter([(variant.clone(), json)]))
+                    }
+                }
+            }
+            (SpecType::Union(variant_specs), GluinoValue::Union(variant_id, value)) => {
+                let variant_spec = variant_specs.get(*variant_id as usize).ok_or_else(|| {
+                    JsonTranscodingError::InvalidVariantId(self.path.clone(), *variant_id)
+                })?;
+                self.write_json(variant_spec, value)?
+            }
+            (SpecType::ConstSet(const_spec, const_values), GluinoValue::ConstSet(index)) => {
+                let value = const_values.get(*index as usize).ok_or_else(|| {
+                    JsonTranscodingError::UnknownConstSetIndex(self.path.clone(), *index)
+                })?;
+                self.write_json(const_spec, value)?
+            }
+            (_, value) => return Err(self.mismatch(spec, value)),
+        })
+    }
+
+    // sign and little endian magnitude of a json integer or string of digits
+    fn integer(&self, spec: &Spec, json: &Value) -> Result<(bool, Vec<u8>), JsonTranscodingError> {
+        let digits = match json {
+            Value::Number(n) if n.is_i64() || n.is_u64() => n.to_string(),
+            Value::String(s) => s.clone(),
+            _ => return Err(self.unexpected(spec, json)),
+        };
+        let (negative, unsigned) = match digits.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, digits.as_str()),
+        };
+        if unsigned.is_empty() || !unsigned.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(JsonTranscodingError::InvalidNumber(
+                self.path.clone(),
+                digits,
+            ));
+        }
+        Ok((negative, digits_to_magnitude(unsigned)))
+    }
+
+    fn int_value(
+        &self,
+        n: u8,
+        negative: bool,
+        magnitude: Vec<u8>,
+    ) -> Result<GluinoValue, JsonTranscodingError> {
+        let out_of_range = || JsonTranscodingError::IntegerOutOfRange {
+            path: self.path.clone(),
+            spec_kind: SpecKind::Int,
+            n,
+        };
+        let width = 1usize.checked_shl(n as u32).ok_or_else(out_of_range)?;
+        let bytes =
+            to_twos_complement(negative, magnitude, width, true).ok_or_else(out_of_range)?;
+        Ok(match n {
+            0 => GluinoValue::Int8(i8::from_le_bytes(bytes.try_into().unwrap())),
+            1 => GluinoValue::Int16(i16::from_le_bytes(bytes.try_into().unwrap())),
+            2 => GluinoValue::Int32(i32::from_le_bytes(bytes.try_into().unwrap())),
+            3 => GluinoValue::Int64(i64::from_le_bytes(bytes.try_into().unwrap())),
+            4 => GluinoValue::Int128(i128::from_le_bytes(bytes.try_into().unwrap())),
+            _ => GluinoValue::BigInt(n, bytes),
+        })
+    }
+
+    fn uint_value(
+        &self,
+        n: u8,
+        negative: bool,
+        magnitude: Vec<u8>,
+    ) -> Result<GluinoValue, JsonTranscodingError> {
+        let out_of_range = || JsonTranscodingError::IntegerOutOfRange {
+            path: self.path.clone(),
+            spec_kind: SpecKind::Uint,
+            n,
+        };
+        let width = 1usize.checked_shl(n as u32).ok_or_else(out_of_range)?;
+        let bytes =
+            to_twos_complement(negative, magnitude, width, false).ok_or_else(out_of_range)?;
+        Ok(match n {
+            0 => GluinoValue::Uint8(bytes[0]),
+            1 => GluinoValue::Uint16(u16::from_le_bytes(bytes.try_into().unwrap())),
+            2 => GluinoValue::Uint32(u32::from_le_bytes(bytes.try_into().unwrap())),
+            3 => GluinoValue::Uint64(u64::from_le_bytes(bytes.try_into().unwrap())),
+            4 => GluinoValue::Uint128(u128::from_le_bytes(bytes.try_into().unwrap())),
+            _ => GluinoValue::BigUint(n, bytes),
+        })
+    }
+
+    fn float(&self, spec: &Spec, json: &Value) -> Result<f64, JsonTranscodingError> {
+        match json {
+            Value::Number(n) => Ok(n.as_f64().unwrap()),
+            Value::String(s) => match s.as_str() {
+                "NaN" => Ok(f64::NAN),
+                "Infinity" => Ok(f64::INFINITY),
+                "-Infinity" => Ok(f64::NEG_INFINITY),
+                _ => Err(JsonTranscodingError::InvalidNumber(
+                    self.path.clone(),
+                    s.clone(),
+                )),
+            },
+            _ => Err(self.unexpected(spec, json)),
+        }
+    }
+
+    fn decimal(&self, fmt: &DecimalFmt, json: &Value) -> Result<Vec<u8>, JsonTranscodingError> {
+        let text = match json {
+            Value::String(s) => s.clone(),
+            json => json.to_string(),
+        };
+        let invalid = || JsonTranscodingError::InvalidNumber(self.path.clone(), text.clone());
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, text.as_str()),
+        };
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if whole.is_empty()
+            || !whole.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        // trailing zeros past the scale do not change the value
+        let fraction = fraction.trim_end_matches('0');
+        let whole = whole.trim_start_matches('0');
+        if fraction.len() as u64 > fmt.scale || whole.len() as u64 + fmt.scale > fmt.precision {
+            return Err(JsonTranscodingError::DecimalOutOfRange {
+                path: self.path.clone(),
+                precision: fmt.precision,
+                scale: fmt.scale,
+            });
+        }
+        let unscaled = format!(
+            "{}{}{}",
+            whole,
+            fraction,
+            "0".repeat(fmt.scale as usize - fraction.len())
+        );
+        let magnitude = digits_to_magnitude(&unscaled);
+        // one more byte than the magnitude always has room for the sign
+        let width = magnitude.len() + 1;
+        let mut bytes = to_twos_complement(negative, magnitude, width, true).ok_or_else(invalid)?;
+        while let [.., second_last, last] = bytes[..]
+            && ((last == 0x00 && second_last & 0x80 == 0)
+                || (last == 0xFF && second_last & 0x80 != 0))
+        {
+            bytes.pop();
+        }
+        Ok(bytes)
+    }
+
+    fn hex(&self, s: &str) -> Result<Vec<u8>, JsonTranscodingError> {
+        hex::decode(s)
+            .map_err(|_| JsonTranscodingError::InvalidBytes(self.path.clone(), s.to_string()))
+    }
+
+    fn bytes(&self, s: &str) -> Result<Vec<u8>, JsonTranscodingError> {
+        match self.options.bytes_encoding {
+            JsonBytesEncoding::Base64 => BASE64
+                .decode(s)
+                .map_err(|_| JsonTranscodingError::InvalidBytes(self.path.clone(), s.to_string())),
+            JsonBytesEncoding::Hex => self.hex(s),
+        }
+    }
+}
+
+fn float_json(v: f64) -> Value {
+    match Number::from_f64(v) {
+        Some(n) => Value::Number(n),
+        None if v.is_nan() => Value::String("NaN".into()),
+        None if v > 0.0 => Value::String("Infinity".into()),
+        None => Value::String("-Infinity".into()),
+    }
+}
+
+// little endian magnitude of a string of decimal digits
+fn digits_to_magnitude(digits: &str) -> Vec<u8> {
+    let mut magnitude: Vec<u8> = Vec::new();
+    for digit in digits.bytes() {
+        let mut carry = (digit - b'0') as u32;
+        for byte in magnitude.iter_mut() {
+            let v = *byte as u32 * 10 + carry;
+            *byte = v as u8;
+            carry = v >> 8;
+        }
+        if carry > 0 {
+            magnitude.push(carry as u8);
+        }
+    }
+    magnitude
+}
+
+pub(crate) fn magnitude_to_digits(mut magnitude: Vec<u8>) -> String {
+    let mut digits = Vec::new();
+    while magnitude.iter().any(|b| *b != 0) {
+        let mut remainder = 0u32;
+        for byte in magnitude.iter_mut().rev() {
+            let v = (remainder << 8) | *byte as u32;
+            *byte = (v / 10) as u8;
+            remainder = v % 10;
+        }
+        digits.push(b'0' + remainder as u8);
+    }
+    if digits.is_empty() {
+        digits.push(b'0');
+    }
+    digits.reverse();
+    String::from_utf8(digits).unwrap()
+}
+
+fn signed_digits(negative: bool, magnitude: Vec<u8>) -> String {
+    let digits = magnitude_to_digits(magnitude);
+    if negative {
+        format!("-{}", digits)
+    } else {
+        digits
+    }
+}
+
+fn negate(bytes: &mut [u8]) {
+    let mut carry = true;
+    for byte in bytes.iter_mut() {
+        let (v, overflow) = (!*byte).overflowing_add(carry as u8);
+        *byte = v;
+        carry = overflow;
+    }
+}
+
+// width bytes of little endian two's complement, None when the value does not fit
+fn to_twos_complement(
+    negative: bool,
+    mut magnitude: Vec<u8>,
+    width: usize,
+    signed: bool,
+) -> Option<Vec<u8>> {
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+    if magnitude.len() > width || (negative && !signed && !magnitude.is_empty()) {
+        return None;
+    }
+    let is_zero = magnitude.is_empty();
+    magnitude.resize(width, 0);
+    let top_bit_set = magnitude[width - 1] & 0x80 != 0;
+    if negative && !is_zero {
+        // only the most negative value keeps its top bit set through the negation
+        let most_negative = top_bit_set
+            && magnitude[..width - 1].iter().all(|b| *b == 0)
+            && magnitude[width - 1] == 0x80;
+        if top_bit_set && !most_negative {
+            return None;
+        }
+        negate(&mut magnitude);
+    } else if signed && top_bit_set {
+        return None;
+    }
+    Some(magnitude)
+}
+
+pub(crate) fn from_twos_complement(bytes: &[u8], signed: bool) -> (bool, Vec<u8>) {
+    let mut magnitude = bytes.to_vec();
+    let negative = signed && bytes.last().is_some_and(|b| b & 0x80 != 0);
+    if negative {
+        negate(&mut magnitude);
+    }
+    (negative, magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use strum::IntoEnumIterator;
+
+    use crate::{
+        spec_parsing::{ParsedSpec, Size},
+        test_utils::get_all_kinds_spec,
+    };
+
+    use super::super::tests::sample_value;
+    use super::*;
+
+    fn orders_spec() -> Spec {
+        Spec::compile(
+            ParsedSpec::from_text(
+                r#"
+                name Orders = record {
+                    id: uint(3),
+                    customer: optional<string>,
+                    orders: list<record {
+                        sku: string<ascii>,
+                        price: decimal(10, 2),
+                        quantity: int(5),
+                    }>,
+                    status: enum { open: void, closed: record { reason: string } },
+                    tags: map<string, bool>,
+                    scores: map<uint(0), float(double)>,
+                    digest: bytes<4>,
+                    pair: tuple { bool, uint(4) },
+                    either: union { uint(0), string },
+                    level: const_set<string> { 0x036c6f77, 0x0468696768 },
+                }
+                "#,
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_json_transcoding() {
+        let spec = orders_spec();
+        let json = json!({
+            "id": 7,
+            "customer": null,
+            "orders": [
+                {"sku": "A-1", "price": "-12.5", "quantity": "-1000000000000000000000000000000000000000"},
+                {"sku": "B-2", "price": 0.07, "quantity": 3},
+            ],
+            "status": {"closed": {"reason": "paid"}},
+            "tags": {"gift": true},
+            "scores": [[1, 0.5], [2, "NaN"]],
+            "digest": "3q2+7w==",
+            "pair": [false, "340282366920938463463374607431768211455"],
+            "either": "left",
+            "level": "high",
+        });
+        let value = json_to_value(&spec, &json).unwrap();
+        let big_negative = {
+            let mut bytes = digits_to_magnitude("1000000000000000000000000000000000000000");
+            bytes.resize(32, 0);
+            negate(&mut bytes);
+            bytes
+        };
+        assert_eq!(
+            GluinoValue::Record(vec![
+                GluinoValue::Uint64(7),
+                GluinoValue::Optional(None),
+                GluinoValue::List(vec![
+                    GluinoValue::Record(vec![
+                        GluinoValue::NonUtf8String(b"A-1".to_vec()),
+                        GluinoValue::Decimal((-1250i16).to_le_bytes().to_vec()),
+                        GluinoValue::BigInt(5, big_negative),
+                    ]),
+                    GluinoValue::Record(vec![
+                        GluinoValue::NonUtf8String(b"B-2".to_vec()),
+                        GluinoValue::Decimal(vec![7]),
+                        GluinoValue::BigInt(5, {
+                            let mut bytes = vec![0; 32];
+                            bytes[0] = 3;
+                            bytes
+                        }),
+                    ]),
+                ]),
+                GluinoValue::Enum(
+                    1,
+                    Box::new(GluinoValue::Record(vec![GluinoValue::String(
+                        "paid".into()
+                    )]))
+                ),
+                GluinoValue::Map(vec![(
+                    GluinoValue::String("gift".into()),
+                    GluinoValue::Bool(true)
+                )]),
+                GluinoValue::Map(vec![
+                    (GluinoValue::Uint8(1), GluinoValue::Double(F64(0.5))),
+                    (GluinoValue::Uint8(2), GluinoValue::Double(F64(f64::NAN))),
+                ]),
+                GluinoValue::Bytes(vec![0xde, 0xad, 0xbe, 0xef]),
+                GluinoValue::Tuple(vec![
+                    GluinoValue::Bool(false),
+                    GluinoValue::Uint128(u128::MAX)
+                ]),
+                GluinoValue::Union(1, Box::new(GluinoValue::String("left".into()))),
+                GluinoValue::ConstSet(1),
+            ]),
+            value
+        );
+        assert_eq!(
+            json!({
+                "id": 7,
+                "customer": null,
+                "orders": [
+                    {"sku": "A-1", "price": "-12.50", "quantity": "-1000000000000000000000000000000000000000"},
+                    {"sku": "B-2", "price": "0.07", "quantity": "3"},
+                ],
+                "status": {"closed": {"reason": "paid"}},
+                "tags": {"gift": true},
+                "scores": [[1, 0.5], [2, "NaN"]],
+                "digest": "3q2+7w==",
+                "pair": [false, "340282366920938463463374607431768211455"],
+                "either": "left",
+                "level": "high",
+            }),
+            value_to_json(&spec, &value).unwrap()
+        );
+        let hex = JsonOptions {
+            bytes_encoding: JsonBytesEncoding::Hex,
+        };
+        assert_eq!(
+            GluinoValue::Bytes(vec![0xde, 0xad, 0xbe, 0xef]),
+            json_to_value_with_options(
+                &Spec::compile(ParsedSpec::Bytes(Size::Variable)).unwrap(),
+                &json!("deadbeef"),
+                &hex
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_json_transcoding_all_kinds() {
+        for parsed_spec in get_all_kinds_spec() {
+            if let ParsedSpec::Uint(n) | ParsedSpec::Int(n) = parsed_spec {
+                // 2^n bytes
+                if n > 10 {
+                    continue;
+                }
+            }
+            // sample utf16 strings are utf8 bytes, covered in test_string_encodings instead
+            if let ParsedSpec::String(_, StringEncodingFmt::Utf16) = parsed_spec {
+                continue;
+            }
+            let spec = Spec::compile(parsed_spec).unwrap();
+            let value = sample_value(&spec, spec.named_schema(), 0);
+            let json = value_to_json(&spec, &value)
+                .unwrap_or_else(|e| panic!("Unable to write {:?} for {:?}: {}", value, spec, e));
+            // union variants and nested empty optionals may come back different but stable
+            let value = json_to_value(&spec, &json)
+                .unwrap_or_else(|e| panic!("Unable to read {} for {:?}: {}", json, spec, e));
+            assert_eq!(json, value_to_json(&spec, &value).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_string_encodings() {
+        let utf16 =
+            Spec::compile(ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf16)).unwrap();
+        let value = json_to_value(&utf16, &json!("gl\u{1F600}")).unwrap();
+        assert_eq!(
+            GluinoValue::NonUtf8String(vec![0x67, 0x00, 0x6C, 0x00, 0x3D, 0xD8, 0x00, 0xDE]),
+            value
+        );
+        assert_eq!(json!("gl\u{1F600}"), value_to_json(&utf16, &value).unwrap());
+        for bytes in [vec![0x67], vec![0x3D, 0xD8]] {
+            assert_eq!(
+                JsonTranscodingErrorKind::InvalidString,
+                (&value_to_json(&utf16, &GluinoValue::NonUtf8String(bytes)).unwrap_err()).into()
+            );
+        }
+        let ascii =
+            Spec::compile(ParsedSpec::String(Size::Variable, StringEncodingFmt::Ascii)).unwrap();
+        assert_eq!(
+            JsonTranscodingErrorKind::InvalidString,
+            (&value_to_json(&ascii, &GluinoValue::NonUtf8String(vec![0xFF])).unwrap_err()).into()
+        );
+    }
+
+    #[test]
+    fn test_integer_and_decimal_edges() {
+        let int = |n: u8| Spec::compile(ParsedSpec::Int(n)).unwrap();
+        let uint = |n: u8| Spec::compile(ParsedSpec::Uint(n)).unwrap();
+        for (spec, json, value) in [
+            (int(0), json!(-128), GluinoValue::Int8(i8::MIN)),
+            (
+                int(3),
+                json!("-9223372036854775808"),
+                GluinoValue::Int64(i64::MIN),
+            ),
+            (
+                int(4),
+                json!("-170141183460469231731687303715884105728"),
+                GluinoValue::Int128(i128::MIN),
+            ),
+            (uint(3), json!(u64::MAX), GluinoValue::Uint64(u64::MAX)),
+            (int(5), json!("-1"), GluinoValue::BigInt(5, vec![0xFF; 32])),
+            (uint(5), json!("0"), GluinoValue::BigUint(5, vec![0; 32])),
+        ] {
+            assert_eq!(value, json_to_value(&spec, &json).unwrap());
+        }
+        for (spec, json) in [
+            (int(0), json!(128)),
+            (int(0), json!(-129)),
+            (uint(0), json!(-1)),
+            (uint(4), json!("340282366920938463463374607431768211456")),
+            (int(255), json!(1)),
+        ] {
+            assert_eq!(
+                JsonTranscodingErrorKind::IntegerOutOfRange,
+                (&json_to_value(&spec, &json).unwrap_err()).into()
+            );
+        }
+        let decimal = Spec::compile(ParsedSpec::Decimal {
+            precision: 5,
+            scale: 2,
+        })
+        .unwrap();
+        for (text, written) in [
+            ("0", "0.00"),
+            ("-0.5", "-0.50"),
+            ("127.99", "127.99"),
+            ("-128", "-128.00"),
+            ("999.990", "999.99"),
+            ("001.10", "1.10"),
+        ] {
+            let value = json_to_value(&decimal, &json!(text)).unwrap();
+            assert_eq!(json!(written), value_to_json(&decimal, &value).unwrap());
+        }
+        assert_eq!(
+            GluinoValue::Decimal(vec![0x00, 0x80, 0x00]),
+            json_to_value(&decimal, &json!("327.68")).unwrap()
+        );
+        assert_eq!(
+            GluinoValue::Decimal(vec![0x00, 0x80]),
+            json_to_value(&decimal, &json!("-327.68")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_json_transcoding_error_kinds() {
+        let spec = orders_spec();
+        let base = || value_to_json(&spec, &json_to_value(&spec, &orders_json()).unwrap()).unwrap();
+        fn orders_json() -> Value {
+            json!({
+                "id": 1,
+                "orders": [{"sku": "A", "price": "1", "quantity": 1}],
+                "status": "open",
+                "tags": {},
+                "scores": [],
+                "digest": "AAAAAA==",
+                "pair": [true, 1],
+                "either": 1,
+                "level": "low",
+            })
+        }
+        let with = |pointer: &str, json: Value| {
+            let mut document = base();
+            let (parent, field) = pointer.rsplit_once('/').unwrap();
+            document
+                .pointer_mut(parent)
+                .and_then(Value::as_object_mut)
+                .unwrap()
+                .insert(field.to_string(), json);
+            document
+        };
+        let without = |field: &str| {
+            let mut document = base();
+            document.as_object_mut().unwrap().remove(field);
+            document
+        };
+        for kind in JsonTranscodingErrorKind::iter() {
+            let read_errors = match kind {
+                JsonTranscodingErrorKind::UnexpectedJson => vec![
+                    (with("/id", json!(true)), "/id"),
+                    (with("/id", json!(1.5)), "/id"),
+                    (with("/orders", json!({})), "/orders"),
+                    (with("/scores", json!([[1]])), "/scores/0"),
+                    (
+                        with("/status", json!({"open": null, "closed": {}})),
+                        "/status",
+                    ),
+                ],
+                JsonTranscodingErrorKind::InvalidNumber => vec![
+                    (with("/id", json!("1e3")), "/id"),
+                    (with("/orders/0/price", json!("1.2.3")), "/orders/0/price"),
+                    (with("/scores", json!([[1, "inf"]])), "/scores/0/1"),
+                ],
+                JsonTranscodingErrorKind::IntegerOutOfRange => {
+                    vec![(with("/scores", json!([[256, 1.0]])), "/scores/0/0")]
+                }
+                JsonTranscodingErrorKind::DecimalOutOfRange => vec![
+                    (with("/orders/0/price", json!("0.001")), "/orders/0/price"),
+                    (
+                        with("/orders/0/price", json!("123456789")),
+                        "/orders/0/price",
+                    ),
+                ],
+                JsonTranscodingErrorKind::InvalidBytes => {
+                    vec![(with("/digest", json!("?")), "/digest")]
+                }
+                JsonTranscodingErrorKind::InvalidString => {
+                    vec![(with("/orders/0/sku", json!("é")), "/orders/0/sku")]
+                }
+                JsonTranscodingErrorKind::IncorrectNumberOfElements => {
+                    vec![(with("/pair", json!([true])), "/pair")]
+                }
+                JsonTranscodingErrorKind::UnknownRecordField => {
+                    vec![(with("/orders/0/colour", json!("red")), "/orders/0")]
+                }
+                JsonTranscodingErrorKind::MissingRecordField => vec![(without("digest"), "")],
+                JsonTranscodingErrorKind::UnknownEnumVariant => {
+                    vec![(with("/status", json!("pending")), "/status")]
+                }
+                JsonTranscodingErrorKind::NoMatchingUnionVariant => {
+                    vec![(with("/either", json!(true)), "/either")]
+                }
+                JsonTranscodingErrorKind::UnknownConstSetValue => {
+                    vec![(with("/level", json!("medium")), "/level")]
+                }
+                // only from writing values
+                JsonTranscodingErrorKind::InvalidVariantId
+                | JsonTranscodingErrorKind::UnknownConstSetIndex
+                | JsonTranscodingErrorKind::ValueSpecMismatch => vec![],
+            };
+            for (json, expected_path) in read_errors {
+                let e = json_to_value(&spec, &json).unwrap_err();
+                assert_eq!(kind, JsonTranscodingErrorKind::from(&e), "{}: {}", json, e);
+                assert!(
+                    e.to_string().starts_with(&format!("{}:", expected_path)),
+                    "{}",
+                    e
+                );
+            }
+        }
+
+        let value = json_to_value(&spec, &orders_json()).unwrap();
+        let with_field = |index: usize, field: GluinoValue| match value.clone() {
+            GluinoValue::Record(mut fields) => {
+                fields[index] = field;
+                GluinoValue::Record(fields)
+            }
+            _ => unreachable!(),
+        };
+        for (value, kind, expected_path) in [
+            (
+                with_field(3, GluinoValue::Enum(2, Box::new(GluinoValue::Void))),
+                JsonTranscodingErrorKind::InvalidVariantId,
+                "/status",
+            ),
+            (
+                with_field(9, GluinoValue::ConstSet(2)),
+                JsonTranscodingErrorKind::UnknownConstSetIndex,
+                "/level",
+            ),
+            (
+                with_field(0, GluinoValue::Uint8(1)),
+                JsonTranscodingErrorKind::ValueSpecMismatch,
+                "/id",
+            ),
+            (
+                with_field(2, GluinoValue::List(vec![GluinoValue::Void])),
+                JsonTranscodingErrorKind::ValueSpecMismatch,
+                "/orders/0",
+            ),
+        ] {
+            let e = value_to_json(&spec, &value).unwrap_err();
+            assert_eq!(kind, JsonTranscodingErrorKind::from(&e), "{}", e);
+            assert!(
+                e.to_string().starts_with(&format!("{}:", expected_path)),
+                "{}",
+                e
+            );
+        }
+    }
+}
@@ -1,29 +1,76 @@
+mod bridge;
 mod de_impls;
+mod json;
+mod project;
+mod resolve;
 mod ser_impls;
+mod skip;
+mod spec_type_impls;
+mod stream;
+mod validate;
+mod value_ref;
 #[macro_use]
 mod encode;
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
     io::{self, Read, Write},
+    string::FromUtf8Error,
 };
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use strum::{EnumDiscriminants, EnumIter};
 
 use crate::{
     spec::{Spec, SpecType},
     spec_parsing::{
-        InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, Size,
-        StringEncodingFmt,
+        InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, ParsedSpec,
+        Size, SpecKind, StringEncodingFmt,
     },
+    spec_path::{PathSegment, SpecPath},
+    limits::{DecodeBudget, DecodeLimitError, DecodeLimits},
 };
-use crate::serde::encode::Encodable;
-use crate::util::WriteAllReturnSize;
-use self::{ser_impls::*, de_impls::{NativeSingleDe, VoidGluinoValueDe}};
+use crate::util::VariableLengthDecodingError;
+pub use crate::util::CountingReader;
+use self::{ser_impls::*, de_impls::*};
+
+pub(crate) use self::de_impls::decode_u64;
+pub use self::bridge::{from_reader, to_writer};
+pub use self::project::{
+    ProjectionError, ProjectionErrorKind, get_projected_deserialization_function,
+    get_projected_deserialization_function_with_limits, get_projection_spec,
+};
+pub use self::skip::{GluinoValueSkip, get_skip_function, get_skip_function_with_limits};
+pub use self::stream::{DEFAULT_MAX_RECORD_LENGTH, RecordStreamReader, RecordStreamWriter};
+pub use self::validate::{ValidationError, ValidationErrorKind};
+pub use self::value_ref::{GluinoValueRef, GluinoValueRefError, GluinoValueRefErrorKind};
+pub(crate) use self::resolve::build_resolver;
+pub use self::resolve::{
+    SpecResolutionError, SpecResolutionErrorKind, get_resolving_deserialization_function,
+};
+pub use self::json::{
+    JsonBytesEncoding, JsonOptions, JsonTranscodingError, JsonTranscodingErrorKind, json_to_value,
+    json_to_value_with_options, value_to_json, value_to_json_with_options,
+};
+#[cfg(feature = "derive")]
+pub use gluino_derive::GluinoSpecType;
 
 pub trait GluinoSpecType {
-    fn get_spec() -> Spec;
+    /// The spec of the type before compiling. `names_defined` holds the names already defined while
+    /// building an enclosing spec, named types found there must be refered to with `ParsedSpec::Ref`.
+    fn get_parsed_spec(names_defined: &mut HashSet<String>) -> ParsedSpec;
+
+    /// How the type is written in the names of derived generic types, `u8` in `Pair<u8>`. Derived
+    /// types are called by their spec name, other types default to the text of their spec.
+    fn spec_type_name() -> String {
+        Self::get_parsed_spec(&mut HashSet::new()).to_text()
+    }
+
+    fn get_spec() -> Spec {
+        Spec::compile(Self::get_parsed_spec(&mut HashSet::new()))
+            .expect("Spec of a GluinoSpecType should compile")
+    }
 }
 
 #[derive(Eq, Debug, PartialEq, Clone, EnumDiscriminants)]
@@ -125,7 +172,10 @@ where
         writer: &mut W,
     ) -> Result<usize, GluinoSerializationError>;
 }
-impl <W> GluinoValueSer<W> for Rc<RefCell<Box<dyn GluinoValueSer<W>>>>
+// shared serializer for named specs, allows for recursive specs
+type SharedGluinoValueSer<W> = Rc<RefCell<Box<dyn GluinoValueSer<W>>>>;
+
+impl <W> GluinoValueSer<W> for SharedGluinoValueSer<W>
 where
     W: Write,
 {
@@ -138,13 +188,111 @@ where
     }
 }
 
+// recursive references to a name hold their ser weakly, the strong one is owned by the reference
+// the name was first defined at, so a recursive ser is not a reference cycle
+type WeakGluinoValueSer<W> = Weak<RefCell<Box<dyn GluinoValueSer<W>>>>;
+
+impl<W> GluinoValueSer<W> for WeakGluinoValueSer<W>
+where
+    W: Write,
+{
+    fn serialize(
+        &self,
+        value: GluinoValue,
+        writer: &mut W,
+    ) -> Result<usize, GluinoSerializationError> {
+        self.upgrade()
+            .expect("Named ser should outlive its recursive references")
+            .borrow()
+            .serialize(value, writer)
+    }
+}
+
 pub trait GluinoValueDe<R>
 where
     R: Read,
 {
     fn deserialize(&self, reader: &mut R) -> Result<GluinoValue, GluinoDeserializationError>;
 }
+// what compiled deserializers read from: the caller's reader, counted so errors can say how far
+// into the bytes they are, and the decode limits of this read
+pub(crate) struct DecodeReader<R> {
+    reader: CountingReader<R>,
+    pub(crate) budget: DecodeBudget,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub(crate) fn new(reader: R, limits: DecodeLimits) -> DecodeReader<R> {
+        DecodeReader {
+            reader: CountingReader::new(reader),
+            budget: DecodeBudget::new(limits),
+        }
+    }
+
+    pub(crate) fn offset(&self) -> u64 {
+        self.reader.offset()
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+// compiled deserializers live as long as the reader type, so they can read borrowed bytes
+pub(crate) type ReaderDe<'d, R> = Box<dyn for<'r> GluinoValueDe<DecodeReader<&'r mut R>> + 'd>;
+// shared deserializer for named specs, allows for recursive specs
+type SharedGluinoValueDe<'d, R> = Rc<RefCell<ReaderDe<'d, R>>>;
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for SharedGluinoValueDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        self.borrow().deserialize(reader)
+    }
+}
+
+// held by recursive references to a name, like WeakGluinoValueSer
+type WeakGluinoValueDe<'d, R> = Weak<RefCell<ReaderDe<'d, R>>>;
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for WeakGluinoValueDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        self.upgrade()
+            .expect("Named de should outlive its recursive references")
+            .borrow()
+            .deserialize(reader)
+    }
+}
+
+// the deserializer handed out for a reader, positions every error it returns
+pub(crate) struct PositionedDe<'d, R> {
+    pub(crate) de: ReaderDe<'d, R>,
+    pub(crate) limits: DecodeLimits,
+}
+
+impl<R> GluinoValueDe<R> for PositionedDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(&self, reader: &mut R) -> Result<GluinoValue, GluinoDeserializationError> {
+        let mut reader = DecodeReader::new(reader, self.limits);
+        self.de.deserialize(&mut reader).map_err(|e| e.positioned(0))
+    }
+}
 
+#[derive(Debug)]
 pub enum GluinoSerializationError {
     WriteError(io::Error),
     IncorrectDataSize {
@@ -179,6 +327,132 @@ pub enum GluinoSerializationError {
         actual_bytes: usize,
     },
     UnknownConstSetIndex (u64),
+    // rust serde bridge
+    RustTypeSpecMismatch {
+        rust_type: &'static str,
+        spec_kind: SpecKind,
+    },
+    IntegerOutOfRange {
+        spec_kind: SpecKind,
+        n: u8,
+    },
+    UnsupportedIntegerSize(u8),
+    UnknownRecordField(String),
+    MissingRecordField(String),
+    UnknownEnumVariant(String),
+    UnknownConstSetValue(GluinoValue),
+    Custom(String),
+    /// An error in a part of a compound value
+    AtPath {
+        path: SpecPath,
+        error: Box<GluinoSerializationError>,
+    },
+}
+
+impl GluinoSerializationError {
+    /// Where in the value the error is, `None` when it is the value itself
+    pub fn path(&self) -> Option<&SpecPath> {
+        match self {
+            Self::AtPath { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The error without its path
+    pub fn cause(&self) -> &GluinoSerializationError {
+        match self {
+            Self::AtPath { error, .. } => error,
+            _ => self,
+        }
+    }
+
+    // the error moved one segment further from the value it is in
+    pub(crate) fn at(self, segment: PathSegment) -> GluinoSerializationError {
+        match self {
+            Self::AtPath { mut path, error } => {
+                path.prepend(segment);
+                Self::AtPath { path, error }
+            }
+            error => {
+                let mut path = SpecPath::root();
+                path.push(segment);
+                Self::AtPath {
+                    path,
+                    error: Box::new(error),
+                }
+            }
+        }
+    }
+}
+
+impl Display for GluinoSerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WriteError(e) => write!(f, "unable to write: {}", e),
+            Self::IncorrectDataSize {
+                expected_size,
+                actual_size,
+                size_value_kind,
+            } => write!(
+                f,
+                "{:?} of size {} does not fit size {:?}",
+                size_value_kind, actual_size, expected_size
+            ),
+            Self::InvalidVariantId {
+                variant_id,
+                max_variant_id,
+            } => write!(f, "variant id {} is greater than max variant id {}", variant_id, max_variant_id),
+            Self::ValueKindMismatch {
+                expected_value_kind,
+                actual_value_kind,
+            } => write!(f, "expected a {:?} value, got {:?}", expected_value_kind, actual_value_kind),
+            Self::ProductKindValueKindMismatch { actual_value_kind } => {
+                write!(f, "expected a record or tuple value, got {:?}", actual_value_kind)
+            }
+            Self::IncorrectNumberOfFields {
+                correct_number_of_fields,
+                actual_number_of_fields,
+            } => write!(
+                f,
+                "expected {} fields, got {}",
+                correct_number_of_fields, actual_number_of_fields
+            ),
+            Self::SumKindValueKindMismatch { actual_value_kind } => {
+                write!(f, "expected an enum or union value, got {:?}", actual_value_kind)
+            }
+            Self::InncorrectNumberOfIntegerBytes {
+                expect_bytes,
+                actual_bytes,
+            } => write!(f, "expected {} integer bytes, got {}", expect_bytes, actual_bytes),
+            Self::IncorrectNumberOfFloatingPointBytes {
+                expext_bytes,
+                actual_bytes,
+            } => write!(f, "expected {} floating point bytes, got {}", expext_bytes, actual_bytes),
+            Self::UnknownConstSetIndex(idx) => write!(f, "no const set value at index {}", idx),
+            Self::RustTypeSpecMismatch {
+                rust_type,
+                spec_kind,
+            } => write!(f, "rust {} can not be serialized as {:?}", rust_type, spec_kind),
+            Self::IntegerOutOfRange { spec_kind, n } => {
+                write!(f, "integer out of range for {:?}({})", spec_kind, n)
+            }
+            Self::UnsupportedIntegerSize(n) => write!(f, "unsupported integer size {}", n),
+            Self::UnknownRecordField(field) => write!(f, "record has no field {:?}", field),
+            Self::MissingRecordField(field) => write!(f, "missing required record field {:?}", field),
+            Self::UnknownEnumVariant(variant) => write!(f, "enum has no variant {:?}", variant),
+            Self::UnknownConstSetValue(value) => write!(f, "{:?} is not in the const set", value),
+            Self::Custom(msg) => f.write_str(msg),
+            Self::AtPath { path, error } => write!(f, "{}: {}", path, error),
+        }
+    }
+}
+
+impl std::error::Error for GluinoSerializationError {}
+
+impl ::serde::ser::Error for GluinoSerializationError {
+    fn custom<T: Display>(msg: T) -> Self {
+        GluinoSerializationError::Custom(msg.to_string())
+    }
 }
 
 impl From<io::Error> for GluinoSerializationError {
@@ -187,19 +461,207 @@ impl From<io::Error> for GluinoSerializationError {
     }
 }
 
+#[derive(Debug, EnumDiscriminants)]
+#[strum_discriminants(name(GluinoDeserializationErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
 pub enum GluinoDeserializationError {
     ReadError(io::Error),
+    UnexpectedEndOfBytes,
+    IntegerOverflowVariableLengthDecodingError(Vec<u8>),
+    IncorrectDataSize {
+        expected_size: Size,
+        actual_size: u64,
+        size_value_kind: GluinoValueKind,
+    },
+    InvalidVariantId {
+        variant_id: usize,
+        max_variant_id: usize,
+    },
+    InvalidUtf8String(FromUtf8Error),
+    LimitExceeded(DecodeLimitError),
+    // a bool stored as anything but 0 or 1
+    InvalidBoolByte(u8),
+    // an optional flag stored as anything but 0 or 1
+    InvalidOptionalFlag(u8),
+    // the bytes ran out before the end of a variable length integer
+    TruncatedVariableLengthInteger,
+    UnsupportedIntegerSize(u8),
+    UnknownConstSetValue(GluinoValue),
+    // resolving between writer and reader specs
+    UnresolvableEnumVariant(String),
+    UnresolvableUnionVariant(u64),
+    // a length prefixed value that does not take up all of its bytes
+    IncorrectValueLength {
+        expected_length: u64,
+        actual_length: u64,
+    },
+    Custom(String),
+    /// An error in the value at `path`, which starts `offset` bytes into the read
+    Positioned {
+        offset: u64,
+        path: SpecPath,
+        error: Box<GluinoDeserializationError>,
+    },
+}
+
+impl GluinoDeserializationError {
+    /// Where in the value the error is, `None` if it was not found while reading bytes
+    pub fn path(&self) -> Option<&SpecPath> {
+        match self {
+            Self::Positioned { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Bytes read before the value with the error, counted from where deserialization started
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Self::Positioned { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The error without its position
+    pub fn cause(&self) -> &GluinoDeserializationError {
+        match self {
+            Self::Positioned { error, .. } => error,
+            _ => self,
+        }
+    }
+
+    pub fn kind(&self) -> GluinoDeserializationErrorKind {
+        GluinoDeserializationErrorKind::from(self.cause())
+    }
+
+    // an error of the value starting at `offset`, kept if it is already in a part of that value
+    pub(crate) fn positioned(self, offset: u64) -> GluinoDeserializationError {
+        match self {
+            Self::Positioned { .. } => self,
+            error => Self::Positioned {
+                offset,
+                path: SpecPath::root(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    // an error of `segment` of a value, where the segment starts at `offset`
+    pub(crate) fn within(self, segment: PathSegment, offset: u64) -> GluinoDeserializationError {
+        match self.positioned(offset) {
+            Self::Positioned {
+                offset,
+                mut path,
+                error,
+            } => {
+                path.prepend(segment);
+                Self::Positioned {
+                    offset,
+                    path,
+                    error,
+                }
+            }
+            _ => unreachable!("Error was positioned"),
+        }
+    }
+}
+
+impl Display for GluinoDeserializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(e) => write!(f, "unable to read: {}", e),
+            Self::UnexpectedEndOfBytes => f.write_str("unexpected end of bytes"),
+            Self::IntegerOverflowVariableLengthDecodingError(_) => {
+                f.write_str("variable length integer does not fit in 64 bits")
+            }
+            Self::IncorrectDataSize {
+                expected_size,
+                actual_size,
+                size_value_kind,
+            } => write!(
+                f,
+                "{:?} of size {} does not fit size {:?}",
+                size_value_kind, actual_size, expected_size
+            ),
+            Self::InvalidVariantId {
+                variant_id,
+                max_variant_id,
+            } => write!(f, "variant id {} is greater than max variant id {}", variant_id, max_variant_id),
+            Self::InvalidUtf8String(e) => write!(f, "invalid utf8 string: {}", e),
+            Self::LimitExceeded(e) => write!(f, "decode limit exceeded: {}", e),
+            Self::InvalidBoolByte(b) => write!(f, "invalid bool byte {:#04x}", b),
+            Self::InvalidOptionalFlag(b) => write!(f, "invalid optional flag {:#04x}", b),
+            Self::TruncatedVariableLengthInteger => {
+                f.write_str("variable length integer ended early")
+            }
+            Self::UnsupportedIntegerSize(n) => write!(f, "unsupported integer size {}", n),
+            Self::UnknownConstSetValue(value) => write!(f, "{:?} is not in the const set", value),
+            Self::UnresolvableEnumVariant(variant) => {
+                write!(f, "reader spec has no enum variant {:?}", variant)
+            }
+            Self::UnresolvableUnionVariant(variant_id) => {
+                write!(f, "reader spec can not read union variant {}", variant_id)
+            }
+            Self::IncorrectValueLength {
+                expected_length,
+                actual_length,
+            } => write!(
+                f,
+                "value takes {} bytes, its length says {}",
+                actual_length, expected_length
+            ),
+            Self::Custom(msg) => f.write_str(msg),
+            Self::Positioned {
+                offset,
+                path,
+                error,
+            } => write!(f, "at byte {}, {}: {}", offset, path, error),
+        }
+    }
+}
+
+impl std::error::Error for GluinoDeserializationError {}
+
+impl ::serde::de::Error for GluinoDeserializationError {
+    fn custom<T: Display>(msg: T) -> Self {
+        GluinoDeserializationError::Custom(msg.to_string())
+    }
 }
 
 impl From<io::Error> for GluinoDeserializationError {
     fn from(e: io::Error) -> Self {
-        GluinoDeserializationError::ReadError(e)
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => GluinoDeserializationError::UnexpectedEndOfBytes,
+            _ => GluinoDeserializationError::ReadError(e),
+        }
+    }
+}
+
+impl From<VariableLengthDecodingError> for GluinoDeserializationError {
+    fn from(e: VariableLengthDecodingError) -> Self {
+        match e {
+            VariableLengthDecodingError::IncompleteVariableLengthEncoding => {
+                GluinoDeserializationError::TruncatedVariableLengthInteger
+            }
+            VariableLengthDecodingError::IoError(e) => e.into(),
+        }
+    }
+}
+
+impl From<DecodeLimitError> for GluinoDeserializationError {
+    fn from(e: DecodeLimitError) -> Self {
+        GluinoDeserializationError::LimitExceeded(e)
+    }
+}
+
+impl From<FromUtf8Error> for GluinoDeserializationError {
+    fn from(e: FromUtf8Error) -> Self {
+        GluinoDeserializationError::InvalidUtf8String(e)
     }
 }
 
 pub fn get_unit_serialization_function<W>(spec: &Spec) -> Box<dyn GluinoValueSer<W>>
 where
-    for<'ser> (dyn GluinoValueSer<W>): 'ser,
+    for<'ser> dyn GluinoValueSer<W>: 'ser,
     for<'write> W: Write + 'write,
 {
     get_unit_serialization_function_internal::<W>(spec, &mut HashMap::new())
@@ -207,10 +669,10 @@ where
 
 fn get_unit_serialization_function_internal<W>(
     spec: &Spec,
-    named_unit_sers: &mut HashMap<String, Rc<RefCell<Box<dyn GluinoValueSer<W>>>>>,
+    named_unit_sers: &mut HashMap<String, WeakGluinoValueSer<W>>,
 ) -> Box<dyn GluinoValueSer<W>>
 where
-    for<'ser> (dyn GluinoValueSer<W>): 'ser,
+    for<'ser> dyn GluinoValueSer<W>: 'ser,
     for<'write> W: Write + 'write,
 {
     match spec.spec_type() {
@@ -223,8 +685,7 @@ where
             3 => Box::new(NativeSingleSer::<u64>::new()),
             4 => Box::new(NativeSingleSer::<u128>::new()),
             _ => {
-                let n = n.clone();
-                Box::new(BigUintValueSer { n })
+                Box::new(BigUintValueSer { n: *n })
             }
         },
         SpecType::Int(n) => match n {
@@ -234,8 +695,7 @@ where
             3 => Box::new(NativeSingleSer::<i64>::new()),
             4 => Box::new(NativeSingleSer::<i128>::new()),
             _ => {
-                let n = n.clone();
-                Box::new(BigIntValueSer { n })
+                Box::new(BigIntValueSer { n: *n })
             }
         },
         SpecType::BinaryFloatingPoint(fmt) => match fmt {
@@ -301,12 +761,14 @@ where
                 .map(|field| field_to_spec.get(field).unwrap())
                 .map(|spec| get_unit_serialization_function_internal::<W>(spec, named_unit_sers))
                 .collect(),
+            field_names: Some(fields.clone()),
         }),
         SpecType::Tuple(fields) => Box::new(ProductValueSer {
             field_sers: fields
                 .iter()
                 .map(|spec| get_unit_serialization_function_internal::<W>(spec, named_unit_sers))
                 .collect(),
+            field_names: None,
         }),
         SpecType::Enum {
             variants,
@@ -319,6 +781,7 @@ where
                 .enumerate()
                 .map(|(a, b)| (a as u64, b))
                 .collect(),
+            variant_names: Some(variants.clone()),
         }),
         SpecType::Union(variants) => Box::new(SumValueSer {
             varient_sers: variants
@@ -327,23 +790,21 @@ where
                 .enumerate()
                 .map(|(a, b)| (a as u64, b))
                 .collect(),
+            variant_names: None,
         }),
         SpecType::Name(name) => match named_unit_sers.get(name) {
             Some(ser) => Box::new(ser.clone()),
             None => {
-                 todo!()
-                // let named_ser: Rc<RefCell<Box<dyn GluinoValueSer<W>>>> =
-                //     Rc::new(RefCell::new(Box::new(VoidGluinoValueSer)));
-                // named_unit_sers.insert(name.clone(), named_ser.clone());
-                // let inner_ser = spec
-                //     .named_schema()
-                //     .get(name)
-                //     .expect("Compiled spec should have named spec")
-                //     .use_ref(|spec| {
-                //         get_unit_serialization_function_internal::<W>(spec, named_unit_sers)
-                //     });
-                // *named_ser.borrow_mut() = inner_ser;
-                // Box::new(named_ser)
+                // tie the knot: recursive references to this name will share this ser
+                let named_ser: SharedGluinoValueSer<W> =
+                    Rc::new(RefCell::new(Box::new(VoidGluinoValueSer)));
+                named_unit_sers.insert(name.clone(), Rc::downgrade(&named_ser));
+                let inner_ser = get_unit_serialization_function_internal::<W>(
+                    &spec.named_definition(name),
+                    named_unit_sers,
+                );
+                *named_ser.borrow_mut() = inner_ser;
+                Box::new(named_ser)
             }
         },
         SpecType::ConstSet(const_spec, const_values) => {
@@ -355,54 +816,805 @@ where
     }
 }
 
-pub fn get_unit_deserialization_function<R>(spec: &Spec) -> Box<dyn GluinoValueDe<R>>
+pub fn get_unit_deserialization_function<'d, R>(spec: &Spec) -> Box<dyn GluinoValueDe<R> + 'd>
 where
-    R: Read,
+    R: Read + 'd,
+{
+    get_unit_deserialization_function_with_limits::<R>(spec, &DecodeLimits::default())
+}
+
+/// Deserializes values of `spec`, failing on values that go over `limits`
+pub fn get_unit_deserialization_function_with_limits<'d, R>(
+    spec: &Spec,
+    limits: &DecodeLimits,
+) -> Box<dyn GluinoValueDe<R> + 'd>
+where
+    R: Read + 'd,
+{
+    Box::new(PositionedDe {
+        de: get_reader_deserialization_function::<R>(spec),
+        limits: *limits,
+    })
+}
+
+pub(crate) fn get_reader_deserialization_function<'d, R>(spec: &Spec) -> ReaderDe<'d, R>
+where
+    R: Read + 'd,
+{
+    get_unit_deserialization_function_internal::<R>(spec, &mut HashMap::new())
+}
+
+fn get_unit_deserialization_function_internal<'d, R>(
+    spec: &Spec,
+    named_unit_des: &mut HashMap<String, WeakGluinoValueDe<'d, R>>,
+) -> ReaderDe<'d, R>
+where
+    R: Read + 'd,
 {
     match spec.spec_type() {
         SpecType::Void => Box::new(VoidGluinoValueDe),
-        SpecType::Bool => Box::new(NativeSingleDe::<bool>::new()),
-        SpecType::Uint(n) => {
-            match n {
-                0 => Box::new(NativeSingleDe::<u8>::new()),
-                1 => Box::new(NativeSingleDe::<u16>::new()),
-                2 => Box::new(NativeSingleDe::<u32>::new()),
-                3 => Box::new(NativeSingleDe::<u64>::new()),
-                4 => Box::new(NativeSingleDe::<u128>::new()),
-                _ => todo!()
-            }
+        SpecType::Bool => Box::new(BoolDe),
+        SpecType::Uint(n) => match n {
+            0 => Box::new(NativeSingleDe::<u8>::new()),
+            1 => Box::new(NativeSingleDe::<u16>::new()),
+            2 => Box::new(NativeSingleDe::<u32>::new()),
+            3 => Box::new(NativeSingleDe::<u64>::new()),
+            4 => Box::new(NativeSingleDe::<u128>::new()),
+            _ => Box::new(BigUintValueDe { n: *n }),
         },
-        SpecType::Int(n) => {
-            match n {
-                0 => Box::new(NativeSingleDe::<i8>::new()),
-                1 => Box::new(NativeSingleDe::<i16>::new()),
-                2 => Box::new(NativeSingleDe::<i32>::new()),
-                3 => Box::new(NativeSingleDe::<i64>::new()),
-                4 => Box::new(NativeSingleDe::<i128>::new()),
-                _ => todo!()
-            }
+        SpecType::Int(n) => match n {
+            0 => Box::new(NativeSingleDe::<i8>::new()),
+            1 => Box::new(NativeSingleDe::<i16>::new()),
+            2 => Box::new(NativeSingleDe::<i32>::new()),
+            3 => Box::new(NativeSingleDe::<i64>::new()),
+            4 => Box::new(NativeSingleDe::<i128>::new()),
+            _ => Box::new(BigIntValueDe { n: *n }),
+        },
+        SpecType::BinaryFloatingPoint(fmt) => match fmt {
+            InterchangeBinaryFloatingPointFormat::Single => Box::new(NativeSingleDe::<F32>::new()),
+            InterchangeBinaryFloatingPointFormat::Double => Box::new(NativeSingleDe::<F64>::new()),
+            _ => Box::new(BinaryFloatingPointValueDe { fmt: fmt.clone() }),
         },
-        SpecType::BinaryFloatingPoint(fmt) => {
-            match fmt {
-                InterchangeBinaryFloatingPointFormat::Single => Box::new(NativeSingleDe::<F32>::new()),
-                InterchangeBinaryFloatingPointFormat::Double => Box::new(NativeSingleDe::<F64>::new()),
-                InterchangeBinaryFloatingPointFormat::Half => todo!(),
-                InterchangeBinaryFloatingPointFormat::Quadruple => todo!(),
-                InterchangeBinaryFloatingPointFormat::Octuple => todo!(),
+        SpecType::DecimalFloatingPoint(fmt) => {
+            Box::new(DecimalFloatingPointValueDe { fmt: fmt.clone() })
+        }
+        SpecType::Decimal(_) => Box::new(DecimalDe),
+        SpecType::Bytes(size) => Box::new(ByteValueDe {
+            spec_size: size.clone(),
+        }),
+        SpecType::String(size, fmt) => match fmt {
+            StringEncodingFmt::Utf8 => Box::new(Utf8De {
+                spec_size: size.clone(),
+            }),
+            StringEncodingFmt::Utf16 | StringEncodingFmt::Ascii => Box::new(NonUtf8De {
+                spec_size: size.clone(),
+            }),
+        },
+        SpecType::Map {
+            size,
+            key_spec,
+            value_spec,
+        } => {
+            let key_de = get_unit_deserialization_function_internal::<R>(key_spec, named_unit_des);
+            let value_de =
+                get_unit_deserialization_function_internal::<R>(value_spec, named_unit_des);
+            Box::new(MapDe {
+                spec_size: size.clone(),
+                key_de,
+                value_de,
+            })
+        }
+        SpecType::List { size, value_spec } => {
+            let value_de =
+                get_unit_deserialization_function_internal::<R>(value_spec, named_unit_des);
+            Box::new(ListDe {
+                spec_size: size.clone(),
+                value_de,
+            })
+        }
+        SpecType::Optional(inner) => {
+            let inner_de = get_unit_deserialization_function_internal::<R>(inner, named_unit_des);
+            Box::new(OptionalValueDe { inner_de })
+        }
+        SpecType::Record {
+            fields,
+            field_to_spec,
+            ..
+        } => Box::new(ProductValueDe {
+            field_des: fields
+                .iter()
+                .map(|field| field_to_spec.get(field).unwrap())
+                .map(|spec| get_unit_deserialization_function_internal::<R>(spec, named_unit_des))
+                .collect(),
+            field_names: Some(fields.clone()),
+            product_value: GluinoValue::Record,
+        }),
+        SpecType::Tuple(fields) => Box::new(ProductValueDe {
+            field_des: fields
+                .iter()
+                .map(|spec| get_unit_deserialization_function_internal::<R>(spec, named_unit_des))
+                .collect(),
+            field_names: None,
+            product_value: GluinoValue::Tuple,
+        }),
+        SpecType::Enum {
+            variants,
+            variant_to_spec,
+        } => Box::new(SumValueDe {
+            variant_des: variants
+                .iter()
+                .map(|variant| variant_to_spec.get(variant).unwrap())
+                .map(|spec| get_unit_deserialization_function_internal::<R>(spec, named_unit_des))
+                .collect(),
+            variant_names: Some(variants.clone()),
+            sum_value: GluinoValue::Enum,
+        }),
+        SpecType::Union(variants) => Box::new(SumValueDe {
+            variant_des: variants
+                .iter()
+                .map(|spec| get_unit_deserialization_function_internal::<R>(spec, named_unit_des))
+                .collect(),
+            variant_names: None,
+            sum_value: GluinoValue::Union,
+        }),
+        SpecType::Name(name) => match named_unit_des.get(name) {
+            Some(de) => Box::new(de.clone()),
+            None => {
+                // as for sers, only this reference to the name owns its de
+                let named_de: SharedGluinoValueDe<'d, R> =
+                    Rc::new(RefCell::new(Box::new(VoidGluinoValueDe)));
+                named_unit_des.insert(name.clone(), Rc::downgrade(&named_de));
+                let inner_de = get_unit_deserialization_function_internal::<R>(
+                    &spec.named_definition(name),
+                    named_unit_des,
+                );
+                *named_de.borrow_mut() = inner_de;
+                Box::new(named_de)
             }
         },
-        SpecType::DecimalFloatingPoint(_) => todo!(),
-        SpecType::Decimal(_) => todo!(),
-        SpecType::Map { size, key_spec, value_spec } => todo!(),
-        SpecType::List { size, value_spec } => todo!(),
-        SpecType::String(_, _) => todo!(),
-        SpecType::Bytes(_) => todo!(),
-        SpecType::Optional(_) => todo!(),
-        SpecType::Record { fields, field_to_spec, field_to_index } => todo!(),
-        SpecType::Tuple(_) => todo!(),
-        SpecType::Enum { variants, variant_to_spec } => todo!(),
-        SpecType::Union(_) => todo!(),
-        SpecType::Name(_) => todo!(),
-        SpecType::ConstSet(_, _) => todo!(),
+        SpecType::ConstSet(const_spec, const_values) => Box::new(ConstSetDe {
+            const_values: const_values.clone(),
+            const_de: get_unit_deserialization_function_internal::<R>(const_spec, named_unit_des),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use strum::IntoEnumIterator;
+
+    use crate::{
+        limits::DecodeLimitErrorKind,
+        spec::NamedSpec,
+        spec_parsing::{ParsedSpec, SpecKind},
+        test_utils::get_valid_specs_for_kind,
+    };
+
+    use super::*;
+
+    fn sample_size(size: &Size) -> u64 {
+        match size {
+            Size::Variable => 3,
+            Size::Fixed(n) => *n,
+            Size::Range(r) => r.start,
+            Size::GreaterThan(n) => *n,
+            Size::LessThan(n) => n.saturating_sub(1),
+        }
+    }
+
+    // a simple valid value for the spec, recursive names bottom out at the first Optional
+    pub(super) fn sample_value(spec: &Spec, named_spec: &HashMap<String, NamedSpec>, depth: usize) -> GluinoValue {
+        match spec.spec_type() {
+            SpecType::Void => GluinoValue::Void,
+            SpecType::Bool => GluinoValue::Bool(true),
+            SpecType::Uint(n) => match n {
+                0 => GluinoValue::Uint8(u8::MAX),
+                1 => GluinoValue::Uint16(u16::MAX),
+                2 => GluinoValue::Uint32(u32::MAX),
+                3 => GluinoValue::Uint64(u64::MAX),
+                4 => GluinoValue::Uint128(u128::MAX),
+                _ => GluinoValue::BigUint(*n, vec![0xAB; 1 << n]),
+            },
+            SpecType::Int(n) => match n {
+                0 => GluinoValue::Int8(i8::MIN),
+                1 => GluinoValue::Int16(i16::MIN),
+                2 => GluinoValue::Int32(i32::MIN),
+                3 => GluinoValue::Int64(i64::MIN),
+                4 => GluinoValue::Int128(i128::MIN),
+                _ => GluinoValue::BigInt(*n, vec![0xCD; 1 << n]),
+            },
+            SpecType::BinaryFloatingPoint(fmt) => match fmt {
+                InterchangeBinaryFloatingPointFormat::Single => GluinoValue::Float(F32(1.5)),
+                InterchangeBinaryFloatingPointFormat::Double => GluinoValue::Double(F64(-2.25)),
+                _ => GluinoValue::BinaryFloatingPoint(
+                    fmt.clone(),
+                    vec![0x11; ((fmt.significand_bits() + fmt.exponent_bits()) >> 3) as usize],
+                ),
+            },
+            SpecType::DecimalFloatingPoint(fmt) => {
+                GluinoValue::DecimalFloatingPoint(fmt.clone(), vec![0x22; fmt.minimum_byes_needed()])
+            }
+            SpecType::Decimal(_) => GluinoValue::Decimal(vec![0x01, 0x02, 0x03]),
+            SpecType::Map {
+                size,
+                key_spec,
+                value_spec,
+            } => GluinoValue::Map(
+                (0..sample_size(size))
+                    .map(|_| {
+                        (
+                            sample_value(key_spec, named_spec, depth + 1),
+                            sample_value(value_spec, named_spec, depth + 1),
+                        )
+                    })
+                    .collect(),
+            ),
+            SpecType::List { size, value_spec } => GluinoValue::List(
+                (0..sample_size(size))
+                    .map(|_| sample_value(value_spec, named_spec, depth + 1))
+                    .collect(),
+            ),
+            SpecType::String(size, fmt) => {
+                let s: String = iter_chars().take(sample_size(size) as usize).collect();
+                match fmt {
+                    StringEncodingFmt::Utf8 => GluinoValue::String(s),
+                    _ => GluinoValue::NonUtf8String(s.into_bytes()),
+                }
+            }
+            SpecType::Bytes(size) => GluinoValue::Bytes(vec![0x33; sample_size(size) as usize]),
+            SpecType::Optional(inner) => GluinoValue::Optional(if depth > 3 {
+                None
+            } else {
+                Some(Box::new(sample_value(inner, named_spec, depth + 1)))
+            }),
+            SpecType::Name(name) => sample_value(named_spec[name].get().unwrap(), named_spec, depth + 1),
+            SpecType::Record {
+                fields,
+                field_to_spec,
+                ..
+            } => GluinoValue::Record(
+                fields
+                    .iter()
+                    .map(|f| sample_value(field_to_spec.get(f).unwrap(), named_spec, depth + 1))
+                    .collect(),
+            ),
+            SpecType::Tuple(fields) => GluinoValue::Tuple(
+                fields
+                    .iter()
+                    .map(|s| sample_value(s, named_spec, depth + 1))
+                    .collect(),
+            ),
+            SpecType::Enum {
+                variants,
+                variant_to_spec,
+            } => {
+                let last = variants.len() - 1;
+                GluinoValue::Enum(
+                    last as u64,
+                    Box::new(sample_value(
+                        variant_to_spec.get(&variants[last]).unwrap(),
+                        named_spec,
+                        depth + 1,
+                    )),
+                )
+            }
+            SpecType::Union(variants) => {
+                let last = variants.len() - 1;
+                GluinoValue::Union(
+                    last as u64,
+                    Box::new(sample_value(&variants[last], named_spec, depth + 1)),
+                )
+            }
+            SpecType::ConstSet(_, const_values) => {
+                GluinoValue::ConstSet(const_values.len() as u64 - 1)
+            }
+        }
+    }
+
+    fn iter_chars() -> impl Iterator<Item = char> {
+        "gluino".chars().cycle()
+    }
+
+    fn test_value_serde(spec: &Spec, value: GluinoValue) {
+        let ser = get_unit_serialization_function::<Vec<u8>>(spec);
+        let de = get_unit_deserialization_function::<Cursor<Vec<u8>>>(spec);
+        let mut bytes = Vec::new();
+        let written = ser
+            .serialize(value.clone(), &mut bytes)
+            .unwrap_or_else(|_| panic!("Unable to serialize {:?} for {:?}", value, spec));
+        assert_eq!(bytes.len(), written);
+        let mut reader = Cursor::new(bytes);
+        assert_eq!(
+            value,
+            de.deserialize(&mut reader)
+                .unwrap_or_else(|_| panic!("Unable to deserialize {:?}", spec))
+        );
+        assert_eq!(written as u64, reader.position());
+    }
+
+    #[test]
+    fn test_unit_serde() {
+        for spec_kind in SpecKind::iter() {
+            for parsed_spec in get_valid_specs_for_kind(spec_kind) {
+                if let ParsedSpec::Uint(n) | ParsedSpec::Int(n) = parsed_spec {
+                    // 2^n bytes
+                    if n > 10 {
+                        continue;
+                    }
+                }
+                let spec = Spec::compile(parsed_spec).expect("Unable to compile");
+                let value = sample_value(&spec, spec.named_schema(), 0);
+                test_value_serde(&spec, value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unit_serde_sized() {
+        for size in [
+            Size::Variable,
+            Size::Fixed(4),
+            Size::Range(crate::spec_parsing::SizeRange { start: 2, end: 9 }),
+            Size::GreaterThan(3),
+            Size::LessThan(7),
+        ] {
+            for parsed_spec in [
+                ParsedSpec::String(size.clone(), StringEncodingFmt::Utf8),
+                ParsedSpec::String(size.clone(), StringEncodingFmt::Ascii),
+                ParsedSpec::Bytes(size.clone()),
+                ParsedSpec::List {
+                    size: size.clone(),
+                    value_spec: ParsedSpec::Uint(1).into(),
+                },
+                ParsedSpec::Map {
+                    size: size.clone(),
+                    key_spec: ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8).into(),
+                    value_spec: ParsedSpec::Optional(ParsedSpec::Bool.into()).into(),
+                },
+            ] {
+                let spec = Spec::compile(parsed_spec).expect("Unable to compile");
+                let value = sample_value(&spec, spec.named_schema(), 0);
+                test_value_serde(&spec, value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_borrowed_reader_deserialization() {
+        let spec = Spec::compile(ParsedSpec::List {
+            size: Size::Variable,
+            value_spec: ParsedSpec::Uint(1).into(),
+        })
+        .expect("Unable to compile");
+        let value = GluinoValue::List(vec![GluinoValue::Uint16(1), GluinoValue::Uint16(2)]);
+        let mut bytes = Vec::new();
+        get_unit_serialization_function::<Vec<u8>>(&spec)
+            .serialize(value.clone(), &mut bytes)
+            .unwrap();
+
+        let mut slice = &bytes[..];
+        let de = get_unit_deserialization_function::<&[u8]>(&spec);
+        assert_eq!(value, de.deserialize(&mut slice).unwrap());
+        assert!(slice.is_empty());
+
+        let mut cursor = Cursor::new(&bytes[..]);
+        let de = get_unit_deserialization_function::<Cursor<&[u8]>>(&spec);
+        assert_eq!(value, de.deserialize(&mut cursor).unwrap());
+
+        let mut cursor = Cursor::new(bytes.clone());
+        let de = get_unit_deserialization_function::<&mut Cursor<Vec<u8>>>(&spec);
+        assert_eq!(value, de.deserialize(&mut &mut cursor).unwrap());
+    }
+
+    #[test]
+    fn test_recursive_serde() {
+        // list = Optional(Tuple(Uint8, list))
+        let linked_list = Spec::compile(ParsedSpec::Name {
+            name: "list".to_string(),
+            spec: ParsedSpec::Optional(
+                ParsedSpec::Tuple(vec![
+                    ParsedSpec::Uint(0),
+                    ParsedSpec::Ref {
+                        name: "list".to_string(),
+                    },
+                ])
+                .into(),
+            )
+            .into(),
+        })
+        .expect("Unable to compile");
+        let value = (1..=5u8).rev().fold(GluinoValue::Optional(None), |tail, n| {
+            GluinoValue::Optional(Some(Box::new(GluinoValue::Tuple(vec![
+                GluinoValue::Uint8(n),
+                tail,
+            ]))))
+        });
+        test_value_serde(&linked_list, value);
+
+        // tree = Record { value: Int32, children: List(tree) }, forest = List(tree)
+        let forest = Spec::compile(ParsedSpec::List {
+            size: Size::Variable,
+            value_spec: ParsedSpec::Name {
+                name: "tree".to_string(),
+                spec: ParsedSpec::Record(vec![
+                    ("value".to_string(), ParsedSpec::Int(2)),
+                    (
+                        "children".to_string(),
+                        ParsedSpec::List {
+                            size: Size::Variable,
+                            value_spec: ParsedSpec::Ref {
+                                name: "tree".to_string(),
+                            }
+                            .into(),
+                        },
+                    ),
+                ])
+                .into(),
+            }
+            .into(),
+        })
+        .expect("Unable to compile");
+        let leaf = |n| GluinoValue::Record(vec![GluinoValue::Int32(n), GluinoValue::List(vec![])]);
+        let value = GluinoValue::List(vec![
+            GluinoValue::Record(vec![
+                GluinoValue::Int32(1),
+                GluinoValue::List(vec![
+                    leaf(2),
+                    GluinoValue::Record(vec![GluinoValue::Int32(3), GluinoValue::List(vec![leaf(4)])]),
+                ]),
+            ]),
+            leaf(5),
+        ]);
+        test_value_serde(&forest, value);
+    }
+
+    #[test]
+    fn test_recursive_serde_dropped() {
+        let spec = Spec::compile(
+            ParsedSpec::from_text("name List = optional<tuple { uint(0), ref List }>").unwrap(),
+        )
+        .unwrap();
+        let mut named_unit_sers = HashMap::new();
+        let ser = get_unit_serialization_function_internal::<Vec<u8>>(&spec, &mut named_unit_sers);
+        let named_ser = named_unit_sers.remove("List").unwrap();
+        assert!(named_ser.upgrade().is_some());
+        drop(ser);
+        assert!(named_ser.upgrade().is_none());
+
+        let mut named_unit_des = HashMap::new();
+        let de = get_unit_deserialization_function_internal::<Cursor<Vec<u8>>>(
+            &spec,
+            &mut named_unit_des,
+        );
+        let named_de = named_unit_des.remove("List").unwrap();
+        assert!(named_de.upgrade().is_some());
+        drop(de);
+        assert!(named_de.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_serialization_error_paths() {
+        fn serialize(text: &str, value: GluinoValue) -> GluinoSerializationError {
+            let spec = Spec::compile(ParsedSpec::from_text(text).unwrap()).unwrap();
+            get_unit_serialization_function::<Vec<u8>>(&spec)
+                .serialize(value, &mut Vec::new())
+                .unwrap_err()
+        }
+        let error = serialize(
+            "record { id: uint(3), orders: list<record { sku: string, tags: map<string, bool> }> }",
+            GluinoValue::Record(vec![
+                GluinoValue::Uint64(1),
+                GluinoValue::List(vec![
+                    GluinoValue::Record(vec![GluinoValue::String("a".into()), GluinoValue::Map(vec![])]),
+                    GluinoValue::Record(vec![
+                        GluinoValue::String("b".into()),
+                        GluinoValue::Map(vec![(GluinoValue::String("c".into()), GluinoValue::Uint8(1))]),
+                    ]),
+                ]),
+            ]),
+        );
+        assert_eq!(Some(".orders[1].tags[0]"), error.path().map(SpecPath::as_str));
+        assert!(matches!(
+            error.cause(),
+            GluinoSerializationError::ValueKindMismatch {
+                expected_value_kind: GluinoValueKind::Bool,
+                actual_value_kind: GluinoValueKind::Uint8,
+            }
+        ));
+        assert_eq!(
+            ".orders[1].tags[0]: expected a Bool value, got Uint8",
+            error.to_string()
+        );
+
+        let error = serialize(
+            "tuple { enum { a: void, b: optional<union { bool, string }> }, map<bool, bool> }",
+            GluinoValue::Tuple(vec![
+                GluinoValue::Enum(1, Box::new(GluinoValue::Optional(Some(Box::new(GluinoValue::Union(
+                    1,
+                    Box::new(GluinoValue::Void),
+                )))))),
+                GluinoValue::Map(vec![]),
+            ]),
+        );
+        assert_eq!(Some(".0.b.1"), error.path().map(SpecPath::as_str));
+        let error = serialize(
+            "map<bool, bool>",
+            GluinoValue::Map(vec![(GluinoValue::Void, GluinoValue::Bool(true))]),
+        );
+        assert_eq!(Some("{0}"), error.path().map(SpecPath::as_str));
+
+        let error = serialize("bool", GluinoValue::Void);
+        assert_eq!(None, error.path());
+        assert!(matches!(error.cause(), GluinoSerializationError::ValueKindMismatch { .. }));
+    }
+
+    #[test]
+    fn test_deserialization_error_positions() {
+        fn deserialize(text: &str, bytes: Vec<u8>) -> GluinoDeserializationError {
+            let spec = Spec::compile(ParsedSpec::from_text(text).unwrap()).unwrap();
+            get_unit_deserialization_function::<Cursor<Vec<u8>>>(&spec)
+                .deserialize(&mut Cursor::new(bytes))
+                .unwrap_err()
+        }
+        let error = deserialize(
+            "record { id: uint(0), tags: list<string>, ok: bool }",
+            vec![0x07, 0x02, 0x01, 0x61, 0x02, 0xC3, 0x28, 0x01],
+        );
+        assert_eq!(Some(".tags[1]"), error.path().map(SpecPath::as_str));
+        assert_eq!(Some(4), error.offset());
+        assert_eq!(GluinoDeserializationErrorKind::InvalidUtf8String, error.kind());
+        assert!(error.to_string().starts_with("at byte 4, .tags[1]: invalid utf8 string"));
+
+        let error = deserialize(
+            "tuple { map<uint(0), optional<bool>>, bool }",
+            vec![0x02, 0x01, 0x00, 0x02, 0x01, 0x05],
+        );
+        assert_eq!(Some(".0[1]"), error.path().map(SpecPath::as_str));
+        assert_eq!(Some(5), error.offset());
+        assert!(matches!(error.cause(), GluinoDeserializationError::InvalidBoolByte(0x05)));
+
+        let error = deserialize("enum { a: void, b: list<uint(0)> }", vec![0x01, 0x80]);
+        assert_eq!(Some(".b"), error.path().map(SpecPath::as_str));
+        assert_eq!(Some(1), error.offset());
+        assert_eq!(GluinoDeserializationErrorKind::TruncatedVariableLengthInteger, error.kind());
+
+        let error = deserialize("list<union { bool, void }>", vec![0x01, 0x03]);
+        assert_eq!(Some("[0]"), error.path().map(SpecPath::as_str));
+        assert_eq!(Some(1), error.offset());
+        assert_eq!(GluinoDeserializationErrorKind::InvalidVariantId, error.kind());
+
+        let error = deserialize("bytes<..2>", vec![0x05]);
+        assert_eq!(Some(""), error.path().map(SpecPath::as_str));
+        assert_eq!(Some(0), error.offset());
+        assert_eq!(GluinoDeserializationErrorKind::IncorrectDataSize, error.kind());
+    }
+
+    #[test]
+    fn test_deserialization_limits() {
+        let limits = DecodeLimits {
+            max_depth: 3,
+            max_total_allocation: 1 << 10,
+            max_collection_length: 4,
+            max_string_length: 8,
+            ..DecodeLimits::default()
+        };
+        let deserialize = |text: &str, value: GluinoValue, limits: &DecodeLimits| {
+            let spec = Spec::compile(ParsedSpec::from_text(text).unwrap()).unwrap();
+            let mut bytes = Vec::new();
+            get_unit_serialization_function::<Vec<u8>>(&spec)
+                .serialize(value.clone(), &mut bytes)
+                .unwrap();
+            let result =
+                get_unit_deserialization_function_with_limits::<Cursor<Vec<u8>>>(&spec, limits)
+                    .deserialize(&mut Cursor::new(bytes.clone()))
+                    .map(|decoded| assert_eq!(value, decoded))
+                    .map_err(|e| match e.cause() {
+                        GluinoDeserializationError::LimitExceeded(e) => {
+                            DecodeLimitErrorKind::from(e)
+                        }
+                        e => panic!("Not a limit error: {}", e),
+                    });
+            // views of the bytes are held to the same limits, walking past values allocates nothing
+            let ref_limit_error = |e: GluinoValueRefError| match &e {
+                GluinoValueRefError::Deserialization(de_error) => match de_error.cause() {
+                    GluinoDeserializationError::LimitExceeded(e) => DecodeLimitErrorKind::from(e),
+                    _ => panic!("Not a limit error: {}", e),
+                },
+                _ => panic!("Not a limit error: {}", e),
+            };
+            let value_ref = GluinoValueRef::new_with_limits(&spec, &bytes, limits);
+            assert_eq!(
+                result,
+                value_ref
+                    .to_value()
+                    .map(|decoded| assert_eq!(value, decoded))
+                    .map_err(ref_limit_error)
+            );
+            if result != Err(DecodeLimitErrorKind::MaxTotalAllocationExceeded) {
+                assert_eq!(
+                    result,
+                    value_ref
+                        .encoded_len()
+                        .map(|len| assert_eq!(bytes.len(), len))
+                        .map_err(ref_limit_error)
+                );
+            }
+            result
+        };
+        let list = |n: u8| GluinoValue::List((0..n).map(GluinoValue::Uint8).collect());
+        let string = |s: &str| GluinoValue::String(s.to_string());
+
+        assert_eq!(Ok(()), deserialize("list<uint(0)>", list(4), &limits));
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxCollectionLengthExceeded),
+            deserialize("list<uint(0)>", list(5), &limits)
+        );
+        assert_eq!(Ok(()), deserialize("string", string("8 bytes!"), &limits));
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxStringLengthExceeded),
+            deserialize("string", string("9 bytes!!"), &limits)
+        );
+        let tree = |depth: usize| {
+            (0..depth).fold(GluinoValue::Optional(None), |tree, _| {
+                GluinoValue::Optional(Some(Box::new(GluinoValue::Record(vec![tree]))))
+            })
+        };
+        let tree_spec = "name Tree = record { child: optional<ref Tree> }";
+        let tree_value = |depth| match tree(depth) {
+            GluinoValue::Optional(Some(tree)) => *tree,
+            _ => unreachable!(),
+        };
+        assert_eq!(Ok(()), deserialize(tree_spec, tree_value(2), &limits));
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxDepthExceeded),
+            deserialize(tree_spec, tree_value(3), &limits)
+        );
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxTotalAllocationExceeded),
+            deserialize(
+                "list<list<uint(0)>>",
+                GluinoValue::List(vec![list(4), list(4), list(4), list(4)]),
+                &DecodeLimits {
+                    max_total_allocation: 4 * size_of::<GluinoValue>() as u64,
+                    ..limits
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_deserialization_errors() {
+        fn deserialize(spec: ParsedSpec, bytes: Vec<u8>) -> Result<GluinoValue, GluinoDeserializationError> {
+            let spec = Spec::compile(spec).expect("Unable to compile");
+            get_unit_deserialization_function::<Cursor<Vec<u8>>>(&spec)
+                .deserialize(&mut Cursor::new(bytes))
+        }
+        fn resolve(
+            writer_spec: ParsedSpec,
+            reader_spec: ParsedSpec,
+            bytes: Vec<u8>,
+        ) -> Result<GluinoValue, GluinoDeserializationError> {
+            let writer_spec = Spec::compile(writer_spec).expect("Unable to compile");
+            let reader_spec = Spec::compile(reader_spec).expect("Unable to compile");
+            get_resolving_deserialization_function::<Cursor<Vec<u8>>>(&writer_spec, &reader_spec)
+                .expect("Unable to resolve")
+                .deserialize(&mut Cursor::new(bytes))
+        }
+        for error_kind in GluinoDeserializationErrorKind::iter() {
+            match error_kind {
+                GluinoDeserializationErrorKind::ReadError => {
+                    //todo find good way to test.
+                    vec![]
+                }
+                GluinoDeserializationErrorKind::UnexpectedEndOfBytes => vec![
+                    deserialize(ParsedSpec::Int(3), vec![0x01, 0x02]),
+                    deserialize(ParsedSpec::Bytes(Size::Variable), vec![0x05, 0x01]),
+                    deserialize(ParsedSpec::Optional(ParsedSpec::Bool.into()), vec![]),
+                ],
+                GluinoDeserializationErrorKind::TruncatedVariableLengthInteger => vec![
+                    deserialize(ParsedSpec::Bytes(Size::Variable), vec![0x80]),
+                    deserialize(ParsedSpec::Union(vec![ParsedSpec::Bool]), vec![0xFF, 0xFF]),
+                ],
+                GluinoDeserializationErrorKind::IntegerOverflowVariableLengthDecodingError => {
+                    vec![deserialize(
+                        ParsedSpec::Bytes(Size::Variable),
+                        vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+                    )]
+                }
+                GluinoDeserializationErrorKind::IncorrectDataSize => vec![
+                    deserialize(ParsedSpec::Bytes(Size::LessThan(2)), vec![0x05]),
+                    deserialize(
+                        ParsedSpec::List {
+                            size: Size::GreaterThan(2),
+                            value_spec: ParsedSpec::Bool.into(),
+                        },
+                        vec![0x01, 0x01],
+                    ),
+                ],
+                GluinoDeserializationErrorKind::InvalidVariantId => vec![
+                    deserialize(
+                        ParsedSpec::Enum(vec![
+                            ("a".into(), ParsedSpec::Bool),
+                            ("b".into(), ParsedSpec::Void),
+                        ]),
+                        vec![0x07],
+                    ),
+                    deserialize(ParsedSpec::Union(vec![ParsedSpec::Bool]), vec![0x01, 0x01]),
+                ],
+                GluinoDeserializationErrorKind::InvalidUtf8String => vec![deserialize(
+                    ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8),
+                    vec![0x02, 0xC3, 0x28],
+                )],
+                GluinoDeserializationErrorKind::LimitExceeded => vec![
+                    // 2^28 voids from 5 bytes
+                    deserialize(
+                        ParsedSpec::List {
+                            size: Size::Variable,
+                            value_spec: ParsedSpec::Void.into(),
+                        },
+                        vec![0x80, 0x80, 0x80, 0x80, 0x01],
+                    ),
+                ],
+                GluinoDeserializationErrorKind::InvalidBoolByte => vec![
+                    deserialize(ParsedSpec::Bool, vec![0x02]),
+                    deserialize(
+                        ParsedSpec::Tuple(vec![ParsedSpec::Bool, ParsedSpec::Bool]),
+                        vec![0x01, 0xFF],
+                    ),
+                ],
+                GluinoDeserializationErrorKind::InvalidOptionalFlag => vec![
+                    deserialize(ParsedSpec::Optional(ParsedSpec::Bool.into()), vec![0x02, 0x01]),
+                    deserialize(
+                        ParsedSpec::List {
+                            size: Size::Variable,
+                            value_spec: ParsedSpec::Optional(ParsedSpec::Void.into()).into(),
+                        },
+                        vec![0x02, 0x01, 0xFF],
+                    ),
+                ],
+                GluinoDeserializationErrorKind::UnsupportedIntegerSize => {
+                    vec![deserialize(ParsedSpec::Uint(200), vec![0x00])]
+                }
+                GluinoDeserializationErrorKind::UnknownConstSetValue => vec![deserialize(
+                    ParsedSpec::ConstSet(ParsedSpec::Uint(0).into(), vec![vec![0x01], vec![0x02]]),
+                    vec![0x03],
+                )],
+                GluinoDeserializationErrorKind::UnresolvableEnumVariant => vec![resolve(
+                    ParsedSpec::Enum(vec![("a".into(), ParsedSpec::Void), ("b".into(), ParsedSpec::Void)]),
+                    ParsedSpec::Enum(vec![("a".into(), ParsedSpec::Void)]),
+                    vec![0x01],
+                )],
+                GluinoDeserializationErrorKind::UnresolvableUnionVariant => vec![resolve(
+                    ParsedSpec::Union(vec![ParsedSpec::Bool, ParsedSpec::Void]),
+                    ParsedSpec::Bool,
+                    vec![0x01],
+                )],
+                GluinoDeserializationErrorKind::IncorrectValueLength => {
+                    let spec = Spec::compile(ParsedSpec::Bool).unwrap();
+                    RecordStreamReader::new(&spec, Cursor::new(vec![0x02, 0x01, 0x00])).collect()
+                }
+                GluinoDeserializationErrorKind::Custom => vec![from_reader::<String, _>(
+                    &Spec::compile(ParsedSpec::Bool).unwrap(),
+                    &mut Cursor::new(vec![0x01]),
+                )
+                .map(GluinoValue::String)],
+                GluinoDeserializationErrorKind::Positioned => {
+                    // wraps the errors above, see test_deserialization_error_positions
+                    vec![]
+                }
+            }
+            .into_iter()
+            .map(|res| res.map_err(|e| e.kind()))
+            .for_each(|res| match res {
+                Ok(unexpected_value) => {
+                    panic!("Unexpectedly deserialized into {:?}", unexpected_value)
+                }
+                Err(e) => {
+                    assert_eq!(e, error_kind, "Unexpected Error Kind")
+                }
+            })
+        }
     }
 }
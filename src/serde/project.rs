@@ -0,0 +1,683 @@
+//! Reading only some fields of encoded values.
+//!
+//! A projection is a list of paths like `.user.name` or `.orders[].price`. The projected value has
+//! the shape of the projection spec: records and tuples keep only the fields on a path, in spec
+//! order, and everything a path ends at is kept whole. Optionals and names are passed through,
+//! `[]` goes into the items of a list or the values of a map. Fields that are not kept are skipped
+//! without being decoded.
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    io::Read,
+};
+
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    spec::{NameScope, Spec, SpecCompileError, SpecType},
+    spec_parsing::{ParsedSpec, SpecKind},
+    spec_path::{PathSegment, SpecPath},
+    limits::DecodeLimits,
+};
+
+use super::{
+    DecodeReader, GluinoDeserializationError, GluinoValue, GluinoValueDe, PositionedDe, ReaderDe,
+    de_impls::{ListDe, MapDe, OptionalValueDe, deserialize_nested},
+    get_reader_deserialization_function,
+    ser_impls::path_segment,
+    skip::{ReaderSkip, get_reader_skip_function, skip_nested},
+};
+
+/// Spec of the values read by `get_projected_deserialization_function` with the same paths
+pub fn get_projection_spec(spec: &Spec, paths: &[&str]) -> Result<Spec, ProjectionError> {
+    let projection = build_projection(spec, paths)?;
+    Spec::compile(projection.to_parsed_spec(&mut HashSet::new()))
+        .map_err(ProjectionError::InvalidProjectionSpec)
+}
+
+/// Deserializes values of `spec` into values of the projection spec, reading past the rest
+pub fn get_projected_deserialization_function<R>(
+    spec: &Spec,
+    paths: &[&str],
+) -> Result<Box<dyn GluinoValueDe<R>>, ProjectionError>
+where
+    for<'de> dyn GluinoValueDe<R>: 'de,
+    for<'read> R: Read + 'read,
+{
+    get_projected_deserialization_function_with_limits::<R>(spec, paths, &DecodeLimits::default())
+}
+
+/// Projects values of `spec`, failing on values that go over `limits`
+pub fn get_projected_deserialization_function_with_limits<R>(
+    spec: &Spec,
+    paths: &[&str],
+    limits: &DecodeLimits,
+) -> Result<Box<dyn GluinoValueDe<R>>, ProjectionError>
+where
+    for<'de> dyn GluinoValueDe<R>: 'de,
+    for<'read> R: Read + 'read,
+{
+    Ok(Box::new(PositionedDe {
+        de: build_projection(spec, paths)?.to_de::<R>(),
+        limits: *limits,
+    }))
+}
+
+#[derive(Debug, EnumDiscriminants)]
+#[strum_discriminants(name(ProjectionErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum ProjectionError {
+    NoPaths,
+    InvalidPath(String),
+    UnknownField { path: SpecPath, field: String },
+    NotProjectable { path: SpecPath, kind: SpecKind },
+    InvalidProjectionSpec(SpecCompileError),
+}
+
+impl Display for ProjectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoPaths => write!(f, "a projection needs at least one path"),
+            Self::InvalidPath(path) => write!(f, "invalid path {:?}", path),
+            Self::UnknownField { path, field } => write!(f, "{}: no field {:?}", path, field),
+            Self::NotProjectable { path, kind } => {
+                write!(f, "{}: can not project into {:?}", path, kind)
+            }
+            Self::InvalidProjectionSpec(e) => {
+                write!(f, "the projection spec does not compile: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProjectionError {}
+
+enum Segment<'p> {
+    Field(&'p str),
+    Item,
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment<'_>>, ProjectionError> {
+    let invalid = || ProjectionError::InvalidPath(path.to_string());
+    let mut segments = Vec::new();
+    let mut rest = match path {
+        "." => "",
+        _ => path,
+    };
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("[]") {
+            segments.push(Segment::Item);
+            rest = after;
+            continue;
+        }
+        // the leading dot may be left out
+        let field = match rest.strip_prefix('.') {
+            Some(field) => field,
+            None if segments.is_empty() => rest,
+            None => return Err(invalid()),
+        };
+        let end = field.find(['.', '[']).unwrap_or(field.len());
+        if end == 0 {
+            return Err(invalid());
+        }
+        segments.push(Segment::Field(&field[..end]));
+        rest = &field[end..];
+    }
+    Ok(segments)
+}
+
+// what is kept of a spec
+enum Projection<'s> {
+    Whole(&'s Spec),
+    // record or tuple, `None` for skipped fields
+    Fields(&'s Spec, Vec<Option<Projection<'s>>>),
+    Optional(Box<Projection<'s>>),
+    // list items or map values
+    Items(&'s Spec, Box<Projection<'s>>),
+}
+
+fn build_projection<'s>(spec: &'s Spec, paths: &[&str]) -> Result<Projection<'s>, ProjectionError> {
+    let mut projection = None;
+    for path in paths {
+        let segments = parse_path(path)?;
+        projection = Some(select(
+            projection,
+            spec,
+            NameScope::default(),
+            &segments,
+            &mut SpecPath::root(),
+        )?);
+    }
+    projection.ok_or(ProjectionError::NoPaths)
+}
+
+/// Adds the path of `segments` to what is kept of `spec`
+fn select<'s>(
+    projection: Option<Projection<'s>>,
+    spec: &'s Spec,
+    names: NameScope<'s>,
+    segments: &[Segment],
+    path: &mut SpecPath,
+) -> Result<Projection<'s>, ProjectionError> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return Ok(Projection::Whole(spec));
+    };
+    if let Some(Projection::Whole(_)) = projection {
+        return Ok(projection.unwrap());
+    }
+    let (spec, names) = names.resolve(spec);
+    match (spec.spec_type(), segment) {
+        (SpecType::Optional(inner), _) => {
+            let inner_projection = match projection {
+                Some(Projection::Optional(inner_projection)) => Some(*inner_projection),
+                _ => None,
+            };
+            Ok(Projection::Optional(Box::new(select(
+                inner_projection,
+                inner,
+                names,
+                segments,
+                path,
+            )?)))
+        }
+        (
+            SpecType::Record {
+                field_to_index,
+                field_to_spec,
+                ..
+            },
+            Segment::Field(field),
+        ) => {
+            let index =
+                *field_to_index
+                    .get(*field)
+                    .ok_or_else(|| ProjectionError::UnknownField {
+                        path: path.clone(),
+                        field: field.to_string(),
+                    })?;
+            let len = path.push(PathSegment::Field(field));
+            let field_spec = field_to_spec.get(*field).unwrap();
+            let projection = select_field(
+                projection,
+                spec,
+                names,
+                index,
+                field_spec,
+                rest,
+                path,
+            );
+            path.truncate(len);
+            projection
+        }
+        (SpecType::Tuple(fields), Segment::Field(field)) => {
+            let index = field
+                .parse::<usize>()
+                .ok()
+                .filter(|index| *index < fields.len())
+                .ok_or_else(|| ProjectionError::UnknownField {
+                    path: path.clone(),
+                    field: field.to_string(),
+                })?;
+            let len = path.push(PathSegment::Index(index));
+            let projection = select_field(
+                projection,
+                spec,
+                names,
+                index,
+                &fields[index],
+                rest,
+                path,
+            );
+            path.truncate(len);
+            projection
+        }
+        (SpecType::List { value_spec, .. } | SpecType::Map { value_spec, .. }, Segment::Item) => {
+            let items_projection = match projection {
+                Some(Projection::Items(_, items_projection)) => Some(*items_projection),
+                _ => None,
+            };
+            let len = path.push(PathSegment::Item);
+            let items_projection = select(items_projection, value_spec, names, rest, path);
+            path.truncate(len);
+            Ok(Projection::Items(spec, Box::new(items_projection?)))
+        }
+        _ => Err(ProjectionError::NotProjectable {
+            path: path.clone(),
+            kind: spec.kind(),
+        }),
+    }
+}
+
+fn select_field<'s>(
+    projection: Option<Projection<'s>>,
+    spec: &'s Spec,
+    names: NameScope<'s>,
+    index: usize,
+    field_spec: &'s Spec,
+    rest: &[Segment],
+    path: &mut SpecPath,
+) -> Result<Projection<'s>, ProjectionError> {
+    let field_count = match spec.spec_type() {
+        SpecType::Record { fields, .. } => fields.len(),
+        SpecType::Tuple(fields) => fields.len(),
+        _ => unreachable!("Only records and tuples have fields"),
+    };
+    let mut fields = match projection {
+        Some(Projection::Fields(_, fields)) => fields,
+        _ => (0..field_count).map(|_| None).collect(),
+    };
+    fields[index] = Some(select(fields[index].take(), field_spec, names, rest, path)?);
+    Ok(Projection::Fields(spec, fields))
+}
+
+impl Projection<'_> {
+    // names are defined where they are first used, whole parts may share them
+    fn to_parsed_spec(&self, names_converted: &mut HashSet<String>) -> ParsedSpec {
+        match self {
+            Projection::Whole(spec) => Spec::make_parsed_spec_internal(
+                spec.named_schema(),
+                names_converted,
+                spec.spec_type(),
+            ),
+            Projection::Fields(spec, fields) => match spec.spec_type() {
+                SpecType::Record {
+                    fields: field_names,
+                    ..
+                } => ParsedSpec::Record(
+                    field_names
+                        .iter()
+                        .zip(fields)
+                        .filter_map(|(name, field)| {
+                            Some((
+                                name.clone(),
+                                field.as_ref()?.to_parsed_spec(names_converted),
+                            ))
+                        })
+                        .collect(),
+                ),
+                _ => ParsedSpec::Tuple(
+                    fields
+                        .iter()
+                        .flatten()
+                        .map(|field| field.to_parsed_spec(names_converted))
+                        .collect(),
+                ),
+            },
+            Projection::Optional(inner) => {
+                ParsedSpec::Optional(Box::new(inner.to_parsed_spec(names_converted)))
+            }
+            Projection::Items(spec, items) => match spec.spec_type() {
+                SpecType::List { size, .. } => ParsedSpec::List {
+                    size: size.clone(),
+                    value_spec: Box::new(items.to_parsed_spec(names_converted)),
+                },
+                SpecType::Map { size, key_spec, .. } => ParsedSpec::Map {
+                    size: size.clone(),
+                    key_spec: Box::new(Spec::make_parsed_spec_internal(
+                        key_spec.named_schema(),
+                        names_converted,
+                        key_spec.spec_type(),
+                    )),
+                    value_spec: Box::new(items.to_parsed_spec(names_converted)),
+                },
+                _ => unreachable!("Only lists and maps have items"),
+            },
+        }
+    }
+
+    fn to_de<R>(&self) -> ReaderDe<'static, R>
+    where
+        for<'read> R: Read + 'read,
+    {
+        match self {
+            Projection::Whole(spec) => get_reader_deserialization_function::<R>(spec),
+            Projection::Fields(spec, fields) => {
+                let field_specs: Vec<&Spec> = match spec.spec_type() {
+                    SpecType::Record {
+                        fields,
+                        field_to_spec,
+                        ..
+                    } => fields
+                        .iter()
+                        .map(|f| field_to_spec.get(f).unwrap())
+                        .collect(),
+                    SpecType::Tuple(fields) => fields.iter().collect(),
+                    _ => unreachable!("Only records and tuples have fields"),
+                };
+                Box::new(ProjectedProductDe {
+                    fields: field_specs
+                        .into_iter()
+                        .zip(fields)
+                        .map(|(field_spec, field)| match field {
+                            Some(field) => ProjectedField::Keep(field.to_de::<R>()),
+                            None => {
+                                ProjectedField::Skip(get_reader_skip_function::<R>(field_spec))
+                            }
+                        })
+                        .collect(),
+                    field_names: match spec.spec_type() {
+                        SpecType::Record { fields, .. } => Some(fields.clone()),
+                        _ => None,
+                    },
+                    product_value: match spec.spec_type() {
+                        SpecType::Record { .. } => GluinoValue::Record,
+                        _ => GluinoValue::Tuple,
+                    },
+                })
+            }
+            Projection::Optional(inner) => Box::new(OptionalValueDe {
+                inner_de: inner.to_de::<R>(),
+            }),
+            Projection::Items(spec, items) => match spec.spec_type() {
+                SpecType::List { size, .. } => Box::new(ListDe {
+                    spec_size: size.clone(),
+                    value_de: items.to_de::<R>(),
+                }),
+                SpecType::Map { size, key_spec, .. } => Box::new(MapDe {
+                    spec_size: size.clone(),
+                    key_de: get_reader_deserialization_function::<R>(key_spec),
+                    value_de: items.to_de::<R>(),
+                }),
+                _ => unreachable!("Only lists and maps have items"),
+            },
+        }
+    }
+}
+
+enum ProjectedField<R> {
+    Keep(ReaderDe<'static, R>),
+    Skip(ReaderSkip<R>),
+}
+
+struct ProjectedProductDe<R> {
+    fields: Vec<ProjectedField<R>>,
+    field_names: Option<Vec<String>>,
+    // GluinoValue::Record or GluinoValue::Tuple
+    product_value: fn(Vec<GluinoValue>) -> GluinoValue,
+}
+
+impl<'r, R> GluinoValueDe<DecodeReader<&'r mut R>> for ProjectedProductDe<R>
+where
+    R: Read,
+{
+    fn deserialize(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let mut values = Vec::new();
+        for (index, field) in self.fields.iter().enumerate() {
+            let offset = reader.offset();
+            let within = |e: GluinoDeserializationError| {
+                e.within(path_segment(&self.field_names, index), offset)
+            };
+            match field {
+                ProjectedField::Keep(de) => {
+                    values.push(deserialize_nested(de, reader).map_err(within)?)
+                }
+                ProjectedField::Skip(skip) => skip_nested(skip, reader).map_err(within)?,
+            }
+        }
+        Ok((self.product_value)(values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        serde::{GluinoDeserializationErrorKind, get_unit_serialization_function},
+        test_utils::{compile, encode, string},
+    };
+
+    use super::*;
+
+    // encodes the values one after another and reads them all back through the projection
+    fn project(spec: &Spec, paths: &[&str], values: Vec<GluinoValue>) -> Vec<GluinoValue> {
+        let ser = get_unit_serialization_function::<Vec<u8>>(spec);
+        let mut bytes = Vec::new();
+        let count = values.len();
+        for value in values {
+            ser.serialize(value, &mut bytes).unwrap();
+        }
+        let len = bytes.len() as u64;
+        let de = get_projected_deserialization_function::<Cursor<Vec<u8>>>(spec, paths).unwrap();
+        let mut reader = Cursor::new(bytes);
+        let projected = (0..count)
+            .map(|_| de.deserialize(&mut reader).unwrap())
+            .collect();
+        assert_eq!(len, reader.position());
+        projected
+    }
+
+    fn error_kind(spec: &Spec, paths: &[&str]) -> ProjectionErrorKind {
+        let error = get_projection_spec(spec, paths).unwrap_err();
+        assert_eq!(
+            ProjectionErrorKind::from(&error),
+            ProjectionErrorKind::from(
+                &get_projected_deserialization_function::<Cursor<Vec<u8>>>(spec, paths)
+                    .err()
+                    .unwrap()
+            )
+        );
+        (&error).into()
+    }
+
+    fn order(id: u64) -> GluinoValue {
+        GluinoValue::Record(vec![
+            GluinoValue::Uint64(id),
+            GluinoValue::Record(vec![string("ada"), GluinoValue::Uint8(36)]),
+            GluinoValue::List(vec![
+                GluinoValue::Record(vec![GluinoValue::Uint32(2), string("nib")]),
+                GluinoValue::Record(vec![GluinoValue::Uint32(5), string("tip")]),
+            ]),
+            GluinoValue::Optional(Some(Box::new(GluinoValue::Tuple(vec![
+                GluinoValue::Bool(true),
+                string("x"),
+            ])))),
+            GluinoValue::Map(vec![(
+                string("k"),
+                GluinoValue::Record(vec![GluinoValue::Int8(-1), string("v")]),
+            )]),
+            GluinoValue::Enum(1, Box::new(string("late"))),
+        ])
+    }
+
+    const ORDER: &str = "record {
+        id: uint(3),
+        user: record { name: string, age: uint(0) },
+        lines: list<record { price: uint(2), sku: string }>,
+        note: optional<tuple { bool, string }>,
+        extra: map<string, record { a: int(0), b: string }>,
+        status: enum { ok: void, held: string },
+    }";
+
+    #[test]
+    fn test_projection() {
+        let spec = compile(ORDER);
+        let paths = [".lines[].price", "user.name", ".note.1", ".extra[].b"];
+        assert_eq!(
+            compile(
+                "record {
+                    user: record { name: string },
+                    lines: list<record { price: uint(2) }>,
+                    note: optional<tuple { string }>,
+                    extra: map<string, record { b: string }>,
+                }"
+            ),
+            get_projection_spec(&spec, &paths).unwrap()
+        );
+        let projected = GluinoValue::Record(vec![
+            GluinoValue::Record(vec![string("ada")]),
+            GluinoValue::List(vec![
+                GluinoValue::Record(vec![GluinoValue::Uint32(2)]),
+                GluinoValue::Record(vec![GluinoValue::Uint32(5)]),
+            ]),
+            GluinoValue::Optional(Some(Box::new(GluinoValue::Tuple(vec![string("x")])))),
+            GluinoValue::Map(vec![(string("k"), GluinoValue::Record(vec![string("v")]))]),
+        ]);
+        assert_eq!(
+            vec![projected; 3],
+            project(&spec, &paths, vec![order(1), order(2), order(3)])
+        );
+
+        // a path to a part keeps it whole, whatever else is asked for in it
+        assert_eq!(
+            compile(
+                "record { user: record { name: string, age: uint(0) }, status: enum { ok: void, held: string } }"
+            ),
+            get_projection_spec(&spec, &[".user.name", "status", ".user"]).unwrap()
+        );
+        assert_eq!(spec, get_projection_spec(&spec, &["."]).unwrap());
+        assert_eq!(vec![order(4)], project(&spec, &[""], vec![order(4)]));
+    }
+
+    #[test]
+    fn test_projection_through_names() {
+        let spec = compile(
+            "name List = record { value: int(0), label: string, next: optional<ref List> }",
+        );
+        let node = |value: i8, next: Option<GluinoValue>| {
+            GluinoValue::Record(vec![
+                GluinoValue::Int8(value),
+                string("label"),
+                GluinoValue::Optional(next.map(Box::new)),
+            ])
+        };
+        let list = node(0, Some(node(1, Some(node(2, None)))));
+        assert_eq!(
+            compile("record { value: int(0), next: optional<record { value: int(0) }> }"),
+            get_projection_spec(&spec, &[".value", ".next.value"]).unwrap()
+        );
+        assert_eq!(
+            vec![GluinoValue::Record(vec![
+                GluinoValue::Int8(0),
+                GluinoValue::Optional(Some(Box::new(GluinoValue::Record(vec![
+                    GluinoValue::Int8(1)
+                ]))))
+            ])],
+            project(&spec, &[".value", ".next.value"], vec![list.clone()])
+        );
+
+        // whole parts of a recursive spec keep the name
+        let paths = [".next.next", ".label"];
+        let projection_spec = get_projection_spec(&spec, &paths).unwrap();
+        assert_eq!(
+            compile(
+                "record { label: string, next: optional<record { next: optional<name List = \
+                 record { value: int(0), label: string, next: optional<ref List> }> }> }"
+            ),
+            projection_spec
+        );
+        let projected = project(&spec, &paths, vec![list]);
+        let GluinoValue::Record(fields) = &projected[0] else {
+            unreachable!()
+        };
+        assert_eq!(string("label"), fields[0]);
+        let mut bytes = Vec::new();
+        get_unit_serialization_function::<Vec<u8>>(&projection_spec)
+            .serialize(projected[0].clone(), &mut bytes)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_projection_from_recursive_sub_spec() {
+        let spec = compile("name Tree = record { value: int(0), children: list<ref Tree> }");
+        let tree = spec.named_schema()["Tree"].get().unwrap();
+        let SpecType::Record { field_to_spec, .. } = tree.spec_type() else {
+            unreachable!()
+        };
+        let SpecType::List { value_spec, .. } = field_to_spec["children"].spec_type() else {
+            unreachable!()
+        };
+        assert_eq!(
+            compile("record { value: int(0) }"),
+            get_projection_spec(value_spec, &[".value"]).unwrap()
+        );
+        assert_eq!(
+            compile(
+                "record { children: list<name Tree = \
+                 record { value: int(0), children: list<ref Tree> }> }"
+            ),
+            get_projection_spec(value_spec, &[".children"]).unwrap()
+        );
+        let child = GluinoValue::Record(vec![GluinoValue::Int8(3), GluinoValue::List(vec![])]);
+        assert_eq!(
+            vec![GluinoValue::Record(vec![GluinoValue::Int8(3)])],
+            project(value_spec, &[".value"], vec![child])
+        );
+    }
+
+    #[test]
+    fn test_projection_limits() {
+        let spec = compile("record { id: uint(0), name: string, note: string }");
+        let value = GluinoValue::Record(vec![
+            GluinoValue::Uint8(1),
+            string("ab"),
+            string("abcd"),
+        ]);
+        let bytes = encode(&spec, value);
+        let limits = DecodeLimits {
+            max_string_length: 3,
+            ..DecodeLimits::default()
+        };
+        let project = |paths: &[&str], limits: &DecodeLimits| {
+            get_projected_deserialization_function_with_limits::<Cursor<Vec<u8>>>(
+                &spec, paths, limits,
+            )
+            .unwrap()
+            .deserialize(&mut Cursor::new(bytes.clone()))
+            .map_err(|e| e.kind())
+        };
+        assert_eq!(
+            Ok(GluinoValue::Record(vec![string("ab")])),
+            project(&[".name"], &DecodeLimits::default())
+        );
+        assert_eq!(
+            Err(GluinoDeserializationErrorKind::LimitExceeded),
+            project(&[".note"], &limits)
+        );
+        // the note is skipped without being allocated
+        assert_eq!(
+            Ok(GluinoValue::Record(vec![string("ab")])),
+            project(&[".name"], &limits)
+        );
+    }
+
+    #[test]
+    fn test_projection_errors() {
+        let spec = compile(ORDER);
+        assert_eq!(ProjectionErrorKind::NoPaths, error_kind(&spec, &[]));
+        for path in ["..id", "user.", "lines[]price", "user..name"] {
+            assert_eq!(
+                ProjectionErrorKind::InvalidPath,
+                error_kind(&spec, &[path]),
+                "{}",
+                path
+            );
+        }
+        for path in [".missing", ".user.missing", ".note.2", ".note.first"] {
+            assert_eq!(
+                ProjectionErrorKind::UnknownField,
+                error_kind(&spec, &[path]),
+                "{}",
+                path
+            );
+        }
+        for path in [".id.value", ".lines.price", ".status.held", ".user[]"] {
+            assert_eq!(
+                ProjectionErrorKind::NotProjectable,
+                error_kind(&spec, &[path]),
+                "{}",
+                path
+            );
+        }
+        assert_eq!(
+            ".user: no field \"missing\"",
+            get_projection_spec(&spec, &[".user.missing"])
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
@@ -0,0 +1,1250 @@
+//! Reading data written with one spec (the writer spec) as values of another (the reader spec).
+//!
+//! Record fields are matched by name, writer fields the reader does not have are dropped and
+//! reader fields the writer does not have must be optional and are read as `None`. Enum variants
+//! are matched by name, integers and binary floating points may widen and values may move into
+//! optionals and unions.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    io::Read,
+    rc::{Rc, Weak},
+};
+
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    fingerprint::SpecFingerprint,
+    spec::{Spec, SpecType},
+    spec_parsing::{
+        InterchangeBinaryFloatingPointFormat, ParsedSpec, Size, SpecKind, StringEncodingFmt,
+    },
+    spec_path::{PathSegment, SpecPath},
+};
+
+use super::{
+    F32, F64, GluinoDeserializationError, GluinoValue, GluinoValueDe, GluinoValueKind,
+    get_unit_deserialization_function,
+};
+
+/// Deserializes data written with `writer_spec` into values of `reader_spec`.
+/// Fails when some data of the writer spec could never be read, data that only sometimes
+/// can not be read, like an enum variant the reader does not have, fails when it is deserialized.
+pub fn get_resolving_deserialization_function<'d, R>(
+    writer_spec: &Spec,
+    reader_spec: &Spec,
+) -> Result<Box<dyn GluinoValueDe<R> + 'd>, Vec<SpecResolutionError>>
+where
+    R: Read + 'd,
+{
+    let (resolver, errors) = build_resolver(writer_spec, reader_spec);
+    let errors: Vec<SpecResolutionError> = errors
+        .into_iter()
+        .filter(|e| !e.is_data_dependent())
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Box::new(ResolvingDe {
+        writer_de: get_unit_deserialization_function::<R>(writer_spec),
+        resolver,
+    }))
+}
+
+/// Every difference between the specs that keeps some or all data of the writer spec from being read
+pub(crate) fn build_resolver(
+    writer_spec: &Spec,
+    reader_spec: &Spec,
+) -> (Resolver, Vec<SpecResolutionError>) {
+    let mut builder = ResolverBuilder {
+        path: SpecPath::root(),
+        errors: Vec::new(),
+        knots: HashMap::new(),
+    };
+    let resolver = builder.build(writer_spec, reader_spec);
+    (resolver, builder.errors)
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, EnumDiscriminants)]
+#[strum_discriminants(name(SpecResolutionErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum SpecResolutionError {
+    KindMismatch {
+        path: SpecPath,
+        writer_kind: SpecKind,
+        reader_kind: SpecKind,
+    },
+    Narrowing {
+        path: SpecPath,
+        writer_spec: ParsedSpec,
+        reader_spec: ParsedSpec,
+    },
+    StringEncodingMismatch {
+        path: SpecPath,
+        writer_encoding: StringEncodingFmt,
+        reader_encoding: StringEncodingFmt,
+    },
+    TupleLengthMismatch {
+        path: SpecPath,
+        writer_length: usize,
+        reader_length: usize,
+    },
+    MissingReaderField {
+        path: SpecPath,
+        field: String,
+    },
+    // the rest only fail for some data
+    SizeNotCovered {
+        path: SpecPath,
+        writer_size: Size,
+        reader_size: Size,
+    },
+    MissingReaderEnumVariant {
+        path: SpecPath,
+        variant: String,
+    },
+    UnresolvableUnionVariant {
+        path: SpecPath,
+        variant_id: u64,
+    },
+    MissingReaderConstSetValue {
+        path: SpecPath,
+        value: GluinoValue,
+    },
+}
+
+impl SpecResolutionError {
+    pub fn path(&self) -> &SpecPath {
+        match self {
+            Self::KindMismatch { path, .. }
+            | Self::Narrowing { path, .. }
+            | Self::StringEncodingMismatch { path, .. }
+            | Self::TupleLengthMismatch { path, .. }
+            | Self::MissingReaderField { path, .. }
+            | Self::SizeNotCovered { path, .. }
+            | Self::MissingReaderEnumVariant { path, .. }
+            | Self::UnresolvableUnionVariant { path, .. }
+            | Self::MissingReaderConstSetValue { path, .. } => path,
+        }
+    }
+
+    /// Whether only some of the data written with the writer spec can not be read
+    pub fn is_data_dependent(&self) -> bool {
+        matches!(
+            self,
+            Self::SizeNotCovered { .. }
+                | Self::MissingReaderEnumVariant { .. }
+                | Self::UnresolvableUnionVariant { .. }
+                | Self::MissingReaderConstSetValue { .. }
+        )
+    }
+}
+
+impl Display for SpecResolutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::KindMismatch {
+                path,
+                writer_kind,
+                reader_kind,
+            } => write!(
+                f,
+                "{}: {:?} can not be read as {:?}",
+                path, writer_kind, reader_kind
+            ),
+            Self::Narrowing {
+                path,
+                writer_spec,
+                reader_spec,
+            } => write!(
+                f,
+                "{}: {} does not fit in {}",
+                path,
+                writer_spec.to_text(),
+                reader_spec.to_text()
+            ),
+            Self::StringEncodingMismatch {
+                path,
+                writer_encoding,
+                reader_encoding,
+            } => write!(
+                f,
+                "{}: {} strings can not be read as {}",
+                path,
+                writer_encoding.name(),
+                reader_encoding.name()
+            ),
+            Self::TupleLengthMismatch {
+                path,
+                writer_length,
+                reader_length,
+            } => write!(
+                f,
+                "{}: tuple of {} can not be read as a tuple of {}",
+                path, writer_length, reader_length
+            ),
+            Self::MissingReaderField { path, field } => {
+                write!(
+                    f,
+                    "{}: reader field {:?} is not written and not optional",
+                    path, field
+                )
+            }
+            Self::SizeNotCovered {
+                path,
+                writer_size,
+                reader_size,
+            } => write!(
+                f,
+                "{}: size {:?} does not cover {:?}",
+                path, reader_size, writer_size
+            ),
+            Self::MissingReaderEnumVariant { path, variant } => {
+                write!(f, "{}: reader has no enum variant {:?}", path, variant)
+            }
+            Self::UnresolvableUnionVariant { path, variant_id } => {
+                write!(
+                    f,
+                    "{}: no reader spec for union variant {}",
+                    path, variant_id
+                )
+            }
+            Self::MissingReaderConstSetValue { path, value } => {
+                write!(f, "{}: reader const set does not have {:?}", path, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpecResolutionError {}
+
+struct ResolvingDe<'d, R>
+where
+    R: Read,
+{
+    writer_de: Box<dyn GluinoValueDe<R> + 'd>,
+    resolver: Resolver,
+}
+
+impl<R> GluinoValueDe<R> for ResolvingDe<'_, R>
+where
+    R: Read,
+{
+    fn deserialize(&self, reader: &mut R) -> Result<GluinoValue, GluinoDeserializationError> {
+        self.resolver.resolve(self.writer_de.deserialize(reader)?)
+    }
+}
+
+/// Turns values of the writer spec into values of the reader spec
+pub(crate) enum Resolver {
+    Identity,
+    Int(u8),
+    Uint(u8),
+    Float(InterchangeBinaryFloatingPointFormat),
+    Sized(Size),
+    Some(Box<Resolver>),
+    Optional(Box<Resolver>),
+    List {
+        size: Option<Size>,
+        item: Box<Resolver>,
+    },
+    Map {
+        size: Option<Size>,
+        key: Box<Resolver>,
+        value: Box<Resolver>,
+    },
+    // for each reader field, the writer field it comes from or None
+    Record(Vec<Option<(usize, Resolver)>>),
+    Tuple(Vec<Resolver>),
+    // for each writer variant
+    Enum(Vec<Result<(u64, Resolver), String>>),
+    Union(Vec<Option<(u64, Resolver)>>),
+    IntoUnion(u64, Box<Resolver>),
+    FromUnion(Vec<Option<Resolver>>),
+    // for each writer const, the reader const or the unknown value
+    ConstSet(Vec<Result<u64, GluinoValue>>),
+    FromConstSet(Vec<Result<GluinoValue, GluinoValue>>),
+    // shared resolver for named specs, allows for recursive specs
+    Named(Rc<RefCell<Resolver>>),
+    // later uses of a named resolver, weak so a recursive resolver is not a reference cycle
+    NamedRef(Weak<RefCell<Resolver>>),
+}
+
+impl Resolver {
+    pub(crate) fn resolve(
+        &self,
+        value: GluinoValue,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        Ok(match (self, value) {
+            (Resolver::Identity, value) => value,
+            (Resolver::Int(n), value) => widen_integer(value, *n, true),
+            (Resolver::Uint(n), value) => widen_integer(value, *n, false),
+            (Resolver::Float(fmt), value) => widen_float(value, fmt),
+            (Resolver::Sized(size), value) => {
+                let actual_size = match &value {
+                    GluinoValue::String(s) => s.len(),
+                    GluinoValue::NonUtf8String(bytes) | GluinoValue::Bytes(bytes) => bytes.len(),
+                    value => {
+                        unreachable!("Writer deserializer produced {:?} for a sized spec", value)
+                    }
+                } as u64;
+                check_size(size, actual_size, (&value).into())?;
+                value
+            }
+            (Resolver::Some(inner), value) => {
+                GluinoValue::Optional(Some(Box::new(inner.resolve(value)?)))
+            }
+            (Resolver::Optional(inner), GluinoValue::Optional(value)) => {
+                GluinoValue::Optional(match value {
+                    Some(value) => Some(Box::new(inner.resolve(*value)?)),
+                    None => None,
+                })
+            }
+            (Resolver::List { size, item }, GluinoValue::List(items)) => {
+                if let Some(size) = size {
+                    check_size(size, items.len() as u64, GluinoValueKind::List)?;
+                }
+                GluinoValue::List(
+                    items
+                        .into_iter()
+                        .map(|v| item.resolve(v))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (Resolver::Map { size, key, value }, GluinoValue::Map(entries)) => {
+                if let Some(size) = size {
+                    check_size(size, entries.len() as u64, GluinoValueKind::Map)?;
+                }
+                GluinoValue::Map(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| Ok((key.resolve(k)?, value.resolve(v)?)))
+                        .collect::<Result<_, GluinoDeserializationError>>()?,
+                )
+            }
+            (Resolver::Record(fields), GluinoValue::Record(values)) => {
+                let mut values: Vec<Option<GluinoValue>> = values.into_iter().map(Some).collect();
+                GluinoValue::Record(
+                    fields
+                        .iter()
+                        .map(|field| match field {
+                            Some((index, resolver)) => resolver.resolve(
+                                values[*index]
+                                    .take()
+                                    .expect("Writer fields are read by one reader field"),
+                            ),
+                            None => Ok(GluinoValue::Optional(None)),
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (Resolver::Tuple(fields), GluinoValue::Tuple(values)) => GluinoValue::Tuple(
+                fields
+                    .iter()
+                    .zip(values)
+                    .map(|(field, v)| field.resolve(v))
+                    .collect::<Result<_, _>>()?,
+            ),
+            (Resolver::Enum(variants), GluinoValue::Enum(variant_id, value)) => {
+                match &variants[variant_id as usize] {
+                    Ok((reader_variant_id, resolver)) => {
+                        GluinoValue::Enum(*reader_variant_id, Box::new(resolver.resolve(*value)?))
+                    }
+                    Err(variant) => {
+                        return Err(GluinoDeserializationError::UnresolvableEnumVariant(
+                            variant.clone(),
+                        ));
+                    }
+                }
+            }
+            (Resolver::Union(variants), GluinoValue::Union(variant_id, value)) => {
+                match &variants[variant_id as usize] {
+                    Some((reader_variant_id, resolver)) => {
+                        GluinoValue::Union(*reader_variant_id, Box::new(resolver.resolve(*value)?))
+                    }
+                    None => {
+                        return Err(GluinoDeserializationError::UnresolvableUnionVariant(
+                            variant_id,
+                        ));
+                    }
+                }
+            }
+            (Resolver::IntoUnion(reader_variant_id, resolver), value) => {
+                GluinoValue::Union(*reader_variant_id, Box::new(resolver.resolve(value)?))
+            }
+            (Resolver::FromUnion(variants), GluinoValue::Union(variant_id, value)) => {
+                match &variants[variant_id as usize] {
+                    Some(resolver) => resolver.resolve(*value)?,
+                    None => {
+                        return Err(GluinoDeserializationError::UnresolvableUnionVariant(
+                            variant_id,
+                        ));
+                    }
+                }
+            }
+            (Resolver::ConstSet(indexes), GluinoValue::ConstSet(index)) => {
+                match &indexes[index as usize] {
+                    Ok(reader_index) => GluinoValue::ConstSet(*reader_index),
+                    Err(value) => {
+                        return Err(GluinoDeserializationError::UnknownConstSetValue(
+                            value.clone(),
+                        ));
+                    }
+                }
+            }
+            (Resolver::FromConstSet(values), GluinoValue::ConstSet(index)) => {
+                match &values[index as usize] {
+                    Ok(value) => value.clone(),
+                    Err(value) => {
+                        return Err(GluinoDeserializationError::UnknownConstSetValue(
+                            value.clone(),
+                        ));
+                    }
+                }
+            }
+            (Resolver::Named(resolver), value) => resolver.borrow().resolve(value)?,
+            (Resolver::NamedRef(resolver), value) => resolver
+                .upgrade()
+                .expect("Named resolver should outlive its later uses")
+                .borrow()
+                .resolve(value)?,
+            (_, value) => unreachable!(
+                "Writer deserializer produced {:?} for the writer spec",
+                value
+            ),
+        })
+    }
+}
+
+fn check_size(
+    size: &Size,
+    actual_size: u64,
+    size_value_kind: GluinoValueKind,
+) -> Result<(), GluinoDeserializationError> {
+    if size.can_be(actual_size) {
+        Ok(())
+    } else {
+        Err(GluinoDeserializationError::IncorrectDataSize {
+            expected_size: size.clone(),
+            actual_size,
+            size_value_kind,
+        })
+    }
+}
+
+fn widen_integer(value: GluinoValue, n: u8, signed: bool) -> GluinoValue {
+    let (mut bytes, negative) = match value {
+        GluinoValue::Int8(v) => (v.to_le_bytes().to_vec(), v < 0),
+        GluinoValue::Int16(v) => (v.to_le_bytes().to_vec(), v < 0),
+        GluinoValue::Int32(v) => (v.to_le_bytes().to_vec(), v < 0),
+        GluinoValue::Int64(v) => (v.to_le_bytes().to_vec(), v < 0),
+        GluinoValue::Int128(v) => (v.to_le_bytes().to_vec(), v < 0),
+        GluinoValue::Uint8(v) => (v.to_le_bytes().to_vec(), false),
+        GluinoValue::Uint16(v) => (v.to_le_bytes().to_vec(), false),
+        GluinoValue::Uint32(v) => (v.to_le_bytes().to_vec(), false),
+        GluinoValue::Uint64(v) => (v.to_le_bytes().to_vec(), false),
+        GluinoValue::Uint128(v) => (v.to_le_bytes().to_vec(), false),
+        GluinoValue::BigInt(_, bytes) => {
+            let negative = bytes.last().is_some_and(|b| b & 0x80 != 0);
+            (bytes, negative)
+        }
+        GluinoValue::BigUint(_, bytes) => (bytes, false),
+        value => unreachable!(
+            "Writer deserializer produced {:?} for an integer spec",
+            value
+        ),
+    };
+    // the builder only widens, 2^n is at least the writer's byte count
+    bytes.resize(1 << n, if negative { 0xFF } else { 0x00 });
+    match (signed, n) {
+        (true, 0) => GluinoValue::Int8(i8::from_le_bytes(bytes.try_into().unwrap())),
+        (true, 1) => GluinoValue::Int16(i16::from_le_bytes(bytes.try_into().unwrap())),
+        (true, 2) => GluinoValue::Int32(i32::from_le_bytes(bytes.try_into().unwrap())),
+        (true, 3) => GluinoValue::Int64(i64::from_le_bytes(bytes.try_into().unwrap())),
+        (true, 4) => GluinoValue::Int128(i128::from_le_bytes(bytes.try_into().unwrap())),
+        (true, n) => GluinoValue::BigInt(n, bytes),
+        (false, 0) => GluinoValue::Uint8(bytes[0]),
+        (false, 1) => GluinoValue::Uint16(u16::from_le_bytes(bytes.try_into().unwrap())),
+        (false, 2) => GluinoValue::Uint32(u32::from_le_bytes(bytes.try_into().unwrap())),
+        (false, 3) => GluinoValue::Uint64(u64::from_le_bytes(bytes.try_into().unwrap())),
+        (false, 4) => GluinoValue::Uint128(u128::from_le_bytes(bytes.try_into().unwrap())),
+        (false, n) => GluinoValue::BigUint(n, bytes),
+    }
+}
+
+fn widen_float(value: GluinoValue, fmt: &InterchangeBinaryFloatingPointFormat) -> GluinoValue {
+    let v = match value {
+        GluinoValue::Float(F32(v)) => v,
+        GluinoValue::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Half, bytes) => {
+            half_to_f32(u16::from_le_bytes([bytes[0], bytes[1]]))
+        }
+        value => unreachable!(
+            "Writer deserializer produced {:?} for a widened float spec",
+            value
+        ),
+    };
+    match fmt {
+        InterchangeBinaryFloatingPointFormat::Single => GluinoValue::Float(F32(v)),
+        InterchangeBinaryFloatingPointFormat::Double => GluinoValue::Double(F64(v as f64)),
+        fmt => unreachable!("Floats are only widened to single or double, not {:?}", fmt),
+    }
+}
+
+// every half precision value is exact in single precision
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1F) as u32;
+    let mantissa = (bits & 0x3FF) as u32;
+    match (exponent, mantissa) {
+        (0, 0) => f32::from_bits(sign),
+        (0, _) => {
+            let v = mantissa as f32 * 2f32.powi(-24);
+            if sign == 0 { v } else { -v }
+        }
+        (0x1F, 0) => f32::from_bits(sign | 0x7F80_0000),
+        (0x1F, _) => f32::from_bits(sign | 0x7FC0_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+struct ResolverBuilder {
+    path: SpecPath,
+    errors: Vec<SpecResolutionError>,
+    knots: HashMap<(SpecFingerprint, SpecFingerprint), Weak<RefCell<Resolver>>>,
+}
+
+impl ResolverBuilder {
+    fn build(&mut self, writer: &Spec, reader: &Spec) -> Resolver {
+        if writer.fingerprint() == reader.fingerprint() {
+            return Resolver::Identity;
+        }
+        if !matches!(writer.spec_type(), SpecType::Name(_))
+            && !matches!(reader.spec_type(), SpecType::Name(_))
+        {
+            return self.build_resolved(writer, reader);
+        }
+        let key = (writer.fingerprint().clone(), reader.fingerprint().clone());
+        // a resolver only built to resolve const values may have been dropped since
+        if let Some(resolver) = self.knots.get(&key)
+            && resolver.strong_count() > 0
+        {
+            return Resolver::NamedRef(resolver.clone());
+        }
+        // tie the knot: recursive pairs of these specs will share this resolver
+        let named_resolver = Rc::new(RefCell::new(Resolver::Identity));
+        self.knots.insert(key, Rc::downgrade(&named_resolver));
+        let resolver = self.build_resolved(&writer.resolve(), &reader.resolve());
+        *named_resolver.borrow_mut() = resolver;
+        Resolver::Named(named_resolver)
+    }
+
+    fn at<T>(&mut self, segment: PathSegment, f: impl FnOnce(&mut Self) -> T) -> T {
+        let len = self.path.push(segment);
+        let result = f(self);
+        self.path.truncate(len);
+        result
+    }
+
+    fn error(&mut self, error: impl FnOnce(SpecPath) -> SpecResolutionError) {
+        self.errors.push(error(self.path.clone()))
+    }
+
+    fn kind_mismatch(&mut self, writer: &Spec, reader: &Spec) -> Resolver {
+        self.error(|path| SpecResolutionError::KindMismatch {
+            path,
+            writer_kind: writer.kind(),
+            reader_kind: reader.kind(),
+        });
+        Resolver::Identity
+    }
+
+    fn narrowing(&mut self, writer: &Spec, reader: &Spec) -> Resolver {
+        self.error(|path| SpecResolutionError::Narrowing {
+            path,
+            writer_spec: writer.to_parsed_spec(),
+            reader_spec: reader.to_parsed_spec(),
+        });
+        Resolver::Identity
+    }
+
+    fn sized(&mut self, writer_size: &Size, reader_size: &Size) -> Option<Size> {
+        if reader_size.covers(writer_size) {
+            None
+        } else {
+            self.error(|path| SpecResolutionError::SizeNotCovered {
+                path,
+                writer_size: writer_size.clone(),
+                reader_size: reader_size.clone(),
+            });
+            Some(reader_size.clone())
+        }
+    }
+
+    // builds without keeping errors or knots unless every writer value can be read
+    fn try_build(&mut self, writer: &Spec, reader: &Spec) -> Option<Resolver> {
+        let errors = std::mem::take(&mut self.errors);
+        let knots: HashSet<_> = self.knots.keys().cloned().collect();
+        let resolver = self.build(writer, reader);
+        let tried_errors = std::mem::replace(&mut self.errors, errors);
+        if tried_errors.is_empty() {
+            Some(resolver)
+        } else {
+            self.knots.retain(|key, _| knots.contains(key));
+            None
+        }
+    }
+
+    fn build_resolved(&mut self, writer: &Spec, reader: &Spec) -> Resolver {
+        match (writer.spec_type(), reader.spec_type()) {
+            (SpecType::Union(writer_variants), SpecType::Union(reader_variants)) => {
+                Resolver::Union(
+                    writer_variants
+                        .iter()
+                        .enumerate()
+                        .map(|(variant_id, writer_variant)| {
+                            let resolved = self.at(PathSegment::Index(variant_id), |b| {
+                                reader_variants.iter().enumerate().find_map(
+                                    |(reader_id, reader_variant)| {
+                                        b.try_build(writer_variant, reader_variant)
+                                            .map(|resolver| (reader_id as u64, resolver))
+                                    },
+                                )
+                            });
+                            if resolved.is_none() {
+                                self.error(|path| SpecResolutionError::UnresolvableUnionVariant {
+                                    path,
+                                    variant_id: variant_id as u64,
+                                });
+                            }
+                            resolved
+                        })
+                        .collect(),
+                )
+            }
+            (SpecType::Union(writer_variants), _) => Resolver::FromUnion(
+                writer_variants
+                    .iter()
+                    .enumerate()
+                    .map(|(variant_id, writer_variant)| {
+                        let resolved = self.at(PathSegment::Index(variant_id), |b| {
+                            b.try_build(writer_variant, reader)
+                        });
+                        if resolved.is_none() {
+                            self.error(|path| SpecResolutionError::UnresolvableUnionVariant {
+                                path,
+                                variant_id: variant_id as u64,
+                            });
+                        }
+                        resolved
+                    })
+                    .collect(),
+            ),
+            (_, SpecType::Union(reader_variants)) => {
+                match reader_variants
+                    .iter()
+                    .enumerate()
+                    .find_map(|(reader_id, reader_variant)| {
+                        self.try_build(writer, reader_variant)
+                            .map(|resolver| (reader_id as u64, resolver))
+                    }) {
+                    Some((reader_id, resolver)) => {
+                        Resolver::IntoUnion(reader_id, Box::new(resolver))
+                    }
+                    None => self.kind_mismatch(writer, reader),
+                }
+            }
+            (
+                SpecType::ConstSet(writer_const_spec, writer_values),
+                SpecType::ConstSet(reader_const_spec, reader_values),
+            ) => {
+                let const_resolver = self.try_build(writer_const_spec, reader_const_spec);
+                Resolver::ConstSet(
+                    writer_values
+                        .iter()
+                        .map(|writer_value| {
+                            let reader_index = const_resolver
+                                .as_ref()
+                                .and_then(|resolver| resolver.resolve(writer_value.clone()).ok())
+                                .and_then(|reader_value| {
+                                    reader_values.iter().position(|v| v == &reader_value)
+                                });
+                            match reader_index {
+                                Some(reader_index) => Ok(reader_index as u64),
+                                None => {
+                                    self.error(|path| {
+                                        SpecResolutionError::MissingReaderConstSetValue {
+                                            path,
+                                            value: writer_value.clone(),
+                                        }
+                                    });
+                                    Err(writer_value.clone())
+                                }
+                            }
+                        })
+                        .collect(),
+                )
+            }
+            (SpecType::ConstSet(writer_const_spec, writer_values), _) => {
+                let const_resolver = self.try_build(writer_const_spec, reader);
+                Resolver::FromConstSet(
+                    writer_values
+                        .iter()
+                        .map(|writer_value| {
+                            match const_resolver
+                                .as_ref()
+                                .and_then(|resolver| resolver.resolve(writer_value.clone()).ok())
+                            {
+                                Some(reader_value) => Ok(reader_value),
+                                None => {
+                                    self.error(|path| {
+                                        SpecResolutionError::MissingReaderConstSetValue {
+                                            path,
+                                            value: writer_value.clone(),
+                                        }
+                                    });
+                                    Err(writer_value.clone())
+                                }
+                            }
+                        })
+                        .collect(),
+                )
+            }
+            (SpecType::Optional(writer_inner), SpecType::Optional(reader_inner)) => {
+                Resolver::Optional(Box::new(self.build(writer_inner, reader_inner)))
+            }
+            (_, SpecType::Optional(reader_inner)) => {
+                Resolver::Some(Box::new(self.build(writer, reader_inner)))
+            }
+            (SpecType::Void, SpecType::Void) | (SpecType::Bool, SpecType::Bool) => {
+                Resolver::Identity
+            }
+            (SpecType::Uint(w), SpecType::Uint(r)) if w <= r => Resolver::Uint(*r),
+            (SpecType::Uint(w), SpecType::Int(r)) if w < r => Resolver::Int(*r),
+            (SpecType::Int(w), SpecType::Int(r)) if w <= r => Resolver::Int(*r),
+            (SpecType::Uint(_) | SpecType::Int(_), SpecType::Uint(_) | SpecType::Int(_)) => {
+                self.narrowing(writer, reader)
+            }
+            (SpecType::BinaryFloatingPoint(w), SpecType::BinaryFloatingPoint(r)) => match (w, r) {
+                (w, r) if w == r => Resolver::Identity,
+                (
+                    InterchangeBinaryFloatingPointFormat::Half,
+                    InterchangeBinaryFloatingPointFormat::Single
+                    | InterchangeBinaryFloatingPointFormat::Double,
+                )
+                | (
+                    InterchangeBinaryFloatingPointFormat::Single,
+                    InterchangeBinaryFloatingPointFormat::Double,
+                ) => Resolver::Float(r.clone()),
+                _ => self.narrowing(writer, reader),
+            },
+            (SpecType::DecimalFloatingPoint(w), SpecType::DecimalFloatingPoint(r)) if w == r => {
+                Resolver::Identity
+            }
+            (SpecType::Decimal(w), SpecType::Decimal(r))
+                if w.scale == r.scale && w.precision <= r.precision =>
+            {
+                Resolver::Identity
+            }
+            (SpecType::DecimalFloatingPoint(_), SpecType::DecimalFloatingPoint(_))
+            | (SpecType::Decimal(_), SpecType::Decimal(_)) => self.narrowing(writer, reader),
+            (
+                SpecType::String(writer_size, writer_encoding),
+                SpecType::String(reader_size, reader_encoding),
+            ) => {
+                if writer_encoding != reader_encoding {
+                    self.error(|path| SpecResolutionError::StringEncodingMismatch {
+                        path,
+                        writer_encoding: writer_encoding.clone(),
+                        reader_encoding: reader_encoding.clone(),
+                    });
+                }
+                match self.sized(writer_size, reader_size) {
+                    Some(size) => Resolver::Sized(size),
+                    None => Resolver::Identity,
+                }
+            }
+            (SpecType::Bytes(writer_size), SpecType::Bytes(reader_size)) => {
+                match self.sized(writer_size, reader_size) {
+                    Some(size) => Resolver::Sized(size),
+                    None => Resolver::Identity,
+                }
+            }
+            (
+                SpecType::List {
+                    size: writer_size,
+                    value_spec: writer_value,
+                },
+                SpecType::List {
+                    size: reader_size,
+                    value_spec: reader_value,
+                },
+            ) => Resolver::List {
+                size: self.sized(writer_size, reader_size),
+                item: Box::new(self.at(PathSegment::Item, |b| b.build(writer_value, reader_value))),
+            },
+            (
+                SpecType::Map {
+                    size: writer_size,
+                    key_spec: writer_key,
+                    value_spec: writer_value,
+                },
+                SpecType::Map {
+                    size: reader_size,
+                    key_spec: reader_key,
+                    value_spec: reader_value,
+                },
+            ) => Resolver::Map {
+                size: self.sized(writer_size, reader_size),
+                key: Box::new(self.at(PathSegment::Key, |b| b.build(writer_key, reader_key))),
+                value: Box::new(
+                    self.at(PathSegment::Item, |b| b.build(writer_value, reader_value)),
+                ),
+            },
+            (
+                SpecType::Record {
+                    field_to_spec: writer_field_to_spec,
+                    field_to_index: writer_field_to_index,
+                    ..
+                },
+                SpecType::Record {
+                    fields: reader_fields,
+                    field_to_spec: reader_field_to_spec,
+                    ..
+                },
+            ) => Resolver::Record(
+                reader_fields
+                    .iter()
+                    .map(|field| {
+                        let reader_field_spec = reader_field_to_spec.get(field).unwrap();
+                        match writer_field_to_index.get(field) {
+                            Some(writer_index) => Some((
+                                *writer_index,
+                                self.at(PathSegment::Field(field), |b| {
+                                    b.build(
+                                        writer_field_to_spec.get(field).unwrap(),
+                                        reader_field_spec,
+                                    )
+                                }),
+                            )),
+                            None => {
+                                if !matches!(
+                                    reader_field_spec.resolve().spec_type(),
+                                    SpecType::Optional(_)
+                                ) {
+                                    self.error(|path| SpecResolutionError::MissingReaderField {
+                                        path,
+                                        field: field.clone(),
+                                    });
+                                }
+                                None
+                            }
+                        }
+                    })
+                    .collect(),
+            ),
+            (SpecType::Tuple(writer_fields), SpecType::Tuple(reader_fields)) => {
+                if writer_fields.len() != reader_fields.len() {
+                    self.error(|path| SpecResolutionError::TupleLengthMismatch {
+                        path,
+                        writer_length: writer_fields.len(),
+                        reader_length: reader_fields.len(),
+                    });
+                }
+                Resolver::Tuple(
+                    writer_fields
+                        .iter()
+                        .zip(reader_fields)
+                        .enumerate()
+                        .map(|(i, (writer_field, reader_field))| {
+                            self.at(PathSegment::Index(i), |b| {
+                                b.build(writer_field, reader_field)
+                            })
+                        })
+                        .collect(),
+                )
+            }
+            (
+                SpecType::Enum {
+                    variants: writer_variants,
+                    variant_to_spec: writer_variant_to_spec,
+                },
+                SpecType::Enum {
+                    variants: reader_variants,
+                    variant_to_spec: reader_variant_to_spec,
+                },
+            ) => Resolver::Enum(
+                writer_variants
+                    .iter()
+                    .map(
+                        |variant| match reader_variants.iter().position(|v| v == variant) {
+                            Some(reader_id) => Ok((
+                                reader_id as u64,
+                                self.at(PathSegment::Field(variant), |b| {
+                                    b.build(
+                                        writer_variant_to_spec.get(variant).unwrap(),
+                                        reader_variant_to_spec.get(variant).unwrap(),
+                                    )
+                                }),
+                            )),
+                            None => {
+                                self.error(|path| SpecResolutionError::MissingReaderEnumVariant {
+                                    path,
+                                    variant: variant.clone(),
+                                });
+                                Err(variant.clone())
+                            }
+                        },
+                    )
+                    .collect(),
+            ),
+            _ => self.kind_mismatch(writer, reader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use std::io::Cursor;
+
+    use crate::{serde::get_unit_serialization_function, test_utils::compile};
+
+    use super::*;
+
+    fn resolve(
+        writer: &Spec,
+        reader: &Spec,
+        value: GluinoValue,
+    ) -> Result<GluinoValue, GluinoDeserializationError> {
+        let mut bytes = Vec::new();
+        get_unit_serialization_function::<Vec<u8>>(writer)
+            .serialize(value, &mut bytes)
+            .unwrap();
+        get_resolving_deserialization_function::<Cursor<Vec<u8>>>(writer, reader)
+            .unwrap_or_else(|e| panic!("Unable to resolve: {:?}", e))
+            .deserialize(&mut Cursor::new(bytes))
+    }
+
+    #[test]
+    fn test_record_evolution() {
+        let writer = compile(
+            "record {
+                id: uint(2),
+                name: string,
+                dropped: bool,
+                score: float(single),
+                kind: enum { a: void, b: int(1) },
+            }",
+        );
+        let reader = compile(
+            "record {
+                kind: enum { b: int(3), c: void, a: void },
+                name: string<..64>,
+                added: optional<bool>,
+                id: int(3),
+                score: float(double),
+            }",
+        );
+        let value = |kind| {
+            GluinoValue::Record(vec![
+                GluinoValue::Uint32(7),
+                GluinoValue::String("gluino".into()),
+                GluinoValue::Bool(true),
+                GluinoValue::Float(F32(1.5)),
+                kind,
+            ])
+        };
+        assert_eq!(
+            GluinoValue::Record(vec![
+                GluinoValue::Enum(0, Box::new(GluinoValue::Int64(-2))),
+                GluinoValue::String("gluino".into()),
+                GluinoValue::Optional(None),
+                GluinoValue::Int64(7),
+                GluinoValue::Double(F64(1.5)),
+            ]),
+            resolve(
+                &writer,
+                &reader,
+                value(GluinoValue::Enum(1, Box::new(GluinoValue::Int16(-2))))
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            GluinoValue::Enum(2, Box::new(GluinoValue::Void)),
+            match resolve(
+                &writer,
+                &reader,
+                value(GluinoValue::Enum(0, Box::new(GluinoValue::Void)))
+            )
+            .unwrap()
+            {
+                GluinoValue::Record(fields) => fields[0].clone(),
+                _ => unreachable!(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_widening() {
+        for (writer, reader, value, resolved) in [
+            (
+                "int(0)",
+                "int(4)",
+                GluinoValue::Int8(-1),
+                GluinoValue::Int128(-1),
+            ),
+            (
+                "int(2)",
+                "int(5)",
+                GluinoValue::Int32(-1),
+                GluinoValue::BigInt(5, vec![0xFF; 32]),
+            ),
+            (
+                "uint(0)",
+                "uint(1)",
+                GluinoValue::Uint8(0xFF),
+                GluinoValue::Uint16(0xFF),
+            ),
+            (
+                "uint(0)",
+                "int(1)",
+                GluinoValue::Uint8(0xFF),
+                GluinoValue::Int16(0xFF),
+            ),
+            (
+                "int(5)",
+                "int(6)",
+                GluinoValue::BigInt(5, vec![0x80; 32]),
+                GluinoValue::BigInt(6, {
+                    let mut bytes = vec![0x80; 32];
+                    bytes.resize(64, 0xFF);
+                    bytes
+                }),
+            ),
+            (
+                "float(half)",
+                "float(double)",
+                GluinoValue::BinaryFloatingPoint(
+                    InterchangeBinaryFloatingPointFormat::Half,
+                    vec![0x00, 0xBE],
+                ),
+                GluinoValue::Double(F64(-1.5)),
+            ),
+            (
+                "float(half)",
+                "float(single)",
+                GluinoValue::BinaryFloatingPoint(
+                    InterchangeBinaryFloatingPointFormat::Half,
+                    vec![0x01, 0x00],
+                ),
+                GluinoValue::Float(F32(2f32.powi(-24))),
+            ),
+            (
+                "decimal(4, 2)",
+                "decimal(9, 2)",
+                GluinoValue::Decimal(vec![0x01]),
+                GluinoValue::Decimal(vec![0x01]),
+            ),
+            (
+                "bool",
+                "optional<bool>",
+                GluinoValue::Bool(true),
+                GluinoValue::Optional(Some(Box::new(GluinoValue::Bool(true)))),
+            ),
+            (
+                "uint(0)",
+                "union { string, uint(2) }",
+                GluinoValue::Uint8(3),
+                GluinoValue::Union(1, Box::new(GluinoValue::Uint32(3))),
+            ),
+            (
+                "union { uint(0), bool }",
+                "union { bool, int(1) }",
+                GluinoValue::Union(0, Box::new(GluinoValue::Uint8(3))),
+                GluinoValue::Union(1, Box::new(GluinoValue::Int16(3))),
+            ),
+            (
+                "union { uint(0), uint(1) }",
+                "uint(2)",
+                GluinoValue::Union(1, Box::new(GluinoValue::Uint16(3))),
+                GluinoValue::Uint32(3),
+            ),
+            (
+                "const_set<uint(0)> { 0x01, 0x02 }",
+                "const_set<uint(1)> { 0x0200, 0x0100 }",
+                GluinoValue::ConstSet(0),
+                GluinoValue::ConstSet(1),
+            ),
+            (
+                "const_set<uint(0)> { 0x01, 0x02 }",
+                "uint(1)",
+                GluinoValue::ConstSet(1),
+                GluinoValue::Uint16(2),
+            ),
+            (
+                "list<uint(0), 3>",
+                "list<uint(1), ..5>",
+                GluinoValue::List(vec![GluinoValue::Uint8(1); 3]),
+                GluinoValue::List(vec![GluinoValue::Uint16(1); 3]),
+            ),
+            (
+                "map<string, int(0)>",
+                "map<string, int(1)>",
+                GluinoValue::Map(vec![(
+                    GluinoValue::String("a".into()),
+                    GluinoValue::Int8(1),
+                )]),
+                GluinoValue::Map(vec![(
+                    GluinoValue::String("a".into()),
+                    GluinoValue::Int16(1),
+                )]),
+            ),
+            (
+                "tuple { bool, uint(0) }",
+                "tuple { bool, uint(3) }",
+                GluinoValue::Tuple(vec![GluinoValue::Bool(true), GluinoValue::Uint8(1)]),
+                GluinoValue::Tuple(vec![GluinoValue::Bool(true), GluinoValue::Uint64(1)]),
+            ),
+        ] {
+            assert_eq!(
+                resolved,
+                resolve(&compile(writer), &compile(reader), value).unwrap(),
+                "{} as {}",
+                writer,
+                reader
+            );
+        }
+    }
+
+    #[test]
+    fn test_recursive_resolution() {
+        let writer = compile("name List = record { value: uint(0), next: optional<ref List> }");
+        let reader = compile(
+            "name Chain = record { next: optional<ref Chain>, value: uint(1), label: optional<string> }",
+        );
+        let node = |value, next| {
+            GluinoValue::Record(vec![GluinoValue::Uint8(value), GluinoValue::Optional(next)])
+        };
+        assert_eq!(
+            GluinoValue::Record(vec![
+                GluinoValue::Optional(Some(Box::new(GluinoValue::Record(vec![
+                    GluinoValue::Optional(None),
+                    GluinoValue::Uint16(2),
+                    GluinoValue::Optional(None),
+                ])))),
+                GluinoValue::Uint16(1),
+                GluinoValue::Optional(None),
+            ]),
+            resolve(&writer, &reader, node(1, Some(Box::new(node(2, None))))).unwrap()
+        );
+
+        let (resolver, errors) = build_resolver(&writer, &reader);
+        assert!(errors.is_empty());
+        let Resolver::Named(named_resolver) = &resolver else {
+            panic!("expected a named resolver")
+        };
+        let named_resolver = Rc::downgrade(named_resolver);
+        drop(resolver);
+        assert!(named_resolver.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_resolution_error_kinds() {
+        for kind in SpecResolutionErrorKind::iter() {
+            let (writer, reader, path) = match kind {
+                SpecResolutionErrorKind::KindMismatch => (
+                    "record { a: list<bool> }",
+                    "record { a: list<string> }",
+                    ".a[]",
+                ),
+                SpecResolutionErrorKind::Narrowing => {
+                    ("map<uint(1), bool>", "map<uint(0), bool>", "{}")
+                }
+                SpecResolutionErrorKind::StringEncodingMismatch => (
+                    "tuple { bool, string<ascii> }",
+                    "tuple { bool, string }",
+                    ".1",
+                ),
+                SpecResolutionErrorKind::TupleLengthMismatch => {
+                    ("tuple { bool }", "tuple { bool, bool }", ".")
+                }
+                SpecResolutionErrorKind::MissingReaderField => {
+                    ("record { a: bool }", "record { a: bool, b: bool }", ".")
+                }
+                SpecResolutionErrorKind::SizeNotCovered => {
+                    ("list<bool, 2..5>", "list<bool, 3..>", ".")
+                }
+                SpecResolutionErrorKind::MissingReaderEnumVariant => {
+                    ("enum { a: void, b: bool }", "enum { b: bool }", ".")
+                }
+                SpecResolutionErrorKind::UnresolvableUnionVariant => {
+                    ("union { bool, string }", "union { bool, uint(0) }", ".")
+                }
+                SpecResolutionErrorKind::MissingReaderConstSetValue => (
+                    "const_set<uint(0)> { 0x01, 0x02 }",
+                    "const_set<uint(0)> { 0x01 }",
+                    ".",
+                ),
+            };
+            let (_, errors) = build_resolver(&compile(writer), &compile(reader));
+            assert_eq!(1, errors.len(), "{} as {}: {:?}", writer, reader, errors);
+            assert_eq!(kind, SpecResolutionErrorKind::from(&errors[0]));
+            assert_eq!(path, errors[0].path().to_string());
+            assert_eq!(
+                errors[0].is_data_dependent(),
+                get_resolving_deserialization_function::<Cursor<Vec<u8>>>(
+                    &compile(writer),
+                    &compile(reader)
+                )
+                .is_ok()
+            );
+        }
+    }
+
+    #[test]
+    fn test_data_dependent_failures() {
+        let check = |writer: &str, reader: &str, value, expected_kind| {
+            let writer = compile(writer);
+            let mut bytes = Vec::new();
+            get_unit_serialization_function::<Vec<u8>>(&writer)
+                .serialize(value, &mut bytes)
+                .unwrap();
+            let e = get_resolving_deserialization_function::<Cursor<Vec<u8>>>(
+                &writer,
+                &compile(reader),
+            )
+            .unwrap()
+            .deserialize(&mut Cursor::new(bytes))
+            .unwrap_err();
+            assert_eq!(expected_kind, e.kind(), "{}", e);
+        };
+        use super::super::GluinoDeserializationErrorKind;
+        check(
+            "list<bool, 2..5>",
+            "list<bool, 3..>",
+            GluinoValue::List(vec![GluinoValue::Bool(true); 2]),
+            GluinoDeserializationErrorKind::IncorrectDataSize,
+        );
+        check(
+            "enum { a: void, b: bool }",
+            "enum { b: bool }",
+            GluinoValue::Enum(0, Box::new(GluinoValue::Void)),
+            GluinoDeserializationErrorKind::UnresolvableEnumVariant,
+        );
+        check(
+            "union { bool, string }",
+            "union { bool, uint(0) }",
+            GluinoValue::Union(1, Box::new(GluinoValue::String("a".into()))),
+            GluinoDeserializationErrorKind::UnresolvableUnionVariant,
+        );
+        check(
+            "const_set<uint(0)> { 0x01, 0x02 }",
+            "const_set<uint(0)> { 0x01 }",
+            GluinoValue::ConstSet(1),
+            GluinoDeserializationErrorKind::UnknownConstSetValue,
+        );
+    }
+}
@@ -4,6 +4,7 @@ use crate::{
     spec_parsing::{
         combine, InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, Size,
     },
+    spec_path::PathSegment,
     util::{variable_length_encode_u64, WriteAllReturnSize},
 };
 
@@ -57,33 +58,6 @@ where
     }
 }
 
-pub(crate) struct BoolGluinoValueSer;
-
-impl<W> GluinoValueSer<W> for BoolGluinoValueSer
-where
-    W: Write,
-{
-    #[inline]
-    fn serialize(
-        &self,
-        value: GluinoValue,
-        writer: &mut W,
-    ) -> Result<usize, GluinoSerializationError> {
-        if let GluinoValue::Bool(b) = value {
-            Ok(if b {
-                writer.write_all_size(&[1])?
-            } else {
-                writer.write_all_size(&[0])?
-            })
-        } else {
-            Err(GluinoSerializationError::ValueKindMismatch {
-                expected_value_kind: GluinoValueKind::Bool,
-                actual_value_kind: value.into(),
-            })
-        }
-    }
-}
-
 pub(crate) struct NativeSingleSer<E: Encodable> {
     _d: PhantomData<E>,
 }
@@ -122,12 +96,12 @@ where
         value: GluinoValue,
         writer: &mut W,
     ) -> Result<usize, GluinoSerializationError> {
-        if let GluinoValue::BigInt(v, bytes) = value {
-            if bytes.len() >> self.n == 1 {
+        if let GluinoValue::BigInt(_, bytes) = value {
+            if 1usize.checked_shl(self.n as u32) == Some(bytes.len()) {
                 Ok(writer.write_all_size(&bytes[..])?)
             } else {
                 Err(GluinoSerializationError::InncorrectNumberOfIntegerBytes {
-                    expect_bytes: 1 << self.n,
+                    expect_bytes: 1usize.checked_shl(self.n as u32).unwrap_or(usize::MAX),
                     actual_bytes: bytes.len(),
                 })
             }
@@ -154,13 +128,13 @@ where
         value: GluinoValue,
         writer: &mut W,
     ) -> Result<usize, GluinoSerializationError> {
-        if let GluinoValue::BigUint(v, bytes) = value {
-            if bytes.len() >> v == 1 {
+        if let GluinoValue::BigUint(_, bytes) = value {
+            if 1usize.checked_shl(self.n as u32) == Some(bytes.len()) {
                 Ok(writer.write_all_size(&bytes[..])?)
             } else {
                 // wrong format
                 Err(GluinoSerializationError::InncorrectNumberOfIntegerBytes {
-                    expect_bytes: 1 << self.n,
+                    expect_bytes: 1usize.checked_shl(self.n as u32).unwrap_or(usize::MAX),
                     actual_bytes: bytes.len(),
                 })
             }
@@ -413,10 +387,15 @@ where
                 };
                 values
                     .into_iter()
-                    .map(|(key, value)| {
+                    .enumerate()
+                    .map(|(index, (key, value))| {
                         combine(
-                            self.key_ser.serialize(key, writer),
-                            self.value_ser.serialize(value, writer),
+                            self.key_ser
+                                .serialize(key, writer)
+                                .map_err(|e| e.at(PathSegment::EntryKey(index))),
+                            self.value_ser
+                                .serialize(value, writer)
+                                .map_err(|e| e.at(PathSegment::Element(index))),
                         )
                     })
                     .fold(written, combine)
@@ -460,7 +439,12 @@ where
                 };
                 values
                     .into_iter()
-                    .map(|value: GluinoValue| self.value_ser.serialize(value, writer))
+                    .enumerate()
+                    .map(|(index, value)| {
+                        self.value_ser
+                            .serialize(value, writer)
+                            .map_err(|e| e.at(PathSegment::Element(index)))
+                    })
                     .fold(written, combine)
             } else {
                 Err(GluinoSerializationError::IncorrectDataSize {
@@ -513,6 +497,8 @@ where
 
 pub(crate) struct ProductValueSer<W> {
     pub(crate) field_sers: Vec<Box<dyn GluinoValueSer<W>>>,
+    // record field names for error paths, tuple fields go by index
+    pub(crate) field_names: Option<Vec<String>>,
 }
 
 impl<W> GluinoValueSer<W> for ProductValueSer<W>
@@ -529,7 +515,11 @@ where
                 fields
                     .into_iter()
                     .zip(self.field_sers.iter())
-                    .map(|(field, ser)| ser.serialize(field, writer))
+                    .enumerate()
+                    .map(|(index, (field, ser))| {
+                        ser.serialize(field, writer)
+                            .map_err(|e| e.at(path_segment(&self.field_names, index)))
+                    })
                     .fold(Ok(0), combine)
             } else {
                 Err(GluinoSerializationError::IncorrectNumberOfFields {
@@ -547,6 +537,8 @@ where
 
 pub(crate) struct SumValueSer<W> {
     pub(crate) varient_sers: HashMap<u64, Box<dyn GluinoValueSer<W>>>,
+    // enum variant names for error paths, union variants go by index
+    pub(crate) variant_names: Option<Vec<String>>,
 }
 
 impl<W> GluinoValueSer<W> for SumValueSer<W>
@@ -562,10 +554,12 @@ where
         {
             if let Some(variant_ser) = self.varient_sers.get(&variant_id) {
                 Ok(variable_length_encode_u64(variant_id, writer)?
-                    + variant_ser.serialize(*value, writer)?)
+                    + variant_ser.serialize(*value, writer).map_err(|e| {
+                        e.at(path_segment(&self.variant_names, variant_id as usize))
+                    })?)
             } else {
                 Err(GluinoSerializationError::InvalidVariantId {
-                    variant_id: variant_id.clone() as usize,
+                    variant_id: variant_id as usize,
                     max_variant_id: self.varient_sers.len() - 1,
                 })
             }
@@ -577,6 +571,15 @@ where
     }
 }
 
+// the path segment of a field or variant, by name for records and enums
+#[inline]
+pub(crate) fn path_segment(names: &Option<Vec<String>>, index: usize) -> PathSegment<'_> {
+    match names {
+        Some(names) => PathSegment::Field(&names[index]),
+        None => PathSegment::Index(index),
+    }
+}
+
 pub(crate) struct ConstSetSer<W> {
     pub(crate) const_values: Vec<GluinoValue>,
     pub(crate) const_ser: Box<dyn GluinoValueSer<W>>,
@@ -0,0 +1,669 @@
+//! Skipping encoded values without decoding them.
+//!
+//! Skippers are compiled from a spec once, like the unit deserializers. Values with a fixed width,
+//! e.g. integers or a record of integers, are skipped as one run of bytes, everything else walks
+//! the size prefixes and variant ids it needs to find the end of the value.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Read},
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    spec::{Spec, SpecType},
+    spec_parsing::{Size, StringEncodingFmt},
+    limits::{DecodeLimitError, DecodeLimits},
+    spec_path::PathSegment,
+};
+
+use super::{
+    DecodeReader, GluinoDeserializationError, GluinoValueKind, de_impls::decode_size, decode_u64,
+    ser_impls::path_segment,
+};
+
+pub trait GluinoValueSkip<R>
+where
+    R: Read,
+{
+    /// Advances the reader past one value
+    fn skip(&self, reader: &mut R) -> Result<(), GluinoDeserializationError>;
+}
+// skippers read from a DecodeReader like the unit deserializers, only its depth and collection
+// length limits apply
+pub(crate) type ReaderSkip<R> = Box<dyn for<'r> GluinoValueSkip<DecodeReader<&'r mut R>>>;
+// shared skipper for named specs, allows for recursive specs
+type SharedGluinoValueSkip<R> = Rc<RefCell<ReaderSkip<R>>>;
+
+impl<'r, R> GluinoValueSkip<DecodeReader<&'r mut R>> for SharedGluinoValueSkip<R>
+where
+    R: Read,
+{
+    fn skip(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<(), GluinoDeserializationError> {
+        self.borrow().skip(reader)
+    }
+}
+
+// held by references to a name after its first, which owns the skipper
+type WeakGluinoValueSkip<R> = Weak<RefCell<ReaderSkip<R>>>;
+
+impl<'r, R> GluinoValueSkip<DecodeReader<&'r mut R>> for WeakGluinoValueSkip<R>
+where
+    R: Read,
+{
+    fn skip(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<(), GluinoDeserializationError> {
+        self.upgrade()
+            .expect("Named skip should outlive its recursive references")
+            .borrow()
+            .skip(reader)
+    }
+}
+
+struct PositionedSkip<R> {
+    skip: ReaderSkip<R>,
+    limits: DecodeLimits,
+}
+
+impl<R> GluinoValueSkip<R> for PositionedSkip<R>
+where
+    R: Read,
+{
+    fn skip(&self, reader: &mut R) -> Result<(), GluinoDeserializationError> {
+        let mut reader = DecodeReader::new(reader, self.limits);
+        self.skip.skip(&mut reader).map_err(|e| e.positioned(0))
+    }
+}
+
+pub fn get_skip_function<R>(spec: &Spec) -> Box<dyn GluinoValueSkip<R>>
+where
+    for<'skip> dyn GluinoValueSkip<R>: 'skip,
+    for<'read> R: Read + 'read,
+{
+    get_skip_function_with_limits::<R>(spec, &DecodeLimits::default())
+}
+
+/// Skips values of `spec`, failing on values that go over the depth and collection length of
+/// `limits`
+pub fn get_skip_function_with_limits<R>(
+    spec: &Spec,
+    limits: &DecodeLimits,
+) -> Box<dyn GluinoValueSkip<R>>
+where
+    for<'skip> dyn GluinoValueSkip<R>: 'skip,
+    for<'read> R: Read + 'read,
+{
+    Box::new(PositionedSkip {
+        skip: get_reader_skip_function::<R>(spec),
+        limits: *limits,
+    })
+}
+
+pub(crate) fn get_reader_skip_function<R>(spec: &Spec) -> ReaderSkip<R>
+where
+    for<'read> R: Read + 'read,
+{
+    get_skip_function_internal::<R>(spec, &mut HashMap::new())
+}
+
+fn get_skip_function_internal<R>(
+    spec: &Spec,
+    named_skips: &mut HashMap<String, WeakGluinoValueSkip<R>>,
+) -> ReaderSkip<R>
+where
+    for<'read> R: Read + 'read,
+{
+    if let Some(width) = fixed_width(spec) {
+        return Box::new(FixedWidthSkip { width });
+    }
+    match spec.spec_type() {
+        SpecType::Uint(n) | SpecType::Int(n) => Box::new(UnsupportedIntegerSkip { n: *n }),
+        SpecType::Decimal(_) => Box::new(SizedBytesSkip {
+            spec_size: Size::Variable,
+            size_value_kind: GluinoValueKind::Decimal,
+        }),
+        SpecType::Bytes(size) => Box::new(SizedBytesSkip {
+            spec_size: size.clone(),
+            size_value_kind: GluinoValueKind::Bytes,
+        }),
+        SpecType::String(size, fmt) => Box::new(SizedBytesSkip {
+            spec_size: size.clone(),
+            size_value_kind: match fmt {
+                StringEncodingFmt::Utf8 => GluinoValueKind::String,
+                StringEncodingFmt::Utf16 | StringEncodingFmt::Ascii => {
+                    GluinoValueKind::NonUtf8String
+                }
+            },
+        }),
+        SpecType::List { size, value_spec } => Box::new(ItemsSkip {
+            spec_size: size.clone(),
+            size_value_kind: GluinoValueKind::List,
+            item_width: fixed_width(value_spec),
+            item_skips: vec![get_skip_function_internal::<R>(value_spec, named_skips)],
+        }),
+        SpecType::Map {
+            size,
+            key_spec,
+            value_spec,
+        } => Box::new(ItemsSkip {
+            spec_size: size.clone(),
+            size_value_kind: GluinoValueKind::Map,
+            item_width: fixed_width(key_spec)
+                .zip(fixed_width(value_spec))
+                .and_then(|(key_width, value_width)| key_width.checked_add(value_width)),
+            item_skips: vec![
+                get_skip_function_internal::<R>(key_spec, named_skips),
+                get_skip_function_internal::<R>(value_spec, named_skips),
+            ],
+        }),
+        SpecType::Optional(inner) => Box::new(OptionalSkip {
+            inner_skip: get_skip_function_internal::<R>(inner, named_skips),
+        }),
+        SpecType::Record {
+            fields,
+            field_to_spec,
+            ..
+        } => Box::new(ProductSkip {
+            field_skips: fields
+                .iter()
+                .map(|field| field_to_spec.get(field).unwrap())
+                .map(|spec| get_skip_function_internal::<R>(spec, named_skips))
+                .collect(),
+            field_names: Some(fields.clone()),
+        }),
+        SpecType::Tuple(fields) => Box::new(ProductSkip {
+            field_skips: fields
+                .iter()
+                .map(|spec| get_skip_function_internal::<R>(spec, named_skips))
+                .collect(),
+            field_names: None,
+        }),
+        SpecType::Enum {
+            variants,
+            variant_to_spec,
+        } => Box::new(SumSkip {
+            variant_skips: variants
+                .iter()
+                .map(|variant| variant_to_spec.get(variant).unwrap())
+                .map(|spec| get_skip_function_internal::<R>(spec, named_skips))
+                .collect(),
+            variant_names: Some(variants.clone()),
+        }),
+        SpecType::Union(variants) => Box::new(SumSkip {
+            variant_skips: variants
+                .iter()
+                .map(|spec| get_skip_function_internal::<R>(spec, named_skips))
+                .collect(),
+            variant_names: None,
+        }),
+        SpecType::Name(name) => match named_skips.get(name) {
+            Some(skip) => Box::new(skip.clone()),
+            None => {
+                // tie the knot weakly, a strong reference back to this skip would keep it alive
+                let named_skip: SharedGluinoValueSkip<R> =
+                    Rc::new(RefCell::new(Box::new(FixedWidthSkip { width: 0 })));
+                named_skips.insert(name.clone(), Rc::downgrade(&named_skip));
+                let inner_skip = get_skip_function_internal::<R>(
+                    &spec.named_definition(name),
+                    named_skips,
+                );
+                *named_skip.borrow_mut() = inner_skip;
+                Box::new(named_skip)
+            }
+        },
+        // const values are not checked when skipping
+        SpecType::ConstSet(const_spec, _) => {
+            get_skip_function_internal::<R>(const_spec, named_skips)
+        }
+        _ => unreachable!("Fixed width specs are handled above"),
+    }
+}
+
+/// Number of bytes every value of the spec takes, if it is the same for all of them.
+/// Names are followed, a recursive name is not taken to have a fixed width.
+fn fixed_width(spec: &Spec) -> Option<u64> {
+    fixed_width_within(spec, &mut Vec::new())
+}
+
+// `names` are those being followed
+fn fixed_width_within(spec: &Spec, names: &mut Vec<String>) -> Option<u64> {
+    let mut width = |spec: &Spec| fixed_width_within(spec, names);
+    match spec.spec_type() {
+        SpecType::Void => Some(0),
+        SpecType::Bool => Some(1),
+        SpecType::Uint(n) | SpecType::Int(n) => 1u64.checked_shl(*n as u32),
+        SpecType::BinaryFloatingPoint(fmt) => {
+            Some((fmt.significand_bits() + fmt.exponent_bits()) >> 3)
+        }
+        SpecType::DecimalFloatingPoint(fmt) => Some(fmt.minimum_byes_needed() as u64),
+        SpecType::Bytes(Size::Fixed(n)) | SpecType::String(Size::Fixed(n), _) => Some(*n),
+        SpecType::List {
+            size: Size::Fixed(n),
+            value_spec,
+        } => width(value_spec)?.checked_mul(*n),
+        SpecType::Map {
+            size: Size::Fixed(n),
+            key_spec,
+            value_spec,
+        } => width(key_spec)?
+            .checked_add(width(value_spec)?)?
+            .checked_mul(*n),
+        SpecType::Record { field_to_spec, .. } => field_to_spec
+            .values()
+            .try_fold(0u64, |total, spec| total.checked_add(width(spec)?)),
+        SpecType::Tuple(fields) => fields
+            .iter()
+            .try_fold(0u64, |total, spec| total.checked_add(width(spec)?)),
+        SpecType::ConstSet(const_spec, _) => width(const_spec),
+        SpecType::Name(name) if !names.contains(name) => {
+            names.push(name.clone());
+            let width = fixed_width_within(&spec.named_definition(name), names);
+            names.pop();
+            width
+        }
+        _ => None,
+    }
+}
+
+/// Read past exactly `n` bytes without buffering them all
+#[inline]
+fn skip_n_bytes<R: Read>(n: u64, reader: &mut R) -> Result<(), GluinoDeserializationError> {
+    if io::copy(&mut reader.take(n), &mut io::sink())? < n {
+        Err(GluinoDeserializationError::UnexpectedEndOfBytes)
+    } else {
+        Ok(())
+    }
+}
+
+/// Skip a part of a value, one level deeper
+#[inline]
+pub(crate) fn skip_nested<R: Read>(
+    skip: &ReaderSkip<R>,
+    reader: &mut DecodeReader<&mut R>,
+) -> Result<(), GluinoDeserializationError> {
+    reader.budget.enter()?;
+    skip.skip(reader)?;
+    reader.budget.exit();
+    Ok(())
+}
+
+struct FixedWidthSkip {
+    width: u64,
+}
+
+impl<R> GluinoValueSkip<R> for FixedWidthSkip
+where
+    R: Read,
+{
+    fn skip(&self, reader: &mut R) -> Result<(), GluinoDeserializationError> {
+        skip_n_bytes(self.width, reader)
+    }
+}
+
+struct UnsupportedIntegerSkip {
+    n: u8,
+}
+
+impl<R> GluinoValueSkip<R> for UnsupportedIntegerSkip
+where
+    R: Read,
+{
+    fn skip(&self, _: &mut R) -> Result<(), GluinoDeserializationError> {
+        Err(GluinoDeserializationError::UnsupportedIntegerSize(self.n))
+    }
+}
+
+struct SizedBytesSkip {
+    spec_size: Size,
+    size_value_kind: GluinoValueKind,
+}
+
+impl<R> GluinoValueSkip<R> for SizedBytesSkip
+where
+    R: Read,
+{
+    fn skip(&self, reader: &mut R) -> Result<(), GluinoDeserializationError> {
+        let size = decode_size(&self.spec_size, self.size_value_kind, reader)?;
+        skip_n_bytes(size, reader)
+    }
+}
+
+struct ItemsSkip<R> {
+    spec_size: Size,
+    size_value_kind: GluinoValueKind,
+    // all items are skipped at once when they have a fixed width
+    item_width: Option<u64>,
+    // one per value of an item, key and value for maps
+    item_skips: Vec<ReaderSkip<R>>,
+}
+
+impl<'r, R> GluinoValueSkip<DecodeReader<&'r mut R>> for ItemsSkip<R>
+where
+    R: Read,
+{
+    fn skip(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<(), GluinoDeserializationError> {
+        let size = decode_size(&self.spec_size, self.size_value_kind, reader)?;
+        // zero width items take no time to skip, so the count is checked like when deserializing
+        reader.budget.collection_length(size)?;
+        if let Some(width) = self.item_width
+            && let Some(total_width) = width.checked_mul(size)
+        {
+            return skip_n_bytes(total_width, reader);
+        }
+        let size = usize::try_from(size).map_err(|_| DecodeLimitError::MaxCollectionLengthExceeded {
+            length: size,
+            max_collection_length: usize::MAX as u64,
+        })?;
+        for index in 0..size {
+            for (n, skip) in self.item_skips.iter().enumerate() {
+                let segment = if n + 1 < self.item_skips.len() {
+                    PathSegment::EntryKey(index)
+                } else {
+                    PathSegment::Element(index)
+                };
+                let offset = reader.offset();
+                skip_nested(skip, reader).map_err(|e| e.within(segment, offset))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct OptionalSkip<R> {
+    inner_skip: ReaderSkip<R>,
+}
+
+impl<'r, R> GluinoValueSkip<DecodeReader<&'r mut R>> for OptionalSkip<R>
+where
+    R: Read,
+{
+    fn skip(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<(), GluinoDeserializationError> {
+        let mut flag = [0u8];
+        reader.read_exact(&mut flag)?;
+        match flag[0] {
+            0 => Ok(()),
+            1 => {
+                let offset = reader.offset();
+                skip_nested(&self.inner_skip, reader).map_err(|e| e.positioned(offset))
+            }
+            b => Err(GluinoDeserializationError::InvalidOptionalFlag(b)),
+        }
+    }
+}
+
+struct ProductSkip<R> {
+    field_skips: Vec<ReaderSkip<R>>,
+    field_names: Option<Vec<String>>,
+}
+
+impl<'r, R> GluinoValueSkip<DecodeReader<&'r mut R>> for ProductSkip<R>
+where
+    R: Read,
+{
+    fn skip(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<(), GluinoDeserializationError> {
+        for (index, skip) in self.field_skips.iter().enumerate() {
+            let offset = reader.offset();
+            skip_nested(skip, reader)
+                .map_err(|e| e.within(path_segment(&self.field_names, index), offset))?;
+        }
+        Ok(())
+    }
+}
+
+struct SumSkip<R> {
+    variant_skips: Vec<ReaderSkip<R>>,
+    variant_names: Option<Vec<String>>,
+}
+
+impl<'r, R> GluinoValueSkip<DecodeReader<&'r mut R>> for SumSkip<R>
+where
+    R: Read,
+{
+    fn skip(
+        &self,
+        reader: &mut DecodeReader<&'r mut R>,
+    ) -> Result<(), GluinoDeserializationError> {
+        let variant_id = decode_u64(reader)?;
+        // checked before indexing, on 32 bit targets a large id would wrap around to a valid one
+        let variant = usize::try_from(variant_id)
+            .ok()
+            .and_then(|index| Some((index, self.variant_skips.get(index)?)));
+        match variant {
+            Some((index, skip)) => {
+                let offset = reader.offset();
+                skip_nested(skip, reader)
+                    .map_err(|e| e.within(path_segment(&self.variant_names, index), offset))
+            }
+            None => Err(GluinoDeserializationError::InvalidVariantId {
+                variant_id: usize::try_from(variant_id).unwrap_or(usize::MAX),
+                max_variant_id: self.variant_skips.len().saturating_sub(1),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use strum::IntoEnumIterator;
+
+    use crate::{
+        serde::{GluinoDeserializationErrorKind, GluinoValue},
+        limits::DecodeLimitErrorKind,
+        spec_parsing::{ParsedSpec, SpecKind},
+        test_utils::{compile, encode, get_valid_specs_for_kind},
+        util::variable_length_encode_u64,
+    };
+
+    use super::super::tests::sample_value;
+    use super::*;
+
+    // skips one value and returns where the reader ended up
+    fn skip(spec: &Spec, bytes: Vec<u8>) -> Result<u64, GluinoDeserializationErrorKind> {
+        let mut reader = Cursor::new(bytes);
+        get_skip_function::<Cursor<Vec<u8>>>(spec)
+            .skip(&mut reader)
+            .map_err(|e| e.kind())?;
+        Ok(reader.position())
+    }
+
+    #[test]
+    fn test_skip_all_kinds() {
+        for spec_kind in SpecKind::iter() {
+            for parsed_spec in get_valid_specs_for_kind(spec_kind) {
+                if let ParsedSpec::Uint(n) | ParsedSpec::Int(n) = parsed_spec {
+                    // 2^n bytes
+                    if n > 10 {
+                        continue;
+                    }
+                }
+                let spec = Spec::compile(parsed_spec).expect("Unable to compile");
+                let value = sample_value(&spec, spec.named_schema(), 0);
+                let mut bytes = encode(&spec, value);
+                let len = bytes.len() as u64;
+                bytes.extend_from_slice(&[0xFF; 4]);
+                assert_eq!(Ok(len), skip(&spec, bytes), "{}", spec.to_text());
+            }
+        }
+    }
+
+    #[test]
+    fn test_recursive_skip_dropped() {
+        let spec = Spec::compile(
+            ParsedSpec::from_text("name List = optional<tuple { uint(0), ref List }>").unwrap(),
+        )
+        .unwrap();
+        let mut named_skips = HashMap::new();
+        let skip = get_skip_function_internal::<Cursor<Vec<u8>>>(&spec, &mut named_skips);
+        let named_skip = named_skips.remove("List").unwrap();
+        assert!(named_skip.upgrade().is_some());
+        drop(skip);
+        assert!(named_skip.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_fixed_width() {
+        let width = |text: &str| fixed_width(&compile(text));
+        assert_eq!(Some(0), width("void"));
+        assert_eq!(
+            Some(15),
+            width("record { a: uint(3), b: tuple { bool, int(1) }, c: bytes<4> }")
+        );
+        assert_eq!(Some(12), width("list<uint(2), 3>"));
+        assert_eq!(Some(0), width("list<uint(2), 0>"));
+        assert_eq!(Some(1), width("const_set<uint(0)> { 0x01, 0x02 }"));
+        assert_eq!(None, width("list<uint(2)>"));
+        assert_eq!(None, width("optional<bool>"));
+        assert_eq!(None, width("enum { a: void }"));
+        assert_eq!(None, width("uint(64)"));
+        assert_eq!(
+            Some(4),
+            width("record { a: name Pair = tuple { uint(0), uint(0) }, b: ref Pair }")
+        );
+        assert_eq!(Some(0), width("list<name Unit = void, 3>"));
+        assert_eq!(None, width("name List = optional<ref List>"));
+    }
+
+    #[test]
+    fn test_skip_values() {
+        let spec = compile(
+            "record { id: uint(3), tags: list<string>, scores: map<string, int(2)>, \
+             points: list<tuple { int(2), int(2) }>, side: enum { buy: void, sell: uint(0) } }",
+        );
+        let value = GluinoValue::Record(vec![
+            GluinoValue::Uint64(1),
+            GluinoValue::List(vec![GluinoValue::String("a".to_string()); 5]),
+            GluinoValue::Map(vec![(
+                GluinoValue::String("b".to_string()),
+                GluinoValue::Int32(-1),
+            )]),
+            GluinoValue::List(vec![GluinoValue::Tuple(vec![GluinoValue::Int32(2); 2]); 10]),
+            GluinoValue::Enum(1, Box::new(GluinoValue::Uint8(3))),
+        ]);
+        let bytes = encode(&spec, value);
+        let len = bytes.len() as u64;
+        assert_eq!(Ok(len), skip(&spec, bytes.clone()));
+        assert_eq!(
+            Err(GluinoDeserializationErrorKind::UnexpectedEndOfBytes),
+            skip(&spec, bytes[..bytes.len() - 1].to_vec())
+        );
+
+        let recursive = compile("name Tree = record { value: int(2), children: list<ref Tree> }");
+        let leaf = || GluinoValue::Record(vec![GluinoValue::Int32(0), GluinoValue::List(vec![])]);
+        let tree = GluinoValue::Record(vec![
+            GluinoValue::Int32(1),
+            GluinoValue::List(vec![
+                leaf(),
+                GluinoValue::Record(vec![GluinoValue::Int32(2), GluinoValue::List(vec![leaf()])]),
+            ]),
+        ]);
+        let bytes = encode(&recursive, tree);
+        let len = bytes.len() as u64;
+        assert_eq!(Ok(len), skip(&recursive, bytes));
+    }
+
+    #[test]
+    fn test_skip_errors() {
+        assert_eq!(
+            Err(GluinoDeserializationErrorKind::InvalidVariantId),
+            skip(&compile("union { bool, string }"), vec![0x02, 0x01])
+        );
+        assert_eq!(
+            Err(GluinoDeserializationErrorKind::IncorrectDataSize),
+            skip(&compile("bytes<..4>"), vec![0x05, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(
+            Err(GluinoDeserializationErrorKind::UnexpectedEndOfBytes),
+            skip(
+                &compile("list<uint(3)>"),
+                vec![0x02, 0, 0, 0, 0, 0, 0, 0, 0]
+            )
+        );
+        assert_eq!(
+            Err(GluinoDeserializationErrorKind::UnsupportedIntegerSize),
+            skip(&compile("uint(64)"), vec![0x00])
+        );
+        assert_eq!(
+            Err(GluinoDeserializationErrorKind::InvalidOptionalFlag),
+            skip(&compile("optional<string>"), vec![0x02, 0x00])
+        );
+
+        let error = get_skip_function::<Cursor<Vec<u8>>>(&compile(
+            "record { id: uint(0), tags: map<string, union { bool, string }> }",
+        ))
+        .skip(&mut Cursor::new(vec![0x01, 0x02, 0x01, 0x61, 0x00, 0x01, 0x01, 0x62, 0x07]))
+        .unwrap_err();
+        assert_eq!(Some(".tags[1]"), error.path().map(|path| path.as_str()));
+        assert_eq!(Some(8), error.offset());
+        assert_eq!(GluinoDeserializationErrorKind::InvalidVariantId, error.kind());
+
+        let mut large_id = Vec::new();
+        variable_length_encode_u64(1 << 40, &mut large_id).unwrap();
+        assert_eq!(
+            Err(GluinoDeserializationErrorKind::InvalidVariantId),
+            skip(&compile("union { bool, string }"), large_id)
+        );
+    }
+
+    #[test]
+    fn test_skip_limits() {
+        // zero width items would otherwise be skipped for as many as the count claims
+        let mut count = Vec::new();
+        variable_length_encode_u64(1 << 40, &mut count).unwrap();
+        for text in ["list<void>", "list<name Unit = void>", "map<void, void>"] {
+            assert_eq!(
+                Err(GluinoDeserializationErrorKind::LimitExceeded),
+                skip(&compile(text), count.clone()),
+                "{}",
+                text
+            );
+        }
+
+        let limits = DecodeLimits {
+            max_depth: 2,
+            max_collection_length: 2,
+            ..DecodeLimits::default()
+        };
+        let skip_with_limits = |text: &str, value: GluinoValue| {
+            let spec = compile(text);
+            let bytes = encode(&spec, value);
+            get_skip_function_with_limits::<Cursor<Vec<u8>>>(&spec, &limits)
+                .skip(&mut Cursor::new(bytes))
+                .map_err(|e| match e.cause() {
+                    GluinoDeserializationError::LimitExceeded(e) => DecodeLimitErrorKind::from(e),
+                    e => panic!("Not a limit error: {}", e),
+                })
+        };
+        let strings = |n| GluinoValue::List(vec![GluinoValue::String("a".to_string()); n]);
+        assert_eq!(Ok(()), skip_with_limits("list<string>", strings(2)));
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxCollectionLengthExceeded),
+            skip_with_limits("list<string>", strings(3))
+        );
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxDepthExceeded),
+            skip_with_limits(
+                "optional<optional<optional<bool>>>",
+                GluinoValue::Optional(Some(Box::new(GluinoValue::Optional(Some(Box::new(
+                    GluinoValue::Optional(Some(Box::new(GluinoValue::Bool(true))))
+                ))))))
+            )
+        );
+    }
+}
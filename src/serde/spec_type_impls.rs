@@ -0,0 +1,200 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    rc::Rc,
+    sync::Arc,
+};
+
+use crate::spec_parsing::{
+    InterchangeBinaryFloatingPointFormat, ParsedSpec, Size, StringEncodingFmt,
+};
+
+use super::GluinoSpecType;
+
+macro_rules! gen_native_spec_types {
+    ($($T:ty => $spec:expr),+ $(,)?) => {
+        $(
+        impl GluinoSpecType for $T {
+            fn get_parsed_spec(_: &mut HashSet<String>) -> ParsedSpec {
+                $spec
+            }
+
+            fn spec_type_name() -> String {
+                stringify!($T).to_string()
+            }
+        }
+        )+
+    }
+}
+
+gen_native_spec_types!(
+    () => ParsedSpec::Void,
+    bool => ParsedSpec::Bool,
+    u8 => ParsedSpec::Uint(0),
+    u16 => ParsedSpec::Uint(1),
+    u32 => ParsedSpec::Uint(2),
+    u64 => ParsedSpec::Uint(3),
+    u128 => ParsedSpec::Uint(4),
+    i8 => ParsedSpec::Int(0),
+    i16 => ParsedSpec::Int(1),
+    i32 => ParsedSpec::Int(2),
+    i64 => ParsedSpec::Int(3),
+    i128 => ParsedSpec::Int(4),
+    f32 => ParsedSpec::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Single),
+    f64 => ParsedSpec::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Double),
+    String => ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8),
+);
+
+// pointers are transparent
+macro_rules! gen_pointer_spec_types {
+    ($($P:ident)+) => {
+        $(
+        impl<T: GluinoSpecType + ?Sized> GluinoSpecType for $P<T> {
+            fn get_parsed_spec(names_defined: &mut HashSet<String>) -> ParsedSpec {
+                T::get_parsed_spec(names_defined)
+            }
+
+            fn spec_type_name() -> String {
+                T::spec_type_name()
+            }
+        }
+        )+
+    }
+}
+
+gen_pointer_spec_types!(Box Rc Arc);
+
+impl<T: GluinoSpecType> GluinoSpecType for Option<T> {
+    fn get_parsed_spec(names_defined: &mut HashSet<String>) -> ParsedSpec {
+        ParsedSpec::Optional(T::get_parsed_spec(names_defined).into())
+    }
+
+    fn spec_type_name() -> String {
+        format!("Option<{}>", T::spec_type_name())
+    }
+}
+
+impl<T: GluinoSpecType> GluinoSpecType for Vec<T> {
+    fn get_parsed_spec(names_defined: &mut HashSet<String>) -> ParsedSpec {
+        ParsedSpec::List {
+            size: Size::Variable,
+            value_spec: T::get_parsed_spec(names_defined).into(),
+        }
+    }
+
+    fn spec_type_name() -> String {
+        format!("Vec<{}>", T::spec_type_name())
+    }
+}
+
+impl<T: GluinoSpecType, const N: usize> GluinoSpecType for [T; N] {
+    fn get_parsed_spec(names_defined: &mut HashSet<String>) -> ParsedSpec {
+        ParsedSpec::List {
+            size: Size::Fixed(N as u64),
+            value_spec: T::get_parsed_spec(names_defined).into(),
+        }
+    }
+
+    fn spec_type_name() -> String {
+        format!("[{}; {}]", T::spec_type_name(), N)
+    }
+}
+
+impl<K: GluinoSpecType, V: GluinoSpecType, S> GluinoSpecType for HashMap<K, V, S> {
+    fn get_parsed_spec(names_defined: &mut HashSet<String>) -> ParsedSpec {
+        ParsedSpec::Map {
+            size: Size::Variable,
+            key_spec: K::get_parsed_spec(names_defined).into(),
+            value_spec: V::get_parsed_spec(names_defined).into(),
+        }
+    }
+
+    fn spec_type_name() -> String {
+        format!("HashMap<{}, {}>", K::spec_type_name(), V::spec_type_name())
+    }
+}
+
+impl<K: GluinoSpecType, V: GluinoSpecType> GluinoSpecType for BTreeMap<K, V> {
+    fn get_parsed_spec(names_defined: &mut HashSet<String>) -> ParsedSpec {
+        ParsedSpec::Map {
+            size: Size::Variable,
+            key_spec: K::get_parsed_spec(names_defined).into(),
+            value_spec: V::get_parsed_spec(names_defined).into(),
+        }
+    }
+
+    fn spec_type_name() -> String {
+        format!("BTreeMap<{}, {}>", K::spec_type_name(), V::spec_type_name())
+    }
+}
+
+macro_rules! gen_tuple_spec_types {
+    ($(($($T:ident)+))+) => {
+        $(
+        impl<$($T: GluinoSpecType),+> GluinoSpecType for ($($T,)+) {
+            fn get_parsed_spec(names_defined: &mut HashSet<String>) -> ParsedSpec {
+                ParsedSpec::Tuple(vec![$($T::get_parsed_spec(names_defined)),+])
+            }
+
+            fn spec_type_name() -> String {
+                let names: Vec<String> = vec![$($T::spec_type_name()),+];
+                match names.as_slice() {
+                    [name] => format!("({},)", name),
+                    names => format!("({})", names.join(", ")),
+                }
+            }
+        }
+        )+
+    }
+}
+
+gen_tuple_spec_types!(
+    (A)
+    (A B)
+    (A B C)
+    (A B C D)
+    (A B C D E)
+    (A B C D E F)
+    (A B C D E F G)
+    (A B C D E F G H)
+);
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        serde::{from_reader, to_writer},
+        spec::Spec,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_native_spec_types_compile() {
+        for spec in [
+            <(u8, i128, bool, ())>::get_spec(),
+            <Vec<Option<String>>>::get_spec(),
+            <[f32; 3]>::get_spec(),
+            <HashMap<String, Box<f64>>>::get_spec(),
+            <BTreeMap<u16, Arc<i8>>>::get_spec(),
+        ] {
+            assert_eq!(spec, Spec::compile(spec.to_parsed_spec()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_native_spec_types_round_trip() {
+        let value: (Vec<Option<String>>, [u32; 2], BTreeMap<i16, bool>) = (
+            vec![Some("gluino".into()), None],
+            [7, 8],
+            BTreeMap::from([(-1, true), (1, false)]),
+        );
+        let spec = <(Vec<Option<String>>, [u32; 2], BTreeMap<i16, bool>)>::get_spec();
+        let mut bytes = Vec::new();
+        to_writer(&spec, &value, &mut bytes).unwrap();
+        assert_eq!(
+            value,
+            from_reader(&spec, &mut Cursor::new(bytes)).unwrap()
+        );
+    }
+}
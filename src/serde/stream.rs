@@ -0,0 +1,278 @@
+//! A stream of values of one spec, each prefixed with its length in bytes as a varint.
+//! The serializer and deserializer of the spec are compiled once for the whole stream.
+
+use std::io::{self, Cursor, Read, Write};
+
+use crate::{limits::DecodeLimitError, spec::Spec, util::variable_length_encode_u64};
+
+use super::{
+    GluinoDeserializationError, GluinoSerializationError, GluinoValue, GluinoValueDe,
+    GluinoValueSer, decode_u64, get_unit_deserialization_function, get_unit_serialization_function,
+};
+
+pub const DEFAULT_MAX_RECORD_LENGTH: u64 = 64 * 1024 * 1024;
+
+pub struct RecordStreamWriter<W: Write> {
+    writer: W,
+    ser: Box<dyn GluinoValueSer<Vec<u8>>>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> RecordStreamWriter<W> {
+    pub fn new(spec: &Spec, writer: W) -> RecordStreamWriter<W> {
+        RecordStreamWriter::with_serializer(
+            get_unit_serialization_function::<Vec<u8>>(spec),
+            writer,
+        )
+    }
+
+    pub fn with_serializer(
+        ser: Box<dyn GluinoValueSer<Vec<u8>>>,
+        writer: W,
+    ) -> RecordStreamWriter<W> {
+        RecordStreamWriter {
+            writer,
+            ser,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Writes the length and the value, returning the number of bytes written.
+    /// Nothing is written if the value does not fit the spec.
+    pub fn write(&mut self, value: GluinoValue) -> Result<usize, GluinoSerializationError> {
+        self.buffer.clear();
+        self.ser.serialize(value, &mut self.buffer)?;
+        let length_size = variable_length_encode_u64(self.buffer.len() as u64, &mut self.writer)?;
+        self.writer.write_all(&self.buffer)?;
+        Ok(length_size + self.buffer.len())
+    }
+
+    pub fn flush(&mut self) -> Result<(), GluinoSerializationError> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Iterates over the values of a stream, stopping after the first error
+pub struct RecordStreamReader<R: Read> {
+    reader: R,
+    de: Box<dyn GluinoValueDe<Cursor<Vec<u8>>>>,
+    max_record_length: u64,
+    buffer: Cursor<Vec<u8>>,
+    failed: bool,
+}
+
+impl<R: Read> RecordStreamReader<R> {
+    pub fn new(spec: &Spec, reader: R) -> RecordStreamReader<R> {
+        RecordStreamReader::with_deserializer(
+            get_unit_deserialization_function::<Cursor<Vec<u8>>>(spec),
+            reader,
+        )
+    }
+
+    /// Reads with any deserializer, e.g. one from `get_resolving_deserialization_function`
+    pub fn with_deserializer(
+        de: Box<dyn GluinoValueDe<Cursor<Vec<u8>>>>,
+        reader: R,
+    ) -> RecordStreamReader<R> {
+        RecordStreamReader {
+            reader,
+            de,
+            max_record_length: DEFAULT_MAX_RECORD_LENGTH,
+            buffer: Cursor::new(Vec::new()),
+            failed: false,
+        }
+    }
+
+    /// Records claiming more bytes fail before anything is read for them
+    pub fn with_max_record_length(mut self, max_record_length: u64) -> RecordStreamReader<R> {
+        self.max_record_length = max_record_length;
+        self
+    }
+
+    /// The next value, `None` at the end of the stream
+    pub fn read(&mut self) -> Result<Option<GluinoValue>, GluinoDeserializationError> {
+        let mut first = [0u8; 1];
+        loop {
+            match self.reader.read(&mut first) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let length = decode_u64(&mut first.chain(&mut self.reader))?;
+        if length > self.max_record_length {
+            return Err(DecodeLimitError::MaxTotalAllocationExceeded(self.max_record_length).into());
+        }
+        let mut bytes = std::mem::take(self.buffer.get_mut());
+        bytes.clear();
+        (&mut self.reader).take(length).read_to_end(&mut bytes)?;
+        if (bytes.len() as u64) < length {
+            return Err(GluinoDeserializationError::UnexpectedEndOfBytes);
+        }
+        self.buffer = Cursor::new(bytes);
+        let value = self.de.deserialize(&mut self.buffer)?;
+        if self.buffer.position() != length {
+            return Err(GluinoDeserializationError::IncorrectValueLength {
+                expected_length: length,
+                actual_length: self.buffer.position(),
+            });
+        }
+        Ok(Some(value))
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for RecordStreamReader<R> {
+    type Item = Result<GluinoValue, GluinoDeserializationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read();
+        self.failed = result.is_err();
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        serde::{GluinoDeserializationErrorKind, get_resolving_deserialization_function},
+        test_utils::{InterruptingReader, compile},
+    };
+
+    use super::*;
+
+    fn event(id: u64) -> GluinoValue {
+        GluinoValue::Record(vec![
+            GluinoValue::Uint64(id),
+            GluinoValue::List(
+                (0..id)
+                    .map(|i| GluinoValue::String(i.to_string()))
+                    .collect(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let spec = compile("record { id: uint(3), tags: list<string> }");
+        let mut writer = RecordStreamWriter::new(&spec, Vec::new());
+        let mut written = 0;
+        for id in 0..200 {
+            written += writer.write(event(id)).unwrap();
+        }
+        assert!(writer.write(GluinoValue::Bool(true)).is_err());
+        let bytes = writer.into_inner();
+        assert_eq!(written, bytes.len());
+        let values: Vec<GluinoValue> = RecordStreamReader::new(&spec, Cursor::new(bytes))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!((0..200).map(event).collect::<Vec<_>>(), values);
+        assert_eq!(
+            0,
+            RecordStreamReader::new(&spec, Cursor::new(Vec::new())).count()
+        );
+    }
+
+    #[test]
+    fn test_interrupted_reads() {
+        let spec = compile("record { id: uint(3), tags: list<string> }");
+        let mut writer = RecordStreamWriter::new(&spec, Vec::new());
+        for id in 0..5 {
+            writer.write(event(id)).unwrap();
+        }
+        let reader =
+            RecordStreamReader::new(&spec, InterruptingReader::new(Cursor::new(writer.into_inner())));
+        assert_eq!(
+            (0..5).map(event).collect::<Vec<_>>(),
+            reader.collect::<Result<Vec<_>, _>>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_resolving_reader() {
+        let writer_spec = compile("record { id: uint(3), tags: list<string> }");
+        let reader_spec = compile("record { id: uint(3), note: optional<string> }");
+        let mut writer = RecordStreamWriter::new(&writer_spec, Vec::new());
+        writer.write(event(2)).unwrap();
+        let reader = RecordStreamReader::with_deserializer(
+            get_resolving_deserialization_function(&writer_spec, &reader_spec).unwrap(),
+            Cursor::new(writer.into_inner()),
+        );
+        assert_eq!(
+            vec![GluinoValue::Record(vec![
+                GluinoValue::Uint64(2),
+                GluinoValue::Optional(None)
+            ])],
+            reader.collect::<Result<Vec<_>, _>>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        let spec = compile("uint(1)");
+        let read = |bytes: Vec<u8>| {
+            RecordStreamReader::new(&spec, Cursor::new(bytes))
+                .map(|r| r.map_err(|e| e.kind()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                Ok(GluinoValue::Uint16(1)),
+                Err(GluinoDeserializationErrorKind::UnexpectedEndOfBytes)
+            ],
+            read(vec![0x02, 0x01, 0x00, 0x02, 0x01])
+        );
+        assert_eq!(
+            vec![Err(GluinoDeserializationErrorKind::UnexpectedEndOfBytes)],
+            read(vec![0x01, 0x01, 0x02, 0x01, 0x00])
+        );
+        assert_eq!(
+            vec![Err(GluinoDeserializationErrorKind::IncorrectValueLength)],
+            read(vec![0x03, 0x01, 0x00, 0x00, 0x02, 0x01, 0x00])
+        );
+        assert_eq!(
+            vec![Err(GluinoDeserializationErrorKind::TruncatedVariableLengthInteger)],
+            read(vec![0x80])
+        );
+        let mut bytes = Vec::new();
+        variable_length_encode_u64(1 << 40, &mut bytes).unwrap();
+        let mut reader = RecordStreamReader::new(&spec, Cursor::new(bytes));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(GluinoDeserializationError::LimitExceeded(
+                DecodeLimitError::MaxTotalAllocationExceeded(DEFAULT_MAX_RECORD_LENGTH)
+            )))
+        ));
+        let mut reader = RecordStreamReader::new(&spec, Cursor::new(vec![0x02, 0x01, 0x00]))
+            .with_max_record_length(1);
+        assert!(matches!(
+            reader.next(),
+            Some(Err(GluinoDeserializationError::LimitExceeded(
+                DecodeLimitError::MaxTotalAllocationExceeded(1)
+            )))
+        ));
+    }
+}
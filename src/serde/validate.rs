@@ -0,0 +1,584 @@
+//! Checking values against a spec without serializing them.
+//!
+//! A value is valid when it would serialize with the unit serializer of the spec. Unlike the
+//! serializer every problem is reported, each with the path of the value it is found at.
+
+use std::fmt::{self, Display};
+
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    spec::{Spec, SpecType},
+    spec_parsing::{
+        InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, Size,
+        SpecKind, StringEncodingFmt,
+    },
+    spec_path::{PathSegment, SpecPath},
+};
+
+use super::{
+    GluinoValue, GluinoValueKind,
+    json::{from_twos_complement, magnitude_to_digits},
+};
+
+impl Spec {
+    /// Every way `value` does not fit the spec
+    pub fn validate(&self, value: &GluinoValue) -> Result<(), Vec<ValidationError>> {
+        let mut validator = Validator {
+            path: SpecPath::root(),
+            errors: Vec::new(),
+        };
+        validator.validate(self, value);
+        if validator.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(validator.errors)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, EnumDiscriminants)]
+#[strum_discriminants(name(ValidationErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum ValidationError {
+    ValueKindMismatch {
+        path: SpecPath,
+        expected_kind: SpecKind,
+        actual_value_kind: GluinoValueKind,
+    },
+    IncorrectDataSize {
+        path: SpecPath,
+        expected_size: Size,
+        actual_size: u64,
+        size_value_kind: GluinoValueKind,
+    },
+    IncorrectNumberOfFields {
+        path: SpecPath,
+        expected: usize,
+        actual: usize,
+    },
+    InvalidVariantId {
+        path: SpecPath,
+        variant_id: u64,
+        max_variant_id: usize,
+    },
+    IncorrectNumberOfBytes {
+        path: SpecPath,
+        expected: u64,
+        actual: usize,
+    },
+    BinaryFloatingPointFormatMismatch {
+        path: SpecPath,
+        expected: InterchangeBinaryFloatingPointFormat,
+        actual: InterchangeBinaryFloatingPointFormat,
+    },
+    DecimalFloatingPointFormatMismatch {
+        path: SpecPath,
+        expected: InterchangeDecimalFloatingPointFormat,
+        actual: InterchangeDecimalFloatingPointFormat,
+    },
+    DecimalOutOfRange {
+        path: SpecPath,
+        precision: u64,
+        scale: u64,
+    },
+    UnknownConstSetIndex {
+        path: SpecPath,
+        index: u64,
+        len: usize,
+    },
+}
+
+impl ValidationError {
+    /// Where in the value the error is
+    pub fn path(&self) -> &SpecPath {
+        match self {
+            Self::ValueKindMismatch { path, .. }
+            | Self::IncorrectDataSize { path, .. }
+            | Self::IncorrectNumberOfFields { path, .. }
+            | Self::InvalidVariantId { path, .. }
+            | Self::IncorrectNumberOfBytes { path, .. }
+            | Self::BinaryFloatingPointFormatMismatch { path, .. }
+            | Self::DecimalFloatingPointFormatMismatch { path, .. }
+            | Self::DecimalOutOfRange { path, .. }
+            | Self::UnknownConstSetIndex { path, .. } => path,
+        }
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ValueKindMismatch {
+                path,
+                expected_kind,
+                actual_value_kind,
+            } => write!(
+                f,
+                "{}: a {:?} value is not a {:?}",
+                path, actual_value_kind, expected_kind
+            ),
+            Self::IncorrectDataSize {
+                path,
+                expected_size,
+                actual_size,
+                size_value_kind,
+            } => write!(
+                f,
+                "{}: {:?} of size {} does not fit size {:?}",
+                path, size_value_kind, actual_size, expected_size
+            ),
+            Self::IncorrectNumberOfFields {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: expected {} fields, found {}",
+                path, expected, actual
+            ),
+            Self::InvalidVariantId {
+                path,
+                variant_id,
+                max_variant_id,
+            } => write!(
+                f,
+                "{}: variant id {} is past the last variant id {}",
+                path, variant_id, max_variant_id
+            ),
+            Self::IncorrectNumberOfBytes {
+                path,
+                expected,
+                actual,
+            } => write!(f, "{}: expected {} bytes, found {}", path, expected, actual),
+            Self::BinaryFloatingPointFormatMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: expected a {:?} float, found {:?}",
+                path, expected, actual
+            ),
+            Self::DecimalFloatingPointFormatMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: expected a {:?} float, found {:?}",
+                path, expected, actual
+            ),
+            Self::DecimalOutOfRange {
+                path,
+                precision,
+                scale,
+            } => write!(
+                f,
+                "{}: decimal does not fit precision {} and scale {}",
+                path, precision, scale
+            ),
+            Self::UnknownConstSetIndex { path, index, len } => write!(
+                f,
+                "{}: const set index {} is past the {} values",
+                path, index, len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+struct Validator {
+    path: SpecPath,
+    errors: Vec<ValidationError>,
+}
+
+impl Validator {
+    fn error(&mut self, error: impl FnOnce(SpecPath) -> ValidationError) {
+        self.errors.push(error(self.path.clone()));
+    }
+
+    fn kind_mismatch(&mut self, spec: &Spec, value: &GluinoValue) {
+        self.error(|path| ValidationError::ValueKindMismatch {
+            path,
+            expected_kind: spec.kind(),
+            actual_value_kind: value.into(),
+        })
+    }
+
+    fn size(&mut self, spec_size: &Size, size: usize, size_value_kind: GluinoValueKind) {
+        if !spec_size.can_be(size as u64) {
+            self.error(|path| ValidationError::IncorrectDataSize {
+                path,
+                expected_size: spec_size.clone(),
+                actual_size: size as u64,
+                size_value_kind,
+            })
+        }
+    }
+
+    fn byte_width(&mut self, expected: Option<u64>, bytes: &[u8]) {
+        if expected != Some(bytes.len() as u64) {
+            self.error(|path| ValidationError::IncorrectNumberOfBytes {
+                path,
+                expected: expected.unwrap_or(u64::MAX),
+                actual: bytes.len(),
+            })
+        }
+    }
+
+    fn descend(&mut self, segment: PathSegment, spec: &Spec, value: &GluinoValue) {
+        let len = self.path.push(segment);
+        self.validate(spec, value);
+        self.path.truncate(len);
+    }
+
+    fn validate(&mut self, spec: &Spec, value: &GluinoValue) {
+        let spec = &spec.resolve();
+        match (spec.spec_type(), value) {
+            (SpecType::Void, GluinoValue::Void) | (SpecType::Bool, GluinoValue::Bool(_)) => {}
+            (SpecType::Uint(0), GluinoValue::Uint8(_))
+            | (SpecType::Uint(1), GluinoValue::Uint16(_))
+            | (SpecType::Uint(2), GluinoValue::Uint32(_))
+            | (SpecType::Uint(3), GluinoValue::Uint64(_))
+            | (SpecType::Uint(4), GluinoValue::Uint128(_))
+            | (SpecType::Int(0), GluinoValue::Int8(_))
+            | (SpecType::Int(1), GluinoValue::Int16(_))
+            | (SpecType::Int(2), GluinoValue::Int32(_))
+            | (SpecType::Int(3), GluinoValue::Int64(_))
+            | (SpecType::Int(4), GluinoValue::Int128(_)) => {}
+            (SpecType::Uint(n), GluinoValue::BigUint(_, bytes))
+            | (SpecType::Int(n), GluinoValue::BigInt(_, bytes))
+                if *n > 4 =>
+            {
+                self.byte_width(1u64.checked_shl(*n as u32), bytes)
+            }
+            (
+                SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Single),
+                GluinoValue::Float(_),
+            )
+            | (
+                SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Double),
+                GluinoValue::Double(_),
+            ) => {}
+            (
+                SpecType::BinaryFloatingPoint(
+                    fmt @ (InterchangeBinaryFloatingPointFormat::Half
+                    | InterchangeBinaryFloatingPointFormat::Quadruple
+                    | InterchangeBinaryFloatingPointFormat::Octuple),
+                ),
+                GluinoValue::BinaryFloatingPoint(value_fmt, bytes),
+            ) => {
+                if fmt != value_fmt {
+                    self.error(|path| ValidationError::BinaryFloatingPointFormatMismatch {
+                        path,
+                        expected: fmt.clone(),
+                        actual: value_fmt.clone(),
+                    })
+                } else {
+                    self.byte_width(
+                        Some((fmt.significand_bits() + fmt.exponent_bits()) >> 3),
+                        bytes,
+                    )
+                }
+            }
+            (
+                SpecType::DecimalFloatingPoint(fmt),
+                GluinoValue::DecimalFloatingPoint(value_fmt, bytes),
+            ) => {
+                if fmt != value_fmt {
+                    self.error(|path| ValidationError::DecimalFloatingPointFormatMismatch {
+                        path,
+                        expected: fmt.clone(),
+                        actual: value_fmt.clone(),
+                    })
+                } else {
+                    self.byte_width(Some(fmt.minimum_byes_needed() as u64), bytes)
+                }
+            }
+            (SpecType::Decimal(fmt), GluinoValue::Decimal(bytes)) => {
+                // the bytes are the unscaled value, it has at most `precision` digits
+                let (_, magnitude) = from_twos_complement(bytes, true);
+                let digits = magnitude_to_digits(magnitude);
+                if digits.trim_start_matches('0').len() as u64 > fmt.precision {
+                    self.error(|path| ValidationError::DecimalOutOfRange {
+                        path,
+                        precision: fmt.precision,
+                        scale: fmt.scale,
+                    })
+                }
+            }
+            (SpecType::Bytes(size), GluinoValue::Bytes(bytes)) => {
+                self.size(size, bytes.len(), GluinoValueKind::Bytes)
+            }
+            (SpecType::String(size, StringEncodingFmt::Utf8), GluinoValue::String(s)) => {
+                self.size(size, s.len(), GluinoValueKind::String)
+            }
+            (
+                SpecType::String(size, StringEncodingFmt::Utf16 | StringEncodingFmt::Ascii),
+                GluinoValue::NonUtf8String(bytes),
+            ) => self.size(size, bytes.len(), GluinoValueKind::NonUtf8String),
+            (SpecType::List { size, value_spec }, GluinoValue::List(values)) => {
+                self.size(size, values.len(), GluinoValueKind::List);
+                for (index, value) in values.iter().enumerate() {
+                    self.descend(PathSegment::Element(index), value_spec, value);
+                }
+            }
+            (
+                SpecType::Map {
+                    size,
+                    key_spec,
+                    value_spec,
+                },
+                GluinoValue::Map(entries),
+            ) => {
+                self.size(size, entries.len(), GluinoValueKind::Map);
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    self.descend(PathSegment::EntryKey(index), key_spec, key);
+                    self.descend(PathSegment::Element(index), value_spec, value);
+                }
+            }
+            (SpecType::Optional(inner), GluinoValue::Optional(value)) => {
+                if let Some(value) = value {
+                    self.validate(inner, value)
+                }
+            }
+            // like the serializer, records and tuples take either product value
+            (
+                SpecType::Record {
+                    fields,
+                    field_to_spec,
+                    ..
+                },
+                GluinoValue::Record(values) | GluinoValue::Tuple(values),
+            ) => {
+                if self.field_count(fields.len(), values.len()) {
+                    for (field, value) in fields.iter().zip(values) {
+                        self.descend(
+                            PathSegment::Field(field),
+                            field_to_spec.get(field).unwrap(),
+                            value,
+                        );
+                    }
+                }
+            }
+            (SpecType::Tuple(fields), GluinoValue::Record(values) | GluinoValue::Tuple(values)) => {
+                if self.field_count(fields.len(), values.len()) {
+                    for (index, (spec, value)) in fields.iter().zip(values).enumerate() {
+                        self.descend(PathSegment::Index(index), spec, value);
+                    }
+                }
+            }
+            // and enums and unions either sum value
+            (
+                SpecType::Enum {
+                    variants,
+                    variant_to_spec,
+                },
+                GluinoValue::Enum(variant_id, value) | GluinoValue::Union(variant_id, value),
+            ) => {
+                if self.variant_id(variants.len(), *variant_id) {
+                    let variant = &variants[*variant_id as usize];
+                    self.descend(
+                        PathSegment::Field(variant),
+                        variant_to_spec.get(variant).unwrap(),
+                        value,
+                    );
+                }
+            }
+            (
+                SpecType::Union(variants),
+                GluinoValue::Enum(variant_id, value) | GluinoValue::Union(variant_id, value),
+            ) => {
+                if self.variant_id(variants.len(), *variant_id) {
+                    self.descend(
+                        PathSegment::Index(*variant_id as usize),
+                        &variants[*variant_id as usize],
+                        value,
+                    );
+                }
+            }
+            (SpecType::ConstSet(_, const_values), GluinoValue::ConstSet(index)) => {
+                if *index as usize >= const_values.len() {
+                    self.error(|path| ValidationError::UnknownConstSetIndex {
+                        path,
+                        index: *index,
+                        len: const_values.len(),
+                    })
+                }
+            }
+            _ => self.kind_mismatch(spec, value),
+        }
+    }
+
+    fn field_count(&mut self, expected: usize, actual: usize) -> bool {
+        if expected != actual {
+            self.error(|path| ValidationError::IncorrectNumberOfFields {
+                path,
+                expected,
+                actual,
+            });
+        }
+        expected == actual
+    }
+
+    fn variant_id(&mut self, variants: usize, variant_id: u64) -> bool {
+        if variant_id >= variants as u64 {
+            self.error(|path| ValidationError::InvalidVariantId {
+                path,
+                variant_id,
+                max_variant_id: variants.saturating_sub(1),
+            });
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use crate::{
+        serde::{F32, get_unit_serialization_function},
+        spec_parsing::ParsedSpec,
+        test_utils::{compile, get_valid_specs_for_kind, string},
+    };
+
+    use super::super::tests::sample_value;
+    use super::*;
+
+    fn errors(spec: &Spec, value: &GluinoValue) -> Vec<(ValidationErrorKind, String)> {
+        match spec.validate(value) {
+            Ok(()) => vec![],
+            Err(errors) => errors
+                .iter()
+                .map(|e| (e.into(), e.path().to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_valid_values() {
+        for spec_kind in SpecKind::iter() {
+            for parsed_spec in get_valid_specs_for_kind(spec_kind) {
+                if let ParsedSpec::Uint(n) | ParsedSpec::Int(n) = parsed_spec {
+                    // 2^n bytes
+                    if n > 10 {
+                        continue;
+                    }
+                }
+                let spec = Spec::compile(parsed_spec).expect("Unable to compile");
+                let value = sample_value(&spec, spec.named_schema(), 0);
+                assert_eq!(Ok(()), spec.validate(&value), "{}", spec.to_text());
+            }
+        }
+    }
+
+    #[test]
+    fn test_all_errors_with_paths() {
+        use ValidationErrorKind::*;
+        let spec = compile(
+            "record {
+                id: uint(3),
+                name: string<..4>,
+                tags: list<string, 1..3>,
+                scores: map<string, int(1)>,
+                price: decimal(4, 2),
+                side: enum { buy: void, sell: uint(0) },
+                point: tuple { int(6), float(half) },
+                status: const_set<uint(0)> { 0x01, 0x02 },
+                note: optional<record { text: string }>,
+            }",
+        );
+        let value = GluinoValue::Record(vec![
+            GluinoValue::Uint32(1),
+            string("gluino"),
+            GluinoValue::List(vec![string("a"), GluinoValue::Bool(true), string("c")]),
+            GluinoValue::Map(vec![
+                (string("x"), GluinoValue::Int16(1)),
+                (GluinoValue::Void, GluinoValue::Int32(2)),
+            ]),
+            GluinoValue::Decimal(12345i32.to_le_bytes().to_vec()),
+            GluinoValue::Enum(2, Box::new(GluinoValue::Void)),
+            GluinoValue::Tuple(vec![
+                GluinoValue::BigInt(6, vec![0; 32]),
+                GluinoValue::BinaryFloatingPoint(
+                    InterchangeBinaryFloatingPointFormat::Single,
+                    vec![0; 4],
+                ),
+            ]),
+            GluinoValue::ConstSet(2),
+            GluinoValue::Optional(Some(Box::new(GluinoValue::Record(vec![])))),
+        ]);
+        assert_eq!(
+            vec![
+                (ValueKindMismatch, ".id".to_string()),
+                (IncorrectDataSize, ".name".to_string()),
+                (IncorrectDataSize, ".tags".to_string()),
+                (ValueKindMismatch, ".tags[1]".to_string()),
+                (ValueKindMismatch, ".scores{1}".to_string()),
+                (ValueKindMismatch, ".scores[1]".to_string()),
+                (DecimalOutOfRange, ".price".to_string()),
+                (InvalidVariantId, ".side".to_string()),
+                (IncorrectNumberOfBytes, ".point.0".to_string()),
+                (BinaryFloatingPointFormatMismatch, ".point.1".to_string()),
+                (UnknownConstSetIndex, ".status".to_string()),
+                (IncorrectNumberOfFields, ".note".to_string()),
+            ],
+            errors(&spec, &value)
+        );
+        // the serializer stops at the first
+        assert!(
+            get_unit_serialization_function::<Vec<u8>>(&spec)
+                .serialize(value, &mut Vec::new())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate_values() {
+        let decimal = compile("decimal(4, 2)");
+        for unscaled in [0i32, 9999, -9999, 120] {
+            let value = GluinoValue::Decimal(unscaled.to_le_bytes().to_vec());
+            assert_eq!(Ok(()), decimal.validate(&value), "{}", unscaled);
+        }
+        for unscaled in [10000i32, -10000, i32::MIN] {
+            let value = GluinoValue::Decimal(unscaled.to_le_bytes().to_vec());
+            assert!(decimal.validate(&value).is_err(), "{}", unscaled);
+        }
+
+        let list = compile("name List = record { value: float(single), next: optional<ref List> }");
+        let node = |value: GluinoValue, next: Option<GluinoValue>| {
+            GluinoValue::Record(vec![value, GluinoValue::Optional(next.map(Box::new))])
+        };
+        let float = || GluinoValue::Float(F32(1.5));
+        assert_eq!(
+            Vec::<(ValidationErrorKind, String)>::new(),
+            errors(&list, &node(float(), Some(node(float(), None))))
+        );
+        assert_eq!(
+            vec![(
+                ValidationErrorKind::ValueKindMismatch,
+                ".next.next.value".to_string()
+            )],
+            errors(
+                &list,
+                &node(
+                    float(),
+                    Some(node(float(), Some(node(GluinoValue::Void, None))))
+                )
+            )
+        );
+
+        let union = compile("union { bool, map<string, string, 1> }");
+        let error = union
+            .validate(&GluinoValue::Union(
+                1,
+                Box::new(GluinoValue::Map(vec![(string("a"), GluinoValue::Void)])),
+            ))
+            .unwrap_err();
+        assert_eq!(".1[0]: a Void value is not a String", error[0].to_string());
+    }
+}
@@ -0,0 +1,961 @@
+//! Lazy, borrowed views of encoded values.
+//!
+//! A `GluinoValueRef` is a spec and the bytes the value starts at. Nothing is decoded until it is
+//! asked for, getting a record field only walks past the fields before it, and strings and bytes are
+//! borrowed from the buffer. Walking past values is held to the same `DecodeLimits` as
+//! deserializing them.
+
+use std::{
+    fmt::{self, Display},
+    str::Utf8Error,
+};
+
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    limits::{DecodeBudget, DecodeLimitError, DecodeLimits},
+    spec::{NameScope, Spec, SpecType},
+    spec_parsing::{InterchangeBinaryFloatingPointFormat, Size, SpecKind, StringEncodingFmt},
+};
+
+use super::{
+    GluinoDeserializationError, GluinoValue, GluinoValueDe, GluinoValueKind, PositionedDe,
+    de_impls::decode_size, decode_u64, get_reader_deserialization_function,
+};
+
+#[derive(Clone, Copy)]
+pub struct GluinoValueRef<'a> {
+    spec: &'a Spec,
+    names: NameScope<'a>,
+    // starts at the value, may go on past it
+    bytes: &'a [u8],
+    limits: DecodeLimits,
+}
+
+#[derive(Debug, EnumDiscriminants)]
+#[strum_discriminants(name(GluinoValueRefErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum GluinoValueRefError {
+    Deserialization(GluinoDeserializationError),
+    InvalidUtf8String(Utf8Error),
+    KindMismatch {
+        expected: &'static str,
+        actual: SpecKind,
+    },
+    UnknownField(String),
+    IndexOutOfBounds {
+        index: u64,
+        len: u64,
+    },
+}
+
+impl Display for GluinoValueRefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Deserialization(e) => write!(f, "{}", e),
+            Self::InvalidUtf8String(e) => write!(f, "invalid utf8 string: {}", e),
+            Self::KindMismatch { expected, actual } => {
+                write!(f, "expected {}, the spec is {:?}", expected, actual)
+            }
+            Self::UnknownField(field) => write!(f, "no field {:?}", field),
+            Self::IndexOutOfBounds { index, len } => {
+                write!(f, "index {} is out of bounds for length {}", index, len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GluinoValueRefError {}
+
+impl From<GluinoDeserializationError> for GluinoValueRefError {
+    fn from(e: GluinoDeserializationError) -> Self {
+        GluinoValueRefError::Deserialization(e)
+    }
+}
+
+impl From<DecodeLimitError> for GluinoValueRefError {
+    fn from(e: DecodeLimitError) -> Self {
+        GluinoValueRefError::Deserialization(e.into())
+    }
+}
+
+impl From<Utf8Error> for GluinoValueRefError {
+    fn from(e: Utf8Error) -> Self {
+        GluinoValueRefError::InvalidUtf8String(e)
+    }
+}
+
+type RefResult<T> = Result<T, GluinoValueRefError>;
+
+impl<'a> GluinoValueRef<'a> {
+    /// A view of the value of `spec` at the start of `bytes`
+    pub fn new(spec: &'a Spec, bytes: &'a [u8]) -> GluinoValueRef<'a> {
+        GluinoValueRef::new_with_limits(spec, bytes, &DecodeLimits::default())
+    }
+
+    /// A view of the value of `spec` at the start of `bytes`, failing on values that go over
+    /// `limits`. Views of the parts of the value keep the limits.
+    pub fn new_with_limits(
+        spec: &'a Spec,
+        bytes: &'a [u8],
+        limits: &DecodeLimits,
+    ) -> GluinoValueRef<'a> {
+        GluinoValueRef::scoped(spec, NameScope::default(), bytes, limits)
+    }
+
+    fn scoped(
+        spec: &'a Spec,
+        names: NameScope<'a>,
+        bytes: &'a [u8],
+        limits: &DecodeLimits,
+    ) -> GluinoValueRef<'a> {
+        // names are transparent to views
+        let (spec, names) = names.resolve(spec);
+        GluinoValueRef {
+            spec,
+            names,
+            bytes,
+            limits: *limits,
+        }
+    }
+
+    /// A copy of the spec of the value, holding the definitions of the names within it
+    pub fn spec(&self) -> Spec {
+        self.spec.clone()
+    }
+
+    pub fn kind(&self) -> SpecKind {
+        self.spec.kind()
+    }
+
+    /// Number of bytes the value takes
+    pub fn encoded_len(&self) -> RefResult<usize> {
+        encoded_len(self.spec, self.bytes, &mut DecodeBudget::new(self.limits))
+    }
+
+    /// Exactly the bytes of the value
+    pub fn encoded_bytes(&self) -> RefResult<&'a [u8]> {
+        Ok(&self.bytes[..self.encoded_len()?])
+    }
+
+    /// Decodes the whole value, reading straight from the bytes
+    pub fn to_value(&self) -> RefResult<GluinoValue> {
+        let de = PositionedDe {
+            de: get_reader_deserialization_function::<&'a [u8]>(self.spec),
+            limits: self.limits,
+        };
+        Ok(de.deserialize(&mut &self.bytes[..])?)
+    }
+
+    fn kind_mismatch<T>(&self, expected: &'static str) -> RefResult<T> {
+        Err(GluinoValueRefError::KindMismatch {
+            expected,
+            actual: self.kind(),
+        })
+    }
+
+    fn at(&self, spec: &'a Spec, offset: usize) -> GluinoValueRef<'a> {
+        GluinoValueRef::scoped(spec, self.names, &self.bytes[offset..], &self.limits)
+    }
+
+    /// Field of a record by name
+    pub fn field(&self, name: &str) -> RefResult<GluinoValueRef<'a>> {
+        match self.spec.spec_type() {
+            SpecType::Record { field_to_index, .. } => match field_to_index.get(name) {
+                Some(index) => self.field_at(*index),
+                None => Err(GluinoValueRefError::UnknownField(name.to_string())),
+            },
+            _ => self.kind_mismatch("a record"),
+        }
+    }
+
+    /// Field of a record or tuple by position
+    pub fn field_at(&self, index: usize) -> RefResult<GluinoValueRef<'a>> {
+        let field_specs: Vec<&'a Spec> = match self.spec.spec_type() {
+            SpecType::Record {
+                fields,
+                field_to_spec,
+                ..
+            } => fields
+                .iter()
+                .map(|f| field_to_spec.get(f).unwrap())
+                .collect(),
+            SpecType::Tuple(fields) => fields.iter().collect(),
+            _ => return self.kind_mismatch("a record or tuple"),
+        };
+        if index >= field_specs.len() {
+            return Err(GluinoValueRefError::IndexOutOfBounds {
+                index: index as u64,
+                len: field_specs.len() as u64,
+            });
+        }
+        let mut budget = DecodeBudget::new(self.limits);
+        let mut offset = 0;
+        for spec in &field_specs[..index] {
+            offset += encoded_len(spec, &self.bytes[offset..], &mut budget)?;
+        }
+        Ok(self.at(field_specs[index], offset))
+    }
+
+    // number of items and where the first one starts
+    fn items(&self) -> RefResult<(u64, usize)> {
+        let (size, kind) = match self.spec.spec_type() {
+            SpecType::List { size, .. } => (size, GluinoValueKind::List),
+            SpecType::Map { size, .. } => (size, GluinoValueKind::Map),
+            _ => return self.kind_mismatch("a list or map"),
+        };
+        let (len, offset) = read_size(size, kind, self.bytes)?;
+        DecodeBudget::new(self.limits).collection_length(len)?;
+        Ok((len, offset))
+    }
+
+    /// Number of elements of a list or entries of a map
+    pub fn len(&self) -> RefResult<u64> {
+        Ok(self.items()?.0)
+    }
+
+    pub fn is_empty(&self) -> RefResult<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Element of a list
+    pub fn element(&self, index: u64) -> RefResult<GluinoValueRef<'a>> {
+        let (len, _) = self.items()?;
+        // checked before casting, on 32 bit targets a large index would wrap around to a small one
+        let nth = match usize::try_from(index) {
+            Ok(nth) if index < len => nth,
+            _ => return Err(GluinoValueRefError::IndexOutOfBounds { index, len }),
+        };
+        match self.elements()?.nth(nth) {
+            Some(element) => element,
+            None => Err(GluinoValueRefError::IndexOutOfBounds { index, len }),
+        }
+    }
+
+    /// Elements of a list, each found by walking past the one before
+    pub fn elements(&self) -> RefResult<impl Iterator<Item = RefResult<GluinoValueRef<'a>>> + 'a> {
+        let value_spec = match self.spec.spec_type() {
+            SpecType::List { value_spec, .. } => value_spec,
+            _ => return self.kind_mismatch("a list"),
+        };
+        let (len, offset) = self.items()?;
+        Ok(Items {
+            specs: [value_spec.as_ref()],
+            bytes: &self.bytes[offset..],
+            remaining: len,
+            limits: self.limits,
+            names: self.names,
+        }
+        .map(|item| item.map(|[value]| value)))
+    }
+
+    /// Entries of a map, in encoded order
+    pub fn entries(
+        &self,
+    ) -> RefResult<impl Iterator<Item = RefResult<(GluinoValueRef<'a>, GluinoValueRef<'a>)>> + 'a>
+    {
+        let (key_spec, value_spec) = match self.spec.spec_type() {
+            SpecType::Map {
+                key_spec,
+                value_spec,
+                ..
+            } => (key_spec, value_spec),
+            _ => return self.kind_mismatch("a map"),
+        };
+        let (len, offset) = self.items()?;
+        Ok(Items {
+            specs: [key_spec.as_ref(), value_spec.as_ref()],
+            bytes: &self.bytes[offset..],
+            remaining: len,
+            limits: self.limits,
+            names: self.names,
+        }
+        .map(|item| item.map(|[key, value]| (key, value))))
+    }
+
+    /// Value of the first entry of a map with a key equal to `key`
+    pub fn get(&self, key: &GluinoValue) -> RefResult<Option<GluinoValueRef<'a>>> {
+        for entry in self.entries()? {
+            let (entry_key, value) = entry?;
+            if &entry_key.to_value()? == key {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn optional(&self) -> RefResult<Option<GluinoValueRef<'a>>> {
+        match self.spec.spec_type() {
+            SpecType::Optional(inner) => match read_byte(self.bytes)? {
+                0 => Ok(None),
+                1 => Ok(Some(self.at(inner, 1))),
+                b => Err(GluinoDeserializationError::InvalidOptionalFlag(b).into()),
+            },
+            _ => self.kind_mismatch("an optional"),
+        }
+    }
+
+    /// Variant id and value of an enum or union
+    pub fn variant(&self) -> RefResult<(u64, GluinoValueRef<'a>)> {
+        let variant_specs: Vec<&'a Spec> = match self.spec.spec_type() {
+            SpecType::Enum {
+                variants,
+                variant_to_spec,
+            } => variants
+                .iter()
+                .map(|v| variant_to_spec.get(v).unwrap())
+                .collect(),
+            SpecType::Union(variants) => variants.iter().collect(),
+            _ => return self.kind_mismatch("an enum or union"),
+        };
+        let (variant_id, offset) = read_variant_id(variant_specs.len(), self.bytes)?;
+        Ok((variant_id as u64, self.at(variant_specs[variant_id], offset)))
+    }
+
+    /// Name of the variant of an enum
+    pub fn variant_name(&self) -> RefResult<&'a str> {
+        match self.spec.spec_type() {
+            SpecType::Enum { variants, .. } => {
+                let (variant_id, _) = read_variant_id(variants.len(), self.bytes)?;
+                Ok(&variants[variant_id])
+            }
+            _ => self.kind_mismatch("an enum"),
+        }
+    }
+
+    /// Index of the value in a const set
+    pub fn const_index(&self) -> RefResult<u64> {
+        match self.spec.spec_type() {
+            SpecType::ConstSet(const_spec, const_values) => {
+                let value = self.at(const_spec, 0).to_value()?;
+                match const_values.iter().position(|v| v == &value) {
+                    Some(index) => Ok(index as u64),
+                    None => Err(GluinoDeserializationError::UnknownConstSetValue(value).into()),
+                }
+            }
+            _ => self.kind_mismatch("a const set"),
+        }
+    }
+
+    pub fn as_bool(&self) -> RefResult<bool> {
+        match self.spec.spec_type() {
+            SpecType::Bool => match read_byte(self.bytes)? {
+                0 => Ok(false),
+                1 => Ok(true),
+                b => Err(GluinoDeserializationError::InvalidBoolByte(b).into()),
+            },
+            _ => self.kind_mismatch("a bool"),
+        }
+    }
+
+    /// Unsigned integers of up to 64 bits
+    pub fn as_u64(&self) -> RefResult<u64> {
+        match self.spec.spec_type() {
+            SpecType::Uint(n) if *n <= 3 => {
+                let mut le_bytes = [0u8; 8];
+                le_bytes[..1 << n].copy_from_slice(read_bytes(1 << n, self.bytes)?);
+                Ok(u64::from_le_bytes(le_bytes))
+            }
+            _ => self.kind_mismatch("an unsigned integer of up to 64 bits"),
+        }
+    }
+
+    /// Signed integers of up to 64 bits
+    pub fn as_i64(&self) -> RefResult<i64> {
+        match self.spec.spec_type() {
+            SpecType::Int(n) if *n <= 3 => {
+                let bytes = read_bytes(1 << n, self.bytes)?;
+                let fill = if bytes[bytes.len() - 1] & 0x80 != 0 {
+                    0xFF
+                } else {
+                    0x00
+                };
+                let mut le_bytes = [fill; 8];
+                le_bytes[..1 << n].copy_from_slice(bytes);
+                Ok(i64::from_le_bytes(le_bytes))
+            }
+            _ => self.kind_mismatch("a signed integer of up to 64 bits"),
+        }
+    }
+
+    /// Single and double precision floats
+    pub fn as_f64(&self) -> RefResult<f64> {
+        match self.spec.spec_type() {
+            SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Single) => {
+                Ok(f32::from_le_bytes(read_bytes(4, self.bytes)?.try_into().unwrap()) as f64)
+            }
+            SpecType::BinaryFloatingPoint(InterchangeBinaryFloatingPointFormat::Double) => Ok(
+                f64::from_le_bytes(read_bytes(8, self.bytes)?.try_into().unwrap()),
+            ),
+            _ => self.kind_mismatch("a single or double float"),
+        }
+    }
+
+    /// Utf8 and ascii strings, borrowed
+    pub fn as_str(&self) -> RefResult<&'a str> {
+        match self.spec.spec_type() {
+            SpecType::String(_, StringEncodingFmt::Utf8 | StringEncodingFmt::Ascii) => {
+                Ok(std::str::from_utf8(self.sized_bytes()?)?)
+            }
+            _ => self.kind_mismatch("a utf8 or ascii string"),
+        }
+    }
+
+    /// Bytes, and strings as their encoded bytes, borrowed
+    pub fn as_bytes(&self) -> RefResult<&'a [u8]> {
+        match self.spec.spec_type() {
+            SpecType::Bytes(_) | SpecType::String(_, _) => self.sized_bytes(),
+            _ => self.kind_mismatch("bytes or a string"),
+        }
+    }
+
+    fn sized_bytes(&self) -> RefResult<&'a [u8]> {
+        let (size, kind) = match self.spec.spec_type() {
+            SpecType::Bytes(size) => (size, GluinoValueKind::Bytes),
+            SpecType::String(size, StringEncodingFmt::Utf8) => (size, GluinoValueKind::String),
+            SpecType::String(size, _) => (size, GluinoValueKind::NonUtf8String),
+            _ => unreachable!("Only called for bytes and strings"),
+        };
+        let (len, offset) = read_size(size, kind, self.bytes)?;
+        DecodeBudget::new(self.limits).string_length(len)?;
+        read_bytes(len, &self.bytes[offset..])
+    }
+}
+
+impl fmt::Debug for GluinoValueRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GluinoValueRef")
+            .field("spec", &self.spec.to_text())
+            .field("bytes", &self.encoded_bytes().unwrap_or(self.bytes))
+            .finish()
+    }
+}
+
+// the items of a list or map, each item is one value per spec
+struct Items<'a, const N: usize> {
+    specs: [&'a Spec; N],
+    bytes: &'a [u8],
+    remaining: u64,
+    limits: DecodeLimits,
+    names: NameScope<'a>,
+}
+
+impl<'a, const N: usize> Iterator for Items<'a, N> {
+    type Item = RefResult<[GluinoValueRef<'a>; N]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let mut budget = DecodeBudget::new(self.limits);
+        let mut offset = 0;
+        let mut values =
+            [GluinoValueRef::scoped(self.specs[0], self.names, self.bytes, &self.limits); N];
+        for (value, spec) in values.iter_mut().zip(self.specs) {
+            *value = GluinoValueRef::scoped(spec, self.names, &self.bytes[offset..], &self.limits);
+            match encoded_len(value.spec, value.bytes, &mut budget) {
+                Ok(len) => offset += len,
+                Err(e) => {
+                    self.remaining = 0;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.bytes = &self.bytes[offset..];
+        Some(Ok(values))
+    }
+}
+
+fn read_byte(bytes: &[u8]) -> RefResult<u8> {
+    bytes
+        .first()
+        .copied()
+        .ok_or(GluinoValueRefError::Deserialization(
+            GluinoDeserializationError::UnexpectedEndOfBytes,
+        ))
+}
+
+fn read_bytes(n: u64, bytes: &[u8]) -> RefResult<&[u8]> {
+    if (bytes.len() as u64) < n {
+        return Err(GluinoDeserializationError::UnexpectedEndOfBytes.into());
+    }
+    Ok(&bytes[..n as usize])
+}
+
+// the value and the number of bytes it took
+fn read_varint(bytes: &[u8]) -> RefResult<(u64, usize)> {
+    let mut reader = bytes;
+    let n = decode_u64(&mut reader)?;
+    Ok((n, bytes.len() - reader.len()))
+}
+
+fn read_size(size: &Size, kind: GluinoValueKind, bytes: &[u8]) -> RefResult<(u64, usize)> {
+    let mut reader = bytes;
+    let n = decode_size(size, kind, &mut reader)?;
+    Ok((n, bytes.len() - reader.len()))
+}
+
+// the variant id is checked as read, on 32 bit targets a large id would wrap around to a valid one
+fn read_variant_id(variants: usize, bytes: &[u8]) -> RefResult<(usize, usize)> {
+    let (variant_id, offset) = read_varint(bytes)?;
+    if variant_id >= variants as u64 {
+        return Err(GluinoDeserializationError::InvalidVariantId {
+            variant_id: usize::try_from(variant_id).unwrap_or(usize::MAX),
+            max_variant_id: variants.saturating_sub(1),
+        }
+        .into());
+    }
+    Ok((variant_id as usize, offset))
+}
+
+/// Number of bytes the value of `spec` at the start of `bytes` takes, walking past its parts
+/// within the limits of `budget` like the unit deserializer reads them
+fn encoded_len(spec: &Spec, bytes: &[u8], budget: &mut DecodeBudget) -> RefResult<usize> {
+    let fixed = |n: u64| read_bytes(n, bytes).map(|b| b.len());
+    let sized = |size: &Size, kind: GluinoValueKind, budget: &mut DecodeBudget| {
+        let (n, offset) = read_size(size, kind, bytes)?;
+        if kind != GluinoValueKind::Decimal {
+            budget.string_length(n)?;
+        }
+        Ok(offset + read_bytes(n, &bytes[offset..])?.len())
+    };
+    match spec.spec_type() {
+        SpecType::Void => Ok(0),
+        SpecType::Bool => fixed(1),
+        SpecType::Uint(n) | SpecType::Int(n) => match 1u64.checked_shl(*n as u32) {
+            Some(n) => fixed(n),
+            None => Err(GluinoDeserializationError::UnsupportedIntegerSize(*n).into()),
+        },
+        SpecType::BinaryFloatingPoint(fmt) => {
+            fixed((fmt.significand_bits() + fmt.exponent_bits()) >> 3)
+        }
+        SpecType::DecimalFloatingPoint(fmt) => fixed(fmt.minimum_byes_needed() as u64),
+        SpecType::Decimal(_) => sized(&Size::Variable, GluinoValueKind::Decimal, budget),
+        SpecType::Bytes(size) => sized(size, GluinoValueKind::Bytes, budget),
+        SpecType::String(size, StringEncodingFmt::Utf8) => {
+            sized(size, GluinoValueKind::String, budget)
+        }
+        SpecType::String(size, _) => sized(size, GluinoValueKind::NonUtf8String, budget),
+        SpecType::Optional(inner) => match read_byte(bytes)? {
+            0 => Ok(1),
+            1 => Ok(1 + nested_len(inner, &bytes[1..], budget)?),
+            b => Err(GluinoDeserializationError::InvalidOptionalFlag(b).into()),
+        },
+        SpecType::List { .. } | SpecType::Map { .. } => {
+            let (size, kind, specs): (_, _, Vec<&Spec>) = match spec.spec_type() {
+                SpecType::List { size, value_spec } => {
+                    (size, GluinoValueKind::List, vec![value_spec])
+                }
+                SpecType::Map {
+                    size,
+                    key_spec,
+                    value_spec,
+                } => (size, GluinoValueKind::Map, vec![key_spec, value_spec]),
+                _ => unreachable!(),
+            };
+            let (len, mut offset) = read_size(size, kind, bytes)?;
+            budget.collection_length(len)?;
+            for _ in 0..len {
+                for spec in &specs {
+                    offset += nested_len(spec, &bytes[offset..], budget)?;
+                }
+            }
+            Ok(offset)
+        }
+        SpecType::Record { .. } | SpecType::Tuple(_) => {
+            let field_specs: Vec<&Spec> = match spec.spec_type() {
+                SpecType::Record {
+                    fields,
+                    field_to_spec,
+                    ..
+                } => fields
+                    .iter()
+                    .map(|f| field_to_spec.get(f).unwrap())
+                    .collect(),
+                SpecType::Tuple(fields) => fields.iter().collect(),
+                _ => unreachable!(),
+            };
+            let mut offset = 0;
+            for spec in field_specs {
+                offset += nested_len(spec, &bytes[offset..], budget)?;
+            }
+            Ok(offset)
+        }
+        SpecType::Enum { .. } | SpecType::Union(_) => {
+            let (_, value) = GluinoValueRef::new(spec, bytes).variant()?;
+            Ok(bytes.len() - value.bytes.len() + nested_len(value.spec, value.bytes, budget)?)
+        }
+        SpecType::Name(_) => encoded_len(&spec.resolve(), bytes, budget),
+        SpecType::ConstSet(const_spec, _) => encoded_len(const_spec, bytes, budget),
+    }
+}
+
+// length of a part of a value, one level deeper
+fn nested_len(spec: &Spec, bytes: &[u8], budget: &mut DecodeBudget) -> RefResult<usize> {
+    budget.enter()?;
+    let len = encoded_len(spec, bytes, budget)?;
+    budget.exit();
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use crate::{
+        limits::DecodeLimitErrorKind,
+        spec_parsing::ParsedSpec,
+        test_utils::{compile, encode, get_valid_specs_for_kind, string},
+        util::variable_length_encode_u64,
+    };
+
+    use super::super::tests::sample_value;
+    use super::*;
+
+    fn error_kind<T>(result: RefResult<T>) -> GluinoValueRefErrorKind {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => (&e).into(),
+        }
+    }
+
+    fn limit_error<T: fmt::Debug>(result: RefResult<T>) -> DecodeLimitError {
+        match result {
+            Err(GluinoValueRefError::Deserialization(e)) => match e.cause() {
+                GluinoDeserializationError::LimitExceeded(e) => e.clone(),
+                e => panic!("expected a limit error, got {}", e),
+            },
+            result => panic!("expected a limit error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_record_fields() {
+        let spec = compile(
+            "record { id: uint(3), name: string, score: int(1), tags: list<string>, \
+             attributes: map<string, bool>, note: optional<string> }",
+        );
+        let bytes = encode(
+            &spec,
+            GluinoValue::Record(vec![
+                GluinoValue::Uint64(7),
+                string("gluino"),
+                GluinoValue::Int16(-300),
+                GluinoValue::List(vec![string("a"), string("bc")]),
+                GluinoValue::Map(vec![
+                    (string("x"), GluinoValue::Bool(false)),
+                    (string("y"), GluinoValue::Bool(true)),
+                ]),
+                GluinoValue::Optional(Some(Box::new(string("hi")))),
+            ]),
+        );
+        let value = GluinoValueRef::new(&spec, &bytes);
+        assert_eq!(bytes.len(), value.encoded_len().unwrap());
+        assert_eq!(7, value.field("id").unwrap().as_u64().unwrap());
+        assert_eq!(-300, value.field("score").unwrap().as_i64().unwrap());
+
+        let name = value.field("name").unwrap().as_str().unwrap();
+        assert_eq!("gluino", name);
+        assert!(bytes.as_ptr_range().contains(&name.as_ptr()));
+        assert_eq!(name, value.field_at(1).unwrap().as_str().unwrap());
+
+        let tags = value.field("tags").unwrap();
+        assert_eq!(2, tags.len().unwrap());
+        assert_eq!("bc", tags.element(1).unwrap().as_str().unwrap());
+        assert_eq!(
+            vec!["a", "bc"],
+            tags.elements()
+                .unwrap()
+                .map(|t| t.and_then(|t| t.as_str()))
+                .collect::<RefResult<Vec<_>>>()
+                .unwrap()
+        );
+
+        let attributes = value.field("attributes").unwrap();
+        assert!(
+            attributes
+                .get(&string("y"))
+                .unwrap()
+                .unwrap()
+                .as_bool()
+                .unwrap()
+        );
+        assert!(attributes.get(&string("z")).unwrap().is_none());
+        assert_eq!(2, attributes.entries().unwrap().count());
+
+        let note = value.field("note").unwrap().optional().unwrap().unwrap();
+        assert_eq!(b"hi", note.as_bytes().unwrap());
+        assert_eq!(string("hi"), note.to_value().unwrap());
+    }
+
+    #[test]
+    fn test_sums_and_names() {
+        let spec = compile(
+            "record { side: enum { buy: void, sell: uint(0) }, payload: union { bool, string }, \
+             status: const_set<uint(0)> { 0x01, 0x02 } }",
+        );
+        let bytes = encode(
+            &spec,
+            GluinoValue::Record(vec![
+                GluinoValue::Enum(1, Box::new(GluinoValue::Uint8(4))),
+                GluinoValue::Union(1, Box::new(string("text"))),
+                GluinoValue::ConstSet(1),
+            ]),
+        );
+        let value = GluinoValueRef::new(&spec, &bytes);
+        let side = value.field("side").unwrap();
+        assert_eq!("sell", side.variant_name().unwrap());
+        assert_eq!(4, side.variant().unwrap().1.as_u64().unwrap());
+        let (variant_id, payload) = value.field("payload").unwrap().variant().unwrap();
+        assert_eq!((1, "text"), (variant_id, payload.as_str().unwrap()));
+        assert_eq!(1, value.field("status").unwrap().const_index().unwrap());
+
+        let spec = compile("name List = record { value: int(0), next: optional<ref List> }");
+        let list = (0..3).rev().fold(GluinoValue::Optional(None), |next, i| {
+            GluinoValue::Optional(Some(Box::new(GluinoValue::Record(vec![
+                GluinoValue::Int8(i),
+                next,
+            ]))))
+        });
+        let GluinoValue::Optional(Some(list)) = list else {
+            unreachable!()
+        };
+        let bytes = encode(&spec, *list);
+        let mut node = Some(GluinoValueRef::new(&spec, &bytes));
+        let mut values = Vec::new();
+        while let Some(current) = node {
+            values.push(current.field("value").unwrap().as_i64().unwrap());
+            node = current.field("next").unwrap().optional().unwrap();
+        }
+        assert_eq!(vec![0, 1, 2], values);
+    }
+
+    #[test]
+    fn test_view_through_recursive_sub_spec() {
+        let spec = compile("name Tree = record { value: int(0), children: list<ref Tree> }");
+        let tree = spec.named_schema()["Tree"].get().unwrap();
+        let SpecType::Record { field_to_spec, .. } = tree.spec_type() else {
+            unreachable!()
+        };
+        let SpecType::List { value_spec, .. } = field_to_spec["children"].spec_type() else {
+            unreachable!()
+        };
+        let tree_value = |value, children| {
+            GluinoValue::Record(vec![GluinoValue::Int8(value), GluinoValue::List(children)])
+        };
+        let child = tree_value(1, vec![tree_value(2, vec![])]);
+        let bytes = encode(value_spec, child.clone());
+
+        let value = GluinoValueRef::new(value_spec, &bytes);
+        assert_eq!(child, value.to_value().unwrap());
+        assert_eq!(1, value.field("value").unwrap().as_i64().unwrap());
+        let grandchild = value.field("children").unwrap().element(0).unwrap();
+        assert_eq!(2, grandchild.field("value").unwrap().as_i64().unwrap());
+        // the spec of a part of the value views it the same way
+        let grandchild_spec = grandchild.spec();
+        let grandchild_bytes = grandchild.encoded_bytes().unwrap();
+        let grandchild = GluinoValueRef::new(&grandchild_spec, grandchild_bytes);
+        assert_eq!(tree_value(2, vec![]), grandchild.to_value().unwrap());
+        assert_eq!(0, grandchild.field("children").unwrap().len().unwrap());
+    }
+
+    #[test]
+    fn test_all_kinds_agree_with_deserializer() {
+        for spec_kind in SpecKind::iter() {
+            for parsed_spec in get_valid_specs_for_kind(spec_kind) {
+                if let ParsedSpec::Uint(n) | ParsedSpec::Int(n) = parsed_spec {
+                    // 2^n bytes
+                    if n > 10 {
+                        continue;
+                    }
+                }
+                let spec = Spec::compile(parsed_spec).expect("Unable to compile");
+                let value = sample_value(&spec, spec.named_schema(), 0);
+                let mut bytes = encode(&spec, value.clone());
+                let len = bytes.len();
+                // trailing bytes are not part of the value
+                bytes.extend_from_slice(&[0xFF; 4]);
+                let value_ref = GluinoValueRef::new(&spec, &bytes);
+                assert_eq!(len, value_ref.encoded_len().unwrap());
+                assert_eq!(value, value_ref.to_value().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let spec =
+            compile("record { id: uint(3), name: string, side: enum { buy: void, sell: void } }");
+        let bytes = encode(
+            &spec,
+            GluinoValue::Record(vec![
+                GluinoValue::Uint64(1),
+                string("a"),
+                GluinoValue::Enum(0, Box::new(GluinoValue::Void)),
+            ]),
+        );
+        let value = GluinoValueRef::new(&spec, &bytes);
+        assert_eq!(
+            GluinoValueRefErrorKind::UnknownField,
+            error_kind(value.field("missing"))
+        );
+        assert_eq!(
+            GluinoValueRefErrorKind::IndexOutOfBounds,
+            error_kind(value.field_at(3))
+        );
+        assert_eq!(
+            GluinoValueRefErrorKind::KindMismatch,
+            error_kind(value.as_str())
+        );
+        assert_eq!(
+            GluinoValueRefErrorKind::KindMismatch,
+            error_kind(value.field("id").unwrap().as_i64())
+        );
+        assert_eq!(
+            GluinoValueRefErrorKind::Deserialization,
+            error_kind(GluinoValueRef::new(&spec, &bytes[..9]).field("side"))
+        );
+
+        let mut bad_variant = bytes.clone();
+        *bad_variant.last_mut().unwrap() = 5;
+        assert_eq!(
+            GluinoValueRefErrorKind::Deserialization,
+            error_kind(
+                GluinoValueRef::new(&spec, &bad_variant)
+                    .field("side")
+                    .unwrap()
+                    .variant_name()
+            )
+        );
+
+        let mut bad_utf8 = bytes.clone();
+        bad_utf8[9] = 0xC0;
+        assert_eq!(
+            GluinoValueRefErrorKind::InvalidUtf8String,
+            error_kind(
+                GluinoValueRef::new(&spec, &bad_utf8)
+                    .field("name")
+                    .unwrap()
+                    .as_str()
+            )
+        );
+
+        let optional = compile("optional<bool>");
+        for value in [
+            GluinoValueRef::new(&optional, &[0x02, 0x01]).optional().map(|_| ()),
+            GluinoValueRef::new(&optional, &[0x02, 0x01]).encoded_len().map(|_| ()),
+        ] {
+            match value {
+                Err(GluinoValueRefError::Deserialization(
+                    GluinoDeserializationError::InvalidOptionalFlag(0x02),
+                )) => {}
+                value => panic!("expected an invalid optional flag, got {:?}", value),
+            }
+        }
+
+        let list = compile("list<bool>");
+        let bytes = encode(&list, GluinoValue::List(vec![GluinoValue::Bool(true)]));
+        assert_eq!(
+            GluinoValueRefErrorKind::IndexOutOfBounds,
+            error_kind(GluinoValueRef::new(&list, &bytes).element(1))
+        );
+        // the index of an element and id of a variant are not truncated before they are checked
+        assert_eq!(
+            GluinoValueRefErrorKind::IndexOutOfBounds,
+            error_kind(GluinoValueRef::new(&list, &bytes).element(1 << 32))
+        );
+        let union = compile("union { bool, int(0) }");
+        let mut bytes = Vec::new();
+        variable_length_encode_u64(1 << 32, &mut bytes).unwrap();
+        bytes.push(0x01);
+        assert!(matches!(
+            GluinoValueRef::new(&union, &bytes).variant(),
+            Err(GluinoValueRefError::Deserialization(
+                GluinoDeserializationError::InvalidVariantId { max_variant_id: 1, .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // every 0x01 0x01 is a present optional and its bool, one level deeper each time
+        let spec = compile("name L = optional<tuple { bool, ref L }>");
+        let bytes = vec![0x01; 4 << 20];
+        let value = GluinoValueRef::new(&spec, &bytes);
+        assert_eq!(
+            DecodeLimitError::MaxDepthExceeded(128),
+            limit_error(value.encoded_len())
+        );
+        assert_eq!(
+            DecodeLimitError::MaxDepthExceeded(128),
+            limit_error(value.to_value())
+        );
+        let inner = value.optional().unwrap().unwrap();
+        assert!(inner.field_at(0).unwrap().as_bool().unwrap());
+        assert_eq!(
+            DecodeLimitError::MaxDepthExceeded(128),
+            limit_error(inner.field_at(1).unwrap().encoded_bytes())
+        );
+
+        let list = compile("list<name L = optional<tuple { bool, ref L }>>");
+        let list = GluinoValueRef::new(&list, &bytes);
+        assert_eq!(1, list.len().unwrap());
+        assert_eq!(
+            DecodeLimitError::MaxDepthExceeded(128),
+            limit_error(list.elements().unwrap().next().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_length_limits() {
+        let limits = DecodeLimits {
+            max_collection_length: 2,
+            max_string_length: 4,
+            ..DecodeLimits::default()
+        };
+        let spec = compile("record { names: list<string>, note: string }");
+        let value = |names: &[&str], note: &str| {
+            encode(
+                &spec,
+                GluinoValue::Record(vec![
+                    GluinoValue::List(names.iter().map(|name| string(name)).collect()),
+                    string(note),
+                ]),
+            )
+        };
+
+        let bytes = value(&["ab", "cdef"], "note");
+        let within = GluinoValueRef::new_with_limits(&spec, &bytes, &limits);
+        assert_eq!(bytes.len(), within.encoded_len().unwrap());
+        assert_eq!("note", within.field("note").unwrap().as_str().unwrap());
+        assert_eq!(2, within.field("names").unwrap().len().unwrap());
+
+        let bytes = value(&["ab", "cd", "ef"], "note");
+        let long_list = GluinoValueRef::new_with_limits(&spec, &bytes, &limits);
+        for e in [
+            limit_error(long_list.encoded_len()),
+            limit_error(long_list.field("note")),
+            limit_error(long_list.field("names").unwrap().len()),
+            limit_error(long_list.to_value()),
+        ] {
+            assert_eq!(DecodeLimitErrorKind::MaxCollectionLengthExceeded, (&e).into());
+        }
+
+        let bytes = value(&["abcde"], "note");
+        let long_element = GluinoValueRef::new_with_limits(&spec, &bytes, &limits);
+        let names = long_element.field("names").unwrap();
+        for e in [
+            limit_error(long_element.field("note")),
+            limit_error(names.elements().unwrap().next().unwrap()),
+            limit_error(names.element(0)),
+        ] {
+            assert_eq!(DecodeLimitErrorKind::MaxStringLengthExceeded, (&e).into());
+        }
+        // the same bytes are fine within the default limits
+        assert_eq!(
+            bytes.len(),
+            GluinoValueRef::new(&spec, &bytes).encoded_len().unwrap()
+        );
+    }
+}
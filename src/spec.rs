@@ -1,83 +1,152 @@
-use crate::serde::GluinoValue;
+use crate::serde::{
+    get_unit_deserialization_function, get_unit_serialization_function, GluinoValue,
+};
 use crate::{
     fingerprint::SpecFingerprint,
+    limits::{DecodeBudget, DecodeLimitError, DecodeLimits},
     spec_parsing::{
         InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, ParsedSpec, Size,
-        StringEncodingFmt,
+        SpecKind, StringEncodingFmt,
     },
+    spec_compiler::SpecCompiler,
+    spec_path::SpecPath,
+    spec_visitor::visit_parsed_spec,
 };
 use core::fmt::Debug;
-use std::rc::Rc;
 use std::{
+    cell::{OnceCell, RefCell},
     collections::{HashMap, HashSet},
-    hash::Hash,
+    io::Cursor,
+    ops::Deref,
+    rc::{Rc, Weak},
 };
 use strum::{EnumDiscriminants, EnumIter};
 
-#[derive(Eq, PartialEq, Clone)]
+#[derive(Clone)]
 pub struct Spec {
-    pub(crate) fingerprint: SpecFingerprint,
-    pub(crate) named_spec: HashMap<String, Rc<Spec>>,
+    // computed on first use, recursive references can only be fingerprinted once their name is defined
+    pub(crate) fingerprint: OnceCell<SpecFingerprint>,
+    pub(crate) named_spec: HashMap<String, NamedSpec>,
     pub(crate) spec_type: SpecType,
 }
 
 impl Debug for Spec {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CompiledSpec")
-            .field("fingerprint", &self.fingerprint)
+            .field("fingerprint", self.fingerprint())
             .field("named_schema", &self.named_spec)
             .field("structure", &self.to_parsed_spec())
             .finish()
     }
 }
 
+// Specs may be recursive, so equality is decided by fingerprint rather than by walking the structure
+impl PartialEq for Spec {
+    fn eq(&self, other: &Self) -> bool {
+        self.fingerprint() == other.fingerprint()
+    }
+}
+
+impl Eq for Spec {}
+
 impl Spec {
     pub fn fingerprint(&self) -> &SpecFingerprint {
-        &self.fingerprint
+        self.fingerprint
+            .get_or_init(|| SpecFingerprint::new(&self.named_spec, &self.spec_type))
     }
 
     pub fn spec_type(&self) -> &SpecType {
         &self.spec_type
     }
 
-    pub fn named_schema(&self) -> &HashMap<String, Spec> {
+    pub fn named_schema(&self) -> &HashMap<String, NamedSpec> {
         &self.named_spec
     }
 
+    pub(crate) fn kind(&self) -> SpecKind {
+        SpecKind::from(&self.to_parsed_spec())
+    }
+
+    /// Follows names to the spec they stand for
+    pub(crate) fn resolve(&self) -> Resolved<'_> {
+        let SpecType::Name(name) = &self.spec_type else {
+            return Resolved::Spec(self);
+        };
+        let mut definition = self.named_definition(name);
+        while let SpecType::Name(name) = definition.spec_type() {
+            definition = definition.named_definition(name);
+        }
+        Resolved::Definition(definition)
+    }
+
+    /// The definition of a name the spec refers to
+    pub(crate) fn named_definition(&self, name: &str) -> Definition {
+        self.named_spec
+            .get(name)
+            .and_then(NamedSpec::definition)
+            .expect("Compiled spec should have named spec")
+    }
+
+    /// Compiles a spec, returning the first error found. See `compile_reporting_all` for every
+    /// error
     pub fn compile(spec: ParsedSpec) -> Result<Spec, SpecCompileError> {
-        Self::compile_in_context(spec, &mut HashMap::new())
+        Self::compile_with_limits(spec, &DecodeLimits::default())
+    }
+
+    /// Compiles a spec from untrusted input, the depth, number of specs and number of fields,
+    /// variants and const values are checked before compiling
+    pub fn compile_with_limits(
+        spec: ParsedSpec,
+        limits: &DecodeLimits,
+    ) -> Result<Spec, SpecCompileError> {
+        Self::compile_reporting_all(spec, limits).map_err(first_error)
+    }
+
+    /// Compiles a spec, carrying on past errors to return every error in the order found. Each
+    /// error is `SpecCompileError::Located` with the path to the spec it is in
+    pub fn compile_reporting_all(
+        spec: ParsedSpec,
+        limits: &DecodeLimits,
+    ) -> Result<Spec, Vec<SpecCompileError>> {
+        check_limits(&spec, &mut DecodeBudget::new(*limits)).map_err(|e| vec![e.into()])?;
+        compile_in_context_reporting_all(&spec, &mut HashMap::new())
     }
 
     pub fn compile_in_context(
         parsed_spec: ParsedSpec,
-        context: &mut HashMap<String, Spec>,
+        context: &mut HashMap<String, NamedSpec>,
     ) -> Result<Spec, SpecCompileError> {
-        compile_spec_internal(parsed_spec, context, &mut HashSet::new(), &mut HashSet::new())
+        compile_in_context_reporting_all(&parsed_spec, context).map_err(first_error)
     }
 
-    //internal placeholder compiled spec used for name resolution workflows
-    fn invalid_compiled_spec() -> Spec {
+    pub(crate) fn new(named_spec: HashMap<String, NamedSpec>, spec_type: SpecType) -> Spec {
         Spec {
-            fingerprint: SpecFingerprint::new(&HashMap::new(), &SpecType::Void),
-            named_spec: HashMap::with_capacity(0),
-            spec_type: SpecType::Void,
+            fingerprint: OnceCell::new(),
+            named_spec,
+            spec_type,
         }
     }
 
+    //internal placeholder compiled spec used for name resolution workflows
+    #[cfg(test)]
+    fn invalid_compiled_spec() -> Spec {
+        Spec::new(HashMap::with_capacity(0), SpecType::Void)
+    }
+
     pub(crate) fn to_parsed_spec(&self) -> ParsedSpec {
         Self::make_parsed_spec(&self.named_spec, &self.spec_type)
     }
 
     //turn the structe of a compiled schema in the provided context into a context free spec
     pub(crate) fn make_parsed_spec(
-        context: &HashMap<String, Spec>,
+        context: &HashMap<String, NamedSpec>,
         structure: &SpecType,
     ) -> ParsedSpec {
         Self::make_parsed_spec_internal(context, &mut HashSet::new(), structure)
     }
 
-    fn make_parsed_spec_internal(
-        context: &HashMap<String, Spec>,
+    pub(crate) fn make_parsed_spec_internal(
+        context: &HashMap<String, NamedSpec>,
         names_converted: &mut HashSet<String>,
         spec_type: &SpecType,
     ) -> ParsedSpec {
@@ -126,21 +195,21 @@ impl Spec {
             SpecType::Optional(s) => ParsedSpec::Optional(Box::new(
                 Self::make_parsed_spec_internal(context, names_converted, &s.spec_type),
             )),
-            SpecType::Name(name) => {
-                if names_converted.contains(name) {
-                    ParsedSpec::Ref { name: name.clone() }
-                } else {
+            SpecType::Name(name) => match context.get(name).and_then(NamedSpec::definition) {
+                // names still being compiled can only be referred to
+                Some(named_spec) if !names_converted.contains(name) => {
                     names_converted.insert(name.clone());
                     ParsedSpec::Name {
                         name: name.clone(),
                         spec: Box::new(Self::make_parsed_spec_internal(
                             context,
                             names_converted,
-                            &context.get(name).unwrap().spec_type,
+                            &named_spec.spec_type,
                         )),
                     }
                 }
-            }
+                _ => ParsedSpec::Ref { name: name.clone() },
+            },
             SpecType::Record {
                 fields,
                 field_to_spec,
@@ -190,7 +259,14 @@ impl Spec {
                     .map(|cs| Self::make_parsed_spec_internal(context, names_converted, &cs.spec_type))
                     .collect(),
             ),
-            SpecType::ConstSet(_, _) => unimplemented!(),
+            SpecType::ConstSet(const_spec, const_values) => ParsedSpec::ConstSet(
+                Box::new(Self::make_parsed_spec_internal(
+                    context,
+                    names_converted,
+                    &const_spec.spec_type,
+                )),
+                encode_const_values(const_spec, const_values),
+            ),
         }
     }
 }
@@ -243,275 +319,437 @@ pub enum SpecCompileError {
     DuplicateUnionVariantSpecs(Vec<Spec>),
     InfinitelyRecursiveTypes(HashSet<String>),
     IllegalDecimalFmt,
+    /// A size that allows no length, as its minimum length is not below its exclusive end
+    InvertedLengthBounds {
+        min_len: u64,
+        end: u64,
+    },
+    DuplicateConstSetValues(Vec<GluinoValue>),
+    UndecodableConstSetValue(Vec<u8>),
+    LimitExceeded(DecodeLimitError),
+    /// An error in the spec at `path`, within the definitions of `names`, outermost first
+    Located {
+        path: SpecPath,
+        names: Vec<String>,
+        error: Box<SpecCompileError>,
+    },
     InternalCompilerError(String),
 }
 
+impl SpecCompileError {
+    /// Where in the spec the error is, `None` if it is not about one place in the spec
+    pub fn path(&self) -> Option<&SpecPath> {
+        match self {
+            Self::Located { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Names whose definitions the error is in, outermost first
+    pub fn names(&self) -> &[String] {
+        match self {
+            Self::Located { names, .. } => names,
+            _ => &[],
+        }
+    }
+
+    /// The error without its location
+    pub fn cause(&self) -> &SpecCompileError {
+        match self {
+            Self::Located { error, .. } => error,
+            _ => self,
+        }
+    }
+
+    pub fn kind(&self) -> SpecCompileErrorKind {
+        SpecCompileErrorKind::from(self.cause())
+    }
+}
+
+impl std::fmt::Display for SpecCompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // sets print in a stable order
+        fn sorted(names: &HashSet<String>) -> Vec<&String> {
+            let mut names: Vec<&String> = names.iter().collect();
+            names.sort();
+            names
+        }
+        match self {
+            Self::DuplicateName(name) => write!(f, "name {:?} is defined more than once", name),
+            Self::UndefinedName(name) => write!(f, "name {:?} is not defined", name),
+            Self::DuplicateRecordFieldNames(fields) => {
+                write!(f, "duplicate record fields {:?}", sorted(fields))
+            }
+            Self::DuplicateEnumVariantNames(variants) => {
+                write!(f, "duplicate enum variants {:?}", sorted(variants))
+            }
+            Self::DuplicateUnionVariantSpecs(specs) => write!(
+                f,
+                "duplicate union variants {:?}",
+                specs.iter().map(Spec::to_text).collect::<Vec<_>>()
+            ),
+            Self::InfinitelyRecursiveTypes(names) => write!(
+                f,
+                "names {:?} refer to themselves without an optional, list or map between",
+                sorted(names)
+            ),
+            Self::IllegalDecimalFmt => write!(f, "{}", IllegalDecimalFmt),
+            Self::InvertedLengthBounds { min_len, end } => write!(
+                f,
+                "no length is at least {} and less than {}",
+                min_len, end
+            ),
+            Self::DuplicateConstSetValues(values) => {
+                write!(f, "duplicate const set values {:?}", values)
+            }
+            Self::UndecodableConstSetValue(bytes) => write!(
+                f,
+                "const set value {} does not decode",
+                hex::encode(bytes)
+            ),
+            Self::LimitExceeded(e) => write!(f, "decode limit exceeded: {}", e),
+            Self::Located { path, names, error } if names.is_empty() => {
+                write!(f, "{}: {}", path, error)
+            }
+            Self::Located { path, names, error } => {
+                write!(f, "{} in {:?}: {}", path, names, error)
+            }
+            Self::InternalCompilerError(msg) => write!(f, "internal compiler error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SpecCompileError {}
+
 impl From<IllegalDecimalFmt> for SpecCompileError {
     fn from(_: IllegalDecimalFmt) -> Self {
         SpecCompileError::IllegalDecimalFmt
     }
 }
 
-pub(crate) fn compile_spec_internal(
-    spec: ParsedSpec,
-    context: &mut HashMap<String, Spec>,
-    non_optional_names: &mut HashSet<String>,
-    names_used: &mut HashSet<String>,
-) -> Result<Spec, SpecCompileError> {
-    let mut internal_names_used = HashSet::new();
-    let structure =
-        compile_structure_internal(spec, context, non_optional_names, &mut internal_names_used)?;
-    let mut named_spec = HashMap::new();
-    for name in internal_names_used.iter() {
-        named_spec.insert(name.clone(), context.get(name).unwrap().clone());
-    }
-    names_used.extend(internal_names_used.into_iter());
-    Ok(Spec {
-        fingerprint: SpecFingerprint::new(&named_spec, &structure),
-        named_spec,
-        spec_type: structure,
+impl From<DecodeLimitError> for SpecCompileError {
+    fn from(e: DecodeLimitError) -> Self {
+        SpecCompileError::LimitExceeded(e)
+    }
+}
+
+fn compile_in_context_reporting_all(
+    spec: &ParsedSpec,
+    context: &mut HashMap<String, NamedSpec>,
+) -> Result<Spec, Vec<SpecCompileError>> {
+    let mut compiler = SpecCompiler::new(context);
+    visit_parsed_spec(spec, &mut compiler);
+    compiler.finalize()
+}
+
+fn first_error(errors: Vec<SpecCompileError>) -> SpecCompileError {
+    errors.into_iter().next().unwrap_or_else(|| {
+        SpecCompileError::InternalCompilerError("Compilation failed without an error".into())
     })
 }
 
-pub(crate) fn compile_structure_internal(
-    spec: ParsedSpec,
-    context: &mut HashMap<String, Spec>,
-    non_optional_names: &mut HashSet<String>,
-    names_used: &mut HashSet<String>,
-) -> Result<SpecType, SpecCompileError> {
-    match spec {
-        ParsedSpec::Bool => Ok(SpecType::Bool),
-        ParsedSpec::Uint(n) => Ok(SpecType::Uint(n)),
-        ParsedSpec::Int(n) => Ok(SpecType::Int(n)),
-        ParsedSpec::BinaryFloatingPoint(fmt) => Ok(SpecType::BinaryFloatingPoint(fmt)),
-        ParsedSpec::DecimalFloatingPoint(fmt) => Ok(SpecType::DecimalFloatingPoint(fmt)),
-        ParsedSpec::Decimal { precision, scale } => Ok(SpecType::Decimal(DecimalFmt::new(
-            precision, scale,
-        )?)),
+// stops at the depth limit, so the compiler only recurses into specs within it
+fn check_limits(spec: &ParsedSpec, budget: &mut DecodeBudget) -> Result<(), DecodeLimitError> {
+    budget.spec_node()?;
+    let sub_specs: Vec<&ParsedSpec> = match spec {
+        ParsedSpec::Name { spec, .. } | ParsedSpec::Optional(spec) => vec![spec],
+        ParsedSpec::List { value_spec, .. } => vec![value_spec],
         ParsedSpec::Map {
-            size,
             key_spec,
             value_spec,
-        } => Ok(SpecType::Map {
-            size,
-            key_spec: box_compile(key_spec, context, names_used)?,
-            value_spec: box_compile(value_spec, context, names_used)?,
-        }),
-        ParsedSpec::List { size, value_spec } => Ok(SpecType::List {
-            size,
-            value_spec: box_compile(value_spec, context, names_used)?,
-        }),
-        ParsedSpec::String(size, fmt) => Ok(SpecType::String(size, fmt)),
-        ParsedSpec::Bytes(size) => Ok(SpecType::Bytes(size)),
-        ParsedSpec::Optional(s) => Ok(SpecType::Optional(box_compile(
-            s, context, names_used,
-        )?)),
-        ParsedSpec::Name { name, spec } => {
-            if context.contains_key(&name) {
-                Err(SpecCompileError::DuplicateName(name.clone()))
-            } else {
-                let compiled_spec_ref = Spec::invalid_compiled_spec();
-                context.insert(name.clone(), compiled_spec_ref.clone());
-                non_optional_names.insert(name.clone());
-                let cs = compile_spec_internal(*spec, context, non_optional_names, names_used)?;
-                context.insert(name.clone(), cs);
-                non_optional_names.remove(&name);
-                names_used.insert(name.clone());
-                Ok(SpecType::Name(name))
-            }
+            ..
+        } => vec![key_spec, value_spec],
+        ParsedSpec::Record(fields) | ParsedSpec::Enum(fields) => {
+            budget.collection(fields.len() as u64, 0)?;
+            fields.iter().map(|(_, spec)| spec).collect()
         }
-        ParsedSpec::Ref { name } => {
-            if non_optional_names.contains(&name) {
-                Err(SpecCompileError::InfinitelyRecursiveTypes(HashSet::from([
-                    name,
-                ])))
-            } else if context.contains_key(&name) {
-                names_used.insert(name.clone());
-                Ok(SpecType::Name(name))
-            } else {
-                Err(SpecCompileError::UndefinedName(name))
-            }
+        ParsedSpec::Tuple(specs) | ParsedSpec::Union(specs) => {
+            budget.collection(specs.len() as u64, 0)?;
+            specs.iter().collect()
         }
-        ParsedSpec::Record(fields) => {
-            let field_names = fields
-                .iter()
-                .map(|f| &f.0)
-                .map(|name| name.clone())
-                .collect();
-            let mut duplicate_name_track = HashSet::new();
-            let field_to_index = fields
-                .iter()
-                .enumerate()
-                .map(|(index, (name, _))| (name.clone(), index))
-                .collect();
-            let mut field_to_spec = HashMap::with_capacity(fields.capacity());
-            for (field_name, field_spec) in fields {
-                if field_to_spec
-                    .insert(
-                        field_name.clone(),
-                        compile_spec_internal(field_spec, context, non_optional_names, names_used)?,
-                    )
-                    .is_some()
-                {
-                    duplicate_name_track.insert(field_name);
-                }
+        ParsedSpec::ConstSet(const_spec, values) => {
+            budget.collection(values.len() as u64, 0)?;
+            vec![const_spec]
+        }
+        _ => vec![],
+    };
+    for sub_spec in sub_specs {
+        budget.enter()?;
+        check_limits(sub_spec, budget)?;
+        budget.exit();
+    }
+    Ok(())
+}
+
+pub(crate) fn encode_const_values(const_spec: &Spec, values: &[GluinoValue]) -> Vec<Vec<u8>> {
+    let const_ser = get_unit_serialization_function::<Vec<u8>>(const_spec);
+    values
+        .iter()
+        .map(|value| {
+            let mut bytes = Vec::new();
+            const_ser
+                .serialize(value.clone(), &mut bytes)
+                .expect("Compiled const set values should serialize");
+            bytes
+        })
+        .collect()
+}
+
+// each constant must be exactly one value of the const spec
+pub(crate) fn decode_const_values(
+    const_spec: &Spec,
+    values: Vec<Vec<u8>>,
+) -> Result<Vec<GluinoValue>, SpecCompileError> {
+    if values.is_empty() {
+        return Ok(Vec::new());
+    }
+    // a const spec refering to a name that is still being compiled has no definition to decode with
+    if const_spec.named_schema().values().any(|named_spec| named_spec.definition().is_none()) {
+        return Err(SpecCompileError::UndecodableConstSetValue(
+            values.into_iter().next().unwrap(),
+        ));
+    }
+    let const_de = get_unit_deserialization_function::<Cursor<Vec<u8>>>(const_spec);
+    values
+        .into_iter()
+        .map(|bytes| {
+            let len = bytes.len() as u64;
+            let mut reader = Cursor::new(bytes);
+            match const_de.deserialize(&mut reader) {
+                Ok(value) if reader.position() == len => Ok(value),
+                _ => Err(SpecCompileError::UndecodableConstSetValue(reader.into_inner())),
+            }
+        })
+        .collect()
+}
+
+impl TryFrom<ParsedSpec> for Spec {
+    type Error = SpecCompileError;
+    fn try_from(spec: ParsedSpec) -> Result<Spec, SpecCompileError> {
+        Spec::compile(spec)
+    }
+}
+
+/// Shared handle to the definition of a named spec. Every reference to a name within a compilation
+/// shares the same definition, which is how recursive specs refer back to their own definition.
+///
+/// The names defined within an outermost definition, that name included, share one set of
+/// [Definitions]. References between them are weak, so a recursive spec is not a reference cycle,
+/// while references from outside hold all of them, so they are dropped together with the last spec
+/// outside of them. Definitions are only lent out of the crate as copies holding their names, so
+/// every spec met outside of it can follow its names.
+pub struct NamedSpec(NamedSpecHandle);
+
+type DefinitionCell = OnceCell<Rc<Spec>>;
+
+enum NamedSpecHandle {
+    // the copy of the definition lent out by `get`, made on first use
+    Strong(Rc<Definitions>, Rc<DefinitionCell>, OnceCell<Box<Spec>>),
+    // only ever within the definitions it refers to
+    Weak(Weak<Definitions>, Weak<DefinitionCell>),
+}
+
+impl NamedSpec {
+    /// Declares `name` within `definitions`, to be defined once its definition is compiled
+    pub(crate) fn declare(definitions: &Rc<Definitions>, name: &str) -> NamedSpec {
+        let cell = Rc::new(OnceCell::new());
+        definitions
+            .declared
+            .borrow_mut()
+            .insert(name.into(), cell.clone());
+        NamedSpec(NamedSpecHandle::Strong(definitions.clone(), cell, OnceCell::new()))
+    }
+
+    /// Handle for a reference from within the definitions the name is in
+    pub(crate) fn recursive_reference(&self) -> NamedSpec {
+        NamedSpec(match &self.0 {
+            NamedSpecHandle::Strong(definitions, cell, _) => {
+                NamedSpecHandle::Weak(Rc::downgrade(definitions), Rc::downgrade(cell))
             }
-            if duplicate_name_track.is_empty() {
-                Ok(SpecType::Record {
-                    fields: field_names,
-                    field_to_spec,
-                    field_to_index,
-                })
-            } else {
-                Err(SpecCompileError::DuplicateRecordFieldNames(
-                    duplicate_name_track,
-                ))
+            NamedSpecHandle::Weak(definitions, cell) => {
+                NamedSpecHandle::Weak(definitions.clone(), cell.clone())
             }
+        })
+    }
+
+    pub(crate) fn define(&self, spec: Spec) {
+        let NamedSpecHandle::Strong(_, cell, _) = &self.0 else {
+            panic!("Named spec defined through a recursive reference")
+        };
+        if cell.set(Rc::new(spec)).is_err() {
+            panic!("Named spec defined more than once")
         }
-        ParsedSpec::Tuple(fields) => {
-            let mut compiled_fields = Vec::with_capacity(fields.capacity());
-            for field_spec in fields {
-                compiled_fields.push(compile_spec_internal(
-                    field_spec,
-                    context,
-                    &mut non_optional_names.clone(),
-                    names_used,
-                )?)
+    }
+
+    /// The definition of the name, None while the definition is being compiled. A copy holding
+    /// the definitions of the names within it, so they can be followed from any part of it
+    pub fn get(&self) -> Option<&Spec> {
+        match &self.0 {
+            NamedSpecHandle::Strong(_, cell, copy) => {
+                let definition = cell.get()?;
+                Some(copy.get_or_init(|| Box::new(Spec::clone(definition))))
             }
-            Ok(SpecType::Tuple(compiled_fields))
+            // never lent out, references within definitions are only met in them
+            NamedSpecHandle::Weak(..) => None,
         }
-        ParsedSpec::Enum(variants) => {
-            let variant_names: Vec<String> =
-                variants.iter().map(|v| &v.0).map(|n| n.clone()).collect();
-            let mut all_names: HashSet<String> = variant_names.clone().into_iter().collect();
-            let duplicate_names: HashSet<String> = variant_names
-                .iter()
-                .filter(|&n| !all_names.remove(n))
-                .map(|n| n.clone())
-                .collect();
-            if !duplicate_names.is_empty() {
-                return Err(SpecCompileError::DuplicateEnumVariantNames(duplicate_names));
+    }
+
+    /// The definition itself, for walks following the names within it through its definitions
+    pub(crate) fn defined(&self) -> Option<&Spec> {
+        match &self.0 {
+            NamedSpecHandle::Strong(_, cell, _) => cell.get().map(Rc::as_ref),
+            NamedSpecHandle::Weak(..) => None,
+        }
+    }
+
+    /// The definition of the name however it is referred to, held along with the definitions it
+    /// is in. None while the definition is being compiled
+    pub(crate) fn definition(&self) -> Option<Definition> {
+        let (definitions, cell) = match &self.0 {
+            NamedSpecHandle::Strong(definitions, cell, _) => (definitions.clone(), cell.clone()),
+            NamedSpecHandle::Weak(definitions, cell) => (definitions.upgrade()?, cell.upgrade()?),
+        };
+        Some(Definition {
+            spec: cell.get()?.clone(),
+            _definitions: definitions,
+        })
+    }
+
+    /// The definitions the name is in, if this handle holds them
+    pub(crate) fn definitions(&self) -> Option<&Definitions> {
+        match &self.0 {
+            NamedSpecHandle::Strong(definitions, ..) => Some(definitions),
+            NamedSpecHandle::Weak(..) => None,
+        }
+    }
+
+    pub(crate) fn is_in(&self, definitions: &Rc<Definitions>) -> bool {
+        match &self.0 {
+            NamedSpecHandle::Strong(in_definitions, ..) => Rc::ptr_eq(in_definitions, definitions),
+            NamedSpecHandle::Weak(in_definitions, _) => {
+                std::ptr::eq(in_definitions.as_ptr(), Rc::as_ptr(definitions))
             }
-            let mut variant_to_spec = HashMap::new();
-            compile_variants_with_loop_checking(
-                variants,
-                &mut variant_to_spec,
-                non_optional_names,
-                context,
-                names_used,
-            )?;
-            Ok(SpecType::Enum {
-                variants: variant_names,
-                variant_to_spec,
-            })
         }
-        ParsedSpec::Union(variants) => {
-            let len = variants.len();
-            let variants: Vec<(usize, ParsedSpec)> = variants.into_iter().enumerate().collect();
-            let mut variants_to_spec = HashMap::new();
-            compile_variants_with_loop_checking(
-                variants,
-                &mut variants_to_spec,
-                non_optional_names,
-                context,
-                names_used,
-            )?;
-            let compiled_variants: Vec<Spec> = (0..len)
-                .map(|index| variants_to_spec.remove(&index).unwrap())
-                .collect();
-            let mut variant_fingerprints: HashSet<&SpecFingerprint> = HashSet::new();
-            let duplicate_variants: Vec<Spec> = compiled_variants
-                .iter()
-                .filter(|&v| !variant_fingerprints.insert(&v.fingerprint))
-                .map(|v| v.clone())
-                .collect();
-            if duplicate_variants.is_empty() {
-                Ok(SpecType::Union(compiled_variants))
-            } else {
-                Err(SpecCompileError::DuplicateUnionVariantSpecs(
-                    duplicate_variants,
-                ))
+    }
+}
+
+// a clone can outlive the definitions it was in, so it holds them
+impl Clone for NamedSpec {
+    fn clone(&self) -> NamedSpec {
+        NamedSpec(match &self.0 {
+            NamedSpecHandle::Strong(definitions, cell, _) => {
+                NamedSpecHandle::Strong(definitions.clone(), cell.clone(), OnceCell::new())
+            }
+            NamedSpecHandle::Weak(definitions, cell) => {
+                match (definitions.upgrade(), cell.upgrade()) {
+                    (Some(definitions), Some(cell)) => {
+                        NamedSpecHandle::Strong(definitions, cell, OnceCell::new())
+                    }
+                    _ => NamedSpecHandle::Weak(definitions.clone(), cell.clone()),
+                }
             }
-        },
-        ParsedSpec::ConstSet(cont_spec, values) => {
-            todo!()
-        },
-        ParsedSpec::Void => Ok(SpecType::Void),
+        })
     }
 }
 
-impl TryFrom<ParsedSpec> for Spec {
-    type Error = SpecCompileError;
-    fn try_from(spec: ParsedSpec) -> Result<Spec, SpecCompileError> {
-        Spec::compile(spec)
+impl PartialEq<Spec> for NamedSpec {
+    fn eq(&self, other: &Spec) -> bool {
+        self.definition().is_some_and(|spec| *spec == *other)
     }
 }
 
-#[inline]
-fn box_compile(
-    spec: Box<ParsedSpec>,
-    context: &mut HashMap<String, Spec>,
-    names_used: &mut HashSet<String>,
-) -> Result<Box<Spec>, SpecCompileError> {
-    Ok(Box::new(compile_spec_internal(
-        *spec,
-        context,
-        &mut HashSet::new(),
-        names_used,
-    )?))
-}
-
-#[inline]
-fn compile_variants_with_loop_checking<T>(
-    variants: Vec<(T, ParsedSpec)>,
-    variant_to_spec: &mut HashMap<T, Spec>,
-    non_optional_names: &mut HashSet<String>,
-    context: &mut HashMap<String, Spec>,
-    names_used: &mut HashSet<String>,
-) -> Result<(), SpecCompileError>
-where
-    T: Eq + PartialEq + Hash + Clone,
-{
-    let num_variants = variants.len();
-    let mut variants_with_non_optional_name_errors = HashSet::new();
-    let mut offending_names_for_all_variants = HashSet::new();
-    for (variant_name, variant_spec) in variants {
-        let mut non_offending_names_for_variant = non_optional_names.clone();
-        let mut offending_names_for_variant = HashSet::new();
-        let cs = loop {
-            //TODO get rid of clone somehow
-            match compile_spec_internal(
-                variant_spec.clone(),
-                context,
-                &mut non_offending_names_for_variant,
-                names_used,
-            ) {
-                Ok(cs) => break Ok(cs),
-                Err(SpecCompileError::InfinitelyRecursiveTypes(offending_names)) => {
-                    offending_names.iter().for_each(|offending_name| {
-                        non_offending_names_for_variant.remove(offending_name);
-                    });
-                    offending_names.into_iter().for_each(|offending_name| {
-                        offending_names_for_variant.insert(offending_name);
-                    });
-                    variants_with_non_optional_name_errors.insert(variant_name.clone());
-                }
-                Err(e) => break Err(e),
-            }
-        }?;
-        offending_names_for_variant
-            .into_iter()
-            .for_each(|offending_name| {
-                offending_names_for_all_variants.insert(offending_name);
-            });
-        variant_to_spec.insert(variant_name.clone(), cs);
+impl Debug for NamedSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.definition() {
+            // the structure rather than the spec itself as the definition may refer back to this name
+            Some(spec) => f
+                .debug_tuple("NamedSpec")
+                .field(&spec.to_parsed_spec())
+                .finish(),
+            None => f.write_str("NamedSpec(<undefined>)"),
+        }
+    }
+}
+
+/// The definitions of the names defined within one outermost definition
+#[derive(Default)]
+pub(crate) struct Definitions {
+    // while the definitions are compiled
+    declared: RefCell<HashMap<String, Rc<DefinitionCell>>>,
+    // once they are all compiled
+    closed: OnceCell<HashMap<String, Rc<DefinitionCell>>>,
+}
+
+impl Definitions {
+    /// Ends the definitions once the outermost definition is compiled
+    pub(crate) fn close(&self) {
+        let _ = self.closed.set(self.declared.take());
     }
-    if variants_with_non_optional_name_errors.len() == num_variants {
-        Err(SpecCompileError::InfinitelyRecursiveTypes(
-            offending_names_for_all_variants,
-        ))
-    } else {
-        Ok(())
+
+    /// The definition of a name within these definitions
+    pub(crate) fn get(&self, name: &str) -> Option<&Spec> {
+        self.closed.get()?.get(name)?.get().map(Rc::as_ref)
+    }
+}
+
+/// The definition of a name, holding the definitions it is in
+pub(crate) struct Definition {
+    spec: Rc<Spec>,
+    _definitions: Rc<Definitions>,
+}
+
+impl Deref for Definition {
+    type Target = Spec;
+
+    fn deref(&self) -> &Spec {
+        &self.spec
+    }
+}
+
+/// A spec with its names followed, either the spec itself or the definition of its name
+pub(crate) enum Resolved<'s> {
+    Spec(&'s Spec),
+    Definition(Definition),
+}
+
+impl Deref for Resolved<'_> {
+    type Target = Spec;
+
+    fn deref(&self) -> &Spec {
+        match self {
+            Resolved::Spec(spec) => spec,
+            Resolved::Definition(definition) => definition,
+        }
+    }
+}
+
+/// Where the names met in a walk of a spec are looked up, borrowing their definitions for as long
+/// as the spec the walk started at. References from within their own definitions are looked up in
+/// the definitions the walk went through to reach them.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct NameScope<'s> {
+    definitions: Option<&'s Definitions>,
+}
+
+impl<'s> NameScope<'s> {
+    /// Follows names to the spec they stand for, along with the scope of names within it
+    pub(crate) fn resolve(mut self, mut spec: &'s Spec) -> (&'s Spec, NameScope<'s>) {
+        while let SpecType::Name(name) = spec.spec_type() {
+            let named_spec = &spec.named_spec[name];
+            spec = match named_spec.defined() {
+                Some(definition) => {
+                    self.definitions = named_spec.definitions();
+                    definition
+                }
+                // specs outside of the crate hold their names, so walks go through a definition
+                // before meeting the references within it
+                None => self
+                    .definitions
+                    .and_then(|definitions| definitions.get(name))
+                    .expect("Names within a definition should be reached through it"),
+            };
+        }
+        (spec, self)
     }
 }
 
@@ -521,8 +759,17 @@ pub struct DecimalFmt {
     pub scale: u64,
 }
 
+#[derive(Debug)]
 pub struct IllegalDecimalFmt;
 
+impl std::fmt::Display for IllegalDecimalFmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("decimal scale is greater than its precision")
+    }
+}
+
+impl std::error::Error for IllegalDecimalFmt {}
+
 impl DecimalFmt {
     pub fn new(precision: u64, scale: u64) -> Result<DecimalFmt, IllegalDecimalFmt> {
         if scale <= precision {
@@ -534,9 +781,11 @@ impl DecimalFmt {
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_closure, clippy::option_map_unit_fn)]
 mod tests {
     use super::*;
-    use crate::test_utils::get_all_kinds_spec;
+    use crate::spec_parsing::SizeRange;
+    use crate::test_utils::{compile, encode, get_all_kinds_spec};
     use strum::IntoEnumIterator;
 
     #[test]
@@ -551,6 +800,64 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compile_error_display() {
+        let compile = |text: &str| Spec::compile(ParsedSpec::from_text(text).unwrap()).unwrap_err();
+        assert_eq!(
+            "[]: name \"Missing\" is not defined",
+            compile("list<ref Missing>").to_string()
+        );
+        assert_eq!(
+            ".: names [\"A\"] refer to themselves without an optional, list or map between",
+            compile("name A = record { a: ref A }").to_string()
+        );
+        assert_eq!(
+            ".a in [\"A\"]: decimal scale is greater than its precision",
+            compile("name A = record { a: decimal(2, 3) }").to_string()
+        );
+    }
+
+    #[test]
+    fn test_compile_reporting_all() {
+        let spec = ParsedSpec::from_text(
+            r#"name Order = record {
+                id: ref Missing,
+                lines: list<name Line = record {
+                    price: decimal(2, 3),
+                    qty: uint(2),
+                    qty: uint(3),
+                }>,
+                status: enum { open: bool, open: void },
+                pick: union { bool, bool },
+                next: name Next = tuple { bool, ref Next },
+                // only found once the enum is done with its variants
+                choice: enum { a: name Inner = record { i: ref Inner }, b: bool },
+            }"#,
+        )
+        .unwrap();
+        let errors = Spec::compile_reporting_all(spec.clone(), &DecodeLimits::default())
+            .unwrap_err();
+        assert_eq!(
+            vec![
+                ".id in [\"Order\"]: name \"Missing\" is not defined",
+                ".lines[].price in [\"Order\", \"Line\"]: decimal scale is greater than its \
+                 precision",
+                ".lines[] in [\"Order\", \"Line\"]: duplicate record fields [\"qty\"]",
+                ".status in [\"Order\"]: duplicate enum variants [\"open\"]",
+                ".pick in [\"Order\"]: duplicate union variants [\"bool\"]",
+                ".next in [\"Order\"]: names [\"Next\"] refer to themselves without an optional, \
+                 list or map between",
+                ".choice.a in [\"Order\"]: names [\"Inner\"] refer to themselves without an \
+                 optional, list or map between",
+            ],
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>()
+        );
+        assert_eq!(".lines[].price", errors[1].path().unwrap().as_str());
+        assert_eq!(["Order", "Line"], errors[1].names());
+        assert_eq!(SpecCompileErrorKind::IllegalDecimalFmt, errors[1].kind());
+        assert_eq!(errors[0], Spec::compile(spec).unwrap_err());
+    }
+
     #[test]
     fn test_compile_error_cases_kinds() {
         // Create a spec the compiles with every error
@@ -683,11 +990,54 @@ mod tests {
                     precision: 3,
                     scale: 4,
                 }],
+                SpecCompileErrorKind::InvertedLengthBounds => vec![
+                    ParsedSpec::Bytes(Size::Range(SizeRange { start: 5, end: 3 })),
+                    ParsedSpec::List {
+                        size: Size::LessThan(0),
+                        value_spec: ParsedSpec::Bool.into(),
+                    },
+                ],
+                SpecCompileErrorKind::DuplicateConstSetValues => vec![
+                    ParsedSpec::ConstSet(
+                        ParsedSpec::Uint(0).into(),
+                        vec![vec![0x01], vec![0x02], vec![0x01]],
+                    ),
+                ],
+                SpecCompileErrorKind::UndecodableConstSetValue => vec![
+                    ParsedSpec::ConstSet(ParsedSpec::Int(2).into(), vec![vec![0x12]]),
+                    ParsedSpec::ConstSet(ParsedSpec::Uint(0).into(), vec![vec![0x01, 0x02]]),
+                    // an optional flag is only 0 or 1
+                    ParsedSpec::ConstSet(
+                        ParsedSpec::Optional(ParsedSpec::Bool.into()).into(),
+                        vec![vec![0x01, 0x01], vec![0x02, 0x01]],
+                    ),
+                    ParsedSpec::ConstSet(
+                        ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8).into(),
+                        vec![vec![0x02, 0xC3, 0x28]],
+                    ),
+                    ParsedSpec::Name {
+                        name: "name".into(),
+                        spec: ParsedSpec::Optional(
+                            ParsedSpec::ConstSet(
+                                ParsedSpec::Ref {
+                                    name: "name".into(),
+                                }
+                                .into(),
+                                vec![vec![0x00]],
+                            )
+                            .into(),
+                        )
+                        .into(),
+                    },
+                ],
+                SpecCompileErrorKind::LimitExceeded => vec![(0..=DecodeLimits::default().max_depth)
+                    .fold(ParsedSpec::Bool, |spec, _| ParsedSpec::Optional(spec.into()))],
+                SpecCompileErrorKind::Located => vec![], // Every other error is located
                 SpecCompileErrorKind::InternalCompilerError => vec![], // Not possible to intentionally have spec that breaks compiler
             }
             .into_iter()
             .for_each(|s| {
-                let error = s.compile().map_err(|e| SpecCompileErrorKind::from(e));
+                let error = s.compile().map_err(|e| e.kind());
                 match error {
                     Ok(compiled_spec) => {
                         panic!(
@@ -703,6 +1053,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_recursion_through_named_variant() {
+        // the first attempt at compiling variant 2 defines "inner" before finding the loop
+        let cs = Spec::compile(ParsedSpec::Name {
+            name: "outer".into(),
+            spec: ParsedSpec::Enum(vec![
+                ("variant 1".into(), ParsedSpec::Bool),
+                (
+                    "variant 2".into(),
+                    ParsedSpec::Name {
+                        name: "inner".into(),
+                        spec: ParsedSpec::Record(vec![(
+                            "field".into(),
+                            ParsedSpec::Ref {
+                                name: "outer".into(),
+                            },
+                        )])
+                        .into(),
+                    },
+                ),
+            ])
+            .into(),
+        })
+        .unwrap();
+        assert!(cs.named_schema().contains_key("inner"));
+        assert_eq!(cs, Spec::compile(cs.to_parsed_spec()).unwrap());
+    }
+
     #[test]
     fn test_recursion() {
         let cs = Spec::compile(ParsedSpec::Name {
@@ -717,7 +1095,7 @@ mod tests {
         .unwrap();
         if let SpecType::Name(name) = cs.spec_type() {
             assert!(cs.named_schema().contains_key(name));
-            cs.named_schema().get(name).map(|spec| {
+            cs.named_schema().get(name).and_then(NamedSpec::get).map(|spec| {
                 if let SpecType::Tuple(compiled_specs) = spec.spec_type() {
                     assert_ne!(
                         compiled_specs[1].named_schema().get("test").unwrap(),
@@ -727,4 +1105,87 @@ mod tests {
             });
         };
     }
+
+    #[test]
+    fn test_recursive_spec_dropped() {
+        let spec = Spec::compile(
+            ParsedSpec::from_text("name List = record { value: int(0), next: optional<ref List> }")
+                .unwrap(),
+        )
+        .unwrap();
+        let definition = match &spec.named_schema()["List"].0 {
+            NamedSpecHandle::Strong(definitions, ..) => Rc::downgrade(definitions),
+            NamedSpecHandle::Weak(..) => panic!("Specs outside of a definition should hold it"),
+        };
+        // a clone of a spec within the definition keeps the definition alive
+        let next = match spec.resolve().spec_type() {
+            SpecType::Record { field_to_spec, .. } => field_to_spec["next"].clone(),
+            _ => unreachable!(),
+        };
+        drop(spec);
+        assert!(definition.upgrade().is_some());
+        let SpecType::Optional(list) = next.spec_type() else {
+            unreachable!()
+        };
+        assert!(matches!(list.resolve().spec_type(), SpecType::Record { .. }));
+        drop(next);
+        assert!(definition.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_name_followed_within_definition() {
+        let spec = compile("name Tree = record { value: int(0), children: list<ref Tree> }");
+        let definitions = match &spec.named_schema()["Tree"].0 {
+            NamedSpecHandle::Strong(definitions, ..) => Rc::downgrade(definitions),
+            NamedSpecHandle::Weak(..) => panic!("Specs outside of a definition should hold it"),
+        };
+        {
+            let tree = spec.named_schema()["Tree"].get().unwrap();
+            let SpecType::Record { field_to_spec, .. } = tree.spec_type() else {
+                unreachable!()
+            };
+            let SpecType::List { value_spec, .. } = field_to_spec["children"].spec_type() else {
+                unreachable!()
+            };
+            // a spec borrowed from within the definition follows the name back to it
+            assert_eq!(value_spec.named_schema()["Tree"].get().unwrap(), tree);
+            let value = GluinoValue::Record(vec![GluinoValue::Int8(5), GluinoValue::List(vec![])]);
+            assert_eq!(encode(value_spec, value.clone()), encode(&spec, value));
+        }
+        drop(spec);
+        assert!(definitions.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_nested_recursive_spec_outlives_root() {
+        let spec = compile("name Tree = record { children: list<name Node = tuple { ref Tree, optional<ref Node> }> }");
+        let node = spec.named_schema()["Node"].clone();
+        let children = match spec.resolve().spec_type() {
+            SpecType::Record { field_to_spec, .. } => field_to_spec["children"].clone(),
+            _ => unreachable!(),
+        };
+        drop(spec);
+
+        // Node refers back to Tree, which is only held through Node now
+        let SpecType::Tuple(fields) = node.get().unwrap().spec_type() else {
+            unreachable!()
+        };
+        assert!(matches!(fields[0].resolve().spec_type(), SpecType::Record { .. }));
+        let SpecType::Optional(next) = fields[1].spec_type() else {
+            unreachable!()
+        };
+        assert!(matches!(next.resolve().spec_type(), SpecType::Tuple(_)));
+
+        let value = GluinoValue::List(vec![GluinoValue::Tuple(vec![
+            GluinoValue::Record(vec![GluinoValue::List(vec![])]),
+            GluinoValue::Optional(None),
+        ])]);
+        let bytes = encode(&children, value.clone());
+        assert_eq!(
+            get_unit_deserialization_function(&children)
+                .deserialize(&mut bytes.as_slice())
+                .unwrap(),
+            value
+        );
+    }
 }
@@ -0,0 +1,660 @@
+//! Fluent construction of specs, e.g.
+//!
+//! ```
+//! use gluino::spec_builder::SpecBuilder;
+//!
+//! let spec = SpecBuilder::named(
+//!     "Tree",
+//!     SpecBuilder::record()
+//!         .field("id", SpecBuilder::uint(3))
+//!         .field("tags", SpecBuilder::list(SpecBuilder::utf8()).max_len(16))
+//!         .field("children", SpecBuilder::list(SpecBuilder::reference("Tree"))),
+//! )
+//! .build()
+//! .unwrap();
+//! ```
+//!
+//! Errors are found as the spec is put together, an illegal decimal when it is made, inverted
+//! length bounds when they are set and duplicate fields or variants when the record or enum is
+//! used, and carry their path within the spec.
+//! Errors that depend on names, such as undefined or infinitely recursive names, are only found
+//! by `build`. Building gives the same spec as compiling the equivalent [ParsedSpec].
+
+use std::collections::HashSet;
+
+use crate::{
+    limits::DecodeLimits,
+    spec::{DecimalFmt, Spec, SpecCompileError},
+    spec_parsing::{
+        InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, ParsedSpec,
+        Size, SizeRange, StringEncodingFmt,
+    },
+    spec_path::{PathSegment, SpecPath},
+};
+
+/// A spec under construction, along with the errors found in it so far
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecBuilder {
+    spec: ParsedSpec,
+    errors: Vec<SpecCompileError>,
+}
+
+impl SpecBuilder {
+    fn new(spec: ParsedSpec) -> SpecBuilder {
+        SpecBuilder {
+            spec,
+            errors: vec![],
+        }
+    }
+
+    pub fn void() -> SpecBuilder {
+        Self::new(ParsedSpec::Void)
+    }
+
+    pub fn bool() -> SpecBuilder {
+        Self::new(ParsedSpec::Bool)
+    }
+
+    /// Unsigned integer of 2^n bytes
+    pub fn uint(n: u8) -> SpecBuilder {
+        Self::new(ParsedSpec::Uint(n))
+    }
+
+    /// Signed integer of 2^n bytes
+    pub fn int(n: u8) -> SpecBuilder {
+        Self::new(ParsedSpec::Int(n))
+    }
+
+    pub fn binary_fp(fmt: InterchangeBinaryFloatingPointFormat) -> SpecBuilder {
+        Self::new(ParsedSpec::BinaryFloatingPoint(fmt))
+    }
+
+    pub fn decimal_fp(fmt: InterchangeDecimalFloatingPointFormat) -> SpecBuilder {
+        Self::new(ParsedSpec::DecimalFloatingPoint(fmt))
+    }
+
+    pub fn decimal(precision: u64, scale: u64) -> SpecBuilder {
+        let mut builder = Self::new(ParsedSpec::Decimal { precision, scale });
+        if let Err(e) = DecimalFmt::new(precision, scale) {
+            builder.error(e.into());
+        }
+        builder
+    }
+
+    pub fn utf8() -> SizedBuilder {
+        Self::string(StringEncodingFmt::Utf8)
+    }
+
+    pub fn string(fmt: StringEncodingFmt) -> SizedBuilder {
+        SizedBuilder(Self::new(ParsedSpec::String(Size::Variable, fmt)))
+    }
+
+    pub fn bytes() -> SizedBuilder {
+        SizedBuilder(Self::new(ParsedSpec::Bytes(Size::Variable)))
+    }
+
+    pub fn list(value: impl Into<SpecBuilder>) -> SizedBuilder {
+        let mut builder = Self::new(ParsedSpec::Void);
+        let value_spec = builder.add(value.into(), PathSegment::Item);
+        builder.spec = ParsedSpec::List {
+            size: Size::Variable,
+            value_spec: value_spec.into(),
+        };
+        SizedBuilder(builder)
+    }
+
+    pub fn map(key: impl Into<SpecBuilder>, value: impl Into<SpecBuilder>) -> SizedBuilder {
+        let mut builder = Self::new(ParsedSpec::Void);
+        let key_spec = builder.add(key.into(), PathSegment::Key);
+        let value_spec = builder.add(value.into(), PathSegment::Item);
+        builder.spec = ParsedSpec::Map {
+            size: Size::Variable,
+            key_spec: key_spec.into(),
+            value_spec: value_spec.into(),
+        };
+        SizedBuilder(builder)
+    }
+
+    pub fn optional(value: impl Into<SpecBuilder>) -> SpecBuilder {
+        let value: SpecBuilder = value.into();
+        SpecBuilder {
+            spec: ParsedSpec::Optional(value.spec.into()),
+            errors: value.errors,
+        }
+    }
+
+    pub fn record() -> RecordBuilder {
+        RecordBuilder {
+            builder: Self::new(ParsedSpec::Record(vec![])),
+            duplicate_fields: HashSet::new(),
+        }
+    }
+
+    pub fn tuple() -> TupleBuilder {
+        TupleBuilder(Self::new(ParsedSpec::Tuple(vec![])))
+    }
+
+    pub fn enumeration() -> EnumBuilder {
+        EnumBuilder {
+            builder: Self::new(ParsedSpec::Enum(vec![])),
+            duplicate_variants: HashSet::new(),
+        }
+    }
+
+    pub fn union() -> UnionBuilder {
+        UnionBuilder(Self::new(ParsedSpec::Union(vec![])))
+    }
+
+    /// One of `values`, each the bytes of a value of `spec`
+    pub fn const_set(spec: impl Into<SpecBuilder>, values: Vec<Vec<u8>>) -> SpecBuilder {
+        let spec: SpecBuilder = spec.into();
+        SpecBuilder {
+            spec: ParsedSpec::ConstSet(spec.spec.into(), values),
+            errors: spec.errors,
+        }
+    }
+
+    /// Defines `name` as `spec`, which may refer back to it with `reference`
+    pub fn named(name: impl Into<String>, spec: impl Into<SpecBuilder>) -> SpecBuilder {
+        let name = name.into();
+        let mut spec: SpecBuilder = spec.into();
+        for error in spec.errors.iter_mut() {
+            if let SpecCompileError::Located { names, .. } = error {
+                names.insert(0, name.clone());
+            }
+        }
+        SpecBuilder {
+            spec: ParsedSpec::Name {
+                name,
+                spec: spec.spec.into(),
+            },
+            errors: spec.errors,
+        }
+    }
+
+    /// Refers to a name defined with `named`, either enclosing this spec or before it
+    pub fn reference(name: impl Into<String>) -> SpecBuilder {
+        Self::new(ParsedSpec::Ref { name: name.into() })
+    }
+
+    /// Errors found so far, `build` finds the rest
+    pub fn errors(&self) -> &[SpecCompileError] {
+        &self.errors
+    }
+
+    pub fn parsed_spec(&self) -> &ParsedSpec {
+        &self.spec
+    }
+
+    pub fn build(self) -> Result<Spec, SpecCompileError> {
+        self.build_reporting_all()
+            .map_err(|errors| errors.into_iter().next().expect("Failed with errors"))
+    }
+
+    /// Errors found while building come with those only compiling finds, in the order of the
+    /// specs they are in
+    pub fn build_reporting_all(self) -> Result<Spec, Vec<SpecCompileError>> {
+        let compiled = Spec::compile_reporting_all(self.spec, &DecodeLimits::default());
+        if self.errors.is_empty() {
+            return compiled;
+        }
+        let mut compile_errors = compiled.err().unwrap_or_default().into_iter();
+        let mut errors = Vec::new();
+        for error in self.errors {
+            // compiling finds most of the same errors, those before it come first
+            if compile_errors.as_slice().contains(&error) {
+                errors.extend(compile_errors.by_ref().take_while(|e| e != &error));
+            }
+            errors.push(error);
+        }
+        errors.extend(compile_errors);
+        Err(errors)
+    }
+
+    fn error(&mut self, error: SpecCompileError) {
+        self.errors.push(SpecCompileError::Located {
+            path: SpecPath::root(),
+            names: vec![],
+            error: Box::new(error),
+        })
+    }
+
+    // takes over the errors of a sub spec at `segment`, returning the sub spec
+    fn add(&mut self, sub_spec: SpecBuilder, segment: PathSegment) -> ParsedSpec {
+        for mut error in sub_spec.errors {
+            if let SpecCompileError::Located { path, .. } = &mut error {
+                path.prepend(segment.clone());
+            }
+            self.errors.push(error);
+        }
+        sub_spec.spec
+    }
+}
+
+impl From<SpecBuilder> for ParsedSpec {
+    fn from(builder: SpecBuilder) -> Self {
+        builder.spec
+    }
+}
+
+/// Builds a string, bytes, list or map, which are variable size unless limited
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizedBuilder(SpecBuilder);
+
+impl SizedBuilder {
+    pub fn size(mut self, size: Size) -> SizedBuilder {
+        *self.size_mut() = size;
+        self
+    }
+
+    /// Exactly `len` items or bytes
+    pub fn fixed_len(self, len: u64) -> SizedBuilder {
+        self.size(Size::Fixed(len))
+    }
+
+    /// At least `min_len`, keeping any maximum
+    pub fn min_len(mut self, min_len: u64) -> SizedBuilder {
+        let (_, end) = self.size_mut().bounds();
+        match end {
+            Some(end) if min_len >= end => self.inverted(min_len, end),
+            end => self.size(size_from_bounds(min_len, end)),
+        }
+    }
+
+    /// At most `max_len`, keeping any minimum
+    pub fn max_len(mut self, max_len: u64) -> SizedBuilder {
+        let (start, _) = self.size_mut().bounds();
+        if start > max_len {
+            // max_len is below start, so has an exclusive end
+            return self.inverted(start, max_len + 1);
+        }
+        // every length is at most u64::MAX, which has no exclusive end
+        let end = max_len.checked_add(1);
+        self.size(size_from_bounds(start, end))
+    }
+
+    pub fn build(self) -> Result<Spec, SpecCompileError> {
+        self.0.build()
+    }
+
+    pub fn build_reporting_all(self) -> Result<Spec, Vec<SpecCompileError>> {
+        self.0.build_reporting_all()
+    }
+
+    // keeps the size as it was
+    fn inverted(mut self, min_len: u64, end: u64) -> SizedBuilder {
+        self.0
+            .error(SpecCompileError::InvertedLengthBounds { min_len, end });
+        self
+    }
+
+    fn size_mut(&mut self) -> &mut Size {
+        match &mut self.0.spec {
+            ParsedSpec::String(size, _)
+            | ParsedSpec::Bytes(size)
+            | ParsedSpec::List { size, .. }
+            | ParsedSpec::Map { size, .. } => size,
+            _ => unreachable!("Sized builders only build sized specs"),
+        }
+    }
+}
+
+impl From<SizedBuilder> for SpecBuilder {
+    fn from(builder: SizedBuilder) -> Self {
+        builder.0
+    }
+}
+
+// inclusive start and exclusive end, None for unbounded
+fn size_from_bounds(start: u64, end: Option<u64>) -> Size {
+    match (start, end) {
+        (0, None) => Size::Variable,
+        (start, None) => Size::GreaterThan(start),
+        (0, Some(end)) => Size::LessThan(end),
+        (start, Some(end)) => Size::Range(SizeRange { start, end }),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordBuilder {
+    builder: SpecBuilder,
+    duplicate_fields: HashSet<String>,
+}
+
+impl RecordBuilder {
+    pub fn field(mut self, name: impl Into<String>, spec: impl Into<SpecBuilder>) -> RecordBuilder {
+        let name = name.into();
+        let spec = self.builder.add(spec.into(), PathSegment::Field(&name));
+        let ParsedSpec::Record(fields) = &mut self.builder.spec else {
+            unreachable!("Record builders only build records")
+        };
+        if fields.iter().any(|(field, _)| field == &name) {
+            self.duplicate_fields.insert(name.clone());
+        }
+        fields.push((name, spec));
+        self
+    }
+
+    pub fn build(self) -> Result<Spec, SpecCompileError> {
+        SpecBuilder::from(self).build()
+    }
+
+    pub fn build_reporting_all(self) -> Result<Spec, Vec<SpecCompileError>> {
+        SpecBuilder::from(self).build_reporting_all()
+    }
+}
+
+impl From<RecordBuilder> for SpecBuilder {
+    fn from(record: RecordBuilder) -> Self {
+        let mut builder = record.builder;
+        if !record.duplicate_fields.is_empty() {
+            builder.error(SpecCompileError::DuplicateRecordFieldNames(
+                record.duplicate_fields,
+            ));
+        }
+        builder
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TupleBuilder(SpecBuilder);
+
+impl TupleBuilder {
+    pub fn field(mut self, spec: impl Into<SpecBuilder>) -> TupleBuilder {
+        let ParsedSpec::Tuple(fields) = &self.0.spec else {
+            unreachable!("Tuple builders only build tuples")
+        };
+        let spec = self.0.add(spec.into(), PathSegment::Index(fields.len()));
+        if let ParsedSpec::Tuple(fields) = &mut self.0.spec {
+            fields.push(spec);
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Spec, SpecCompileError> {
+        self.0.build()
+    }
+
+    pub fn build_reporting_all(self) -> Result<Spec, Vec<SpecCompileError>> {
+        self.0.build_reporting_all()
+    }
+}
+
+impl From<TupleBuilder> for SpecBuilder {
+    fn from(builder: TupleBuilder) -> Self {
+        builder.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumBuilder {
+    builder: SpecBuilder,
+    duplicate_variants: HashSet<String>,
+}
+
+impl EnumBuilder {
+    pub fn variant(mut self, name: impl Into<String>, spec: impl Into<SpecBuilder>) -> EnumBuilder {
+        let name = name.into();
+        let spec = self.builder.add(spec.into(), PathSegment::Field(&name));
+        let ParsedSpec::Enum(variants) = &mut self.builder.spec else {
+            unreachable!("Enum builders only build enums")
+        };
+        if variants.iter().any(|(variant, _)| variant == &name) {
+            self.duplicate_variants.insert(name.clone());
+        }
+        variants.push((name, spec));
+        self
+    }
+
+    pub fn build(self) -> Result<Spec, SpecCompileError> {
+        SpecBuilder::from(self).build()
+    }
+
+    pub fn build_reporting_all(self) -> Result<Spec, Vec<SpecCompileError>> {
+        SpecBuilder::from(self).build_reporting_all()
+    }
+}
+
+impl From<EnumBuilder> for SpecBuilder {
+    fn from(enumeration: EnumBuilder) -> Self {
+        let mut builder = enumeration.builder;
+        if !enumeration.duplicate_variants.is_empty() {
+            builder.error(SpecCompileError::DuplicateEnumVariantNames(
+                enumeration.duplicate_variants,
+            ));
+        }
+        builder
+    }
+}
+
+/// Builds a union, whose variants are told apart by their index
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnionBuilder(SpecBuilder);
+
+impl UnionBuilder {
+    pub fn variant(mut self, spec: impl Into<SpecBuilder>) -> UnionBuilder {
+        let ParsedSpec::Union(variants) = &self.0.spec else {
+            unreachable!("Union builders only build unions")
+        };
+        let spec = self.0.add(spec.into(), PathSegment::Index(variants.len()));
+        if let ParsedSpec::Union(variants) = &mut self.0.spec {
+            variants.push(spec);
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Spec, SpecCompileError> {
+        self.0.build()
+    }
+
+    pub fn build_reporting_all(self) -> Result<Spec, Vec<SpecCompileError>> {
+        self.0.build_reporting_all()
+    }
+}
+
+impl From<UnionBuilder> for SpecBuilder {
+    fn from(builder: UnionBuilder) -> Self {
+        builder.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::SpecCompileErrorKind;
+
+    #[test]
+    fn test_builds_compiled_spec() {
+        let text = r#"name Tree = record {
+            id: uint(3),
+            tags: list<string<..17>, 1..5>,
+            children: map<bytes<32>, ref Tree>,
+            shape: enum {
+                leaf: void,
+                branch: tuple { decimal(10, 2), optional<bool> },
+            },
+            value: union { int(2), float(double) },
+            flag: const_set<uint(0)> { 0x01, 0x02 },
+        }"#;
+        let built = SpecBuilder::named(
+            "Tree",
+            SpecBuilder::record()
+                .field("id", SpecBuilder::uint(3))
+                .field(
+                    "tags",
+                    SpecBuilder::list(SpecBuilder::utf8().max_len(16))
+                        .min_len(1)
+                        .max_len(4),
+                )
+                .field(
+                    "children",
+                    SpecBuilder::map(
+                        SpecBuilder::bytes().fixed_len(32),
+                        SpecBuilder::reference("Tree"),
+                    ),
+                )
+                .field(
+                    "shape",
+                    SpecBuilder::enumeration()
+                        .variant("leaf", SpecBuilder::void())
+                        .variant(
+                            "branch",
+                            SpecBuilder::tuple()
+                                .field(SpecBuilder::decimal(10, 2))
+                                .field(SpecBuilder::optional(SpecBuilder::bool())),
+                        ),
+                )
+                .field(
+                    "value",
+                    SpecBuilder::union().variant(SpecBuilder::int(2)).variant(
+                        SpecBuilder::binary_fp(InterchangeBinaryFloatingPointFormat::Double),
+                    ),
+                )
+                .field(
+                    "flag",
+                    SpecBuilder::const_set(SpecBuilder::uint(0), vec![vec![0x01], vec![0x02]]),
+                ),
+        );
+        let parsed = ParsedSpec::from_text(text).unwrap();
+        assert_eq!(&parsed, built.parsed_spec());
+        let compiled = Spec::compile(parsed).unwrap();
+        let built = built.build().unwrap();
+        assert_eq!(compiled.fingerprint(), built.fingerprint());
+        assert_eq!(compiled.to_parsed_spec(), built.to_parsed_spec());
+    }
+
+    #[test]
+    fn test_errors() {
+        let builder: SpecBuilder = SpecBuilder::named(
+            "Order",
+            SpecBuilder::record()
+                .field(
+                    "lines",
+                    SpecBuilder::list(
+                        SpecBuilder::record()
+                            .field("price", SpecBuilder::decimal(2, 3))
+                            .field("qty", SpecBuilder::uint(2))
+                            .field("qty", SpecBuilder::uint(3)),
+                    ),
+                )
+                .field(
+                    "status",
+                    SpecBuilder::enumeration()
+                        .variant("open", SpecBuilder::bool())
+                        .variant("open", SpecBuilder::void()),
+                ),
+        );
+        // found while building, as compiling would find them
+        let compile_errors =
+            Spec::compile_reporting_all(builder.parsed_spec().clone(), &DecodeLimits::default())
+                .unwrap_err();
+        assert_eq!(compile_errors, builder.errors());
+        assert_eq!(
+            vec![".lines[].price", ".lines[]", ".status"],
+            builder
+                .errors()
+                .iter()
+                .map(|e| e.path().unwrap().as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            compile_errors,
+            builder.clone().build_reporting_all().unwrap_err()
+        );
+
+        // names are only checked when building
+        let builder = SpecBuilder::named(
+            "Loop",
+            SpecBuilder::tuple()
+                .field(SpecBuilder::reference("Loop"))
+                .field(SpecBuilder::reference("Missing")),
+        );
+        assert!(builder.errors().is_empty());
+        assert_eq!(
+            vec![
+                SpecCompileErrorKind::UndefinedName,
+                SpecCompileErrorKind::InfinitelyRecursiveTypes
+            ],
+            builder
+                .build_reporting_all()
+                .unwrap_err()
+                .iter()
+                .map(SpecCompileError::kind)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_length_bounds() {
+        let size = |builder: SizedBuilder| match SpecBuilder::from(builder).spec {
+            ParsedSpec::List { size, .. } => size,
+            spec => panic!("Not a list: {:?}", spec),
+        };
+        let list = || SpecBuilder::list(SpecBuilder::bool());
+        assert_eq!(Size::Variable, size(list().max_len(u64::MAX)));
+        assert_eq!(
+            Size::GreaterThan(3),
+            size(list().min_len(3).max_len(u64::MAX))
+        );
+        assert_eq!(
+            Size::Range(SizeRange { start: 5, end: 6 }),
+            size(list().min_len(5).max_len(5))
+        );
+
+        // the inverted bound is left out
+        for (builder, kept, error) in [
+            (
+                list().min_len(10).max_len(5),
+                Size::GreaterThan(10),
+                ".items: no length is at least 10 and less than 6",
+            ),
+            (
+                list().max_len(5).min_len(10),
+                Size::LessThan(6),
+                ".items: no length is at least 10 and less than 6",
+            ),
+            (
+                list().size(Size::LessThan(0)).min_len(3),
+                Size::LessThan(0),
+                ".items: no length is at least 3 and less than 0",
+            ),
+        ] {
+            assert_eq!(kept, size(builder.clone()));
+            let errors: SpecBuilder = SpecBuilder::record().field("items", builder).into();
+            assert_eq!(
+                vec![error],
+                errors
+                    .errors()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_reports_compile_errors() {
+        let builder = SpecBuilder::record()
+            .field("next", SpecBuilder::reference("Missing"))
+            .field("price", SpecBuilder::decimal(2, 3))
+            .field(
+                "tags",
+                SpecBuilder::list(SpecBuilder::utf8()).min_len(3).max_len(2),
+            )
+            .field("other", SpecBuilder::reference("Other"));
+        assert_eq!(
+            vec![
+                SpecCompileErrorKind::UndefinedName,
+                SpecCompileErrorKind::IllegalDecimalFmt,
+                SpecCompileErrorKind::InvertedLengthBounds,
+                SpecCompileErrorKind::UndefinedName,
+            ],
+            builder
+                .build_reporting_all()
+                .unwrap_err()
+                .iter()
+                .map(SpecCompileError::kind)
+                .collect::<Vec<_>>()
+        );
+    }
+}
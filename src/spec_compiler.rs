@@ -0,0 +1,745 @@
+use crate::serde::GluinoValue;
+use crate::spec::{
+    Definitions, DecimalFmt, NamedSpec, Spec, SpecCompileError, SpecType, decode_const_values,
+};
+use crate::spec_parsing::{
+    InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, ParsedSpec, Size,
+    StringEncodingFmt,
+};
+use crate::spec_path::{PathSegment, SpecPath};
+use crate::spec_visitor::SpecVisitor;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Compiles the visited spec, carrying on past errors so every error is found in one pass.
+///
+/// Each visited spec leaves one [StackSpec] on the stack, the end of a spec with sub specs pops
+/// theirs. A spec with an error leaves a void placeholder marked as failed, so checks that
+/// would trip over the placeholder are skipped rather than reporting errors that are not there.
+pub(crate) struct SpecCompiler<'c> {
+    // Named specs by name, declared when their definition starts
+    context: &'c mut HashMap<String, NamedSpec>,
+    errors: Vec<SpecCompileError>,
+    spec_stack: Vec<StackSpec>,
+    // Names whose definitions are being compiled, outermost first. No handle if already defined
+    names_in_definition: Vec<(String, Option<NamedSpec>)>,
+    // Definitions of the names within the outermost definition being compiled
+    definitions: Option<Rc<Definitions>>,
+    // Names with an error in their definition
+    failed_names: HashSet<String>,
+    path: SpecPath,
+    path_lens: Vec<usize>,
+}
+
+impl<'c> SpecCompiler<'c> {
+    pub(crate) fn new(context: &'c mut HashMap<String, NamedSpec>) -> SpecCompiler<'c> {
+        SpecCompiler {
+            context,
+            errors: vec![],
+            spec_stack: vec![],
+            names_in_definition: vec![],
+            definitions: None,
+            failed_names: HashSet::new(),
+            path: SpecPath::root(),
+            path_lens: vec![],
+        }
+    }
+
+    pub(crate) fn finalize(mut self) -> Result<Spec, Vec<SpecCompileError>> {
+        if !self.errors.is_empty() {
+            Err(self.errors)
+        } else if let Some(stack_spec) = self.spec_stack.pop()
+            && self.spec_stack.is_empty()
+        {
+            Ok(stack_spec.spec)
+        } else {
+            Err(vec![SpecCompileError::InternalCompilerError(format!(
+                "{} specs on compiler stack, expected 1",
+                self.spec_stack.len() + 1
+            ))])
+        }
+    }
+
+    fn fail(&mut self, error: SpecCompileError) {
+        self.errors.push(SpecCompileError::Located {
+            path: self.path.clone(),
+            names: self
+                .names_in_definition
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect(),
+            error: Box::new(error),
+        })
+    }
+
+    fn enter(&mut self, segment: PathSegment) {
+        let len = self.path.push(segment);
+        self.path_lens.push(len);
+    }
+
+    fn exit(&mut self) {
+        if let Some(len) = self.path_lens.pop() {
+            self.path.truncate(len)
+        }
+    }
+
+    fn push(&mut self, spec_type: SpecType) {
+        self.spec_stack.push(self.stack_spec(spec_type, vec![]))
+    }
+
+    // builds a spec with sub specs, which are popped off the stack
+    fn push_with_sub_specs(&mut self, sub_specs: Vec<StackSpec>, spec_type: SpecType) {
+        self.spec_stack.push(self.stack_spec(spec_type, sub_specs))
+    }
+
+    fn stack_spec(&self, spec_type: SpecType, sub_specs: Vec<StackSpec>) -> StackSpec {
+        let mut names_used = HashSet::new();
+        let mut names_recursed = HashSet::new();
+        let mut failed = false;
+        for sub_spec in sub_specs {
+            names_used.extend(sub_spec.names_used);
+            names_recursed.extend(sub_spec.names_recursed);
+            failed |= sub_spec.failed;
+        }
+        self.stack_spec_using(spec_type, names_used, names_recursed, failed)
+    }
+
+    fn stack_spec_using(
+        &self,
+        spec_type: SpecType,
+        names_used: HashSet<String>,
+        names_recursed: HashSet<String>,
+        failed: bool,
+    ) -> StackSpec {
+        let named_spec = names_used
+            .iter()
+            .filter_map(|name| {
+                let named_spec = self.context.get(name)?;
+                // references between the names defined within the outermost definition must not
+                // keep their definitions alive
+                Some((
+                    name.clone(),
+                    if self
+                        .definitions
+                        .as_ref()
+                        .is_some_and(|definitions| named_spec.is_in(definitions))
+                    {
+                        named_spec.recursive_reference()
+                    } else {
+                        named_spec.clone()
+                    },
+                ))
+            })
+            .collect();
+        StackSpec {
+            spec: Spec::new(named_spec, spec_type),
+            names_used,
+            names_recursed,
+            failed,
+        }
+    }
+
+    // the name's definition is being compiled, so the name is referred to from within it
+    fn in_definition(&self, name: &str) -> bool {
+        self.names_in_definition
+            .iter()
+            .any(|(defined, named_spec)| defined == name && named_spec.is_some())
+    }
+
+    // a size must allow some length, else no value fits it
+    fn check_size(&mut self, size: &Size) -> bool {
+        let (min_len, end) = match size {
+            Size::Range(size_range) if size_range.start >= size_range.end => {
+                (size_range.start, size_range.end)
+            }
+            Size::LessThan(0) => (0, 0),
+            _ => return true,
+        };
+        self.fail(SpecCompileError::InvertedLengthBounds { min_len, end });
+        false
+    }
+
+    fn push_failed(&mut self) {
+        self.spec_stack.push(StackSpec {
+            spec: Spec::new(HashMap::new(), SpecType::Void),
+            names_used: HashSet::new(),
+            names_recursed: HashSet::new(),
+            failed: true,
+        })
+    }
+
+    // fails the spec on top of the stack, keeping the names its sub specs use
+    fn mark_failed(&mut self) {
+        if let Some(stack_spec) = self.spec_stack.last_mut() {
+            stack_spec.failed = true
+        }
+    }
+
+    fn pop(&mut self) -> StackSpec {
+        self.spec_stack.pop().unwrap_or_else(|| {
+            self.errors.push(SpecCompileError::InternalCompilerError(
+                "not enough specs on compiler stack".into(),
+            ));
+            StackSpec {
+                spec: Spec::new(HashMap::new(), SpecType::Void),
+                names_used: HashSet::new(),
+                names_recursed: HashSet::new(),
+                failed: true,
+            }
+        })
+    }
+
+    fn pop_n(&mut self, n: usize) -> Vec<StackSpec> {
+        let mut stack_specs: Vec<StackSpec> = (0..n).map(|_| self.pop()).collect();
+        stack_specs.reverse();
+        stack_specs
+    }
+
+    // names in more than one place
+    fn duplicates(names: &[String]) -> HashSet<String> {
+        let mut seen = HashSet::new();
+        names
+            .iter()
+            .filter(|&name| !seen.insert(name))
+            .cloned()
+            .collect()
+    }
+
+    // A choice between variants only recurses if every variant does, otherwise a value can always
+    // end by picking one that does not
+    fn variants_recursed(variants: &[StackSpec]) -> HashSet<String> {
+        if variants
+            .iter()
+            .all(|variant| !variant.names_recursed.is_empty())
+        {
+            variants
+                .iter()
+                .flat_map(|variant| variant.names_recursed.iter().cloned())
+                .collect()
+        } else {
+            HashSet::new()
+        }
+    }
+}
+
+struct StackSpec {
+    spec: Spec,
+    // names referred to or defined within the spec
+    names_used: HashSet<String>,
+    // names in definition that the spec always contains, with no optional, list or map between
+    names_recursed: HashSet<String>,
+    // the spec or one of its sub specs has an error
+    failed: bool,
+}
+
+impl StackSpec {
+    // moves the spec into its parent, as a clone would hold recursive references strongly
+    fn take_spec(&mut self) -> Spec {
+        std::mem::replace(&mut self.spec, Spec::new(HashMap::new(), SpecType::Void))
+    }
+}
+
+impl SpecVisitor for SpecCompiler<'_> {
+    fn visit_bool(&mut self) {
+        self.push(SpecType::Bool)
+    }
+
+    fn visit_uint(&mut self, n: u8) {
+        self.push(SpecType::Uint(n))
+    }
+
+    fn visit_int(&mut self, n: u8) {
+        self.push(SpecType::Int(n))
+    }
+
+    fn visit_binary_fp(&mut self, fpf: &InterchangeBinaryFloatingPointFormat) {
+        self.push(SpecType::BinaryFloatingPoint(fpf.clone()))
+    }
+
+    fn visit_decimal_fp(&mut self, fp: &InterchangeDecimalFloatingPointFormat) {
+        self.push(SpecType::DecimalFloatingPoint(fp.clone()))
+    }
+
+    fn visit_decimal(&mut self, precision: u64, scale: u64) {
+        match DecimalFmt::new(precision, scale) {
+            Ok(fmt) => self.push(SpecType::Decimal(fmt)),
+            Err(e) => {
+                self.fail(e.into());
+                self.push_failed()
+            }
+        }
+    }
+
+    fn visit_string(&mut self, size: &Size, fmt: &StringEncodingFmt) {
+        if self.check_size(size) {
+            self.push(SpecType::String(size.clone(), fmt.clone()))
+        } else {
+            self.push_failed()
+        }
+    }
+
+    fn visit_bytes(&mut self, size: &Size) {
+        if self.check_size(size) {
+            self.push(SpecType::Bytes(size.clone()))
+        } else {
+            self.push_failed()
+        }
+    }
+
+    fn visit_optional_start(&mut self) {
+        // pass
+    }
+
+    fn visit_optional_end(&mut self) {
+        let mut value = self.pop();
+        // a value can always end with an empty optional
+        value.names_recursed.clear();
+        let spec_type = SpecType::Optional(Box::new(value.take_spec()));
+        self.push_with_sub_specs(vec![value], spec_type)
+    }
+
+    fn visit_map_start_key(&mut self) {
+        self.enter(PathSegment::Key)
+    }
+
+    fn visit_map_end_key(&mut self) {
+        self.exit()
+    }
+
+    fn visit_map_start_value(&mut self) {
+        self.enter(PathSegment::Item)
+    }
+
+    fn visit_map_end_value(&mut self, size: &Size) {
+        self.exit();
+        let mut entry = self.pop_n(2);
+        // a value can always end with an empty map
+        entry
+            .iter_mut()
+            .for_each(|spec| spec.names_recursed.clear());
+        let spec_type = SpecType::Map {
+            size: size.clone(),
+            key_spec: Box::new(entry[0].take_spec()),
+            value_spec: Box::new(entry[1].take_spec()),
+        };
+        self.push_with_sub_specs(entry, spec_type);
+        if !self.check_size(size) {
+            self.mark_failed()
+        }
+    }
+
+    fn visit_list_start(&mut self) {
+        self.enter(PathSegment::Item)
+    }
+
+    fn visit_list_end(&mut self, size: &Size) {
+        self.exit();
+        let mut value = self.pop();
+        // a value can always end with an empty list
+        value.names_recursed.clear();
+        let spec_type = SpecType::List {
+            size: size.clone(),
+            value_spec: Box::new(value.take_spec()),
+        };
+        self.push_with_sub_specs(vec![value], spec_type);
+        if !self.check_size(size) {
+            self.mark_failed()
+        }
+    }
+
+    fn visit_record_start(&mut self) {
+        // pass
+    }
+
+    fn visit_record_field_start(&mut self, field: &str) {
+        self.enter(PathSegment::Field(field))
+    }
+
+    fn visit_record_field_end(&mut self, _field: &str) {
+        self.exit()
+    }
+
+    fn visit_record_end(&mut self, fields: Vec<String>) {
+        let mut field_specs = self.pop_n(fields.len());
+        let duplicate_fields = Self::duplicates(&fields);
+        let field_to_index = fields
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.clone(), index))
+            .collect();
+        let field_to_spec = fields
+            .iter()
+            .cloned()
+            .zip(field_specs.iter_mut().map(StackSpec::take_spec))
+            .collect();
+        self.push_with_sub_specs(
+            field_specs,
+            SpecType::Record {
+                fields,
+                field_to_spec,
+                field_to_index,
+            },
+        );
+        if !duplicate_fields.is_empty() {
+            self.fail(SpecCompileError::DuplicateRecordFieldNames(
+                duplicate_fields,
+            ));
+            self.pop();
+            self.push_failed()
+        }
+    }
+
+    fn visit_tuple_start(&mut self) {
+        // pass
+    }
+
+    fn visit_tuple_field_start(&mut self, index: usize) {
+        self.enter(PathSegment::Index(index))
+    }
+
+    fn visit_tuple_field_end(&mut self, _index: usize) {
+        self.exit()
+    }
+
+    fn visit_tuple_end(&mut self, n: usize) {
+        let mut field_specs = self.pop_n(n);
+        let spec_type = SpecType::Tuple(field_specs.iter_mut().map(StackSpec::take_spec).collect());
+        self.push_with_sub_specs(field_specs, spec_type)
+    }
+
+    fn visit_enum_start(&mut self) {
+        // pass
+    }
+
+    fn visit_enum_variant_start(&mut self, variant: &str) {
+        self.enter(PathSegment::Field(variant))
+    }
+
+    fn visit_enum_variant_end(&mut self, _variant: &str) {
+        self.exit()
+    }
+
+    fn visit_enum_end(&mut self, variants: Vec<String>) {
+        let mut variant_specs = self.pop_n(variants.len());
+        let duplicate_variants = Self::duplicates(&variants);
+        let names_recursed = Self::variants_recursed(&variant_specs);
+        variant_specs
+            .iter_mut()
+            .for_each(|variant| variant.names_recursed.clear());
+        let variant_to_spec = variants
+            .iter()
+            .cloned()
+            .zip(variant_specs.iter_mut().map(StackSpec::take_spec))
+            .collect();
+        self.push_with_sub_specs(
+            variant_specs,
+            SpecType::Enum {
+                variants,
+                variant_to_spec,
+            },
+        );
+        if let Some(enum_spec) = self.spec_stack.last_mut() {
+            enum_spec.names_recursed = names_recursed;
+        }
+        if !duplicate_variants.is_empty() {
+            self.fail(SpecCompileError::DuplicateEnumVariantNames(
+                duplicate_variants,
+            ));
+            self.pop();
+            self.push_failed()
+        }
+    }
+
+    fn visit_union_start(&mut self) {
+        // pass
+    }
+
+    fn visit_union_member_start(&mut self, index: usize) {
+        self.enter(PathSegment::Index(index))
+    }
+
+    fn visit_union_member_end(&mut self, _index: usize) {
+        self.exit()
+    }
+
+    fn visit_union_end(&mut self, n: usize) {
+        let mut variant_specs = self.pop_n(n);
+        let names_recursed = Self::variants_recursed(&variant_specs);
+        variant_specs
+            .iter_mut()
+            .for_each(|variant| variant.names_recursed.clear());
+        // variants may refer to names still being compiled, which cannot be fingerprinted yet
+        let mut variant_structures: HashSet<ParsedSpec> = HashSet::new();
+        let duplicate_variants: Vec<Spec> = variant_specs
+            .iter()
+            .filter(|&variant| {
+                !variant.failed && !variant_structures.insert(variant.spec.to_parsed_spec())
+            })
+            .map(|variant| variant.spec.clone())
+            .collect();
+        let spec_type = SpecType::Union(
+            variant_specs
+                .iter_mut()
+                .map(StackSpec::take_spec)
+                .collect(),
+        );
+        self.push_with_sub_specs(variant_specs, spec_type);
+        if let Some(union_spec) = self.spec_stack.last_mut() {
+            union_spec.names_recursed = names_recursed;
+        }
+        if !duplicate_variants.is_empty() {
+            self.fail(SpecCompileError::DuplicateUnionVariantSpecs(
+                duplicate_variants,
+            ));
+            self.pop();
+            self.push_failed()
+        }
+    }
+
+    fn visit_const_set_start(&mut self) {
+        // pass
+    }
+
+    fn visit_const_set_end(&mut self, consts: &[Vec<u8>]) {
+        let mut const_spec = self.pop();
+        if const_spec.failed {
+            // the placeholder can not decode the values
+            self.push_failed();
+            return;
+        }
+        let error = match decode_const_values(&const_spec.spec, consts.to_vec()) {
+            Ok(const_values) => {
+                let duplicate_values: Vec<GluinoValue> = const_values
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, value)| const_values[..*index].contains(value))
+                    .map(|(_, value)| value.clone())
+                    .collect();
+                let spec_type = SpecType::ConstSet(Box::new(const_spec.take_spec()), const_values);
+                self.push_with_sub_specs(vec![const_spec], spec_type);
+                if duplicate_values.is_empty() {
+                    return;
+                }
+                self.pop();
+                SpecCompileError::DuplicateConstSetValues(duplicate_values)
+            }
+            Err(e) => e,
+        };
+        self.fail(error);
+        self.push_failed()
+    }
+
+    fn visit_name_start(&mut self, name: &str) {
+        let named_spec = if self.context.contains_key(name) {
+            self.fail(SpecCompileError::DuplicateName(name.into()));
+            None
+        } else {
+            // declared before compiling the definition so recursive references share it
+            let definitions = self.definitions.get_or_insert_default();
+            let named_spec = NamedSpec::declare(definitions, name);
+            self.context.insert(name.into(), named_spec.clone());
+            Some(named_spec)
+        };
+        self.names_in_definition.push((name.into(), named_spec));
+    }
+
+    fn visit_name_end(&mut self, name: &str) {
+        let named_spec = self
+            .names_in_definition
+            .pop()
+            .and_then(|(_, named_spec)| named_spec);
+        if self.names_in_definition.is_empty()
+            && let Some(definitions) = self.definitions.take()
+        {
+            definitions.close();
+        }
+        let mut definition = self.pop();
+        if definition.names_recursed.contains(name) {
+            let names = std::mem::take(&mut definition.names_recursed);
+            self.fail(SpecCompileError::InfinitelyRecursiveTypes(names));
+            definition.failed = true;
+        }
+        let Some(named_spec) = named_spec else {
+            self.push_failed();
+            return;
+        };
+        named_spec.define(definition.spec);
+        if definition.failed {
+            self.failed_names.insert(name.into());
+        }
+        let mut names_used = definition.names_used;
+        names_used.insert(name.into());
+        let stack_spec = self.stack_spec_using(
+            SpecType::Name(name.into()),
+            names_used,
+            definition.names_recursed,
+            definition.failed,
+        );
+        self.spec_stack.push(stack_spec)
+    }
+
+    fn visit_ref(&mut self, name: &str) {
+        let in_definition = self.in_definition(name);
+        if !self.context.contains_key(name) {
+            self.fail(SpecCompileError::UndefinedName(name.into()));
+            self.push_failed();
+            return;
+        }
+        let names_recursed = if in_definition {
+            HashSet::from([name.to_string()])
+        } else {
+            HashSet::new()
+        };
+        let stack_spec = self.stack_spec_using(
+            SpecType::Name(name.into()),
+            HashSet::from([name.to_string()]),
+            names_recursed,
+            self.failed_names.contains(name),
+        );
+        self.spec_stack.push(stack_spec)
+    }
+
+    fn visit_void(&mut self) {
+        self.push(SpecType::Void)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::DecodeLimits;
+    use crate::test_utils::compile;
+
+    // errors with where they were found, as (path, names, error)
+    fn located_errors(text: &str) -> Vec<(String, Vec<String>, SpecCompileError)> {
+        Spec::compile_reporting_all(ParsedSpec::from_text(text).unwrap(), &DecodeLimits::default())
+            .unwrap_err()
+            .into_iter()
+            .map(|error| match error {
+                SpecCompileError::Located { path, names, error } => {
+                    (path.as_str().to_string(), names, *error)
+                }
+                error => panic!("Compile errors should be located, got {:?}", error),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_self_recursive_name() {
+        let spec = compile("name List = optional<tuple { uint(0), ref List }>");
+        let list = spec.named_schema()["List"].defined().unwrap();
+        let SpecType::Optional(inner) = list.spec_type() else {
+            panic!("List should be optional")
+        };
+        let SpecType::Tuple(fields) = inner.spec_type() else {
+            panic!("List should hold a tuple")
+        };
+        // the reference back to List shares its definition without holding it
+        let reference = &fields[1].named_schema()["List"];
+        assert!(reference.definitions().is_none());
+        assert!(std::ptr::eq(&*reference.definition().unwrap(), list));
+
+        assert_eq!(
+            vec![(
+                String::new(),
+                vec![],
+                SpecCompileError::InfinitelyRecursiveTypes(HashSet::from(["List".to_string()]))
+            )],
+            located_errors("name List = tuple { uint(0), ref List }")
+        );
+    }
+
+    #[test]
+    fn test_mutually_recursive_names() {
+        let spec = compile("name Tree = record { children: list<name Node = tuple { ref Tree, optional<ref Node> }> }");
+        let tree = spec.named_schema()["Tree"].definitions().unwrap();
+        let node = spec.named_schema()["Node"].definitions().unwrap();
+        // names defined within one another are defined together
+        assert!(std::ptr::eq(tree, node));
+
+        let node = spec.named_schema()["Node"].defined().unwrap();
+        let SpecType::Tuple(fields) = node.spec_type() else {
+            panic!("Node should be a tuple")
+        };
+        let reference = &fields[0].named_schema()["Tree"];
+        assert!(reference.definitions().is_none());
+        assert_eq!(*reference, *spec.named_schema()["Tree"].defined().unwrap());
+
+        assert_eq!(
+            vec![(
+                String::new(),
+                vec![],
+                SpecCompileError::InfinitelyRecursiveTypes(HashSet::from(["A".to_string()]))
+            )],
+            located_errors("name A = tuple { name B = tuple { ref A } }")
+        );
+    }
+
+    #[test]
+    fn test_duplicate_union_variant() {
+        assert_eq!(
+            vec![(
+                ".a".to_string(),
+                vec![],
+                SpecCompileError::DuplicateUnionVariantSpecs(vec![compile("uint(0)")])
+            )],
+            located_errors("record { a: union { uint(0), bool, uint(0) } }")
+        );
+        // a name is the same variant as its definition
+        assert_eq!(
+            vec![(
+                String::new(),
+                vec![],
+                SpecCompileError::DuplicateUnionVariantSpecs(vec![compile("name B = bool")])
+            )],
+            located_errors("union { name B = bool, ref B }")
+        );
+    }
+
+    #[test]
+    fn test_const_set_of_name_being_defined() {
+        assert_eq!(
+            vec![(
+                String::new(),
+                vec!["A".to_string()],
+                SpecCompileError::UndecodableConstSetValue(vec![0x00])
+            )],
+            located_errors("name A = optional<const_set<optional<ref A>> { 0x00 }>")
+        );
+        // once defined the name can be used
+        let spec = compile("record { a: name A = optional<uint(0)>, b: const_set<ref A> { 0x00 } }");
+        let SpecType::Record { field_to_spec, .. } = spec.spec_type() else {
+            panic!("Spec should be a record")
+        };
+        assert!(matches!(
+            field_to_spec["b"].spec_type(),
+            SpecType::ConstSet(_, values) if values == &vec![GluinoValue::Optional(None)]
+        ));
+    }
+
+    #[test]
+    fn test_inverted_size() {
+        assert_eq!(
+            vec![
+                (
+                    ".a".to_string(),
+                    vec![],
+                    SpecCompileError::InvertedLengthBounds { min_len: 5, end: 3 }
+                ),
+                (
+                    ".b".to_string(),
+                    vec!["B".to_string()],
+                    SpecCompileError::InvertedLengthBounds { min_len: 0, end: 0 }
+                ),
+                (
+                    ".c".to_string(),
+                    vec![],
+                    SpecCompileError::InvertedLengthBounds { min_len: 2, end: 2 }
+                ),
+            ],
+            located_errors(
+                "record { a: bytes<5..3>, b: name B = list<ref B, ..0>, c: map<string<1..2>, bool, 2..2> }"
+            )
+        );
+    }
+}
@@ -0,0 +1,524 @@
+//! Structural differences between two specs, each at the path it is found, e.g. `.orders[].price`.
+//!
+//! Names are followed to the specs they name, so only changes to structure are reported. Subtrees
+//! with equal fingerprints are not compared. Tuple fields and union members are compared by position,
+//! record fields and enum variants by name.
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+};
+
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    fingerprint::SpecFingerprint,
+    serde::GluinoValue,
+    spec::{Spec, SpecType},
+    spec_parsing::{ParsedSpec, Size, StringEncodingFmt},
+    spec_path::{PathSegment, SpecPath},
+};
+
+impl Spec {
+    /// Every difference from `self` to `other`, empty when they have the same structure
+    pub fn diff(&self, other: &Spec) -> Vec<SpecDifference> {
+        let mut differ = Differ {
+            path: SpecPath::root(),
+            differences: Vec::new(),
+            compared: HashSet::new(),
+        };
+        differ.diff(self, other);
+        differ.differences
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, EnumDiscriminants)]
+#[strum_discriminants(name(SpecDifferenceKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum SpecDifference {
+    FieldAdded {
+        path: SpecPath,
+        field: String,
+        spec: ParsedSpec,
+    },
+    FieldRemoved {
+        path: SpecPath,
+        field: String,
+        spec: ParsedSpec,
+    },
+    // relative to the fields both records have
+    FieldMoved {
+        path: SpecPath,
+        field: String,
+        from: usize,
+        to: usize,
+    },
+    VariantAdded {
+        path: SpecPath,
+        variant: String,
+        spec: ParsedSpec,
+    },
+    VariantRemoved {
+        path: SpecPath,
+        variant: String,
+        spec: ParsedSpec,
+    },
+    ElementAdded {
+        path: SpecPath,
+        index: usize,
+        spec: ParsedSpec,
+    },
+    ElementRemoved {
+        path: SpecPath,
+        index: usize,
+        spec: ParsedSpec,
+    },
+    ConstSetValuesChanged {
+        path: SpecPath,
+        added: Vec<GluinoValue>,
+        removed: Vec<GluinoValue>,
+    },
+    SizeChanged {
+        path: SpecPath,
+        from: Size,
+        to: Size,
+    },
+    NumericChanged {
+        path: SpecPath,
+        from: ParsedSpec,
+        to: ParsedSpec,
+    },
+    StringEncodingChanged {
+        path: SpecPath,
+        from: StringEncodingFmt,
+        to: StringEncodingFmt,
+    },
+    SpecChanged {
+        path: SpecPath,
+        from: ParsedSpec,
+        to: ParsedSpec,
+    },
+}
+
+impl SpecDifference {
+    pub fn path(&self) -> &SpecPath {
+        match self {
+            Self::FieldAdded { path, .. }
+            | Self::FieldRemoved { path, .. }
+            | Self::FieldMoved { path, .. }
+            | Self::VariantAdded { path, .. }
+            | Self::VariantRemoved { path, .. }
+            | Self::ElementAdded { path, .. }
+            | Self::ElementRemoved { path, .. }
+            | Self::ConstSetValuesChanged { path, .. }
+            | Self::SizeChanged { path, .. }
+            | Self::NumericChanged { path, .. }
+            | Self::StringEncodingChanged { path, .. }
+            | Self::SpecChanged { path, .. } => path,
+        }
+    }
+}
+
+impl Display for SpecDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FieldAdded { path, field, spec } => {
+                write!(f, "{}: added field {:?}: {}", path, field, spec.to_text())
+            }
+            Self::FieldRemoved { path, field, spec } => {
+                write!(f, "{}: removed field {:?}: {}", path, field, spec.to_text())
+            }
+            Self::FieldMoved {
+                path,
+                field,
+                from,
+                to,
+            } => write!(
+                f,
+                "{}: moved field {:?} from {} to {}",
+                path, field, from, to
+            ),
+            Self::VariantAdded {
+                path,
+                variant,
+                spec,
+            } => write!(
+                f,
+                "{}: added variant {:?}: {}",
+                path,
+                variant,
+                spec.to_text()
+            ),
+            Self::VariantRemoved {
+                path,
+                variant,
+                spec,
+            } => write!(
+                f,
+                "{}: removed variant {:?}: {}",
+                path,
+                variant,
+                spec.to_text()
+            ),
+            Self::ElementAdded { path, index, spec } => {
+                write!(f, "{}: added {}: {}", path, index, spec.to_text())
+            }
+            Self::ElementRemoved { path, index, spec } => {
+                write!(f, "{}: removed {}: {}", path, index, spec.to_text())
+            }
+            Self::ConstSetValuesChanged {
+                path,
+                added,
+                removed,
+            } => write!(
+                f,
+                "{}: added values {:?}, removed values {:?}",
+                path, added, removed
+            ),
+            Self::SizeChanged { path, from, to } => {
+                write!(f, "{}: size changed from {:?} to {:?}", path, from, to)
+            }
+            Self::NumericChanged { path, from, to } | Self::SpecChanged { path, from, to } => {
+                write!(
+                    f,
+                    "{}: changed from {} to {}",
+                    path,
+                    from.to_text(),
+                    to.to_text()
+                )
+            }
+            Self::StringEncodingChanged { path, from, to } => write!(
+                f,
+                "{}: encoding changed from {} to {}",
+                path,
+                from.name(),
+                to.name()
+            ),
+        }
+    }
+}
+
+struct Differ {
+    path: SpecPath,
+    differences: Vec<SpecDifference>,
+    // pairs of named specs already compared, recursive specs compare each pair once
+    compared: HashSet<(SpecFingerprint, SpecFingerprint)>,
+}
+
+impl Differ {
+    fn at(&mut self, segment: PathSegment, f: impl FnOnce(&mut Self)) {
+        let len = self.path.push(segment);
+        f(self);
+        self.path.truncate(len);
+    }
+
+    fn difference(&mut self, difference: impl FnOnce(SpecPath) -> SpecDifference) {
+        self.differences.push(difference(self.path.clone()))
+    }
+
+    fn size(&mut self, from: &Size, to: &Size) {
+        if from != to {
+            self.difference(|path| SpecDifference::SizeChanged {
+                path,
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+    }
+
+    fn diff(&mut self, from: &Spec, to: &Spec) {
+        if from.fingerprint() == to.fingerprint() {
+            return;
+        }
+        if matches!(from.spec_type(), SpecType::Name(_))
+            || matches!(to.spec_type(), SpecType::Name(_))
+        {
+            if !self
+                .compared
+                .insert((from.fingerprint().clone(), to.fingerprint().clone()))
+            {
+                return;
+            }
+            return self.diff(&from.resolve(), &to.resolve());
+        }
+        match (from.spec_type(), to.spec_type()) {
+            (SpecType::Uint(_), SpecType::Uint(_))
+            | (SpecType::Uint(_), SpecType::Int(_))
+            | (SpecType::Int(_), SpecType::Uint(_))
+            | (SpecType::Int(_), SpecType::Int(_))
+            | (SpecType::BinaryFloatingPoint(_), SpecType::BinaryFloatingPoint(_))
+            | (SpecType::DecimalFloatingPoint(_), SpecType::DecimalFloatingPoint(_))
+            | (SpecType::Decimal(_), SpecType::Decimal(_)) => {
+                self.difference(|path| SpecDifference::NumericChanged {
+                    path,
+                    from: from.to_parsed_spec(),
+                    to: to.to_parsed_spec(),
+                })
+            }
+            (
+                SpecType::String(from_size, from_encoding),
+                SpecType::String(to_size, to_encoding),
+            ) => {
+                if from_encoding != to_encoding {
+                    self.difference(|path| SpecDifference::StringEncodingChanged {
+                        path,
+                        from: from_encoding.clone(),
+                        to: to_encoding.clone(),
+                    });
+                }
+                self.size(from_size, to_size);
+            }
+            (SpecType::Bytes(from_size), SpecType::Bytes(to_size)) => self.size(from_size, to_size),
+            (SpecType::Optional(from_inner), SpecType::Optional(to_inner)) => {
+                self.diff(from_inner, to_inner)
+            }
+            (
+                SpecType::List {
+                    size: from_size,
+                    value_spec: from_value,
+                },
+                SpecType::List {
+                    size: to_size,
+                    value_spec: to_value,
+                },
+            ) => {
+                self.size(from_size, to_size);
+                self.at(PathSegment::Item, |d| d.diff(from_value, to_value));
+            }
+            (
+                SpecType::Map {
+                    size: from_size,
+                    key_spec: from_key,
+                    value_spec: from_value,
+                },
+                SpecType::Map {
+                    size: to_size,
+                    key_spec: to_key,
+                    value_spec: to_value,
+                },
+            ) => {
+                self.size(from_size, to_size);
+                self.at(PathSegment::Key, |d| d.diff(from_key, to_key));
+                self.at(PathSegment::Item, |d| d.diff(from_value, to_value));
+            }
+            (
+                SpecType::Record {
+                    fields: from_fields,
+                    field_to_spec: from_field_to_spec,
+                    ..
+                },
+                SpecType::Record {
+                    fields: to_fields,
+                    field_to_spec: to_field_to_spec,
+                    ..
+                },
+            ) => {
+                let from_common: Vec<&String> = from_fields
+                    .iter()
+                    .filter(|f| to_field_to_spec.contains_key(*f))
+                    .collect();
+                let to_common: Vec<&String> = to_fields
+                    .iter()
+                    .filter(|f| from_field_to_spec.contains_key(*f))
+                    .collect();
+                for field in from_fields {
+                    let from_spec = from_field_to_spec.get(field).unwrap();
+                    match to_field_to_spec.get(field) {
+                        Some(to_spec) => {
+                            let from_index = from_common.iter().position(|f| *f == field).unwrap();
+                            let to_index = to_common.iter().position(|f| *f == field).unwrap();
+                            if from_index != to_index {
+                                self.difference(|path| SpecDifference::FieldMoved {
+                                    path,
+                                    field: field.clone(),
+                                    from: from_index,
+                                    to: to_index,
+                                });
+                            }
+                            self.at(PathSegment::Field(field), |d| d.diff(from_spec, to_spec));
+                        }
+                        None => self.difference(|path| SpecDifference::FieldRemoved {
+                            path,
+                            field: field.clone(),
+                            spec: from_spec.to_parsed_spec(),
+                        }),
+                    }
+                }
+                for field in to_fields
+                    .iter()
+                    .filter(|f| !from_field_to_spec.contains_key(*f))
+                {
+                    self.difference(|path| SpecDifference::FieldAdded {
+                        path,
+                        field: field.clone(),
+                        spec: to_field_to_spec.get(field).unwrap().to_parsed_spec(),
+                    });
+                }
+            }
+            (
+                SpecType::Enum {
+                    variants: from_variants,
+                    variant_to_spec: from_variant_to_spec,
+                },
+                SpecType::Enum {
+                    variants: to_variants,
+                    variant_to_spec: to_variant_to_spec,
+                },
+            ) => {
+                for variant in from_variants {
+                    let from_spec = from_variant_to_spec.get(variant).unwrap();
+                    match to_variant_to_spec.get(variant) {
+                        Some(to_spec) => {
+                            self.at(PathSegment::Field(variant), |d| d.diff(from_spec, to_spec))
+                        }
+                        None => self.difference(|path| SpecDifference::VariantRemoved {
+                            path,
+                            variant: variant.clone(),
+                            spec: from_spec.to_parsed_spec(),
+                        }),
+                    }
+                }
+                for variant in to_variants
+                    .iter()
+                    .filter(|v| !from_variant_to_spec.contains_key(*v))
+                {
+                    self.difference(|path| SpecDifference::VariantAdded {
+                        path,
+                        variant: variant.clone(),
+                        spec: to_variant_to_spec.get(variant).unwrap().to_parsed_spec(),
+                    });
+                }
+            }
+            (SpecType::Tuple(from_elements), SpecType::Tuple(to_elements))
+            | (SpecType::Union(from_elements), SpecType::Union(to_elements)) => {
+                for (index, (from_element, to_element)) in
+                    from_elements.iter().zip(to_elements).enumerate()
+                {
+                    self.at(PathSegment::Index(index), |d| {
+                        d.diff(from_element, to_element)
+                    });
+                }
+                for (index, spec) in from_elements.iter().enumerate().skip(to_elements.len()) {
+                    self.difference(|path| SpecDifference::ElementRemoved {
+                        path,
+                        index,
+                        spec: spec.to_parsed_spec(),
+                    });
+                }
+                for (index, spec) in to_elements.iter().enumerate().skip(from_elements.len()) {
+                    self.difference(|path| SpecDifference::ElementAdded {
+                        path,
+                        index,
+                        spec: spec.to_parsed_spec(),
+                    });
+                }
+            }
+            (
+                SpecType::ConstSet(from_spec, from_values),
+                SpecType::ConstSet(to_spec, to_values),
+            ) => {
+                self.diff(from_spec, to_spec);
+                let added: Vec<GluinoValue> = to_values
+                    .iter()
+                    .filter(|v| !from_values.contains(v))
+                    .cloned()
+                    .collect();
+                let removed: Vec<GluinoValue> = from_values
+                    .iter()
+                    .filter(|v| !to_values.contains(v))
+                    .cloned()
+                    .collect();
+                if !added.is_empty() || !removed.is_empty() {
+                    self.difference(|path| SpecDifference::ConstSetValuesChanged {
+                        path,
+                        added,
+                        removed,
+                    });
+                }
+            }
+            _ => self.difference(|path| SpecDifference::SpecChanged {
+                path,
+                from: from.to_parsed_spec(),
+                to: to.to_parsed_spec(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::compile;
+
+    use super::*;
+
+    fn diff(from: &str, to: &str) -> Vec<(SpecDifferenceKind, String)> {
+        compile(from)
+            .diff(&compile(to))
+            .iter()
+            .map(|d| (d.into(), d.path().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        use SpecDifferenceKind::*;
+        let from = "record {
+            id: string<..32>,
+            orders: list<record {
+                price: decimal(9, 2),
+                quantity: uint(1),
+                side: enum { buy: void, sell: void },
+            }>,
+            tags: map<string, bytes>,
+            status: const_set<uint(0)> { 0x01, 0x02 },
+            note: string,
+            payload: union { bool, string },
+            dropped: bool,
+        }";
+        let to = "record {
+            orders: list<record {
+                price: decimal(12, 2),
+                quantity: int(1),
+                side: enum { sell: void, hold: void },
+            }>,
+            id: string<..64>,
+            tags: map<string<ascii>, bytes<16>>,
+            status: const_set<uint(0)> { 0x02, 0x03 },
+            note: optional<string>,
+            payload: union { bool, string, bytes },
+            added: bool,
+        }";
+        assert_eq!(
+            vec![
+                (FieldMoved, ".".to_string()),
+                (SizeChanged, ".id".to_string()),
+                (FieldMoved, ".".to_string()),
+                (NumericChanged, ".orders[].price".to_string()),
+                (NumericChanged, ".orders[].quantity".to_string()),
+                (VariantRemoved, ".orders[].side".to_string()),
+                (VariantAdded, ".orders[].side".to_string()),
+                (StringEncodingChanged, ".tags{}".to_string()),
+                (SizeChanged, ".tags[]".to_string()),
+                (ConstSetValuesChanged, ".status".to_string()),
+                (SpecChanged, ".note".to_string()),
+                (ElementAdded, ".payload".to_string()),
+                (FieldRemoved, ".".to_string()),
+                (FieldAdded, ".".to_string()),
+            ],
+            diff(from, to)
+        );
+    }
+
+    #[test]
+    fn test_diff_follows_names() {
+        assert!(diff("name A = list<ref A>", "name B = list<ref B>").is_empty());
+        assert_eq!(
+            vec![(SpecDifferenceKind::NumericChanged, ".value".to_string())],
+            diff(
+                "name Node = record { value: int(0), next: optional<ref Node> }",
+                "name Node = record { value: int(1), next: optional<ref Node> }"
+            )
+        );
+        assert!(compile("bool").diff(&compile("bool")).is_empty());
+    }
+}
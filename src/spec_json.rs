@@ -0,0 +1,565 @@
+//! Canonical json encoding of specs for tools that can not link the binary spec format.
+//!
+//! Every spec is an object tagged by `"type"`, field and variant order is kept by using arrays.
+//! ```json
+//! {"type": "name", "name": "Tree", "spec": {"type": "record", "fields": [
+//!     {"name": "id", "spec": {"type": "uint", "n": 3}},
+//!     {"name": "label", "spec": {"type": "string", "encoding": "utf8", "size": {"less_than": 64}}},
+//!     {"name": "children", "spec": {"type": "list", "size": "variable", "value": {"type": "ref", "name": "Tree"}}}
+//! ]}}
+//! ```
+//! Sizes are `"variable"`, `{"fixed": n}`, `{"range": {"start": a, "end": b}}`, `{"greater_than": n}`
+//! or `{"less_than": n}` and const set values are hex strings of their encoded bytes.
+//! The printer always writes sizes and string encodings, the parser defaults them when missing.
+
+use std::fmt::{self, Display};
+
+use serde_json::{Map, Value, json};
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    spec::Spec,
+    spec_parsing::{
+        InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, ParsedSpec,
+        Size, SizeRange, StringEncodingFmt,
+    },
+};
+
+impl ParsedSpec {
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+
+    pub fn to_json_value(&self) -> Value {
+        match self {
+            ParsedSpec::Void => json!({"type": "void"}),
+            ParsedSpec::Bool => json!({"type": "bool"}),
+            ParsedSpec::Uint(n) => json!({"type": "uint", "n": n}),
+            ParsedSpec::Int(n) => json!({"type": "int", "n": n}),
+            ParsedSpec::BinaryFloatingPoint(fmt) => {
+                json!({"type": "binary_float", "format": fmt.name()})
+            }
+            ParsedSpec::DecimalFloatingPoint(fmt) => {
+                json!({"type": "decimal_float", "format": fmt.name()})
+            }
+            ParsedSpec::Decimal { precision, scale } => {
+                json!({"type": "decimal", "precision": precision, "scale": scale})
+            }
+            ParsedSpec::String(size, fmt) => {
+                json!({"type": "string", "encoding": fmt.name(), "size": size_to_json(size)})
+            }
+            ParsedSpec::Bytes(size) => json!({"type": "bytes", "size": size_to_json(size)}),
+            ParsedSpec::Optional(inner) => {
+                json!({"type": "optional", "spec": inner.to_json_value()})
+            }
+            ParsedSpec::List { size, value_spec } => json!({
+                "type": "list",
+                "size": size_to_json(size),
+                "value": value_spec.to_json_value(),
+            }),
+            ParsedSpec::Map {
+                size,
+                key_spec,
+                value_spec,
+            } => json!({
+                "type": "map",
+                "size": size_to_json(size),
+                "key": key_spec.to_json_value(),
+                "value": value_spec.to_json_value(),
+            }),
+            ParsedSpec::Record(fields) => {
+                json!({"type": "record", "fields": named_to_json(fields)})
+            }
+            ParsedSpec::Enum(variants) => {
+                json!({"type": "enum", "variants": named_to_json(variants)})
+            }
+            ParsedSpec::Tuple(fields) => json!({
+                "type": "tuple",
+                "fields": fields.iter().map(ParsedSpec::to_json_value).collect::<Vec<_>>(),
+            }),
+            ParsedSpec::Union(variants) => json!({
+                "type": "union",
+                "variants": variants.iter().map(ParsedSpec::to_json_value).collect::<Vec<_>>(),
+            }),
+            ParsedSpec::ConstSet(const_spec, values) => json!({
+                "type": "const_set",
+                "spec": const_spec.to_json_value(),
+                "values": values.iter().map(hex::encode).collect::<Vec<_>>(),
+            }),
+            ParsedSpec::Name { name, spec } => {
+                json!({"type": "name", "name": name, "spec": spec.to_json_value()})
+            }
+            ParsedSpec::Ref { name } => json!({"type": "ref", "name": name}),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<ParsedSpec, SpecJsonError> {
+        let value: Value =
+            serde_json::from_str(json).map_err(|e| SpecJsonError::InvalidJson(e.to_string()))?;
+        ParsedSpec::from_json_value(&value)
+    }
+
+    pub fn from_json_value(value: &Value) -> Result<ParsedSpec, SpecJsonError> {
+        spec_from_json(value, &mut String::new())
+    }
+}
+
+impl Spec {
+    pub fn to_json(&self) -> String {
+        self.to_parsed_spec().to_json()
+    }
+}
+
+/// Errors carry the json pointer, e.g. `/fields/0/spec`, of the offending value
+#[derive(Debug, Eq, PartialEq, Clone, EnumDiscriminants)]
+#[strum_discriminants(name(SpecJsonErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum SpecJsonError {
+    InvalidJson(String),
+    UnexpectedValue {
+        path: String,
+        expected: &'static str,
+        found: Value,
+    },
+    MissingField(String, &'static str),
+    UnknownSpecType(String, String),
+    UnknownFormat(String, String),
+    IntegerOutOfRange(String, Value),
+    InvalidHex(String, String),
+}
+
+impl Display for SpecJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidJson(e) => write!(f, "invalid json: {}", e),
+            Self::UnexpectedValue {
+                path,
+                expected,
+                found,
+            } => write!(f, "{}: expected {}, found {}", path, expected, found),
+            Self::MissingField(path, field) => write!(f, "{}: missing field {:?}", path, field),
+            Self::UnknownSpecType(path, spec_type) => {
+                write!(f, "{}: unknown spec type {:?}", path, spec_type)
+            }
+            Self::UnknownFormat(path, format) => write!(f, "{}: unknown format {:?}", path, format),
+            Self::IntegerOutOfRange(path, n) => write!(f, "{}: integer {} out of range", path, n),
+            Self::InvalidHex(path, hex) => write!(f, "{}: invalid hex bytes {:?}", path, hex),
+        }
+    }
+}
+
+impl std::error::Error for SpecJsonError {}
+
+fn size_to_json(size: &Size) -> Value {
+    match size {
+        Size::Variable => json!("variable"),
+        Size::Fixed(n) => json!({ "fixed": n }),
+        Size::Range(SizeRange { start, end }) => json!({"range": {"start": start, "end": end}}),
+        Size::GreaterThan(n) => json!({ "greater_than": n }),
+        Size::LessThan(n) => json!({ "less_than": n }),
+    }
+}
+
+fn named_to_json(entries: &[(String, ParsedSpec)]) -> Vec<Value> {
+    entries
+        .iter()
+        .map(|(name, spec)| json!({"name": name, "spec": spec.to_json_value()}))
+        .collect()
+}
+
+// the path is extended while descending and truncated again on the way back out
+fn with_path<T>(
+    path: &mut String,
+    segment: impl Display,
+    f: impl FnOnce(&mut String) -> Result<T, SpecJsonError>,
+) -> Result<T, SpecJsonError> {
+    let len = path.len();
+    path.push('/');
+    path.push_str(&segment.to_string().replace('~', "~0").replace('/', "~1"));
+    let result = f(path);
+    path.truncate(len);
+    result
+}
+
+fn unexpected(path: &str, expected: &'static str, found: &Value) -> SpecJsonError {
+    SpecJsonError::UnexpectedValue {
+        path: path.to_string(),
+        expected,
+        found: found.clone(),
+    }
+}
+
+fn as_object<'a>(value: &'a Value, path: &str) -> Result<&'a Map<String, Value>, SpecJsonError> {
+    value
+        .as_object()
+        .ok_or_else(|| unexpected(path, "an object", value))
+}
+
+fn field<'a>(
+    object: &'a Map<String, Value>,
+    name: &'static str,
+    path: &str,
+) -> Result<&'a Value, SpecJsonError> {
+    object
+        .get(name)
+        .ok_or_else(|| SpecJsonError::MissingField(path.to_string(), name))
+}
+
+fn string_field<'a>(
+    object: &'a Map<String, Value>,
+    name: &'static str,
+    path: &mut String,
+) -> Result<&'a str, SpecJsonError> {
+    let value = field(object, name, path)?;
+    with_path(path, name, |path| {
+        value
+            .as_str()
+            .ok_or_else(|| unexpected(path, "a string", value))
+    })
+}
+
+fn integer<T: TryFrom<u64>>(value: &Value, path: &str) -> Result<T, SpecJsonError> {
+    let n = value
+        .as_u64()
+        .ok_or_else(|| unexpected(path, "a non negative integer", value))?;
+    T::try_from(n).map_err(|_| SpecJsonError::IntegerOutOfRange(path.to_string(), value.clone()))
+}
+
+fn integer_field<T: TryFrom<u64>>(
+    object: &Map<String, Value>,
+    name: &'static str,
+    path: &mut String,
+) -> Result<T, SpecJsonError> {
+    let value = field(object, name, path)?;
+    with_path(path, name, |path| integer(value, path))
+}
+
+fn array_field<'a>(
+    object: &'a Map<String, Value>,
+    name: &'static str,
+    path: &mut String,
+) -> Result<&'a Vec<Value>, SpecJsonError> {
+    let value = field(object, name, path)?;
+    with_path(path, name, |path| {
+        value
+            .as_array()
+            .ok_or_else(|| unexpected(path, "an array", value))
+    })
+}
+
+fn spec_field(
+    object: &Map<String, Value>,
+    name: &'static str,
+    path: &mut String,
+) -> Result<Box<ParsedSpec>, SpecJsonError> {
+    let value = field(object, name, path)?;
+    with_path(path, name, |path| spec_from_json(value, path).map(Box::new))
+}
+
+fn size_field(object: &Map<String, Value>, path: &mut String) -> Result<Size, SpecJsonError> {
+    match object.get("size") {
+        None => Ok(Size::Variable),
+        Some(value) => with_path(path, "size", |path| size_from_json(value, path)),
+    }
+}
+
+fn size_from_json(value: &Value, path: &mut String) -> Result<Size, SpecJsonError> {
+    if value.as_str() == Some("variable") {
+        return Ok(Size::Variable);
+    }
+    let object = as_object(value, path)?;
+    let mut entries = object.iter();
+    let (Some((kind, bound)), None) = (entries.next(), entries.next()) else {
+        return Err(unexpected(path, "a single size kind", value));
+    };
+    with_path(path, kind, |path| {
+        Ok(match kind.as_str() {
+            "fixed" => Size::Fixed(integer(bound, path)?),
+            "greater_than" => Size::GreaterThan(integer(bound, path)?),
+            "less_than" => Size::LessThan(integer(bound, path)?),
+            "range" => {
+                let range = as_object(bound, path)?;
+                Size::Range(SizeRange {
+                    start: integer_field(range, "start", path)?,
+                    end: integer_field(range, "end", path)?,
+                })
+            }
+            _ => return Err(SpecJsonError::UnknownFormat(path.clone(), kind.clone())),
+        })
+    })
+}
+
+fn named_from_json(
+    object: &Map<String, Value>,
+    name: &'static str,
+    path: &mut String,
+) -> Result<Vec<(String, ParsedSpec)>, SpecJsonError> {
+    let entries = array_field(object, name, path)?;
+    with_path(path, name, |path| {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                with_path(path, i, |path| {
+                    let entry = as_object(entry, path)?;
+                    Ok((
+                        string_field(entry, "name", path)?.to_string(),
+                        *spec_field(entry, "spec", path)?,
+                    ))
+                })
+            })
+            .collect()
+    })
+}
+
+fn specs_from_json(
+    object: &Map<String, Value>,
+    name: &'static str,
+    path: &mut String,
+) -> Result<Vec<ParsedSpec>, SpecJsonError> {
+    let specs = array_field(object, name, path)?;
+    with_path(path, name, |path| {
+        specs
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| with_path(path, i, |path| spec_from_json(spec, path)))
+            .collect()
+    })
+}
+
+fn format_field<T>(
+    object: &Map<String, Value>,
+    name: &'static str,
+    from_name: impl Fn(&str) -> Option<T>,
+    path: &mut String,
+) -> Result<T, SpecJsonError> {
+    let format = string_field(object, name, path)?;
+    from_name(format).ok_or_else(|| {
+        SpecJsonError::UnknownFormat(format!("{}/{}", path, name), format.to_string())
+    })
+}
+
+fn spec_from_json(value: &Value, path: &mut String) -> Result<ParsedSpec, SpecJsonError> {
+    let object = as_object(value, path)?;
+    let spec_type = string_field(object, "type", path)?;
+    Ok(match spec_type {
+        "void" => ParsedSpec::Void,
+        "bool" => ParsedSpec::Bool,
+        "uint" => ParsedSpec::Uint(integer_field(object, "n", path)?),
+        "int" => ParsedSpec::Int(integer_field(object, "n", path)?),
+        "binary_float" => ParsedSpec::BinaryFloatingPoint(format_field(
+            object,
+            "format",
+            InterchangeBinaryFloatingPointFormat::from_name,
+            path,
+        )?),
+        "decimal_float" => ParsedSpec::DecimalFloatingPoint(format_field(
+            object,
+            "format",
+            InterchangeDecimalFloatingPointFormat::from_name,
+            path,
+        )?),
+        "decimal" => ParsedSpec::Decimal {
+            precision: integer_field(object, "precision", path)?,
+            scale: integer_field(object, "scale", path)?,
+        },
+        "string" => ParsedSpec::String(
+            size_field(object, path)?,
+            if object.contains_key("encoding") {
+                format_field(object, "encoding", StringEncodingFmt::from_name, path)?
+            } else {
+                StringEncodingFmt::default()
+            },
+        ),
+        "bytes" => ParsedSpec::Bytes(size_field(object, path)?),
+        "optional" => ParsedSpec::Optional(spec_field(object, "spec", path)?),
+        "list" => ParsedSpec::List {
+            size: size_field(object, path)?,
+            value_spec: spec_field(object, "value", path)?,
+        },
+        "map" => ParsedSpec::Map {
+            size: size_field(object, path)?,
+            key_spec: spec_field(object, "key", path)?,
+            value_spec: spec_field(object, "value", path)?,
+        },
+        "record" => ParsedSpec::Record(named_from_json(object, "fields", path)?),
+        "enum" => ParsedSpec::Enum(named_from_json(object, "variants", path)?),
+        "tuple" => ParsedSpec::Tuple(specs_from_json(object, "fields", path)?),
+        "union" => ParsedSpec::Union(specs_from_json(object, "variants", path)?),
+        "const_set" => {
+            let const_spec = spec_field(object, "spec", path)?;
+            let values = array_field(object, "values", path)?;
+            let values = with_path(path, "values", |path| {
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        with_path(path, i, |path| {
+                            let hex = value
+                                .as_str()
+                                .ok_or_else(|| unexpected(path, "a hex string", value))?;
+                            hex::decode(hex).map_err(|_| {
+                                SpecJsonError::InvalidHex(path.clone(), hex.to_string())
+                            })
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })?;
+            ParsedSpec::ConstSet(const_spec, values)
+        }
+        "name" => ParsedSpec::Name {
+            name: string_field(object, "name", path)?.to_string(),
+            spec: spec_field(object, "spec", path)?,
+        },
+        "ref" => ParsedSpec::Ref {
+            name: string_field(object, "name", path)?.to_string(),
+        },
+        _ => {
+            return Err(SpecJsonError::UnknownSpecType(
+                format!("{}/type", path),
+                spec_type.to_string(),
+            ));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use crate::test_utils::get_all_kinds_spec;
+
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        for spec in get_all_kinds_spec() {
+            let json = spec.to_json();
+            assert_eq!(
+                spec,
+                ParsedSpec::from_json(&json)
+                    .unwrap_or_else(|e| panic!("Unable to parse {}: {}", json, e)),
+                "{}",
+                json
+            );
+        }
+        for size in [
+            Size::Variable,
+            Size::Fixed(4),
+            Size::Range(SizeRange { start: 2, end: 9 }),
+            Size::GreaterThan(3),
+            Size::LessThan(u64::MAX),
+        ] {
+            let spec = ParsedSpec::Map {
+                size: size.clone(),
+                key_spec: ParsedSpec::Bytes(size.clone()).into(),
+                value_spec: ParsedSpec::String(size, StringEncodingFmt::Ascii).into(),
+            };
+            assert_eq!(spec, ParsedSpec::from_json(&spec.to_json()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_json_canonical_form() {
+        let spec = ParsedSpec::Name {
+            name: "Tree".into(),
+            spec: ParsedSpec::Record(vec![
+                ("id".into(), ParsedSpec::Uint(3)),
+                (
+                    "label".into(),
+                    ParsedSpec::String(Size::LessThan(64), StringEncodingFmt::Utf8),
+                ),
+                (
+                    "children".into(),
+                    ParsedSpec::List {
+                        size: Size::Variable,
+                        value_spec: ParsedSpec::Ref {
+                            name: "Tree".into(),
+                        }
+                        .into(),
+                    },
+                ),
+                (
+                    "kind".into(),
+                    ParsedSpec::ConstSet(ParsedSpec::Uint(0).into(), vec![vec![0x01], vec![0xab]]),
+                ),
+            ])
+            .into(),
+        };
+        assert_eq!(
+            json!({"type": "name", "name": "Tree", "spec": {"type": "record", "fields": [
+                {"name": "id", "spec": {"type": "uint", "n": 3}},
+                {"name": "label", "spec": {"type": "string", "encoding": "utf8", "size": {"less_than": 64}}},
+                {"name": "children", "spec": {"type": "list", "size": "variable", "value": {"type": "ref", "name": "Tree"}}},
+                {"name": "kind", "spec": {"type": "const_set", "spec": {"type": "uint", "n": 0}, "values": ["01", "ab"]}},
+            ]}}),
+            spec.to_json_value()
+        );
+        assert_eq!(
+            spec.to_json(),
+            Spec::compile(spec.clone()).unwrap().to_json()
+        );
+        // defaults
+        assert_eq!(
+            ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8),
+            ParsedSpec::from_json(r#"{"type": "string"}"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_json_error_kinds() {
+        for kind in SpecJsonErrorKind::iter() {
+            match kind {
+                SpecJsonErrorKind::InvalidJson => vec![("", ""), ("{\"type\": ", "")],
+                SpecJsonErrorKind::UnexpectedValue => vec![
+                    ("\"bool\"", ""),
+                    (r#"{"type": 1}"#, "/type"),
+                    (r#"{"type": "uint", "n": -1}"#, "/n"),
+                    (r#"{"type": "record", "fields": {}}"#, "/fields"),
+                    (r#"{"type": "tuple", "fields": [{"type": "bool"}, []]}"#, "/fields/1"),
+                    (r#"{"type": "bytes", "size": {"fixed": 1, "less_than": 2}}"#, "/size"),
+                    (r#"{"type": "const_set", "spec": {"type": "bool"}, "values": [1]}"#, "/values/0"),
+                ],
+                SpecJsonErrorKind::MissingField => vec![
+                    ("{}", ""),
+                    (r#"{"type": "optional"}"#, ""),
+                    (r#"{"type": "enum", "variants": [{"name": "a"}]}"#, "/variants/0"),
+                    (r#"{"type": "list", "size": {"range": {"start": 1}}, "value": {"type": "bool"}}"#, "/size/range"),
+                ],
+                SpecJsonErrorKind::UnknownSpecType => vec![
+                    (r#"{"type": "boolean"}"#, "/type"),
+                    (r#"{"type": "optional", "spec": {"type": "float"}}"#, "/spec/type"),
+                ],
+                SpecJsonErrorKind::UnknownFormat => vec![
+                    (r#"{"type": "binary_float", "format": "triple"}"#, "/format"),
+                    (r#"{"type": "decimal_float", "format": "dec16"}"#, "/format"),
+                    (r#"{"type": "string", "encoding": "utf32"}"#, "/encoding"),
+                    (r#"{"type": "bytes", "size": {"at_most": 3}}"#, "/size/at_most"),
+                ],
+                SpecJsonErrorKind::IntegerOutOfRange => vec![
+                    (r#"{"type": "int", "n": 256}"#, "/n"),
+                ],
+                SpecJsonErrorKind::InvalidHex => vec![
+                    (r#"{"type": "const_set", "spec": {"type": "bool"}, "values": ["0"]}"#, "/values/0"),
+                ],
+            }
+            .into_iter()
+            .for_each(|(json, expected_path)| match ParsedSpec::from_json(json) {
+                Ok(spec) => panic!("{:?} unexpectedly parsed into {:?}", json, spec),
+                Err(e) => {
+                    assert_eq!(kind, SpecJsonErrorKind::from(&e), "{:?}: {}", json, e);
+                    let path = match &e {
+                        SpecJsonError::InvalidJson(_) => "",
+                        SpecJsonError::UnexpectedValue { path, .. }
+                        | SpecJsonError::MissingField(path, _)
+                        | SpecJsonError::UnknownSpecType(path, _)
+                        | SpecJsonError::UnknownFormat(path, _)
+                        | SpecJsonError::IntegerOutOfRange(path, _)
+                        | SpecJsonError::InvalidHex(path, _) => path,
+                    };
+                    assert_eq!(expected_path, path, "{:?}: {}", json, e);
+                }
+            });
+        }
+    }
+}
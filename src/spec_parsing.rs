@@ -1,15 +1,21 @@
 use core::slice;
 use std::{
+    fmt::{self, Display},
     io::Read,
     io::{self, Write},
+    mem::size_of,
+    string::FromUtf8Error,
 };
+use strum::IntoEnumIterator;
 use strum_macros::{EnumDiscriminants, EnumIter};
 
 use crate::{
+    limits::{DecodeBudget, DecodeLimitError, DecodeLimits},
     spec::{Spec, SpecCompileError},
+    spec_path::{PathSegment, SpecPath},
     util::{
-        self, variable_length_decode_u64, variable_length_encode_u64, VariableLengthDecodingError,
-        WriteAllReturnSize,
+        self, variable_length_decode_u64, variable_length_encode_u64, CountingReader,
+        VariableLengthDecodingError, WriteAllReturnSize,
     },
 };
 
@@ -88,6 +94,7 @@ const DOUBLE_FP: u8 = 9;
 const UTF8_STRING: u8 = 10;
 
 // never used  (except for testing)
+#[cfg(test)]
 const NEVER_USED: u8 = 0xFF;
 
 impl ParsedSpec {
@@ -147,7 +154,7 @@ impl ParsedSpec {
             }
             ParsedSpec::Bytes(size) => out.write_all_size(&[BYTES])? + size.encode(out)?,
             ParsedSpec::Optional(optional_type) => {
-                out.write_all_size(&[OPTIONAL])? + ParsedSpec::to_bytes_internal(&optional_type, out)?
+                out.write_all_size(&[OPTIONAL])? + ParsedSpec::to_bytes_internal(optional_type, out)?
             }
             ParsedSpec::Name { name, spec } => {
                 out.write_all_size(&[NAME])?
@@ -163,7 +170,7 @@ impl ParsedSpec {
                     .map(|(name, spec)| {
                         combine(
                             encode_string_utf8(name, out),
-                            ParsedSpec::to_bytes_internal(&spec, out),
+                            ParsedSpec::to_bytes_internal(spec, out),
                         )
                     })
                     .fold(Ok(0usize), combine)?
@@ -173,7 +180,7 @@ impl ParsedSpec {
                     + variable_length_encode_u64(fields.len() as u64, out)?
                     + fields
                     .iter()
-                    .map(|spec| ParsedSpec::to_bytes_internal(&spec, out))
+                    .map(|spec| ParsedSpec::to_bytes_internal(spec, out))
                     .fold(Ok(0usize), combine)?
             }
             ParsedSpec::Enum(variants) => {
@@ -212,118 +219,180 @@ impl ParsedSpec {
         })
     }
 
+    /// Errors are positioned at the spec they are in, counting bytes from where reading started
     pub fn read_from_bytes<R: Read>(input: &mut R) -> Result<ParsedSpec, SpecParsingError> {
-        match next_byte(input)? {
-            BOOL => Ok(ParsedSpec::Bool),
-            VOID => Ok(ParsedSpec::Void),
-            UINT => Ok(ParsedSpec::Uint(next_byte(input)?)),
-            INT => Ok(ParsedSpec::Int(next_byte(input)?)),
-            NAME => {
-                let name = decode_utf8_string(input)?;
-                let spec = ParsedSpec::read_from_bytes(input)?.into();
-                Ok(ParsedSpec::Name { name, spec })
-            }
-            REF => Ok(ParsedSpec::Ref {
-                name: decode_utf8_string(input)?,
-            }),
-            BINARY_FP => Ok(ParsedSpec::BinaryFloatingPoint(
-                InterchangeBinaryFloatingPointFormat::decode(input)?,
-            )),
-            DECIMAL_FP => Ok(ParsedSpec::DecimalFloatingPoint(
-                InterchangeDecimalFloatingPointFormat::decode(input)?,
-            )),
-            LIST => {
-                let size = Size::decode(input)?;
-                let value_spec = ParsedSpec::read_from_bytes(input)?.into();
-                Ok(ParsedSpec::List { size, value_spec })
-            }
-            MAP => {
-                let size = Size::decode(input)?;
-                let key_spec = ParsedSpec::read_from_bytes(input)?.into();
-                let value_spec = ParsedSpec::read_from_bytes(input)?.into();
-                Ok(ParsedSpec::Map {
-                    size,
-                    key_spec,
-                    value_spec,
-                })
-            }
-            DECIMAL => {
-                let precision = decode_u64(input)?;
-                let scale = decode_u64(input)?;
-                Ok(ParsedSpec::Decimal { precision, scale })
-            }
-            BYTES => {
-                let size = Size::decode(input)?;
-                Ok(ParsedSpec::Bytes(size))
-            }
-            STRING => {
-                let size = Size::decode(input)?;
-                let str_fmt = StringEncodingFmt::decode(input)?;
-                Ok(ParsedSpec::String(size, str_fmt))
-            }
-            OPTIONAL => Ok(ParsedSpec::Optional(ParsedSpec::read_from_bytes(input)?.into())),
-            RECORD => {
-                let n = decode_u64(input)?;
-                let mut v = Vec::with_capacity(n as usize);
-                for _ in 0..n {
-                    v.push((decode_utf8_string(input)?, ParsedSpec::read_from_bytes(input)?));
-                }
-                Ok(ParsedSpec::Record(v))
+        ParsedSpec::read_from_bytes_with_limits(input, &DecodeLimits::default())
+    }
+
+    pub fn read_from_bytes_with_limits<R: Read>(
+        input: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<ParsedSpec, SpecParsingError> {
+        let mut budget = DecodeBudget::new(*limits);
+        read_spec(&mut CountingReader::new(input), &mut budget).map_err(|e| e.positioned(0))
+    }
+}
+
+fn read_spec<R: Read>(
+    input: &mut CountingReader<R>,
+    budget: &mut DecodeBudget,
+) -> Result<ParsedSpec, SpecParsingError> {
+    // reads the spec of `segment`, positioning its errors
+    fn read_within<R: Read>(
+        input: &mut CountingReader<R>,
+        budget: &mut DecodeBudget,
+        segment: PathSegment,
+    ) -> Result<ParsedSpec, SpecParsingError> {
+        let offset = input.offset();
+        read_nested(input, budget).map_err(|e| e.within(segment, offset))
+    }
+    // reads a spec that adds nothing to the path
+    fn read_inner<R: Read>(
+        input: &mut CountingReader<R>,
+        budget: &mut DecodeBudget,
+    ) -> Result<ParsedSpec, SpecParsingError> {
+        let offset = input.offset();
+        read_nested(input, budget).map_err(|e| e.positioned(offset))
+    }
+    fn read_nested<R: Read>(
+        input: &mut CountingReader<R>,
+        budget: &mut DecodeBudget,
+    ) -> Result<ParsedSpec, SpecParsingError> {
+        budget.enter()?;
+        let spec = read_spec(input, budget)?;
+        budget.exit();
+        Ok(spec)
+    }
+    // the number of fields, variants or const values that follows
+    fn read_length<R: Read>(
+        input: &mut CountingReader<R>,
+        budget: &mut DecodeBudget,
+        item_size: usize,
+    ) -> Result<u64, SpecParsingError> {
+        let n = decode_u64(input)?;
+        budget.collection(n, item_size)?;
+        Ok(n)
+    }
+    budget.spec_node()?;
+    budget.allocate(size_of::<ParsedSpec>() as u64)?;
+    match next_byte(input)? {
+        BOOL => Ok(ParsedSpec::Bool),
+        VOID => Ok(ParsedSpec::Void),
+        UINT => Ok(ParsedSpec::Uint(next_byte(input)?)),
+        INT => Ok(ParsedSpec::Int(next_byte(input)?)),
+        NAME => {
+            let name = decode_utf8_string(input, budget)?;
+            let spec = read_inner(input, budget)?.into();
+            Ok(ParsedSpec::Name { name, spec })
+        }
+        REF => Ok(ParsedSpec::Ref {
+            name: decode_utf8_string(input, budget)?,
+        }),
+        BINARY_FP => Ok(ParsedSpec::BinaryFloatingPoint(
+            InterchangeBinaryFloatingPointFormat::decode(input)?,
+        )),
+        DECIMAL_FP => Ok(ParsedSpec::DecimalFloatingPoint(
+            InterchangeDecimalFloatingPointFormat::decode(input)?,
+        )),
+        LIST => {
+            let size = Size::decode(input)?;
+            let value_spec = read_within(input, budget, PathSegment::Item)?.into();
+            Ok(ParsedSpec::List { size, value_spec })
+        }
+        MAP => {
+            let size = Size::decode(input)?;
+            let key_spec = read_within(input, budget, PathSegment::Key)?.into();
+            let value_spec = read_within(input, budget, PathSegment::Item)?.into();
+            Ok(ParsedSpec::Map {
+                size,
+                key_spec,
+                value_spec,
+            })
+        }
+        DECIMAL => {
+            let precision = decode_u64(input)?;
+            let scale = decode_u64(input)?;
+            Ok(ParsedSpec::Decimal { precision, scale })
+        }
+        BYTES => {
+            let size = Size::decode(input)?;
+            Ok(ParsedSpec::Bytes(size))
+        }
+        STRING => {
+            let size = Size::decode(input)?;
+            let str_fmt = StringEncodingFmt::decode(input)?;
+            Ok(ParsedSpec::String(size, str_fmt))
+        }
+        OPTIONAL => Ok(ParsedSpec::Optional(read_inner(input, budget)?.into())),
+        RECORD => {
+            let n = read_length(input, budget, size_of::<(String, ParsedSpec)>())?;
+            let mut v = Vec::new();
+            for _ in 0..n {
+                let field = decode_utf8_string(input, budget)?;
+                let spec = read_within(input, budget, PathSegment::Field(&field))?;
+                v.push((field, spec));
             }
-            TUPLE => {
-                let n = decode_u64(input)?;
-                let mut v = Vec::with_capacity(n as usize);
-                for _ in 0..n {
-                    v.push(ParsedSpec::read_from_bytes(input)?);
-                }
-                Ok(ParsedSpec::Tuple(v))
+            Ok(ParsedSpec::Record(v))
+        }
+        TUPLE => {
+            let n = read_length(input, budget, size_of::<ParsedSpec>())?;
+            let mut v = Vec::new();
+            for index in 0..n as usize {
+                v.push(read_within(input, budget, PathSegment::Index(index))?);
             }
-            ENUM => {
-                let n = decode_u64(input)?;
-                let mut v = Vec::with_capacity(n as usize);
-                for _ in 0..n {
-                    v.push((decode_utf8_string(input)?, ParsedSpec::read_from_bytes(input)?));
-                }
-                Ok(ParsedSpec::Enum(v))
+            Ok(ParsedSpec::Tuple(v))
+        }
+        ENUM => {
+            let n = read_length(input, budget, size_of::<(String, ParsedSpec)>())?;
+            let mut v = Vec::new();
+            for _ in 0..n {
+                let variant = decode_utf8_string(input, budget)?;
+                let spec = read_within(input, budget, PathSegment::Field(&variant))?;
+                v.push((variant, spec));
             }
-            UNION => {
-                let n = decode_u64(input)?;
-                let mut v = Vec::with_capacity(n as usize);
-                for _ in 0..n {
-                    v.push(ParsedSpec::read_from_bytes(input)?);
-                }
-                Ok(ParsedSpec::Union(v))
+            Ok(ParsedSpec::Enum(v))
+        }
+        UNION => {
+            let n = read_length(input, budget, size_of::<ParsedSpec>())?;
+            let mut v = Vec::new();
+            for index in 0..n as usize {
+                v.push(read_within(input, budget, PathSegment::Index(index))?);
             }
-            CONST_SET => {
-                let const_spec = ParsedSpec::read_from_bytes(input)?;
-                let num_consts = decode_u64(input)?;
-                let mut v = Vec::with_capacity(num_consts as usize);
-                for _ in 0..num_consts {
-                    let const_size = decode_u64(input)?;
-                    let mut buf = Vec::with_capacity(const_size as usize);
-                    input.take(const_size).read(&mut buf)?;
-                    v.push(buf)
+            Ok(ParsedSpec::Union(v))
+        }
+        CONST_SET => {
+            let const_spec = read_inner(input, budget)?;
+            let num_consts = read_length(input, budget, size_of::<Vec<u8>>())?;
+            let mut v = Vec::new();
+            for _ in 0..num_consts {
+                let const_size = decode_u64(input)?;
+                budget.string(const_size)?;
+                let mut buf = Vec::new();
+                let n_actual = input.take(const_size).read_to_end(&mut buf)?;
+                if (n_actual as u64) < const_size {
+                    return Err(SpecParsingError::UnexpectedEndOfBytes);
                 }
-                Ok(ParsedSpec::ConstSet(Box::new(const_spec), v))
+                v.push(buf)
             }
-            // aliases
-            UINT_0 => Ok(ParsedSpec::Uint(0)),
-            UINT_1 => Ok(ParsedSpec::Uint(1)),
-            UINT_2 => Ok(ParsedSpec::Uint(2)),
-            UINT_3 => Ok(ParsedSpec::Uint(3)),
-            INT_0 => Ok(ParsedSpec::Int(0)),
-            INT_1 => Ok(ParsedSpec::Int(1)),
-            INT_2 => Ok(ParsedSpec::Int(2)),
-            INT_3 => Ok(ParsedSpec::Int(3)),
-            SINGLE_FP => Ok(ParsedSpec::BinaryFloatingPoint(
-                InterchangeBinaryFloatingPointFormat::Single,
-            )),
-            DOUBLE_FP => Ok(ParsedSpec::BinaryFloatingPoint(
-                InterchangeBinaryFloatingPointFormat::Double,
-            )),
-            UTF8_STRING => Ok(ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8)),
-            flag => Err(SpecParsingError::UnknownSpecFlag(flag)),
+            Ok(ParsedSpec::ConstSet(Box::new(const_spec), v))
         }
+        // aliases
+        UINT_0 => Ok(ParsedSpec::Uint(0)),
+        UINT_1 => Ok(ParsedSpec::Uint(1)),
+        UINT_2 => Ok(ParsedSpec::Uint(2)),
+        UINT_3 => Ok(ParsedSpec::Uint(3)),
+        INT_0 => Ok(ParsedSpec::Int(0)),
+        INT_1 => Ok(ParsedSpec::Int(1)),
+        INT_2 => Ok(ParsedSpec::Int(2)),
+        INT_3 => Ok(ParsedSpec::Int(3)),
+        SINGLE_FP => Ok(ParsedSpec::BinaryFloatingPoint(
+            InterchangeBinaryFloatingPointFormat::Single,
+        )),
+        DOUBLE_FP => Ok(ParsedSpec::BinaryFloatingPoint(
+            InterchangeBinaryFloatingPointFormat::Double,
+        )),
+        UTF8_STRING => Ok(ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8)),
+        flag => Err(SpecParsingError::UnknownSpecFlag(flag)),
     }
 }
 
@@ -333,14 +402,18 @@ fn encode_string_utf8<W: Write>(string: &String, out: &mut W) -> Result<usize, i
     Ok(variable_length_encode_u64(b.len() as u64, out)? + out.write_all_size(b)?)
 }
 
-fn decode_utf8_string<R: Read>(input: &mut R) -> Result<String, SpecParsingError> {
+fn decode_utf8_string<R: Read>(
+    input: &mut R,
+    budget: &mut DecodeBudget,
+) -> Result<String, SpecParsingError> {
     let n = decode_u64(input)?;
-    let mut s = String::with_capacity(n as usize);
-    let n_actual = input.take(n).read_to_string(&mut s)?;
+    budget.string(n)?;
+    let mut bytes = Vec::new();
+    let n_actual = input.take(n).read_to_end(&mut bytes)?;
     if (n_actual as u64) < n {
         Err(SpecParsingError::UnexpectedEndOfBytes)
     } else {
-        Ok(s)
+        Ok(String::from_utf8(bytes)?)
     }
 }
 
@@ -356,10 +429,13 @@ fn decode_u64<R: Read>(input: &mut R) -> Result<u64, SpecParsingError> {
 #[inline]
 fn next_byte<R: Read>(input: &mut R) -> Result<u8, SpecParsingError> {
     let mut flag: u8 = 255;
-    if 0usize == input.read(slice::from_mut(&mut flag))? {
-        Err(SpecParsingError::UnexpectedEndOfBytes)
-    } else {
-        Ok(flag)
+    loop {
+        match input.read(slice::from_mut(&mut flag)) {
+            Ok(0) => return Err(SpecParsingError::UnexpectedEndOfBytes),
+            Ok(_) => return Ok(flag),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
     }
 }
 
@@ -375,8 +451,114 @@ pub enum SpecParsingError {
     UnknownStringFormatFlag(u8),
     UnknownSizeFormatFlag(u8),
     IntegerOverflowVariableLengthDecodingError(Vec<u8>),
+    LimitExceeded(DecodeLimitError),
+    // the bytes ran out before the end of a variable length integer
+    TruncatedVariableLengthInteger,
+    InvalidUtf8String(FromUtf8Error),
+    /// An error in the spec at `path`, which starts `offset` bytes into the read
+    Positioned {
+        offset: u64,
+        path: SpecPath,
+        error: Box<SpecParsingError>,
+    },
+}
+
+impl SpecParsingError {
+    /// Where in the spec the error is, `None` for errors from outside of the spec bytes
+    pub fn path(&self) -> Option<&SpecPath> {
+        match self {
+            Self::Positioned { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Bytes read before the spec with the error, counted from where reading started
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Self::Positioned { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The error without its position
+    pub fn cause(&self) -> &SpecParsingError {
+        match self {
+            Self::Positioned { error, .. } => error,
+            _ => self,
+        }
+    }
+
+    pub fn kind(&self) -> SpecParsingErrorKind {
+        SpecParsingErrorKind::from(self.cause())
+    }
+
+    // an error of the spec starting at `offset`, kept if it is already in a part of that spec
+    fn positioned(self, offset: u64) -> SpecParsingError {
+        match self {
+            Self::Positioned { .. } => self,
+            error => Self::Positioned {
+                offset,
+                path: SpecPath::root(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    // an error of the `segment` spec, which starts at `offset`
+    fn within(self, segment: PathSegment, offset: u64) -> SpecParsingError {
+        match self.positioned(offset) {
+            Self::Positioned {
+                offset,
+                mut path,
+                error,
+            } => {
+                path.prepend(segment);
+                Self::Positioned {
+                    offset,
+                    path,
+                    error,
+                }
+            }
+            _ => unreachable!("Error was positioned"),
+        }
+    }
 }
 
+impl Display for SpecParsingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(e) => write!(f, "unable to read spec: {}", e),
+            Self::UnexpectedEndOfBytes => f.write_str("unexpected end of spec bytes"),
+            Self::UnknownSpecFlag(flag) => write!(f, "unknown spec flag {}", flag),
+            Self::UnknownBinaryFormatFlag(flag) => {
+                write!(f, "unknown binary floating point format flag {}", flag)
+            }
+            Self::UnknownDecimalFormatFlag(flag) => {
+                write!(f, "unknown decimal floating point format flag {}", flag)
+            }
+            Self::UnknownStringFormatFlag(flag) => write!(f, "unknown string encoding flag {}", flag),
+            Self::UnknownSizeFormatFlag(flag) => write!(f, "unknown size flag {}", flag),
+            Self::IntegerOverflowVariableLengthDecodingError(bytes) => write!(
+                f,
+                "variable length integer {} does not fit in 64 bits",
+                hex::encode(bytes)
+            ),
+            Self::LimitExceeded(e) => write!(f, "decode limit exceeded: {}", e),
+            Self::TruncatedVariableLengthInteger => {
+                f.write_str("variable length integer ended early")
+            }
+            Self::InvalidUtf8String(e) => write!(f, "invalid utf8 string: {}", e),
+            Self::Positioned {
+                offset,
+                path,
+                error,
+            } => write!(f, "at byte {}, {}: {}", offset, path, error),
+        }
+    }
+}
+
+impl std::error::Error for SpecParsingError {}
+
 impl From<io::Error> for SpecParsingError {
     fn from(e: io::Error) -> Self {
         match e.kind() {
@@ -390,13 +572,25 @@ impl From<VariableLengthDecodingError> for SpecParsingError {
     fn from(e: VariableLengthDecodingError) -> Self {
         match e {
             VariableLengthDecodingError::IncompleteVariableLengthEncoding => {
-                SpecParsingError::UnexpectedEndOfBytes
+                SpecParsingError::TruncatedVariableLengthInteger
             }
             VariableLengthDecodingError::IoError(e) => SpecParsingError::ReadError(e),
         }
     }
 }
 
+impl From<DecodeLimitError> for SpecParsingError {
+    fn from(e: DecodeLimitError) -> Self {
+        SpecParsingError::LimitExceeded(e)
+    }
+}
+
+impl From<FromUtf8Error> for SpecParsingError {
+    fn from(e: FromUtf8Error) -> Self {
+        SpecParsingError::InvalidUtf8String(e)
+    }
+}
+
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
 pub enum Size {
     Variable,
@@ -457,8 +651,7 @@ impl Size {
     }
 
     /// Is the given count a possible value for the Size Spec
-    pub(crate) fn can_be<T>(&self, count: u64) -> bool
-    {
+    pub(crate) fn can_be(&self, count: u64) -> bool {
         match self {
             Size::Variable => true,
             Size::Fixed(n) => n == &count,
@@ -467,6 +660,33 @@ impl Size {
             Size::LessThan(upper_bound) => &count < upper_bound,
         }
     }
+
+    /// Is every count possible for the other Size Spec also possible for this one
+    pub(crate) fn covers(&self, other: &Size) -> bool {
+        let (start, end) = self.bounds();
+        let (other_start, other_end) = other.bounds();
+        // nothing is possible for an empty range
+        if other_end.is_some_and(|other_end| other_end <= other_start) {
+            return true;
+        }
+        start <= other_start
+            && match (end, other_end) {
+                (None, _) => true,
+                (Some(end), Some(other_end)) => other_end <= end,
+                (Some(_), None) => false,
+            }
+    }
+
+    // inclusive start and exclusive end, None for unbounded
+    pub(crate) fn bounds(&self) -> (u64, Option<u64>) {
+        match self {
+            Size::Variable => (0, None),
+            Size::Fixed(n) => (*n, Some(n.saturating_add(1))),
+            Size::Range(size_range) => (size_range.start, Some(size_range.end)),
+            Size::GreaterThan(lower_bound) => (*lower_bound, None),
+            Size::LessThan(upper_bound) => (0, Some(*upper_bound)),
+        }
+    }
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone, EnumIter)]
@@ -479,6 +699,21 @@ pub enum InterchangeBinaryFloatingPointFormat {
 }
 
 impl InterchangeBinaryFloatingPointFormat {
+    /// Lower case name used by the text and json spec formats
+    pub fn name(&self) -> &'static str {
+        match self {
+            InterchangeBinaryFloatingPointFormat::Half => "half",
+            InterchangeBinaryFloatingPointFormat::Single => "single",
+            InterchangeBinaryFloatingPointFormat::Double => "double",
+            InterchangeBinaryFloatingPointFormat::Quadruple => "quadruple",
+            InterchangeBinaryFloatingPointFormat::Octuple => "octuple",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<InterchangeBinaryFloatingPointFormat> {
+        InterchangeBinaryFloatingPointFormat::iter().find(|fmt| fmt.name() == name)
+    }
+
     pub fn significand_bits(&self) -> u64 {
         match self {
             InterchangeBinaryFloatingPointFormat::Half => 11,
@@ -533,6 +768,19 @@ pub enum InterchangeDecimalFloatingPointFormat {
 }
 
 impl InterchangeDecimalFloatingPointFormat {
+    /// Lower case name used by the text and json spec formats
+    pub fn name(&self) -> &'static str {
+        match self {
+            InterchangeDecimalFloatingPointFormat::Dec32 => "dec32",
+            InterchangeDecimalFloatingPointFormat::Dec64 => "dec64",
+            InterchangeDecimalFloatingPointFormat::Dec128 => "dec128",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<InterchangeDecimalFloatingPointFormat> {
+        InterchangeDecimalFloatingPointFormat::iter().find(|fmt| fmt.name() == name)
+    }
+
     pub fn significand_bits(&self) -> u64 {
         match self {
             InterchangeDecimalFloatingPointFormat::Dec32 => 7,
@@ -588,6 +836,19 @@ pub enum StringEncodingFmt {
 }
 
 impl StringEncodingFmt {
+    /// Lower case name used by the text and json spec formats
+    pub fn name(&self) -> &'static str {
+        match self {
+            StringEncodingFmt::Utf8 => "utf8",
+            StringEncodingFmt::Utf16 => "utf16",
+            StringEncodingFmt::Ascii => "ascii",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StringEncodingFmt> {
+        StringEncodingFmt::iter().find(|fmt| fmt.name() == name)
+    }
+
     #[inline]
     pub(crate) fn encode<W: Write>(&self, out: &mut W) -> Result<usize, io::Error> {
         match self {
@@ -617,11 +878,12 @@ pub(crate) fn combine<E>(a: Result<usize, E>, b: Result<usize, E>) -> Result<usi
 }
 
 #[cfg(test)]
+#[allow(clippy::expect_fun_call, clippy::assertions_on_constants)]
 mod tests {
 
     use strum::IntoEnumIterator;
 
-    use crate::test_utils::get_all_kinds_spec;
+    use crate::{limits::DecodeLimitErrorKind, test_utils::get_all_kinds_spec};
 
     use super::*;
     use std::io::Cursor;
@@ -676,8 +938,9 @@ mod tests {
                 .expect(format!("Unable to write to bytes. Spec: {:?}", spec).as_str());
             v.truncate(v.len() / 2);
             let res: Result<ParsedSpec, SpecParsingError> = ParsedSpec::read_from_bytes(&mut Cursor::new(&v));
-            if let SpecParsingError::UnexpectedEndOfBytes =
-                res.expect_err("Unexpectedly parsed bytes to Spec")
+            if let SpecParsingError::UnexpectedEndOfBytes
+            | SpecParsingError::TruncatedVariableLengthInteger =
+                res.expect_err("Unexpectedly parsed bytes to Spec").cause()
             {
                 assert!(true);
             } else {
@@ -738,9 +1001,30 @@ mod tests {
                         ])),
                     ]
                 }
+                SpecParsingErrorKind::TruncatedVariableLengthInteger => {
+                    vec![ParsedSpec::read_from_bytes(&mut Cursor::new(&[TUPLE, 0x81]))]
+                }
+                SpecParsingErrorKind::InvalidUtf8String => {
+                    vec![ParsedSpec::read_from_bytes(&mut Cursor::new(&[
+                        REF, 0x02, 0xC3, 0x28,
+                    ]))]
+                }
+                SpecParsingErrorKind::LimitExceeded => vec![
+                    // a record claiming 2^28 fields
+                    ParsedSpec::read_from_bytes(&mut Cursor::new(&[
+                        RECORD, 0x80, 0x80, 0x80, 0x80, 0x01,
+                    ])),
+                    ParsedSpec::read_from_bytes(&mut Cursor::new(
+                        [OPTIONAL; 200].into_iter().chain([BOOL]).collect::<Vec<_>>(),
+                    )),
+                ],
+                SpecParsingErrorKind::Positioned => {
+                    // wraps the errors above, see test_spec_parsing_error_positions
+                    Vec::<Result<ParsedSpec, SpecParsingError>>::with_capacity(0)
+                }
             }
             .into_iter()
-            .map(|res| res.map_err(SpecParsingErrorKind::from))
+            .map(|res| res.map_err(|e| e.kind()))
             .for_each(|res| match res {
                 Ok(unexpected_spec) => {
                     assert!(false, "Unexpectedly parsed into {:?}", unexpected_spec)
@@ -751,4 +1035,101 @@ mod tests {
             })
         }
     }
+
+    #[test]
+    fn test_spec_parsing_error_positions() {
+        let spec = ParsedSpec::from_text(
+            "record { id: uint(3), tags: map<string, list<float(single)>> }",
+        )
+        .unwrap();
+        let bytes = spec.to_bytes();
+        // the list item spec is written last, as its flag and format
+        let flag_offset = bytes.len() - 2;
+        assert_eq!(BINARY_FP, bytes[flag_offset]);
+        let mut corrupt = bytes.clone();
+        corrupt[flag_offset] = NEVER_USED;
+        let error = ParsedSpec::read_from_bytes(&mut Cursor::new(&corrupt)).unwrap_err();
+        assert_eq!(Some(".tags[][]"), error.path().map(SpecPath::as_str));
+        assert_eq!(Some(flag_offset as u64), error.offset());
+        assert_eq!(SpecParsingErrorKind::UnknownSpecFlag, error.kind());
+        assert_eq!(
+            format!("at byte {}, .tags[][]: unknown spec flag {}", flag_offset, NEVER_USED),
+            error.to_string()
+        );
+
+        let error = ParsedSpec::read_from_bytes(&mut Cursor::new(&[NEVER_USED])).unwrap_err();
+        assert_eq!(Some(""), error.path().map(SpecPath::as_str));
+        assert_eq!(Some(0), error.offset());
+    }
+
+    #[test]
+    fn test_parsing_limits() {
+        let limits = DecodeLimits {
+            max_depth: 2,
+            max_total_allocation: 1 << 10,
+            max_collection_length: 3,
+            max_string_length: 4,
+            max_spec_nodes: 6,
+        };
+        let read = |text: &str| {
+            ParsedSpec::read_from_bytes_with_limits(
+                &mut Cursor::new(ParsedSpec::from_text(text).unwrap().to_bytes()),
+                &limits,
+            )
+            .map_err(|e| match e.cause() {
+                SpecParsingError::LimitExceeded(e) => DecodeLimitErrorKind::from(e),
+                e => panic!("Not a limit error: {}", e),
+            })
+        };
+        assert!(read("record { a: list<bool>, b: optional<bool> }").is_ok());
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxDepthExceeded),
+            read("list<list<list<bool>>>")
+        );
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxCollectionLengthExceeded),
+            read("tuple { bool, bool, bool, bool }")
+        );
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxStringLengthExceeded),
+            read("record { abcde: bool }")
+        );
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxSpecNodesExceeded),
+            read("tuple { list<bool>, list<bool>, list<bool> }")
+        );
+        assert_eq!(
+            Err(DecodeLimitErrorKind::MaxTotalAllocationExceeded),
+            ParsedSpec::read_from_bytes_with_limits(
+                &mut Cursor::new(ParsedSpec::Bool.to_bytes()),
+                &DecodeLimits {
+                    max_total_allocation: 1,
+                    ..limits
+                },
+            )
+            .map_err(|e| match e.cause() {
+                SpecParsingError::LimitExceeded(e) => DecodeLimitErrorKind::from(e),
+                e => panic!("Not a limit error: {}", e),
+            })
+        );
+    }
+
+    #[test]
+    fn test_size_covers() {
+        let range = |start, end| Size::Range(SizeRange { start, end });
+        for (size, other, covers) in [
+            (Size::Variable, Size::Fixed(3), true),
+            (Size::Fixed(3), Size::Variable, false),
+            (Size::Fixed(3), Size::Fixed(3), true),
+            (range(1, 5), Size::Fixed(4), true),
+            (range(1, 5), Size::Fixed(5), false),
+            (Size::LessThan(5), range(0, 5), true),
+            (Size::LessThan(5), Size::GreaterThan(1), false),
+            (Size::GreaterThan(2), range(2, 9), true),
+            (Size::GreaterThan(2), Size::LessThan(9), false),
+            (Size::Fixed(0), range(3, 3), true),
+        ] {
+            assert_eq!(covers, size.covers(&other), "{:?} covers {:?}", size, other);
+        }
+    }
 }
@@ -0,0 +1,82 @@
+use std::fmt::{self, Display};
+
+/// Location of a sub spec, e.g. `.orders[].price`.
+///
+/// Record fields and enum variants are `.name`, tuple fields and union variants `.0`,
+/// list items and map values `[]` and map keys `{}`. Names, optionals and const sets add nothing.
+/// Paths into values number the items, `[2]` is the third list item or map value and `{2}` the
+/// third map key.
+#[derive(Debug, Default, Eq, PartialEq, Hash, Clone)]
+pub struct SpecPath(String);
+
+impl SpecPath {
+    pub fn root() -> SpecPath {
+        SpecPath::default()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Descends into `segment`, returning the length to `truncate` back to on the way out
+    pub(crate) fn push(&mut self, segment: PathSegment) -> usize {
+        let len = self.0.len();
+        match segment {
+            PathSegment::Field(name) => {
+                self.0.push('.');
+                self.0.push_str(name);
+            }
+            PathSegment::Index(index) => {
+                self.0.push('.');
+                self.0.push_str(&index.to_string());
+            }
+            PathSegment::Item => self.0.push_str("[]"),
+            PathSegment::Key => self.0.push_str("{}"),
+            PathSegment::Element(index) => {
+                self.0.push('[');
+                self.0.push_str(&index.to_string());
+                self.0.push(']');
+            }
+            PathSegment::EntryKey(index) => {
+                self.0.push('{');
+                self.0.push_str(&index.to_string());
+                self.0.push('}');
+            }
+        }
+        len
+    }
+
+    /// Puts `segment` in front of the path
+    pub(crate) fn prepend(&mut self, segment: PathSegment) {
+        let mut path = SpecPath::root();
+        path.push(segment);
+        path.0.push_str(&self.0);
+        *self = path;
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.0.truncate(len)
+    }
+}
+
+impl Display for SpecPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            f.write_str(".")
+        } else {
+            f.write_str(&self.0)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) enum PathSegment<'a> {
+    Field(&'a str),
+    Index(usize),
+    Item,
+    Key,
+    // list item or map value of a value
+    Element(usize),
+    // map key of a value
+    EntryKey(usize),
+}
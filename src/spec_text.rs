@@ -0,0 +1,845 @@
+//! Human readable text syntax for specs, e.g.
+//!
+//! ```text
+//! name Tree = record {
+//!     id: uint(3),
+//!     label: string<utf8, ..64>,
+//!     children: list<ref Tree>,
+//! }
+//! ```
+//!
+//! Sizes are written like rust ranges, `8` is fixed, `2..9` a range with an exclusive end,
+//! `3..` at least 3 and `..64` less than 64. Leaving out the size means variable.
+//! Names and field names that are not plain identifiers are quoted, `"field 1": bool`.
+//! Const set values are the hex encoded bytes of each constant, `const_set<uint(0)> { 0x01, 0x02 }`.
+//! Line comments start with `//`.
+
+use std::{
+    fmt::{self, Display, Write},
+    str::FromStr,
+};
+
+use strum::{EnumDiscriminants, EnumIter};
+
+use crate::{
+    spec::Spec,
+    spec_parsing::{
+        InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, ParsedSpec,
+        Size, SizeRange, StringEncodingFmt,
+    },
+};
+
+const INDENT: &str = "    ";
+
+impl ParsedSpec {
+    pub fn from_text(text: &str) -> Result<ParsedSpec, SpecTextError> {
+        let mut parser = Parser::new(text)?;
+        let spec = parser.spec()?;
+        match parser.peek() {
+            None => Ok(spec),
+            Some(token) => Err(SpecTextError::TrailingText(token.position)),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        write_spec(&mut out, self, 0).expect("Writing to a string does not fail");
+        out
+    }
+}
+
+impl FromStr for ParsedSpec {
+    type Err = SpecTextError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ParsedSpec::from_text(s)
+    }
+}
+
+impl Spec {
+    pub fn to_text(&self) -> String {
+        self.to_parsed_spec().to_text()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct TextPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for TextPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, EnumDiscriminants)]
+#[strum_discriminants(name(SpecTextErrorKind))]
+#[strum_discriminants(derive(EnumIter))]
+pub enum SpecTextError {
+    UnexpectedEndOfText,
+    UnexpectedCharacter(TextPosition, char),
+    UnterminatedString(TextPosition),
+    UnexpectedToken {
+        position: TextPosition,
+        expected: &'static str,
+        found: String,
+    },
+    UnknownSpecKeyword(TextPosition, String),
+    UnknownFormat(TextPosition, String),
+    InvalidNumber(TextPosition, String),
+    InvalidHex(TextPosition, String),
+    TrailingText(TextPosition),
+}
+
+impl Display for SpecTextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEndOfText => f.write_str("unexpected end of spec text"),
+            Self::UnexpectedCharacter(position, c) => {
+                write!(f, "{}: unexpected character {:?}", position, c)
+            }
+            Self::UnterminatedString(position) => write!(f, "{}: unterminated string", position),
+            Self::UnexpectedToken {
+                position,
+                expected,
+                found,
+            } => write!(f, "{}: expected {}, found {}", position, expected, found),
+            Self::UnknownSpecKeyword(position, keyword) => {
+                write!(f, "{}: unknown spec {:?}", position, keyword)
+            }
+            Self::UnknownFormat(position, format) => {
+                write!(f, "{}: unknown format {:?}", position, format)
+            }
+            Self::InvalidNumber(position, number) => {
+                write!(f, "{}: invalid number {:?}", position, number)
+            }
+            Self::InvalidHex(position, hex) => {
+                write!(f, "{}: invalid hex bytes {:?}", position, hex)
+            }
+            Self::TrailingText(position) => {
+                write!(f, "{}: text after the end of the spec", position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpecTextError {}
+
+// printing
+
+fn write_spec(out: &mut String, spec: &ParsedSpec, depth: usize) -> fmt::Result {
+    match spec {
+        ParsedSpec::Void => out.write_str("void"),
+        ParsedSpec::Bool => out.write_str("bool"),
+        ParsedSpec::Uint(n) => write!(out, "uint({})", n),
+        ParsedSpec::Int(n) => write!(out, "int({})", n),
+        ParsedSpec::BinaryFloatingPoint(fmt) => {
+            write!(out, "float({})", fmt.name())
+        }
+        ParsedSpec::DecimalFloatingPoint(fmt) => {
+            write!(out, "decimal_float({})", fmt.name())
+        }
+        ParsedSpec::Decimal { precision, scale } => {
+            write!(out, "decimal({}, {})", precision, scale)
+        }
+        ParsedSpec::String(size, fmt) => {
+            out.write_str("string")?;
+            match (fmt, size) {
+                (StringEncodingFmt::Utf8, Size::Variable) => Ok(()),
+                (fmt, Size::Variable) => write!(out, "<{}>", fmt.name()),
+                (StringEncodingFmt::Utf8, size) => write!(out, "<{}>", SizeText(size)),
+                (fmt, size) => write!(out, "<{}, {}>", fmt.name(), SizeText(size)),
+            }
+        }
+        ParsedSpec::Bytes(size) => match size {
+            Size::Variable => out.write_str("bytes"),
+            size => write!(out, "bytes<{}>", SizeText(size)),
+        },
+        ParsedSpec::Optional(inner) => {
+            out.write_str("optional<")?;
+            write_spec(out, inner, depth)?;
+            out.write_char('>')
+        }
+        ParsedSpec::List { size, value_spec } => {
+            out.write_str("list<")?;
+            write_spec(out, value_spec, depth)?;
+            write_size_arg(out, size)?;
+            out.write_char('>')
+        }
+        ParsedSpec::Map {
+            size,
+            key_spec,
+            value_spec,
+        } => {
+            out.write_str("map<")?;
+            write_spec(out, key_spec, depth)?;
+            out.write_str(", ")?;
+            write_spec(out, value_spec, depth)?;
+            write_size_arg(out, size)?;
+            out.write_char('>')
+        }
+        ParsedSpec::Record(fields) => write_block(out, "record ", fields, depth, write_field),
+        ParsedSpec::Enum(variants) => write_block(out, "enum ", variants, depth, write_field),
+        ParsedSpec::Tuple(fields) => write_block(out, "tuple ", fields, depth, write_spec),
+        ParsedSpec::Union(variants) => write_block(out, "union ", variants, depth, write_spec),
+        ParsedSpec::ConstSet(const_spec, values) => {
+            out.write_str("const_set<")?;
+            write_spec(out, const_spec, depth)?;
+            write_block(out, "> ", values, depth, |out, value, _| {
+                write!(out, "0x{}", hex::encode(value))
+            })
+        }
+        ParsedSpec::Name { name, spec } => {
+            out.write_str("name ")?;
+            write_ident(out, name)?;
+            out.write_str(" = ")?;
+            write_spec(out, spec, depth)
+        }
+        ParsedSpec::Ref { name } => {
+            out.write_str("ref ")?;
+            write_ident(out, name)
+        }
+    }
+}
+
+fn write_field(out: &mut String, (name, spec): &(String, ParsedSpec), depth: usize) -> fmt::Result {
+    write_ident(out, name)?;
+    out.write_str(": ")?;
+    write_spec(out, spec, depth)
+}
+
+// one entry per line so changes to a spec review line by line
+fn write_block<T>(
+    out: &mut String,
+    keyword: &str,
+    entries: &[T],
+    depth: usize,
+    write_entry: impl Fn(&mut String, &T, usize) -> fmt::Result,
+) -> fmt::Result {
+    out.write_str(keyword)?;
+    if entries.is_empty() {
+        return out.write_str("{}");
+    }
+    out.write_str("{\n")?;
+    for entry in entries {
+        out.write_str(&INDENT.repeat(depth + 1))?;
+        write_entry(out, entry, depth + 1)?;
+        out.write_str(",\n")?;
+    }
+    out.write_str(&INDENT.repeat(depth))?;
+    out.write_char('}')
+}
+
+fn write_size_arg(out: &mut String, size: &Size) -> fmt::Result {
+    match size {
+        Size::Variable => Ok(()),
+        size => write!(out, ", {}", SizeText(size)),
+    }
+}
+
+fn write_ident(out: &mut String, ident: &str) -> fmt::Result {
+    if is_plain_ident(ident) {
+        out.write_str(ident)
+    } else {
+        out.write_char('"')?;
+        for c in ident.chars() {
+            match c {
+                '"' => out.write_str("\\\"")?,
+                '\\' => out.write_str("\\\\")?,
+                '\n' => out.write_str("\\n")?,
+                '\t' => out.write_str("\\t")?,
+                c => out.write_char(c)?,
+            }
+        }
+        out.write_char('"')
+    }
+}
+
+fn is_plain_ident(ident: &str) -> bool {
+    let mut chars = ident.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct SizeText<'a>(&'a Size);
+
+impl Display for SizeText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Size::Variable => f.write_str(".."),
+            Size::Fixed(n) => write!(f, "{}", n),
+            Size::Range(SizeRange { start, end }) => write!(f, "{}..{}", start, end),
+            Size::GreaterThan(n) => write!(f, "{}..", n),
+            Size::LessThan(n) => write!(f, "..{}", n),
+        }
+    }
+}
+
+// parsing
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    Ident(String),
+    Str(String),
+    Number(String),
+    Hex(String),
+    DotDot,
+    Punct(char),
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Ident(ident) => write!(f, "{:?}", ident),
+            TokenKind::Str(s) => write!(f, "string {:?}", s),
+            TokenKind::Number(n) => write!(f, "number {}", n),
+            TokenKind::Hex(h) => write!(f, "hex 0x{}", h),
+            TokenKind::DotDot => f.write_str("\"..\""),
+            TokenKind::Punct(c) => write!(f, "{:?}", c),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: TextPosition,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, SpecTextError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let (mut line, mut column) = (1, 1);
+    // consume one char keeping track of the position
+    macro_rules! bump {
+        () => {{
+            let c = chars.next();
+            if c == Some('\n') {
+                line += 1;
+                column = 1;
+            } else if c.is_some() {
+                column += 1;
+            }
+            c
+        }};
+    }
+    while let Some(&c) = chars.peek() {
+        let position = TextPosition { line, column };
+        let kind = match c {
+            c if c.is_whitespace() => {
+                bump!();
+                continue;
+            }
+            '/' => {
+                bump!();
+                if chars.peek() != Some(&'/') {
+                    return Err(SpecTextError::UnexpectedCharacter(position, '/'));
+                }
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    bump!();
+                }
+                continue;
+            }
+            '<' | '>' | '(' | ')' | '{' | '}' | ',' | ':' | '=' => {
+                bump!();
+                TokenKind::Punct(c)
+            }
+            '.' => {
+                bump!();
+                if bump!() != Some('.') {
+                    return Err(SpecTextError::UnexpectedCharacter(position, '.'));
+                }
+                TokenKind::DotDot
+            }
+            '"' => {
+                bump!();
+                let mut s = String::new();
+                loop {
+                    match bump!() {
+                        None => return Err(SpecTextError::UnterminatedString(position)),
+                        Some('"') => break,
+                        Some('\\') => match bump!() {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(c @ ('"' | '\\')) => s.push(c),
+                            Some(c) => {
+                                return Err(SpecTextError::UnexpectedCharacter(
+                                    TextPosition {
+                                        line,
+                                        column: column - 1,
+                                    },
+                                    c,
+                                ));
+                            }
+                            None => return Err(SpecTextError::UnterminatedString(position)),
+                        },
+                        Some(c) => s.push(c),
+                    }
+                }
+                TokenKind::Str(s)
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        word.push(c);
+                        bump!();
+                    } else {
+                        break;
+                    }
+                }
+                if let Some(hex) = word.strip_prefix("0x") {
+                    TokenKind::Hex(hex.to_string())
+                } else if word.starts_with(|c: char| c.is_ascii_digit()) {
+                    TokenKind::Number(word)
+                } else {
+                    TokenKind::Ident(word)
+                }
+            }
+            c => return Err(SpecTextError::UnexpectedCharacter(position, c)),
+        };
+        tokens.push(Token { kind, position });
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: std::vec::IntoIter<Token>,
+    peeked: Option<Token>,
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Parser, SpecTextError> {
+        let mut tokens = tokenize(text)?.into_iter();
+        let peeked = tokens.next();
+        Ok(Parser { tokens, peeked })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peeked.as_ref()
+    }
+
+    fn next(&mut self) -> Result<Token, SpecTextError> {
+        let token = self
+            .peeked
+            .take()
+            .ok_or(SpecTextError::UnexpectedEndOfText)?;
+        self.peeked = self.tokens.next();
+        Ok(token)
+    }
+
+    fn peek_is(&self, c: char) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Punct(p), .. }) if *p == c)
+    }
+
+    fn unexpected(token: Token, expected: &'static str) -> SpecTextError {
+        SpecTextError::UnexpectedToken {
+            position: token.position,
+            expected,
+            found: token.kind.to_string(),
+        }
+    }
+
+    fn expect(&mut self, c: char, expected: &'static str) -> Result<(), SpecTextError> {
+        let token = self.next()?;
+        if token.kind == TokenKind::Punct(c) {
+            Ok(())
+        } else {
+            Err(Self::unexpected(token, expected))
+        }
+    }
+
+    fn keyword(&mut self, expected: &'static str) -> Result<(String, TextPosition), SpecTextError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Ident(ident) => Ok((ident, token.position)),
+            _ => Err(Self::unexpected(token, expected)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, SpecTextError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Ident(ident) | TokenKind::Str(ident) => Ok(ident),
+            _ => Err(Self::unexpected(token, "a name")),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, SpecTextError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Number(n) => n
+                .parse()
+                .map_err(|_| SpecTextError::InvalidNumber(token.position, n)),
+            _ => Err(Self::unexpected(token, "a number")),
+        }
+    }
+
+    fn spec(&mut self) -> Result<ParsedSpec, SpecTextError> {
+        let (keyword, position) = self.keyword("a spec")?;
+        Ok(match keyword.as_str() {
+            "void" => ParsedSpec::Void,
+            "bool" => ParsedSpec::Bool,
+            "uint" => ParsedSpec::Uint(self.parenthesized(Self::number)?),
+            "int" => ParsedSpec::Int(self.parenthesized(Self::number)?),
+            "float" => ParsedSpec::BinaryFloatingPoint(self.parenthesized(|parser| {
+                let (name, position) = parser.keyword("a binary floating point format")?;
+                InterchangeBinaryFloatingPointFormat::from_name(&name)
+                    .ok_or(SpecTextError::UnknownFormat(position, name))
+            })?),
+            "decimal_float" => ParsedSpec::DecimalFloatingPoint(self.parenthesized(|parser| {
+                let (name, position) = parser.keyword("a decimal floating point format")?;
+                InterchangeDecimalFloatingPointFormat::from_name(&name)
+                    .ok_or(SpecTextError::UnknownFormat(position, name))
+            })?),
+            "decimal" => {
+                let (precision, scale) = self.parenthesized(|parser| {
+                    let precision = parser.number()?;
+                    parser.expect(',', "\",\"")?;
+                    Ok((precision, parser.number()?))
+                })?;
+                ParsedSpec::Decimal { precision, scale }
+            }
+            "string" => {
+                let (mut fmt, mut size) = (StringEncodingFmt::Utf8, Size::Variable);
+                if self.peek_is('<') {
+                    self.next()?;
+                    if matches!(
+                        self.peek(),
+                        Some(Token {
+                            kind: TokenKind::Ident(_),
+                            ..
+                        })
+                    ) {
+                        fmt = self.string_fmt()?;
+                        if self.peek_is(',') {
+                            self.next()?;
+                            size = self.size()?;
+                        }
+                    } else {
+                        size = self.size()?;
+                    }
+                    self.expect('>', "\">\"")?;
+                }
+                ParsedSpec::String(size, fmt)
+            }
+            "bytes" => {
+                let mut size = Size::Variable;
+                if self.peek_is('<') {
+                    self.next()?;
+                    size = self.size()?;
+                    self.expect('>', "\">\"")?;
+                }
+                ParsedSpec::Bytes(size)
+            }
+            "optional" => {
+                self.expect('<', "\"<\"")?;
+                let inner = self.spec()?;
+                self.expect('>', "\">\"")?;
+                ParsedSpec::Optional(inner.into())
+            }
+            "list" => {
+                self.expect('<', "\"<\"")?;
+                let value_spec = self.spec()?.into();
+                let size = self.size_arg()?;
+                ParsedSpec::List { size, value_spec }
+            }
+            "map" => {
+                self.expect('<', "\"<\"")?;
+                let key_spec = self.spec()?.into();
+                self.expect(',', "\",\"")?;
+                let value_spec = self.spec()?.into();
+                let size = self.size_arg()?;
+                ParsedSpec::Map {
+                    size,
+                    key_spec,
+                    value_spec,
+                }
+            }
+            "record" => ParsedSpec::Record(self.block(Self::field)?),
+            "enum" => ParsedSpec::Enum(self.block(Self::field)?),
+            "tuple" => ParsedSpec::Tuple(self.block(Self::spec)?),
+            "union" => ParsedSpec::Union(self.block(Self::spec)?),
+            "const_set" => {
+                self.expect('<', "\"<\"")?;
+                let const_spec = self.spec()?;
+                self.expect('>', "\">\"")?;
+                let values = self.block(|parser| {
+                    let token = parser.next()?;
+                    match token.kind {
+                        TokenKind::Hex(h) => hex::decode(&h)
+                            .map_err(|_| SpecTextError::InvalidHex(token.position, h)),
+                        _ => Err(Self::unexpected(token, "hex bytes")),
+                    }
+                })?;
+                ParsedSpec::ConstSet(const_spec.into(), values)
+            }
+            "name" => {
+                let name = self.ident()?;
+                self.expect('=', "\"=\"")?;
+                ParsedSpec::Name {
+                    name,
+                    spec: self.spec()?.into(),
+                }
+            }
+            "ref" => ParsedSpec::Ref {
+                name: self.ident()?,
+            },
+            _ => return Err(SpecTextError::UnknownSpecKeyword(position, keyword)),
+        })
+    }
+
+    fn parenthesized<T>(
+        &mut self,
+        inner: impl FnOnce(&mut Self) -> Result<T, SpecTextError>,
+    ) -> Result<T, SpecTextError> {
+        self.expect('(', "\"(\"")?;
+        let value = inner(self)?;
+        self.expect(')', "\")\"")?;
+        Ok(value)
+    }
+
+    // comma separated entries in braces, a trailing comma is allowed
+    fn block<T>(
+        &mut self,
+        entry: impl Fn(&mut Self) -> Result<T, SpecTextError>,
+    ) -> Result<Vec<T>, SpecTextError> {
+        self.expect('{', "\"{\"")?;
+        let mut entries = Vec::new();
+        while !self.peek_is('}') {
+            entries.push(entry(self)?);
+            if !self.peek_is('}') {
+                self.expect(',', "\",\" or \"}\"")?;
+            }
+        }
+        self.next()?;
+        Ok(entries)
+    }
+
+    fn field(&mut self) -> Result<(String, ParsedSpec), SpecTextError> {
+        let name = self.ident()?;
+        self.expect(':', "\":\"")?;
+        Ok((name, self.spec()?))
+    }
+
+    fn string_fmt(&mut self) -> Result<StringEncodingFmt, SpecTextError> {
+        let (name, position) = self.keyword("a string encoding")?;
+        StringEncodingFmt::from_name(&name).ok_or(SpecTextError::UnknownFormat(position, name))
+    }
+
+    // optional trailing size of a list or map and the closing bracket
+    fn size_arg(&mut self) -> Result<Size, SpecTextError> {
+        let size = if self.peek_is(',') {
+            self.next()?;
+            self.size()?
+        } else {
+            Size::Variable
+        };
+        self.expect('>', "\">\"")?;
+        Ok(size)
+    }
+
+    fn size(&mut self) -> Result<Size, SpecTextError> {
+        let start = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Number(_),
+                ..
+            }) => Some(self.number()?),
+            _ => None,
+        };
+        if !matches!(
+            self.peek(),
+            Some(Token {
+                kind: TokenKind::DotDot,
+                ..
+            })
+        ) {
+            return match start {
+                Some(n) => Ok(Size::Fixed(n)),
+                None => Err(Self::unexpected(self.next()?, "a size")),
+            };
+        }
+        self.next()?;
+        let end = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Number(_),
+                ..
+            }) => Some(self.number()?),
+            _ => None,
+        };
+        Ok(match (start, end) {
+            (None, None) => Size::Variable,
+            (Some(start), None) => Size::GreaterThan(start),
+            (None, Some(end)) => Size::LessThan(end),
+            (Some(start), Some(end)) => Size::Range(SizeRange { start, end }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use crate::test_utils::get_all_kinds_spec;
+
+    use super::*;
+
+    #[test]
+    fn test_text_round_trip() {
+        for spec in get_all_kinds_spec() {
+            let text = spec.to_text();
+            assert_eq!(
+                spec,
+                ParsedSpec::from_text(&text)
+                    .unwrap_or_else(|e| panic!("Unable to parse {}: {}", text, e)),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_text_sizes_round_trip() {
+        for size in [
+            Size::Variable,
+            Size::Fixed(4),
+            Size::Range(SizeRange { start: 2, end: 9 }),
+            Size::GreaterThan(3),
+            Size::LessThan(7),
+        ] {
+            for spec in [
+                ParsedSpec::String(size.clone(), StringEncodingFmt::Utf8),
+                ParsedSpec::String(size.clone(), StringEncodingFmt::Utf16),
+                ParsedSpec::Bytes(size.clone()),
+                ParsedSpec::List {
+                    size: size.clone(),
+                    value_spec: ParsedSpec::Bool.into(),
+                },
+                ParsedSpec::Map {
+                    size: size.clone(),
+                    key_spec: ParsedSpec::Bool.into(),
+                    value_spec: ParsedSpec::Void.into(),
+                },
+            ] {
+                assert_eq!(spec, ParsedSpec::from_text(&spec.to_text()).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_example() {
+        let spec: ParsedSpec = r#"
+            // a tree of labeled nodes
+            name Tree = record {
+                id: uint(3),
+                name: string<utf8, ..64>,
+                tags: list<string>,
+                "child nodes": list<ref Tree, 0..16>,
+                kind: const_set<uint(0)> { 0x01, 0x02 },
+            }
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            ParsedSpec::Name {
+                name: "Tree".into(),
+                spec: ParsedSpec::Record(vec![
+                    ("id".into(), ParsedSpec::Uint(3)),
+                    (
+                        "name".into(),
+                        ParsedSpec::String(Size::LessThan(64), StringEncodingFmt::Utf8)
+                    ),
+                    (
+                        "tags".into(),
+                        ParsedSpec::List {
+                            size: Size::Variable,
+                            value_spec: ParsedSpec::String(Size::Variable, StringEncodingFmt::Utf8)
+                                .into(),
+                        }
+                    ),
+                    (
+                        "child nodes".into(),
+                        ParsedSpec::List {
+                            size: Size::Range(SizeRange { start: 0, end: 16 }),
+                            value_spec: ParsedSpec::Ref {
+                                name: "Tree".into()
+                            }
+                            .into(),
+                        }
+                    ),
+                    (
+                        "kind".into(),
+                        ParsedSpec::ConstSet(
+                            ParsedSpec::Uint(0).into(),
+                            vec![vec![0x01], vec![0x02]]
+                        )
+                    ),
+                ])
+                .into(),
+            },
+            spec
+        );
+        assert_eq!(
+            spec.to_text(),
+            r#"name Tree = record {
+    id: uint(3),
+    name: string<..64>,
+    tags: list<string>,
+    "child nodes": list<ref Tree, 0..16>,
+    kind: const_set<uint(0)> {
+        0x01,
+        0x02,
+    },
+}"#
+        );
+        assert_eq!(
+            spec.to_text(),
+            Spec::compile(spec.clone()).unwrap().to_text()
+        );
+    }
+
+    #[test]
+    fn test_text_error_kinds() {
+        for kind in SpecTextErrorKind::iter() {
+            match kind {
+                SpecTextErrorKind::UnexpectedEndOfText => vec!["", "record { a: bool", "list<"],
+                SpecTextErrorKind::UnexpectedCharacter => vec![
+                    "bool;",
+                    "string<.5>",
+                    "record { a/b: bool }",
+                    "name \"\\q\" = void",
+                ],
+                SpecTextErrorKind::UnterminatedString => vec!["ref \"Tree", "name \"a\\"],
+                SpecTextErrorKind::UnexpectedToken => vec![
+                    "record { a bool }",
+                    "list<bool 3>",
+                    "uint 3",
+                    "map<bool>",
+                    "tuple { bool bool }",
+                    "bytes<utf8>",
+                    "const_set<bool> { 1 }",
+                ],
+                SpecTextErrorKind::UnknownSpecKeyword => vec!["boolean", "record { a: integer }"],
+                SpecTextErrorKind::UnknownFormat => {
+                    vec!["float(triple)", "decimal_float(dec16)", "string<utf32>"]
+                }
+                SpecTextErrorKind::InvalidNumber => {
+                    vec!["uint(256)", "int(12ab)", "bytes<99999999999999999999>"]
+                }
+                SpecTextErrorKind::InvalidHex => {
+                    vec!["const_set<bool> { 0x1 }", "const_set<bool> { 0xzz }"]
+                }
+                SpecTextErrorKind::TrailingText => vec!["bool bool", "record {},"],
+            }
+            .into_iter()
+            .for_each(|text| match ParsedSpec::from_text(text) {
+                Ok(spec) => panic!("{:?} unexpectedly parsed into {:?}", text, spec),
+                Err(e) => assert_eq!(kind, SpecTextErrorKind::from(&e), "{:?}: {}", text, e),
+            });
+        }
+    }
+}
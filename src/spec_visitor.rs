@@ -0,0 +1,459 @@
+//! Walking and transforming specs without writing out the recursion over every kind of spec.
+//!
+//! [visit_spec] and [visit_parsed_spec] call a [SpecVisitor] for every spec, depth first. A name
+//! is visited with its definition the first time it is met and as a reference every time after,
+//! so recursive specs are visited once. A compiled spec is visited exactly as its
+//! `to_parsed_spec` would be.
+//!
+//! [fold_parsed_spec] and [fold_spec] rebuild a spec bottom up, passing each spec to a [SpecFold]
+//! once its sub specs have been folded.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    spec::{NamedSpec, Spec, SpecCompileError, SpecType, encode_const_values},
+    spec_parsing::{
+        InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, ParsedSpec,
+        Size, StringEncodingFmt,
+    },
+};
+
+/// Called for each spec in a walk. Specs with sub specs get a start before their sub specs and an
+/// end after, record fields, tuple fields, enum variants and union variants get their own start
+/// and end around their spec. Every method does nothing unless implemented.
+#[allow(unused_variables)]
+pub trait SpecVisitor {
+    fn visit_bool(&mut self) {}
+    fn visit_uint(&mut self, n: u8) {}
+    fn visit_int(&mut self, n: u8) {}
+    fn visit_binary_fp(&mut self, fpf: &InterchangeBinaryFloatingPointFormat) {}
+    fn visit_decimal_fp(&mut self, fp: &InterchangeDecimalFloatingPointFormat) {}
+    fn visit_decimal(&mut self, precision: u64, scale: u64) {}
+    fn visit_string(&mut self, size: &Size, fmt: &StringEncodingFmt) {}
+    fn visit_bytes(&mut self, size: &Size) {}
+    fn visit_optional_start(&mut self) {}
+    fn visit_optional_end(&mut self) {}
+    fn visit_map_start_key(&mut self) {}
+    fn visit_map_end_key(&mut self) {}
+    fn visit_map_start_value(&mut self) {}
+    fn visit_map_end_value(&mut self, size: &Size) {}
+    fn visit_list_start(&mut self) {}
+    fn visit_list_end(&mut self, size: &Size) {}
+    fn visit_record_start(&mut self) {}
+    fn visit_record_field_start(&mut self, field: &str) {}
+    fn visit_record_field_end(&mut self, field: &str) {}
+    fn visit_record_end(&mut self, fields: Vec<String>) {}
+    fn visit_tuple_start(&mut self) {}
+    fn visit_tuple_field_start(&mut self, index: usize) {}
+    fn visit_tuple_field_end(&mut self, index: usize) {}
+    fn visit_tuple_end(&mut self, n: usize) {}
+    fn visit_enum_start(&mut self) {}
+    fn visit_enum_variant_start(&mut self, variant: &str) {}
+    fn visit_enum_variant_end(&mut self, variant: &str) {}
+    fn visit_enum_end(&mut self, variants: Vec<String>) {}
+    fn visit_union_start(&mut self) {}
+    fn visit_union_member_start(&mut self, index: usize) {}
+    fn visit_union_member_end(&mut self, index: usize) {}
+    fn visit_union_end(&mut self, n: usize) {}
+    fn visit_const_set_start(&mut self) {}
+    /// `consts` are the bytes of each value of the const spec
+    fn visit_const_set_end(&mut self, consts: &[Vec<u8>]) {}
+    /// Start of the definition of `name`
+    fn visit_name_start(&mut self, name: &str) {}
+    fn visit_name_end(&mut self, name: &str) {}
+    /// A name that is defined elsewhere in the spec, or is still being defined
+    fn visit_ref(&mut self, name: &str) {}
+    fn visit_void(&mut self) {}
+}
+
+/// Walks `spec` depth first, sub specs are visited between the start and end of their parent
+pub fn visit_parsed_spec<V: SpecVisitor>(spec: &ParsedSpec, visitor: &mut V) {
+    match spec {
+        ParsedSpec::Bool => visitor.visit_bool(),
+        ParsedSpec::Uint(n) => visitor.visit_uint(*n),
+        ParsedSpec::Int(n) => visitor.visit_int(*n),
+        ParsedSpec::BinaryFloatingPoint(fmt) => visitor.visit_binary_fp(fmt),
+        ParsedSpec::DecimalFloatingPoint(fmt) => visitor.visit_decimal_fp(fmt),
+        ParsedSpec::Decimal { precision, scale } => visitor.visit_decimal(*precision, *scale),
+        ParsedSpec::String(size, fmt) => visitor.visit_string(size, fmt),
+        ParsedSpec::Bytes(size) => visitor.visit_bytes(size),
+        ParsedSpec::Optional(spec) => {
+            visitor.visit_optional_start();
+            visit_parsed_spec(spec, visitor);
+            visitor.visit_optional_end();
+        }
+        ParsedSpec::Map {
+            size,
+            key_spec,
+            value_spec,
+        } => {
+            visitor.visit_map_start_key();
+            visit_parsed_spec(key_spec, visitor);
+            visitor.visit_map_end_key();
+            visitor.visit_map_start_value();
+            visit_parsed_spec(value_spec, visitor);
+            visitor.visit_map_end_value(size);
+        }
+        ParsedSpec::List { size, value_spec } => {
+            visitor.visit_list_start();
+            visit_parsed_spec(value_spec, visitor);
+            visitor.visit_list_end(size);
+        }
+        ParsedSpec::Record(fields) => {
+            visitor.visit_record_start();
+            for (field, spec) in fields {
+                visitor.visit_record_field_start(field);
+                visit_parsed_spec(spec, visitor);
+                visitor.visit_record_field_end(field);
+            }
+            visitor.visit_record_end(fields.iter().map(|(field, _)| field.clone()).collect());
+        }
+        ParsedSpec::Tuple(specs) => {
+            visitor.visit_tuple_start();
+            for (index, spec) in specs.iter().enumerate() {
+                visitor.visit_tuple_field_start(index);
+                visit_parsed_spec(spec, visitor);
+                visitor.visit_tuple_field_end(index);
+            }
+            visitor.visit_tuple_end(specs.len());
+        }
+        ParsedSpec::Enum(variants) => {
+            visitor.visit_enum_start();
+            for (variant, spec) in variants {
+                visitor.visit_enum_variant_start(variant);
+                visit_parsed_spec(spec, visitor);
+                visitor.visit_enum_variant_end(variant);
+            }
+            visitor.visit_enum_end(
+                variants
+                    .iter()
+                    .map(|(variant, _)| variant.clone())
+                    .collect(),
+            );
+        }
+        ParsedSpec::Union(specs) => {
+            visitor.visit_union_start();
+            for (index, spec) in specs.iter().enumerate() {
+                visitor.visit_union_member_start(index);
+                visit_parsed_spec(spec, visitor);
+                visitor.visit_union_member_end(index);
+            }
+            visitor.visit_union_end(specs.len());
+        }
+        ParsedSpec::ConstSet(const_spec, values) => {
+            visitor.visit_const_set_start();
+            visit_parsed_spec(const_spec, visitor);
+            visitor.visit_const_set_end(values);
+        }
+        ParsedSpec::Name { name, spec } => {
+            visitor.visit_name_start(name);
+            visit_parsed_spec(spec, visitor);
+            visitor.visit_name_end(name);
+        }
+        ParsedSpec::Ref { name } => visitor.visit_ref(name),
+        ParsedSpec::Void => visitor.visit_void(),
+    }
+}
+
+/// Walks `spec` depth first, visiting the definition of each name the first time it is met
+pub fn visit_spec<V: SpecVisitor>(spec: &Spec, visitor: &mut V) {
+    visit_spec_type(
+        spec.named_schema(),
+        &mut HashSet::new(),
+        spec.spec_type(),
+        visitor,
+    )
+}
+
+// names are looked up in the context of the spec the walk started at, like `to_parsed_spec`
+fn visit_spec_type<V: SpecVisitor>(
+    context: &HashMap<String, NamedSpec>,
+    names_visited: &mut HashSet<String>,
+    spec_type: &SpecType,
+    visitor: &mut V,
+) {
+    if let SpecType::Name(name) = spec_type {
+        match context.get(name).and_then(NamedSpec::defined) {
+            // names still being compiled can only be referred to
+            Some(named_spec) if !names_visited.contains(name) => {
+                names_visited.insert(name.clone());
+                visitor.visit_name_start(name);
+                visit_spec_type(context, names_visited, named_spec.spec_type(), visitor);
+                visitor.visit_name_end(name);
+            }
+            _ => visitor.visit_ref(name),
+        }
+        return;
+    }
+    let mut visit = |spec: &Spec, visitor: &mut V| {
+        visit_spec_type(context, names_visited, spec.spec_type(), visitor)
+    };
+    match spec_type {
+        SpecType::Void => visitor.visit_void(),
+        SpecType::Bool => visitor.visit_bool(),
+        SpecType::Uint(n) => visitor.visit_uint(*n),
+        SpecType::Int(n) => visitor.visit_int(*n),
+        SpecType::BinaryFloatingPoint(fmt) => visitor.visit_binary_fp(fmt),
+        SpecType::DecimalFloatingPoint(fmt) => visitor.visit_decimal_fp(fmt),
+        SpecType::Decimal(fmt) => visitor.visit_decimal(fmt.precision, fmt.scale),
+        SpecType::String(size, fmt) => visitor.visit_string(size, fmt),
+        SpecType::Bytes(size) => visitor.visit_bytes(size),
+        SpecType::Optional(spec) => {
+            visitor.visit_optional_start();
+            visit(spec, visitor);
+            visitor.visit_optional_end();
+        }
+        SpecType::Map {
+            size,
+            key_spec,
+            value_spec,
+        } => {
+            visitor.visit_map_start_key();
+            visit(key_spec, visitor);
+            visitor.visit_map_end_key();
+            visitor.visit_map_start_value();
+            visit(value_spec, visitor);
+            visitor.visit_map_end_value(size);
+        }
+        SpecType::List { size, value_spec } => {
+            visitor.visit_list_start();
+            visit(value_spec, visitor);
+            visitor.visit_list_end(size);
+        }
+        SpecType::Record {
+            fields,
+            field_to_spec,
+            ..
+        } => {
+            visitor.visit_record_start();
+            for field in fields {
+                visitor.visit_record_field_start(field);
+                visit(&field_to_spec[field], visitor);
+                visitor.visit_record_field_end(field);
+            }
+            visitor.visit_record_end(fields.clone());
+        }
+        SpecType::Tuple(specs) => {
+            visitor.visit_tuple_start();
+            for (index, spec) in specs.iter().enumerate() {
+                visitor.visit_tuple_field_start(index);
+                visit(spec, visitor);
+                visitor.visit_tuple_field_end(index);
+            }
+            visitor.visit_tuple_end(specs.len());
+        }
+        SpecType::Enum {
+            variants,
+            variant_to_spec,
+        } => {
+            visitor.visit_enum_start();
+            for variant in variants {
+                visitor.visit_enum_variant_start(variant);
+                visit(&variant_to_spec[variant], visitor);
+                visitor.visit_enum_variant_end(variant);
+            }
+            visitor.visit_enum_end(variants.clone());
+        }
+        SpecType::Union(specs) => {
+            visitor.visit_union_start();
+            for (index, spec) in specs.iter().enumerate() {
+                visitor.visit_union_member_start(index);
+                visit(spec, visitor);
+                visitor.visit_union_member_end(index);
+            }
+            visitor.visit_union_end(specs.len());
+        }
+        SpecType::ConstSet(const_spec, values) => {
+            visitor.visit_const_set_start();
+            visit(const_spec, visitor);
+            visitor.visit_const_set_end(&encode_const_values(const_spec, values));
+        }
+        SpecType::Name(_) => unreachable!("Names are visited above"),
+    }
+}
+
+/// Rebuilds specs bottom up
+pub trait SpecFold {
+    /// Called with each spec once its sub specs have been folded, returns the spec to put in its
+    /// place
+    fn fold(&mut self, spec: ParsedSpec) -> ParsedSpec;
+}
+
+impl<F> SpecFold for F
+where
+    F: FnMut(ParsedSpec) -> ParsedSpec,
+{
+    fn fold(&mut self, spec: ParsedSpec) -> ParsedSpec {
+        self(spec)
+    }
+}
+
+pub fn fold_parsed_spec<F: SpecFold>(spec: ParsedSpec, folder: &mut F) -> ParsedSpec {
+    let mut fold = |spec: ParsedSpec| fold_parsed_spec(spec, folder);
+    let spec = match spec {
+        ParsedSpec::Optional(spec) => ParsedSpec::Optional(fold(*spec).into()),
+        ParsedSpec::Map {
+            size,
+            key_spec,
+            value_spec,
+        } => ParsedSpec::Map {
+            size,
+            key_spec: fold(*key_spec).into(),
+            value_spec: fold(*value_spec).into(),
+        },
+        ParsedSpec::List { size, value_spec } => ParsedSpec::List {
+            size,
+            value_spec: fold(*value_spec).into(),
+        },
+        ParsedSpec::Record(fields) => ParsedSpec::Record(
+            fields
+                .into_iter()
+                .map(|(field, spec)| (field, fold(spec)))
+                .collect(),
+        ),
+        ParsedSpec::Tuple(specs) => ParsedSpec::Tuple(specs.into_iter().map(fold).collect()),
+        ParsedSpec::Enum(variants) => ParsedSpec::Enum(
+            variants
+                .into_iter()
+                .map(|(variant, spec)| (variant, fold(spec)))
+                .collect(),
+        ),
+        ParsedSpec::Union(specs) => ParsedSpec::Union(specs.into_iter().map(fold).collect()),
+        ParsedSpec::ConstSet(const_spec, values) => {
+            ParsedSpec::ConstSet(fold(*const_spec).into(), values)
+        }
+        ParsedSpec::Name { name, spec } => ParsedSpec::Name {
+            name,
+            spec: fold(*spec).into(),
+        },
+        spec => spec,
+    };
+    folder.fold(spec)
+}
+
+/// Folds the structure of a compiled spec, with each name defined once, and compiles the result
+pub fn fold_spec<F: SpecFold>(spec: &Spec, folder: &mut F) -> Result<Spec, SpecCompileError> {
+    Spec::compile(fold_parsed_spec(spec.to_parsed_spec(), folder))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::get_all_kinds_spec;
+
+    // writes out the walk, one line per event
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl SpecVisitor for Recorder {
+        fn visit_uint(&mut self, n: u8) {
+            self.0.push(format!("uint {}", n))
+        }
+
+        fn visit_list_start(&mut self) {
+            self.0.push("list".into())
+        }
+
+        fn visit_list_end(&mut self, size: &Size) {
+            self.0.push(format!("end list {:?}", size))
+        }
+
+        fn visit_record_field_start(&mut self, field: &str) {
+            self.0.push(format!("field {}", field))
+        }
+
+        fn visit_record_end(&mut self, fields: Vec<String>) {
+            self.0.push(format!("end record {:?}", fields))
+        }
+
+        fn visit_const_set_end(&mut self, consts: &[Vec<u8>]) {
+            self.0.push(format!("end const set {:?}", consts))
+        }
+
+        fn visit_name_start(&mut self, name: &str) {
+            self.0.push(format!("name {}", name))
+        }
+
+        fn visit_name_end(&mut self, name: &str) {
+            self.0.push(format!("end name {}", name))
+        }
+
+        fn visit_ref(&mut self, name: &str) {
+            self.0.push(format!("ref {}", name))
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let spec = ParsedSpec::from_text(
+            r#"name Tree = record {
+                id: uint(3),
+                children: list<ref Tree>,
+                flag: const_set<uint(0)> { 0x01 },
+            }"#,
+        )
+        .unwrap();
+        let mut recorder = Recorder::default();
+        visit_spec(&spec.clone().compile().unwrap(), &mut recorder);
+        assert_eq!(
+            vec![
+                "name Tree",
+                "field id",
+                "uint 3",
+                "field children",
+                "list",
+                "ref Tree",
+                "end list Variable",
+                "field flag",
+                "uint 0",
+                "end const set [[1]]",
+                "end record [\"id\", \"children\", \"flag\"]",
+                "end name Tree",
+            ],
+            recorder.0
+        );
+
+        // compiled specs are walked as their parsed spec is
+        for spec in get_all_kinds_spec() {
+            let (mut parsed, mut compiled) = (Recorder::default(), Recorder::default());
+            visit_parsed_spec(&spec, &mut parsed);
+            visit_spec(&spec.compile().unwrap(), &mut compiled);
+            assert_eq!(parsed.0, compiled.0);
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        // widen every integer, inside names too
+        let mut widen = |spec: ParsedSpec| match spec {
+            ParsedSpec::Uint(n) if n < 3 => ParsedSpec::Uint(3),
+            ParsedSpec::Int(n) if n < 3 => ParsedSpec::Int(3),
+            spec => spec,
+        };
+        let spec = Spec::compile(
+            ParsedSpec::from_text(
+                "name Node = tuple { uint(1), optional<ref Node>, map<int(0), list<ref Node>> }",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            Spec::compile(
+                ParsedSpec::from_text(
+                    "name Node = tuple { uint(3), optional<ref Node>, map<int(3), list<ref Node>> }",
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+            fold_spec(&spec, &mut widen).unwrap()
+        );
+
+        let mut specs_folded = 0;
+        let folded = fold_parsed_spec(spec.to_parsed_spec(), &mut |spec| {
+            specs_folded += 1;
+            spec
+        });
+        assert_eq!(spec.to_parsed_spec(), folded);
+        assert_eq!(9, specs_folded);
+    }
+}
@@ -1,12 +1,59 @@
-use std::iter;
+use std::{
+    io::{self, Read},
+    iter,
+};
 use hex::FromHex;
 use strum::IntoEnumIterator;
 
+use crate::serde::{GluinoValue, get_unit_serialization_function};
+use crate::spec::Spec;
 use crate::spec_parsing::{
     InterchangeBinaryFloatingPointFormat, InterchangeDecimalFloatingPointFormat, Size, ParsedSpec,
     SpecKind, StringEncodingFmt,
 };
 
+pub(crate) fn compile(text: &str) -> Spec {
+    Spec::compile(ParsedSpec::from_text(text).unwrap()).unwrap()
+}
+
+pub(crate) fn encode(spec: &Spec, value: GluinoValue) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    get_unit_serialization_function::<Vec<u8>>(spec)
+        .serialize(value, &mut bytes)
+        .unwrap();
+    bytes
+}
+
+pub(crate) fn string(s: &str) -> GluinoValue {
+    GluinoValue::String(s.to_string())
+}
+
+// fails every other read with `Interrupted`, which readers should retry
+pub(crate) struct InterruptingReader<R> {
+    reader: R,
+    interrupt: bool,
+}
+
+impl<R> InterruptingReader<R> {
+    pub(crate) fn new(reader: R) -> InterruptingReader<R> {
+        InterruptingReader {
+            reader,
+            interrupt: true,
+        }
+    }
+}
+
+impl<R: Read> Read for InterruptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.interrupt = !self.interrupt;
+        if self.interrupt {
+            Err(io::ErrorKind::Interrupted.into())
+        } else {
+            self.reader.read(buf)
+        }
+    }
+}
+
 pub(crate) fn get_all_kinds_spec() -> Vec<ParsedSpec> {
     let mut specs = Vec::with_capacity(256);
     for spec_kind in SpecKind::iter() {
@@ -18,6 +65,7 @@ pub(crate) fn get_all_kinds_spec() -> Vec<ParsedSpec> {
     specs
 }
 
+#[allow(clippy::redundant_closure)]
 pub(crate) fn get_valid_specs_for_kind(spec_kind: SpecKind) -> Box<dyn Iterator<Item =ParsedSpec>> {
     match spec_kind {
         SpecKind::Bool => Box::new(iter::once(ParsedSpec::Bool)),
@@ -141,6 +189,6 @@ pub(crate) fn get_valid_specs_for_kind(spec_kind: SpecKind) -> Box<dyn Iterator<
         ]))),
         SpecKind::Union => Box::new(iter::once(ParsedSpec::Union(vec![ParsedSpec::Bool, ParsedSpec::Int(4)]))),
         SpecKind::Void => Box::new(iter::once(ParsedSpec::Void)),
-        SpecKind::ConstSet => Box::new(iter::once(ParsedSpec::ConstSet(Box::new(ParsedSpec::Int(2)), vec![Vec::from_hex("12").unwrap(), Vec::from_hex("34").unwrap()]))),
+        SpecKind::ConstSet => Box::new(iter::once(ParsedSpec::ConstSet(Box::new(ParsedSpec::Int(2)), vec![Vec::from_hex("12000000").unwrap(), Vec::from_hex("34000000").unwrap()]))),
     }
 }
@@ -1,4 +1,7 @@
-use std::io::{self, Read, Write};
+use std::{
+    fmt::{self, Display},
+    io::{self, Read, Write},
+};
 
 pub trait WriteAllReturnSize {
     fn write_all_size(&mut self, bytes: &[u8]) -> Result<usize, io::Error>;
@@ -12,6 +15,45 @@ impl<W: Write> WriteAllReturnSize for W {
     }
 }
 
+/// A reader that keeps count of the bytes read through it
+#[derive(Debug)]
+pub struct CountingReader<R> {
+    reader: R,
+    offset: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(reader: R) -> CountingReader<R> {
+        CountingReader { reader, offset: 0 }
+    }
+
+    /// Number of bytes read so far
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
 const MAX_LANG_BYTES: usize = 16;
 const MAX_SYSTEM_BYTES_VLE: usize = MAX_LANG_BYTES * 8 / 7 + 1;
 
@@ -32,6 +74,7 @@ pub fn variable_length_decode_u64<R: Read>(
     variable_lenth_decode(input)
 }
 
+#[allow(dead_code)]
 pub fn variable_length_encode_u128<W: Write>(mut z: u128, out: &mut W) -> Result<usize, io::Error> {
     let mut encoding = [0u8; MAX_SYSTEM_BYTES_VLE];
     let mut n = 0usize;
@@ -44,6 +87,7 @@ pub fn variable_length_encode_u128<W: Write>(mut z: u128, out: &mut W) -> Result
     out.write_all_size(&encoding[0..=n])
 }
 
+#[allow(dead_code)]
 pub fn variable_length_decode_u128<R: Read>(
     input: &mut R,
 ) -> Result<VariableLengthResult<u128>, VariableLengthDecodingError> {
@@ -138,6 +182,45 @@ pub enum VariableLengthDecodingError {
     IoError(io::Error),
 }
 
+impl Display for VariableLengthDecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncompleteVariableLengthEncoding => {
+                f.write_str("variable length integer ended early")
+            }
+            Self::IoError(e) => write!(f, "unable to read variable length integer: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VariableLengthDecodingError {}
+
+// CRC-32C (Castagnoli), reflected polynomial
+const CRC32C_POLY: u32 = 0x82F6_3B78;
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ CRC32C_POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, b| {
+        CRC32C_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -204,7 +287,7 @@ mod tests {
             v2[0..v.len()].copy_from_slice(&v[..]);
             assert_eq!(268435455, u32::from_le_bytes(v2));
         } else {
-            assert!(false);
+            panic!("Should be unrepresentable");
         }
         if let VariableLengthResult::<u32>::Unrepresentable(v) =
             variable_lenth_decode(&mut out).unwrap()
@@ -213,7 +296,7 @@ mod tests {
             v2[0..v.len()].copy_from_slice(&v[..]);
             assert_eq!(268435456, u64::from_le_bytes(v2));
         } else {
-            assert!(false);
+            panic!("Should be unrepresentable");
         }
     }
 
@@ -224,15 +307,31 @@ mod tests {
             0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
             0xFF, 0x01,
         ])) {
-            Ok(VariableLengthResult::Respresentable(n)) => {
-                assert!(false, "Should overflow")
-            }
-            Ok(VariableLengthResult::Unrepresentable(_)) => {
-                assert!(true);
-            }
-            Err(e) => {
-                assert!(true);
+            Ok(VariableLengthResult::Respresentable(_)) => {
+                panic!("Should overflow")
             }
+            Ok(VariableLengthResult::Unrepresentable(_)) | Err(_) => {}
         };
     }
+
+    #[test]
+    fn test_counting_reader() {
+        let mut reader = CountingReader::new(Cursor::new(vec![0x80, 0x01, 0x02, 0x03]));
+        assert!(matches!(
+            variable_length_decode_u64(&mut reader).unwrap(),
+            VariableLengthResult::Respresentable(128)
+        ));
+        assert_eq!(2, reader.offset());
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(4, reader.offset());
+        assert_eq!(vec![0x02, 0x03], rest);
+    }
+
+    #[test]
+    fn test_crc32c() {
+        assert_eq!(0, crc32c(b""));
+        assert_eq!(0xE306_9283, crc32c(b"123456789"));
+        assert_eq!(0x8A91_36AA, crc32c(&[0u8; 32]));
+    }
 }